@@ -0,0 +1,44 @@
+use std::time::{Duration, Instant};
+
+use crate::JwtError;
+
+/// Tokens this close to the expiry are regenerated, so they don't expire in flight
+const REFRESH_MARGIN: Duration = Duration::from_secs(5);
+
+/// Source of the tokens cached by [`JwtCache`]
+pub trait JwtGenerator {
+    /// Sign a new token, returns it together with its lifetime
+    fn generate(&self) -> Result<(String, Duration), JwtError>;
+}
+
+/// Keeps the last generated token until it's about to expire,
+/// signing is comparatively expensive, especially with RSA keys
+#[derive(Debug, Default)]
+pub struct JwtCache {
+    cached: Option<(String, Instant)>,
+}
+
+impl JwtCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cached token if it's valid for at least 5 more seconds, otherwise a new one from `generator`
+    pub fn get_or_refresh(&mut self, generator: &impl JwtGenerator) -> Result<&str, JwtError> {
+        let is_fresh = self
+            .cached
+            .as_ref()
+            .is_some_and(|(_, expires_at)| Instant::now() + REFRESH_MARGIN < *expires_at);
+        if !is_fresh {
+            let (jwt, lifetime) = generator.generate()?;
+            self.cached = Some((jwt, Instant::now() + lifetime));
+        }
+
+        Ok(self.cached.as_ref().map_or("", |(jwt, _)| jwt.as_str()))
+    }
+
+    /// Drop the cached token, eg after the key was changed
+    pub fn clear(&mut self) {
+        self.cached = None;
+    }
+}
@@ -0,0 +1,69 @@
+//! Concurrent chunk downloads over HTTP/1.1 vs HTTP/2 from the local mock server,
+//! run with `cargo bench --bench chunk_download`
+//!
+//! Mock server speaks plain HTTP, where ALPN can't pick HTTP/2, so the HTTP/2 clients use prior
+//! knowledge with the flow control windows of `Connection::default_client_builder_h2`.
+//! Loopback has no latency, so this measures the protocol overhead and the effect of the windows,
+//! the connection reuse pays off against the remote storage only.
+
+use std::collections::HashMap;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use snowflake_api::connection::Connection;
+
+/// Result chunks are up to ~16 MiB, smaller ones keep the benchmark quick
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Same as the windows of `Connection::default_client_builder_h2`
+const H2_STREAM_WINDOW_SIZE: u32 = 4 * 1024 * 1024;
+const H2_CONNECTION_WINDOW_SIZE: u32 = 16 * 1024 * 1024;
+
+fn h2_connection(tuned_windows: bool) -> Connection {
+    let mut client = reqwest::ClientBuilder::new().http2_prior_knowledge();
+    if tuned_windows {
+        client = client
+            .http2_initial_stream_window_size(H2_STREAM_WINDOW_SIZE)
+            .http2_initial_connection_window_size(H2_CONNECTION_WINDOW_SIZE);
+    }
+    Connection::new_with_middware(
+        reqwest_middleware::ClientBuilder::new(client.build().unwrap()).build(),
+    )
+}
+
+fn chunk_download(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut server = mockito::Server::new();
+    let _chunk = server
+        .mock("GET", "/chunk")
+        .with_body(vec![b'x'; CHUNK_SIZE])
+        .expect_at_least(0)
+        .create();
+    let url = format!("{}/chunk", server.url());
+    let headers = HashMap::new();
+
+    let connections = [
+        ("http1", Connection::new().unwrap()),
+        ("h2_default_windows", h2_connection(false)),
+        ("h2_tuned_windows", h2_connection(true)),
+    ];
+
+    let mut group = c.benchmark_group("chunk_download");
+    group.sample_size(10);
+    for count in [1, 8, 32] {
+        let urls = vec![url.as_str(); count];
+        group.throughput(Throughput::Bytes((count * CHUNK_SIZE) as u64));
+        for (name, connection) in &connections {
+            group.bench_with_input(BenchmarkId::new(*name, count), &urls, |b, urls| {
+                b.iter(|| {
+                    runtime
+                        .block_on(connection.get_chunks_parallel(urls.iter().copied(), &headers))
+                        .unwrap()
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, chunk_download);
+criterion_main!(benches);
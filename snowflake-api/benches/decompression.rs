@@ -0,0 +1,80 @@
+//! Sequential vs parallel decompression of the result chunks, run with `cargo bench`
+
+use std::io::Write;
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use snowflake_api::{decompress_chunks_parallel, CompressionFormat};
+
+/// Result chunks are up to ~16 MiB uncompressed, smaller ones keep the benchmark quick
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// JSON rows compress about as well as the real ones
+fn chunk(seed: usize) -> Vec<u8> {
+    let mut rows = Vec::with_capacity(CHUNK_SIZE);
+    let mut i = seed;
+    while rows.len() < CHUNK_SIZE {
+        write!(
+            rows,
+            "[\"{i}\",\"name-{}\",\"{}.{:02}\"],",
+            i % 997,
+            i * 31,
+            i % 100
+        )
+        .unwrap();
+        i += 1;
+    }
+    rows
+}
+
+fn compressed_chunks(count: usize, format: CompressionFormat) -> Vec<Bytes> {
+    (0..count)
+        .map(|i| {
+            let chunk = chunk(i * 100_000);
+            Bytes::from(match format {
+                CompressionFormat::Gzip => {
+                    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(&chunk).unwrap();
+                    encoder.finish().unwrap()
+                }
+                CompressionFormat::Zstd => zstd::encode_all(chunk.as_slice(), 3).unwrap(),
+                _ => unreachable!(),
+            })
+        })
+        .collect()
+}
+
+fn decompression(c: &mut Criterion) {
+    let sequential = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+
+    for format in [CompressionFormat::Gzip, CompressionFormat::Zstd] {
+        let mut group = c.benchmark_group(format!("decompress_{format:?}").to_lowercase());
+        group.sample_size(10);
+        for count in [1, 8, 32] {
+            let chunks = compressed_chunks(count, format);
+            group.throughput(Throughput::Bytes((count * CHUNK_SIZE) as u64));
+
+            group.bench_with_input(
+                BenchmarkId::new("sequential", count),
+                &chunks,
+                |b, chunks| {
+                    b.iter(|| {
+                        sequential.install(|| decompress_chunks_parallel(chunks.clone(), format))
+                    });
+                },
+            );
+            group.bench_with_input(BenchmarkId::new("parallel", count), &chunks, |b, chunks| {
+                b.iter(|| decompress_chunks_parallel(chunks.clone(), format));
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, decompression);
+criterion_main!(benches);
@@ -34,7 +34,7 @@ async fn main() -> Result<()> {
 
     dotenv::dotenv().ok();
 
-    let mut client = Connection::default_client_builder()?;
+    let mut client = Connection::default_client_builder(None)?;
     client = client
         .with_init(Extension(OtelName(std::borrow::Cow::Borrowed(
             "snowflake-api",
@@ -0,0 +1,55 @@
+use std::fmt::{Display, Formatter};
+
+use crate::ConfigError;
+
+const HOST_SUFFIX: &str = ".snowflakecomputing.com";
+
+/// Normalized account identifier, one of `<account>`, `<org>-<account>`
+/// or `<account>.<region>[.<platform>]`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AccountIdentifier(String);
+
+impl AccountIdentifier {
+    /// Lowercase the identifier, the host name suffix is stripped if it was included by mistake
+    pub fn parse(raw: &str) -> Result<Self, ConfigError> {
+        let mut account = raw.trim().to_lowercase();
+        if let Some(host) = account.strip_prefix("https://") {
+            account = host.trim_end_matches('/').to_string();
+        }
+        if let Some(stripped) = account.strip_suffix(HOST_SUFFIX) {
+            account.truncate(stripped.len());
+        }
+
+        if account.is_empty() {
+            return Err(ConfigError::MissingField("account_identifier"));
+        }
+        let invalid = |reason: String| ConfigError::InvalidField {
+            field: "account_identifier",
+            reason,
+        };
+        if let Some(c) = account
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        {
+            return Err(invalid(format!(
+                "`{raw}` contains unexpected character `{c}`"
+            )));
+        }
+        if account.split('.').any(str::is_empty) || account.split('.').count() > 3 {
+            return Err(invalid(format!(
+                "`{raw}` should be `<account>`, `<org>-<account>` or `<account>.<region>.<platform>`"
+            )));
+        }
+        Ok(Self(account))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for AccountIdentifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
@@ -0,0 +1,190 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::metadata::yes_no;
+use crate::quote::quote_literal;
+use crate::{SnowflakeApi, SnowflakeApiError};
+
+/// Row of `SNOWFLAKE.ACCOUNT_USAGE.QUERY_HISTORY`, columns which aren't listed here are ignored
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueryHistoryRow {
+    pub query_id: String,
+    pub query_text: String,
+    #[serde(default)]
+    pub database_name: Option<String>,
+    #[serde(default)]
+    pub schema_name: Option<String>,
+    /// `SELECT`, `INSERT`, `CREATE_TABLE`, etc
+    pub query_type: String,
+    pub session_id: i64,
+    pub user_name: String,
+    #[serde(default)]
+    pub role_name: Option<String>,
+    #[serde(default)]
+    pub warehouse_name: Option<String>,
+    #[serde(default)]
+    pub warehouse_size: Option<String>,
+    /// `SUCCESS`, `FAIL` or `INCIDENT`
+    pub execution_status: String,
+    #[serde(default)]
+    pub error_message: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// Milliseconds spent executing, without the compilation and queueing
+    pub execution_time: u64,
+    /// Milliseconds from the start to the end of the query
+    pub total_elapsed_time: u64,
+    pub credits_used_cloud_services: f64,
+}
+
+/// Row of `SNOWFLAKE.ACCOUNT_USAGE.WAREHOUSE_METERING_HISTORY`, one per warehouse and hour
+#[derive(Debug, Clone, Deserialize)]
+pub struct WarehouseMeteringRow {
+    pub warehouse_name: String,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// Sum of the compute and cloud services credits
+    pub credits_used: f64,
+    pub credits_used_compute: f64,
+    pub credits_used_cloud_services: f64,
+}
+
+/// Row of `SNOWFLAKE.ACCOUNT_USAGE.STORAGE_USAGE`, average daily usage of the account
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorageUsageRow {
+    pub usage_date: NaiveDate,
+    /// Tables, including Time Travel data
+    pub storage_bytes: f64,
+    pub stage_bytes: f64,
+    pub failsafe_bytes: f64,
+}
+
+/// Row of `SNOWFLAKE.ACCOUNT_USAGE.LOGIN_HISTORY`, columns which aren't listed here are ignored
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoginHistoryRow {
+    pub event_id: i64,
+    pub event_timestamp: DateTime<Utc>,
+    pub user_name: String,
+    pub client_ip: String,
+    /// `JDBC_DRIVER`, `SNOWFLAKE_UI`, `OTHER`, etc
+    pub reported_client_type: String,
+    /// `PASSWORD`, `RSA_KEYPAIR`, `OAUTH_ACCESS_TOKEN`, etc
+    pub first_authentication_factor: String,
+    #[serde(default)]
+    pub second_authentication_factor: Option<String>,
+    #[serde(default, deserialize_with = "yes_no")]
+    pub is_success: bool,
+    #[serde(default)]
+    pub error_code: Option<i64>,
+    #[serde(default)]
+    pub error_message: Option<String>,
+}
+
+/// Queries of the `SNOWFLAKE.ACCOUNT_USAGE` views, see [`SnowflakeApi::account_usage`].
+/// The views lag behind by up to a few hours and need `IMPORTED PRIVILEGES`
+/// on the `SNOWFLAKE` database.
+pub struct AccountUsage<'a> {
+    api: &'a SnowflakeApi,
+}
+
+impl SnowflakeApi {
+    pub fn account_usage(&self) -> AccountUsage<'_> {
+        AccountUsage { api: self }
+    }
+}
+
+impl AccountUsage<'_> {
+    /// Queries which have started within `[start, end)`, in order of their start
+    pub async fn query_history(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<QueryHistoryRow>, SnowflakeApiError> {
+        self.select(
+            "QUERY_ID, QUERY_TEXT, DATABASE_NAME, SCHEMA_NAME, QUERY_TYPE, SESSION_ID, USER_NAME, \
+            ROLE_NAME, WAREHOUSE_NAME, WAREHOUSE_SIZE, EXECUTION_STATUS, ERROR_MESSAGE, \
+            START_TIME, END_TIME, EXECUTION_TIME, TOTAL_ELAPSED_TIME, CREDITS_USED_CLOUD_SERVICES",
+            "QUERY_HISTORY",
+            &time_range("START_TIME", start, end),
+            "START_TIME",
+        )
+        .await
+    }
+
+    /// Hourly credit usage of the warehouses within `[start, end)`
+    pub async fn warehouse_metering_history(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<WarehouseMeteringRow>, SnowflakeApiError> {
+        self.select(
+            "WAREHOUSE_NAME, START_TIME, END_TIME, CREDITS_USED, CREDITS_USED_COMPUTE, \
+            CREDITS_USED_CLOUD_SERVICES",
+            "WAREHOUSE_METERING_HISTORY",
+            &time_range("START_TIME", start, end),
+            "START_TIME, WAREHOUSE_NAME",
+        )
+        .await
+    }
+
+    /// Daily storage usage of the account, both dates are inclusive
+    pub async fn storage_usage(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<StorageUsageRow>, SnowflakeApiError> {
+        let filter = format!(
+            "USAGE_DATE BETWEEN {}::DATE AND {}::DATE",
+            quote_literal(&start.to_string()),
+            quote_literal(&end.to_string())
+        );
+        self.select(
+            "USAGE_DATE, STORAGE_BYTES, STAGE_BYTES, FAILSAFE_BYTES",
+            "STORAGE_USAGE",
+            &filter,
+            "USAGE_DATE",
+        )
+        .await
+    }
+
+    /// Login attempts within `[start, end)`, including the failed ones
+    pub async fn login_history(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<LoginHistoryRow>, SnowflakeApiError> {
+        self.select(
+            "EVENT_ID, EVENT_TIMESTAMP, USER_NAME, CLIENT_IP, REPORTED_CLIENT_TYPE, \
+            FIRST_AUTHENTICATION_FACTOR, SECOND_AUTHENTICATION_FACTOR, IS_SUCCESS, ERROR_CODE, \
+            ERROR_MESSAGE",
+            "LOGIN_HISTORY",
+            &time_range("EVENT_TIMESTAMP", start, end),
+            "EVENT_TIMESTAMP",
+        )
+        .await
+    }
+
+    async fn select<T: DeserializeOwned>(
+        &self,
+        columns: &str,
+        view: &str,
+        filter: &str,
+        order_by: &str,
+    ) -> Result<Vec<T>, SnowflakeApiError> {
+        self.api
+            .query_as(&format!(
+                "SELECT {columns} FROM SNOWFLAKE.ACCOUNT_USAGE.{view} \
+                WHERE {filter} ORDER BY {order_by}"
+            ))
+            .await
+    }
+}
+
+fn time_range(column: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+    format!(
+        "{column} >= {}::TIMESTAMP_LTZ AND {column} < {}::TIMESTAMP_LTZ",
+        quote_literal(&start.to_rfc3339()),
+        quote_literal(&end.to_rfc3339())
+    )
+}
@@ -0,0 +1,525 @@
+//! Rows of the Arrow result in the JSON encoding of Snowflake values, so they are read
+//! with [`crate::FromSnowflakeValue`] and [`crate::Row::deserialize`] like the JSON ones
+
+use std::fmt::Write;
+
+use arrow::array::{Array, AsArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Decimal128Type, Field, Float64Type, Int32Type, Int64Type};
+use arrow::record_batch::RecordBatch;
+use serde_json::Value;
+
+use crate::responses::SnowflakeType;
+use crate::rows::{Rows, TypeError};
+use crate::FieldSchema;
+
+/// Timestamps with the separate fraction have it in nanoseconds
+const FRACTION_SCALE: u32 = 9;
+
+impl Rows {
+    /// Rows of the record batches, eg the ones of [`crate::QueryResult::Arrow`].
+    /// Column types are taken from the Snowflake metadata of the Arrow fields,
+    /// or from the Arrow types for batches which don't have it.
+    pub fn from_record_batches(batches: &[RecordBatch]) -> Result<Self, TypeError> {
+        let schema = batches.first().map_or_else(Vec::new, |batch| {
+            batch
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| field_schema(field))
+                .collect()
+        });
+        batch_rows(batches, schema)
+    }
+}
+
+/// Rows of the record batches with the column types known from the response
+pub(crate) fn batch_rows(
+    batches: &[RecordBatch],
+    schema: Vec<FieldSchema>,
+) -> Result<Rows, TypeError> {
+    let mut rows = vec![];
+    for batch in batches {
+        let columns = batch
+            .columns()
+            .iter()
+            .zip(&schema)
+            .map(|(column, field)| column_values(column.as_ref(), field))
+            .collect::<Result<Vec<_>, _>>()?;
+        for idx in 0..batch.num_rows() {
+            rows.push(columns.iter().map(|column| column[idx].clone()).collect());
+        }
+    }
+    Ok(Rows::new(rows, schema))
+}
+
+fn field_schema(field: &Field) -> FieldSchema {
+    let metadata = field.metadata();
+    let number = |key: &str| metadata.get(key).and_then(|value| value.parse().ok());
+    let logical_type = metadata
+        .get("logicalType")
+        .and_then(|type_| serde_json::from_value(Value::String(type_.to_lowercase())).ok());
+    let (type_, scale) = match (logical_type, field.data_type()) {
+        (Some(type_), _) => (type_, number("scale")),
+        (None, DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64) => {
+            (SnowflakeType::Fixed, Some(0))
+        }
+        (None, DataType::Decimal128(_, scale)) => (SnowflakeType::Fixed, Some(i64::from(*scale))),
+        (None, DataType::Float32 | DataType::Float64) => (SnowflakeType::Real, None),
+        (None, DataType::Boolean) => (SnowflakeType::Boolean, None),
+        (None, DataType::Date32) => (SnowflakeType::Date, None),
+        (None, DataType::Binary) => (SnowflakeType::Binary, None),
+        (None, _) => (SnowflakeType::Text, None),
+    };
+    FieldSchema {
+        name: field.name().clone(),
+        type_,
+        scale,
+        precision: number("precision"),
+        nullable: field.is_nullable(),
+    }
+}
+
+/// Values of the column as Snowflake sends them in JSON, see [`crate::FromSnowflakeValue`]
+fn column_values(array: &dyn Array, field: &FieldSchema) -> Result<Vec<Value>, TypeError> {
+    let scale = u32::try_from(field.scale.unwrap_or(0)).unwrap_or(0);
+    let unsupported = || TypeError::UnsupportedArrowType {
+        column: field.name.clone(),
+        data_type: array.data_type().to_string(),
+    };
+    let text: Vec<Option<String>> = match (field.type_, array.data_type()) {
+        (_, DataType::Null) => vec![None; array.len()],
+        (
+            SnowflakeType::Fixed
+            | SnowflakeType::Time
+            | SnowflakeType::TimestampNtz
+            | SnowflakeType::TimestampLtz,
+            DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64,
+        ) => int64(array)
+            .ok_or_else(unsupported)?
+            .iter()
+            .map(|value| value.map(|value| scaled(i128::from(value), scale)))
+            .collect(),
+        (SnowflakeType::Fixed, DataType::Decimal128(_, decimal_scale)) => {
+            let decimal_scale = u32::try_from(*decimal_scale).map_err(|_| unsupported())?;
+            array
+                .as_primitive::<Decimal128Type>()
+                .iter()
+                .map(|value| value.map(|value| scaled(value, decimal_scale)))
+                .collect()
+        }
+        (SnowflakeType::Real | SnowflakeType::Fixed, DataType::Float32 | DataType::Float64) => {
+            cast(array, &DataType::Float64)
+                .map_err(|_| unsupported())?
+                .as_primitive::<Float64Type>()
+                .iter()
+                .map(|value| value.map(|value| value.to_string()))
+                .collect()
+        }
+        (SnowflakeType::Date, DataType::Date32) => array
+            .as_primitive::<arrow::datatypes::Date32Type>()
+            .iter()
+            .map(|days| days.map(|days| days.to_string()))
+            .collect(),
+        (SnowflakeType::Boolean, DataType::Boolean) => array
+            .as_boolean()
+            .iter()
+            .map(|value| value.map(|value| value.to_string()))
+            .collect(),
+        (_, DataType::Binary) => array
+            .as_binary::<i32>()
+            .iter()
+            .map(|value| value.map(hex))
+            .collect(),
+        (
+            SnowflakeType::TimestampNtz | SnowflakeType::TimestampLtz | SnowflakeType::TimestampTz,
+            DataType::Struct(_),
+        ) => timestamps(array, scale).ok_or_else(unsupported)?,
+        (_, DataType::Utf8) => array
+            .as_string::<i32>()
+            .iter()
+            .map(|value| value.map(ToString::to_string))
+            .collect(),
+        (_, DataType::LargeUtf8) => array
+            .as_string::<i64>()
+            .iter()
+            .map(|value| value.map(ToString::to_string))
+            .collect(),
+        _ => return Err(unsupported()),
+    };
+    Ok(text
+        .into_iter()
+        .map(|value| value.map_or(Value::Null, Value::String))
+        .collect())
+}
+
+fn int64(array: &dyn Array) -> Option<Vec<Option<i64>>> {
+    let array = cast(array, &DataType::Int64).ok()?;
+    Some(array.as_primitive::<Int64Type>().iter().collect())
+}
+
+/// Timestamps which don't fit into a single integer are sent as structs of the `epoch`
+/// seconds and the `fraction` nanoseconds, `TIMESTAMP_TZ` also has the `timezone` offset.
+/// The `epoch` of the struct without the `fraction` is in units of the column scale.
+fn timestamps(array: &dyn Array, scale: u32) -> Option<Vec<Option<String>>> {
+    let array = array.as_struct_opt()?;
+    let epoch = int64(array.column_by_name("epoch")?.as_ref())?;
+    let fraction = match array.column_by_name("fraction") {
+        Some(fraction) => Some(
+            cast(fraction.as_ref(), &DataType::Int32)
+                .ok()?
+                .as_primitive::<Int32Type>()
+                .clone(),
+        ),
+        None => None,
+    };
+    let timezone = match array.column_by_name("timezone") {
+        Some(timezone) => Some(
+            cast(timezone.as_ref(), &DataType::Int32)
+                .ok()?
+                .as_primitive::<Int32Type>()
+                .clone(),
+        ),
+        None => None,
+    };
+
+    Some(
+        (0..array.len())
+            .map(|idx| {
+                if array.is_null(idx) {
+                    return None;
+                }
+                let epoch = i128::from(epoch[idx]?);
+                let mut text = match &fraction {
+                    Some(fraction) => scaled(
+                        epoch * 10i128.pow(FRACTION_SCALE) + i128::from(fraction.value(idx)),
+                        FRACTION_SCALE,
+                    ),
+                    None => scaled(epoch, scale),
+                };
+                if let Some(timezone) = &timezone {
+                    let _ = write!(text, " {}", timezone.value(idx));
+                }
+                Some(text)
+            })
+            .collect(),
+    )
+}
+
+/// Decimal text of the integer in units of `10^-scale`, eg `1250` of scale 2 is `12.50`
+fn scaled(value: i128, scale: u32) -> String {
+    if scale == 0 {
+        return value.to_string();
+    }
+    let digits = value.unsigned_abs().to_string();
+    let scale = scale as usize;
+    let digits = format!("{digits:0>width$}", width = scale + 1);
+    let (int, fraction) = digits.split_at(digits.len() - scale);
+    let sign = if value < 0 { "-" } else { "" };
+    format!("{sign}{int}.{fraction}")
+}
+
+/// Binary values are hex-encoded in JSON results
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02X}");
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use arrow::array::{
+        ArrayRef, BinaryArray, BooleanArray, Date32Array, Decimal128Array, Float64Array,
+        Int16Array, Int32Array, Int64Array, StringArray, StructArray,
+    };
+    use arrow::datatypes::{Fields, Schema};
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::DeserializeError;
+
+    /// Column along with the metadata Snowflake attaches to the Arrow fields
+    fn column(name: &str, logical_type: &str, scale: i64, array: ArrayRef) -> (Field, ArrayRef) {
+        let field =
+            Field::new(name, array.data_type().clone(), true).with_metadata(HashMap::from([
+                ("logicalType".to_string(), logical_type.to_string()),
+                ("scale".to_string(), scale.to_string()),
+                ("precision".to_string(), "38".to_string()),
+            ]));
+        (field, array)
+    }
+
+    fn batch(columns: Vec<(Field, ArrayRef)>) -> RecordBatch {
+        let (fields, arrays): (Vec<_>, Vec<_>) = columns.into_iter().unzip();
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays).unwrap()
+    }
+
+    fn timestamp_struct(columns: Vec<(&str, ArrayRef)>) -> ArrayRef {
+        let fields: Fields = columns
+            .iter()
+            .map(|(name, array)| Field::new(*name, array.data_type().clone(), false))
+            .collect();
+        let arrays = columns.into_iter().map(|(_, array)| array).collect();
+        Arc::new(StructArray::new(fields, arrays, None))
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Event {
+        id: i64,
+        amount: f64,
+        exact_amount: String,
+        ratio: f64,
+        name: Option<String>,
+        is_active: bool,
+        day: NaiveDate,
+        at: NaiveTime,
+        created_ntz: NaiveDateTime,
+        created_ltz: DateTime<Utc>,
+        created_tz: DateTime<Utc>,
+        precise_tz: DateTime<Utc>,
+        payload: HashMap<String, i64>,
+        raw: Vec<u8>,
+    }
+
+    #[test]
+    #[allow(clippy::too_many_lines)]
+    fn snowflake_encoded_batch() {
+        let batch = batch(vec![
+            column(
+                "ID",
+                "FIXED",
+                0,
+                Arc::new(Int16Array::from(vec![Some(42), Some(-7)])),
+            ),
+            column(
+                "AMOUNT",
+                "FIXED",
+                2,
+                Arc::new(Int64Array::from(vec![1250, -5])),
+            ),
+            column(
+                "EXACT_AMOUNT",
+                "FIXED",
+                10,
+                Arc::new(
+                    Decimal128Array::from(vec![123_456_789_012_345_678_901_234_567_i128, -1])
+                        .with_precision_and_scale(38, 10)
+                        .unwrap(),
+                ),
+            ),
+            column(
+                "RATIO",
+                "REAL",
+                0,
+                Arc::new(Float64Array::from(vec![0.25, 1e-3])),
+            ),
+            column(
+                "NAME",
+                "TEXT",
+                0,
+                Arc::new(StringArray::from(vec![Some("alice"), None])),
+            ),
+            column(
+                "IS_ACTIVE",
+                "BOOLEAN",
+                0,
+                Arc::new(BooleanArray::from(vec![true, false])),
+            ),
+            column(
+                "DAY",
+                "DATE",
+                0,
+                Arc::new(Date32Array::from(vec![19723, -1])),
+            ),
+            column(
+                "AT",
+                "TIME",
+                3,
+                Arc::new(Int64Array::from(vec![45_296_500, 0])),
+            ),
+            column(
+                "CREATED_NTZ",
+                "TIMESTAMP_NTZ",
+                3,
+                Arc::new(Int64Array::from(vec![1_704_067_200_123, -1500])),
+            ),
+            column(
+                "CREATED_LTZ",
+                "TIMESTAMP_LTZ",
+                9,
+                timestamp_struct(vec![
+                    ("epoch", Arc::new(Int64Array::from(vec![1_704_067_200, -2]))),
+                    ("fraction", Arc::new(Int32Array::from(vec![0, 500_000_000]))),
+                ]),
+            ),
+            column(
+                "CREATED_TZ",
+                "TIMESTAMP_TZ",
+                3,
+                timestamp_struct(vec![
+                    (
+                        "epoch",
+                        Arc::new(Int64Array::from(vec![1_704_067_200_000, 0])),
+                    ),
+                    ("timezone", Arc::new(Int32Array::from(vec![1500, 1440]))),
+                ]),
+            ),
+            column(
+                "PRECISE_TZ",
+                "TIMESTAMP_TZ",
+                9,
+                timestamp_struct(vec![
+                    ("epoch", Arc::new(Int64Array::from(vec![1_704_067_200, 0]))),
+                    ("fraction", Arc::new(Int32Array::from(vec![1, 0]))),
+                    ("timezone", Arc::new(Int32Array::from(vec![1380, 1440]))),
+                ]),
+            ),
+            column(
+                "PAYLOAD",
+                "OBJECT",
+                0,
+                Arc::new(StringArray::from(vec!["{\"a\": 1}", "{}"])),
+            ),
+            column(
+                "RAW",
+                "BINARY",
+                0,
+                Arc::new(BinaryArray::from(vec![&[0xCA_u8, 0xFE][..], &[]])),
+            ),
+        ]);
+
+        let events: Vec<Event> = Rows::from_record_batches(&[batch])
+            .unwrap()
+            .map(|row| row.deserialize())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            events[0],
+            Event {
+                id: 42,
+                amount: 12.5,
+                exact_amount: "12345678901234567.8901234567".to_string(),
+                ratio: 0.25,
+                name: Some("alice".to_string()),
+                is_active: true,
+                day: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                at: NaiveTime::from_hms_milli_opt(12, 34, 56, 500).unwrap(),
+                created_ntz: NaiveDate::from_ymd_opt(2024, 1, 1)
+                    .unwrap()
+                    .and_hms_milli_opt(0, 0, 0, 123)
+                    .unwrap(),
+                created_ltz: DateTime::from_timestamp(1_704_067_200, 0).unwrap(),
+                created_tz: DateTime::from_timestamp(1_704_067_200, 0).unwrap(),
+                precise_tz: DateTime::from_timestamp(1_704_067_200, 1).unwrap(),
+                payload: HashMap::from([("a".to_string(), 1)]),
+                raw: vec![0xCA, 0xFE],
+            }
+        );
+        assert!((events[1].amount + 0.05).abs() < f64::EPSILON);
+        assert_eq!(events[1].exact_amount, "-0.0000000001");
+        assert_eq!(events[1].name, None);
+        assert_eq!(
+            events[1].day,
+            NaiveDate::from_ymd_opt(1969, 12, 31).unwrap()
+        );
+        assert_eq!(
+            events[1].created_ntz,
+            DateTime::from_timestamp(-2, 500_000_000)
+                .unwrap()
+                .naive_utc()
+        );
+        assert_eq!(
+            events[1].created_ltz,
+            DateTime::from_timestamp(-2, 500_000_000).unwrap()
+        );
+    }
+
+    #[test]
+    fn batch_without_snowflake_metadata() {
+        let batch = batch(vec![
+            (
+                Field::new("id", DataType::Int32, false),
+                Arc::new(Int32Array::from(vec![1, 2])),
+            ),
+            (
+                Field::new("name", DataType::Utf8, true),
+                Arc::new(StringArray::from(vec![Some("a"), None])),
+            ),
+        ]);
+
+        let rows: Vec<(i64, Option<String>)> = Rows::from_record_batches(&[batch.clone(), batch])
+            .unwrap()
+            .map(|row| row.deserialize())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                (1, Some("a".to_string())),
+                (2, None),
+                (1, Some("a".to_string())),
+                (2, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_arrow_type_names_the_column() {
+        let batch = batch(vec![column(
+            "AMOUNT",
+            "FIXED",
+            0,
+            Arc::new(BooleanArray::from(vec![true])),
+        )]);
+
+        let Err(err) = Rows::from_record_batches(&[batch]) else {
+            panic!("expected the unsupported type error");
+        };
+        assert_eq!(
+            err.to_string(),
+            "Column `AMOUNT` has unsupported Arrow type `Boolean`"
+        );
+    }
+
+    #[test]
+    fn incompatible_value_names_the_column() {
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Target {
+            id: i64,
+        }
+        let batch = batch(vec![column(
+            "ID",
+            "TEXT",
+            0,
+            Arc::new(StringArray::from(vec!["x1"])),
+        )]);
+
+        let err = Rows::from_record_batches(&[batch])
+            .unwrap()
+            .next()
+            .unwrap()
+            .deserialize::<Target>()
+            .unwrap_err();
+        assert!(matches!(err, DeserializeError::TypeError(_)), "{err:?}");
+        assert_eq!(
+            err.to_string(),
+            "Can not convert value `\"x1\"` of column `ID` to `i64`"
+        );
+    }
+
+    #[test]
+    fn scaled_integers() {
+        assert_eq!(scaled(0, 0), "0");
+        assert_eq!(scaled(1250, 2), "12.50");
+        assert_eq!(scaled(5, 3), "0.005");
+        assert_eq!(scaled(-5, 3), "-0.005");
+        assert_eq!(scaled(-1500, 3), "-1.500");
+        assert_eq!(scaled(i128::from(i64::MIN), 0), i64::MIN.to_string());
+    }
+}
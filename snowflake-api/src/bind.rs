@@ -0,0 +1,493 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
+use std::fmt::Write;
+
+use std::collections::BTreeMap;
+
+use crate::requests::{Binding, BindingValue};
+use crate::SnowflakeApiError;
+
+/// Type of the bind value, needed to bind `NULL`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindType {
+    Text,
+    Fixed,
+    Real,
+    Boolean,
+    Date,
+    Time,
+    TimestampNtz,
+    TimestampLtz,
+    TimestampTz,
+    Binary,
+}
+
+impl BindType {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Text => "TEXT",
+            Self::Fixed => "FIXED",
+            Self::Real => "REAL",
+            Self::Boolean => "BOOLEAN",
+            Self::Date => "DATE",
+            Self::Time => "TIME",
+            Self::TimestampNtz => "TIMESTAMP_NTZ",
+            Self::TimestampLtz => "TIMESTAMP_LTZ",
+            Self::TimestampTz => "TIMESTAMP_TZ",
+            Self::Binary => "BINARY",
+        }
+    }
+}
+
+/// Value bound to the `?` placeholder of the statement, placeholders are numbered in order
+#[derive(Debug, Clone, PartialEq)]
+pub enum BindValue {
+    Text(String),
+    Fixed(i64),
+    /// Number which doesn't fit into `i64`, eg `NUMBER(38, 10)`, in its decimal text form
+    Decimal(String),
+    Real(f64),
+    Boolean(bool),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    TimestampNtz(NaiveDateTime),
+    TimestampLtz(DateTime<Utc>),
+    TimestampTz(DateTime<FixedOffset>),
+    Binary(Vec<u8>),
+    Null(BindType),
+}
+
+impl BindValue {
+    pub fn bind_type(&self) -> BindType {
+        match self {
+            Self::Text(_) => BindType::Text,
+            Self::Fixed(_) | Self::Decimal(_) => BindType::Fixed,
+            Self::Real(_) => BindType::Real,
+            Self::Boolean(_) => BindType::Boolean,
+            Self::Date(_) => BindType::Date,
+            Self::Time(_) => BindType::Time,
+            Self::TimestampNtz(_) => BindType::TimestampNtz,
+            Self::TimestampLtz(_) => BindType::TimestampLtz,
+            Self::TimestampTz(_) => BindType::TimestampTz,
+            Self::Binary(_) => BindType::Binary,
+            Self::Null(t) => *t,
+        }
+    }
+
+    /// Values are sent as strings in the format the server expects for the type:
+    /// dates as epoch milliseconds, time as nanoseconds since midnight,
+    /// timestamps as epoch nanoseconds, followed by the offset for `TIMESTAMP_TZ`
+    fn to_wire(&self) -> Option<String> {
+        let value = match self {
+            Self::Text(s) | Self::Decimal(s) => s.clone(),
+            Self::Fixed(n) => n.to_string(),
+            Self::Real(n) => n.to_string(),
+            Self::Boolean(b) => b.to_string(),
+            Self::Date(d) => (d.and_time(NaiveTime::MIN).and_utc().timestamp() * 1000).to_string(),
+            Self::Time(t) => {
+                let nanos = u64::from(t.num_seconds_from_midnight()) * 1_000_000_000
+                    + u64::from(t.nanosecond());
+                nanos.to_string()
+            }
+            Self::TimestampNtz(ts) => epoch_nanos(&ts.and_utc()),
+            Self::TimestampLtz(ts) => epoch_nanos(ts),
+            Self::TimestampTz(ts) => {
+                // offset is encoded in minutes, shifted by 24 hours to keep it positive
+                let offset = ts.offset().local_minus_utc() / 60 + 1440;
+                format!("{} {offset}", epoch_nanos(&ts.with_timezone(&Utc)))
+            }
+            Self::Binary(bytes) => bytes.iter().fold(String::new(), |mut hex, b| {
+                let _ = write!(hex, "{b:02X}");
+                hex
+            }),
+            Self::Null(_) => return None,
+        };
+        Some(value)
+    }
+
+    pub(crate) fn to_binding(&self) -> Binding {
+        Binding {
+            type_: self.bind_type().as_str(),
+            value: BindingValue::Scalar(self.to_wire()),
+        }
+    }
+
+    /// Text representation for the CSV file loaded from the stage, it's parsed by the server
+    /// like a literal, so temporal values are formatted instead of being sent as epoch offsets.
+    /// `NULL` is an empty field, while strings are always quoted to keep empty strings apart.
+    fn to_csv_field(&self) -> String {
+        let text = match self {
+            Self::Null(_) => return String::new(),
+            Self::Date(d) => d.format("%Y-%m-%d").to_string(),
+            Self::Time(t) => t.format("%H:%M:%S%.9f").to_string(),
+            Self::TimestampNtz(ts) => ts.format("%Y-%m-%d %H:%M:%S%.9f").to_string(),
+            Self::TimestampLtz(ts) => ts.format("%Y-%m-%d %H:%M:%S%.9f %:z").to_string(),
+            Self::TimestampTz(ts) => ts.format("%Y-%m-%d %H:%M:%S%.9f %:z").to_string(),
+            other => other.to_wire().unwrap_or_default(),
+        };
+        format!("\"{}\"", text.replace('"', "\"\""))
+    }
+}
+
+/// Affected row counts of [`crate::SnowflakeApi::exec_batch`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchResult {
+    /// Number of rows of bind values, the statement is executed once for each of them
+    pub rows: usize,
+    pub rows_inserted: u64,
+    pub rows_updated: u64,
+    pub rows_deleted: u64,
+}
+
+impl BatchResult {
+    /// Total number of inserted, updated and deleted rows
+    pub fn total(&self) -> u64 {
+        self.rows_inserted + self.rows_updated + self.rows_deleted
+    }
+
+    /// Affected rows of each execution in the order of bind rows. The server only reports totals
+    /// of the batch, so they are known when every execution affected a single row,
+    /// eg `INSERT ... VALUES (?, ?)`, and `None` otherwise.
+    pub fn row_counts(&self) -> Option<Vec<u64>> {
+        (self.total() == self.rows as u64).then(|| vec![1; self.rows])
+    }
+}
+
+/// Check that the rows have the same width and each column holds values of the single type,
+/// returns the column types. Columns of `NULL`s only are bound as text.
+pub(crate) fn column_types(rows: &[Vec<BindValue>]) -> Result<Vec<BindType>, SnowflakeApiError> {
+    let width = rows.first().map_or(0, Vec::len);
+    let mut types: Vec<Option<BindType>> = vec![None; width];
+    for (row_idx, row) in rows.iter().enumerate() {
+        if row.len() != width {
+            return Err(SnowflakeApiError::InvalidBindings(format!(
+                "row {row_idx} has {} values, expected {width}",
+                row.len()
+            )));
+        }
+        for (col_idx, value) in row.iter().enumerate() {
+            if matches!(value, BindValue::Null(_)) {
+                continue;
+            }
+            match types[col_idx] {
+                None => types[col_idx] = Some(value.bind_type()),
+                Some(t) if t == value.bind_type() => {}
+                Some(t) => {
+                    return Err(SnowflakeApiError::InvalidBindings(format!(
+                        "column {col_idx} mixes {t:?} and {:?} values",
+                        value.bind_type()
+                    )))
+                }
+            }
+        }
+    }
+    Ok(types
+        .into_iter()
+        .map(|t| t.unwrap_or(BindType::Text))
+        .collect())
+}
+
+/// Bindings with the values of each column for all of the rows
+pub(crate) fn array_bindings(
+    rows: &[Vec<BindValue>],
+    types: &[BindType],
+) -> BTreeMap<String, Binding> {
+    types
+        .iter()
+        .enumerate()
+        .map(|(col_idx, t)| {
+            let values = rows.iter().map(|row| row[col_idx].to_wire()).collect();
+            (
+                (col_idx + 1).to_string(),
+                Binding {
+                    type_: t.as_str(),
+                    value: BindingValue::Array(values),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Bindings of the `?` placeholders, they are numbered from 1
+pub(crate) fn positional_bindings(params: &[BindValue]) -> BTreeMap<String, Binding> {
+    params
+        .iter()
+        .enumerate()
+        .map(|(i, p)| ((i + 1).to_string(), p.to_binding()))
+        .collect()
+}
+
+pub(crate) fn to_csv(rows: &[Vec<BindValue>]) -> String {
+    let mut csv = String::new();
+    for row in rows {
+        let fields: Vec<String> = row.iter().map(BindValue::to_csv_field).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Computed in `i128`, as nanoseconds overflow `i64` outside of 1677..2262 years range
+fn epoch_nanos(ts: &DateTime<Utc>) -> String {
+    (i128::from(ts.timestamp()) * 1_000_000_000 + i128::from(ts.timestamp_subsec_nanos()))
+        .to_string()
+}
+
+impl From<&str> for BindValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+impl From<String> for BindValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<i64> for BindValue {
+    fn from(value: i64) -> Self {
+        Self::Fixed(value)
+    }
+}
+
+impl From<i32> for BindValue {
+    fn from(value: i32) -> Self {
+        Self::Fixed(value.into())
+    }
+}
+
+impl From<f64> for BindValue {
+    fn from(value: f64) -> Self {
+        Self::Real(value)
+    }
+}
+
+impl From<bool> for BindValue {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+
+impl From<NaiveDate> for BindValue {
+    fn from(value: NaiveDate) -> Self {
+        Self::Date(value)
+    }
+}
+
+impl From<NaiveTime> for BindValue {
+    fn from(value: NaiveTime) -> Self {
+        Self::Time(value)
+    }
+}
+
+impl From<NaiveDateTime> for BindValue {
+    fn from(value: NaiveDateTime) -> Self {
+        Self::TimestampNtz(value)
+    }
+}
+
+impl From<DateTime<Utc>> for BindValue {
+    fn from(value: DateTime<Utc>) -> Self {
+        Self::TimestampLtz(value)
+    }
+}
+
+impl From<DateTime<FixedOffset>> for BindValue {
+    fn from(value: DateTime<FixedOffset>) -> Self {
+        Self::TimestampTz(value)
+    }
+}
+
+impl From<Vec<u8>> for BindValue {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Binary(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn values() -> Vec<BindValue> {
+        let noon = date(2024, 1, 15).and_hms_milli_opt(12, 0, 0, 500).unwrap();
+        vec![
+            BindValue::Text("O'Brien".to_string()),
+            BindValue::Fixed(-42),
+            BindValue::Decimal("12345678901234567890.0123".to_string()),
+            BindValue::Real(1.5),
+            BindValue::Boolean(true),
+            BindValue::Date(date(2024, 1, 15)),
+            BindValue::Date(date(1969, 12, 31)),
+            BindValue::Time(NaiveTime::from_hms_nano_opt(13, 45, 30, 123_456_789).unwrap()),
+            BindValue::TimestampNtz(noon),
+            BindValue::TimestampNtz(date(2300, 1, 1).and_time(NaiveTime::MIN)),
+            BindValue::TimestampLtz(noon.and_utc()),
+            BindValue::TimestampTz(
+                DateTime::parse_from_rfc3339("2024-01-15T12:00:00+02:00").unwrap(),
+            ),
+            BindValue::TimestampTz(
+                DateTime::parse_from_rfc3339("2024-01-15T12:00:00-05:30").unwrap(),
+            ),
+            BindValue::Binary(vec![0xCA, 0xFE, 0x01]),
+            BindValue::Null(BindType::Date),
+        ]
+    }
+
+    #[tokio::test]
+    async fn bindings_are_sent_in_wire_format() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({
+                "bindings": {
+                    "1": {"type": "TEXT", "value": "O'Brien"},
+                    "2": {"type": "FIXED", "value": "-42"},
+                    "3": {"type": "FIXED", "value": "12345678901234567890.0123"},
+                    "4": {"type": "REAL", "value": "1.5"},
+                    "5": {"type": "BOOLEAN", "value": "true"},
+                    "6": {"type": "DATE", "value": "1705276800000"},
+                    "7": {"type": "DATE", "value": "-86400000"},
+                    "8": {"type": "TIME", "value": "49530123456789"},
+                    "9": {"type": "TIMESTAMP_NTZ", "value": "1705320000500000000"},
+                    // beyond the `i64` nanoseconds range
+                    "10": {"type": "TIMESTAMP_NTZ", "value": "10413792000000000000"},
+                    "11": {"type": "TIMESTAMP_LTZ", "value": "1705320000500000000"},
+                    // offset in minutes shifted by 1440
+                    "12": {"type": "TIMESTAMP_TZ", "value": "1705312800000000000 1560"},
+                    "13": {"type": "TIMESTAMP_TZ", "value": "1705339800000000000 1110"},
+                    "14": {"type": "BINARY", "value": "CAFE01"},
+                    "15": {"type": "DATE", "value": null}
+                }
+            })))
+            .with_body(mock::query_body(&json!({})))
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let placeholders = vec!["?"; values().len()].join(", ");
+        api.exec_with_binds(&format!("INSERT INTO t VALUES ({placeholders})"), &values())
+            .await
+            .unwrap();
+        query.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn batch_is_sent_as_array_bindings() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({
+                "bindings": {
+                    "1": {"type": "FIXED", "value": ["1", "2", null]},
+                    "2": {"type": "DATE", "value": [null, "1705276800000", "0"]},
+                    // column of `NULL`s only
+                    "3": {"type": "TEXT", "value": [null, null, null]}
+                }
+            })))
+            .with_body(mock::query_body(&json!({
+                "statementTypeId": 12544,
+                "stats": { "numRowsInserted": 3 }
+            })))
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let rows = vec![
+            vec![
+                BindValue::Fixed(1),
+                BindValue::Null(BindType::Date),
+                BindValue::Null(BindType::Text),
+            ],
+            vec![
+                BindValue::Fixed(2),
+                BindValue::Date(date(2024, 1, 15)),
+                BindValue::Null(BindType::Fixed),
+            ],
+            vec![
+                BindValue::Null(BindType::Fixed),
+                BindValue::Date(date(1970, 1, 1)),
+                BindValue::Null(BindType::Binary),
+            ],
+        ];
+        let res = api
+            .exec_batch("INSERT INTO t VALUES (?, ?, ?)", rows)
+            .await
+            .unwrap();
+        query.assert_async().await;
+        assert_eq!((res.rows, res.total()), (3, 3));
+        assert_eq!(res.row_counts(), Some(vec![1, 1, 1]));
+    }
+
+    #[test]
+    fn row_counts_are_known_for_single_row_executions() {
+        let res = BatchResult {
+            rows: 2,
+            rows_updated: 5,
+            ..BatchResult::default()
+        };
+        assert_eq!(res.total(), 5);
+        assert_eq!(res.row_counts(), None);
+        assert_eq!(BatchResult::default().row_counts(), Some(vec![]));
+    }
+
+    #[test]
+    fn mixed_columns_are_rejected() {
+        let rows = vec![
+            vec![BindValue::Fixed(1)],
+            vec![BindValue::Text("2".to_string())],
+        ];
+        let err = column_types(&rows).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Bind values are invalid: column 0 mixes Fixed and Text values"
+        );
+
+        let rows = vec![vec![BindValue::Fixed(1)], vec![]];
+        let err = column_types(&rows).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Bind values are invalid: row 1 has 0 values, expected 1"
+        );
+    }
+
+    #[test]
+    fn stage_csv() {
+        let rows = vec![values()];
+        assert_eq!(
+            to_csv(&rows),
+            "\"O'Brien\",\"-42\",\"12345678901234567890.0123\",\"1.5\",\"true\",\
+            \"2024-01-15\",\"1969-12-31\",\"13:45:30.123456789\",\
+            \"2024-01-15 12:00:00.500000000\",\"2300-01-01 00:00:00.000000000\",\
+            \"2024-01-15 12:00:00.500000000 +00:00\",\
+            \"2024-01-15 12:00:00.000000000 +02:00\",\"2024-01-15 12:00:00.000000000 -05:30\",\
+            \"CAFE01\",\n"
+        );
+
+        let rows = vec![
+            vec![
+                BindValue::Text(String::new()),
+                BindValue::Null(BindType::Text),
+            ],
+            vec![
+                BindValue::Text("say \"hi\", bye".to_string()),
+                BindValue::Text("multi\nline".to_string()),
+            ],
+        ];
+        assert_eq!(
+            to_csv(&rows),
+            "\"\",\n\"say \"\"hi\"\", bye\",\"multi\nline\"\n"
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use std::sync::OnceLock;
+
+use arrow::json::ArrayWriter;
+use regex::Regex;
+
+use crate::connection::QueryType;
+use crate::metadata::qualified_identifier;
+use crate::requests::ExecRequest;
+use crate::responses::{ExecResponse, QueryExecResponse, SnowflakeType};
+use crate::{bind, query_response, BindValue, QueryResult, SnowflakeApi, SnowflakeApiError};
+
+/// Name of the result column of `EXECUTE IMMEDIATE` blocks which return a scalar
+const ANONYMOUS_BLOCK_COLUMN: &str = "anonymous block";
+
+/// Result of the stored procedure or Snowflake Scripting block
+pub enum CallResult {
+    /// Scalar return value as text, `VARIANT` values are JSON, `None` for `NULL`
+    Value(Option<String>),
+    /// Rows of the procedure declared with `RETURNS TABLE`, or block ending with `RETURN TABLE(...)`
+    Table(QueryResult),
+}
+
+impl SnowflakeApi {
+    /// Execute `CALL <procedure>(...)` or an anonymous block, eg `EXECUTE IMMEDIATE $$ ... $$`,
+    /// and return what it has returned. `$$` quoted bodies are sent as is.
+    pub async fn call(&self, sql: &str) -> Result<CallResult, SnowflakeApiError> {
+        let (result, _) = self.call_request(ExecRequest::new(sql)).await?;
+        Ok(result)
+    }
+
+    /// Call the stored procedure with the bound arguments, eg `call_procedure("db.schema.proc", &args)`.
+    /// Unquoted names are resolved case-insensitively, quoted parts are passed as is.
+    ///
+    /// Scalar return value is converted according to the declared return type: `VARIANT`, `OBJECT`
+    /// and `ARRAY` are parsed as JSON, numbers and booleans are converted, anything else,
+    /// including dates and timestamps, is a string. `NULL` is `Value::Null`.
+    ///
+    /// Procedures declared with `RETURNS TABLE(...)` return rows instead, they are fetched
+    /// the same way as the query results and are returned as an array of objects
+    /// keyed by the column name. Use [`SnowflakeApi::call`] to get them as [`QueryResult`].
+    pub async fn call_procedure(
+        &self,
+        name: &str,
+        args: &[BindValue],
+    ) -> Result<serde_json::Value, SnowflakeApiError> {
+        let placeholders = vec!["?"; args.len()].join(", ");
+        let sql = format!("CALL {}({placeholders})", qualified_identifier(name)?);
+        let request = ExecRequest {
+            bindings: bind::positional_bindings(args),
+            ..ExecRequest::new(&sql)
+        };
+
+        match self.call_request(request).await? {
+            (CallResult::Value(None), _) => Ok(serde_json::Value::Null),
+            (CallResult::Value(Some(text)), type_) => Ok(json_return_value(text, type_)),
+            (CallResult::Table(table), _) => table_to_json(table),
+        }
+    }
+
+    /// Result of the call along with the type of the scalar return value
+    async fn call_request(
+        &self,
+        request: ExecRequest,
+    ) -> Result<(CallResult, Option<SnowflakeType>), SnowflakeApiError> {
+        let sql = request.sql_text.clone();
+        let resp = self
+            .run_exec_request::<ExecResponse>(request, QueryType::JsonQuery)
+            .await?;
+        let mut resp = query_response(resp)?;
+
+        // the returned table could be handed out as the result of the child statement
+        if let Some(child_id) = resp
+            .data
+            .result_ids
+            .as_deref()
+            .and_then(|ids| ids.split(',').rfind(|id| !id.is_empty()))
+        {
+            log::debug!("Following the result {child_id} returned by the call");
+            resp = self.fetch_query_response(child_id).await?;
+        }
+
+        if let Some(value) = return_value(&sql, &resp) {
+            let type_ = resp.data.rowtype.first().map(|column| column.type_);
+            return Ok((CallResult::Value(value), type_));
+        }
+        Ok((
+            CallResult::Table(self.raw_query_result(resp).await?.deserialize_arrow()?),
+            None,
+        ))
+    }
+
+    async fn fetch_query_response(
+        &self,
+        query_id: &str,
+    ) -> Result<QueryExecResponse, SnowflakeApiError> {
+        let parts = self.session.get_token().await?;
+        let resp = self
+            .connection
+            .get_request::<ExecResponse>(
+                QueryType::QueryResult {
+                    query_id: query_id.to_string(),
+                },
+                &self.account_identifier,
+                &[],
+                Some(&parts.session_token_auth_header),
+            )
+            .await?;
+        query_response(resp)
+    }
+}
+
+/// Scalar results come as a single row with the single column named after the procedure,
+/// or `anonymous block`, anything else is the returned table
+#[allow(clippy::option_option)]
+fn return_value(sql: &str, resp: &QueryExecResponse) -> Option<Option<String>> {
+    let [column] = resp.data.rowtype.as_slice() else {
+        return None;
+    };
+    let is_return_column = column.name.eq_ignore_ascii_case(ANONYMOUS_BLOCK_COLUMN)
+        || procedure_name(sql).is_some_and(|name| column.name.eq_ignore_ascii_case(name));
+    if !is_return_column || resp.data.returned != 1 || !resp.data.chunks.is_empty() {
+        return None;
+    }
+
+    let rows: Vec<Vec<Option<String>>> = serde_json::from_value(resp.data.rowset.clone()?).ok()?;
+    rows.into_iter().next()?.into_iter().next()
+}
+
+fn json_return_value(text: String, type_: Option<SnowflakeType>) -> serde_json::Value {
+    let is_json = matches!(
+        type_,
+        Some(
+            SnowflakeType::Variant
+                | SnowflakeType::Object
+                | SnowflakeType::Array
+                | SnowflakeType::Fixed
+                | SnowflakeType::Real
+                | SnowflakeType::Boolean
+        )
+    );
+    if is_json {
+        // `NaN` and `inf` of the `FLOAT` procedures aren't valid JSON, they are kept as text
+        serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text))
+    } else {
+        serde_json::Value::String(text)
+    }
+}
+
+fn table_to_json(table: QueryResult) -> Result<serde_json::Value, SnowflakeApiError> {
+    match table {
+        QueryResult::Empty => Ok(serde_json::Value::Array(vec![])),
+        QueryResult::Json(json) => {
+            let rows: Vec<Vec<serde_json::Value>> = serde_json::from_value(json.value)?;
+            Ok(rows
+                .into_iter()
+                .map(|row| {
+                    let row: serde_json::Map<_, _> = json
+                        .schema
+                        .iter()
+                        .map(|field| field.name.clone())
+                        .zip(row)
+                        .collect();
+                    serde_json::Value::Object(row)
+                })
+                .collect::<Vec<_>>()
+                .into())
+        }
+        QueryResult::Arrow(batches) => {
+            let mut writer = ArrayWriter::new(Vec::new());
+            writer.write_batches(&batches.iter().collect::<Vec<_>>())?;
+            writer.finish()?;
+            let buf = writer.into_inner();
+            if buf.is_empty() {
+                return Ok(serde_json::Value::Array(vec![]));
+            }
+            Ok(serde_json::from_slice(&buf)?)
+        }
+    }
+}
+
+/// Unqualified name of the procedure of the `CALL` statement
+fn procedure_name(sql: &str) -> Option<&str> {
+    static CALL_RE: OnceLock<Regex> = OnceLock::new();
+    let re = CALL_RE.get_or_init(|| {
+        Regex::new(r#"(?i)^\s*(?:/\*(?:[^*]|\*+[^*/])*\*+/\s*)*call\s+(?:[\w$]+\.|"[^"]*"\.)*"?([^"(\s]+)"?\s*\("#)
+            .unwrap()
+    });
+    Some(re.captures(sql)?.get(1)?.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Matcher, ServerGuard};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::mock;
+
+    fn column(name: &str, type_: &str) -> Value {
+        json!({
+            "name": name, "type": type_, "nullable": true,
+            "byteLength": null, "length": null, "precision": null, "scale": null
+        })
+    }
+
+    async fn call_response(server: &mut ServerGuard, sql: &str, data: &Value) -> mockito::Mock {
+        server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({ "sqlText": sql })))
+            .with_body(mock::query_body(data))
+            .create_async()
+            .await
+    }
+
+    #[tokio::test]
+    async fn scalar_return_value() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let data = json!({
+            "rowtype": [column("ADD_ONE", "fixed")],
+            "rowset": [["42"]],
+            "total": 1,
+            "returned": 1
+        });
+        let call = call_response(&mut server, "CALL analytics.add_one(?)", &data)
+            .await
+            .expect(2);
+
+        let api = mock::api(&server);
+        let value = api
+            .call_procedure("analytics.add_one", &[BindValue::Fixed(41)])
+            .await
+            .unwrap();
+        assert_eq!(value, json!(42));
+
+        let Ok(CallResult::Value(value)) = api.call("CALL analytics.add_one(?)").await else {
+            panic!("scalar is expected");
+        };
+        assert_eq!(value.as_deref(), Some("42"));
+        call.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn anonymous_block_return_value() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let sql = "EXECUTE IMMEDIATE $$\nBEGIN\n  RETURN 'done';\nEND;\n$$";
+        let data = json!({
+            "rowtype": [column("anonymous block", "text")],
+            "rowset": [["done"]],
+            "total": 1,
+            "returned": 1
+        });
+        let _call = call_response(&mut server, sql, &data).await;
+
+        let api = mock::api(&server);
+        let Ok(CallResult::Value(value)) = api.call(sql).await else {
+            panic!("scalar is expected");
+        };
+        assert_eq!(value.as_deref(), Some("done"));
+    }
+
+    #[tokio::test]
+    async fn returned_table_is_fetched_by_result_id() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let data = json!({
+            "rowtype": [column("TOP_USERS", "text")],
+            "rowset": [["01b2c3d4-0000-1111-0000-000000000002"]],
+            "total": 1,
+            "returned": 1,
+            "resultIds": "01b2c3d4-0000-1111-0000-000000000002"
+        });
+        let _call = call_response(&mut server, "CALL top_users(?)", &data).await;
+        let table = server
+            .mock(
+                "GET",
+                "/queries/01b2c3d4-0000-1111-0000-000000000002/result",
+            )
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(&json!({
+                "queryId": "01b2c3d4-0000-1111-0000-000000000002",
+                "rowtype": [column("NAME", "text"), column("VISITS", "fixed")],
+                "rowset": [["alice", "7"], ["bob", null]],
+                "total": 2,
+                "returned": 2
+            })))
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let rows = api
+            .call_procedure("top_users", &[BindValue::Fixed(2)])
+            .await
+            .unwrap();
+        table.assert_async().await;
+        assert_eq!(
+            rows,
+            json!([
+                {"NAME": "alice", "VISITS": "7"},
+                {"NAME": "bob", "VISITS": null}
+            ])
+        );
+    }
+
+    #[test]
+    fn procedure_names() {
+        assert_eq!(procedure_name("CALL add_one(1)"), Some("add_one"));
+        assert_eq!(
+            procedure_name("/* tag */ call db.\"My Schema\".\"Proc\" (1)"),
+            Some("Proc")
+        );
+        assert_eq!(procedure_name("SELECT add_one(1)"), None);
+    }
+
+    #[test]
+    fn return_values_by_type() {
+        let value = |text: &str, type_| json_return_value(text.to_string(), Some(type_));
+        assert_eq!(
+            value(r#"{"a": [1, 2]}"#, SnowflakeType::Variant),
+            json!({"a": [1, 2]})
+        );
+        assert_eq!(value("1.5", SnowflakeType::Real), json!(1.5));
+        assert_eq!(value("NaN", SnowflakeType::Real), json!("NaN"));
+        assert_eq!(value("true", SnowflakeType::Boolean), json!(true));
+        assert_eq!(
+            value("2024-01-15", SnowflakeType::Date),
+            json!("2024-01-15")
+        );
+        assert_eq!(value("42", SnowflakeType::Text), json!("42"));
+    }
+}
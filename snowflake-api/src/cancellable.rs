@@ -0,0 +1,195 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::{ExecOptions, QueryResult, SnowflakeApi, SnowflakeApiError};
+
+tokio::task_local! {
+    static CURRENT_QUERY: Arc<Mutex<CancellableQuery>>;
+}
+
+/// Ids of the statement sent by the cancellable call, captured as soon as they are known
+#[derive(Debug, Default)]
+pub(crate) struct CancellableQuery {
+    pub(crate) request_id: Option<String>,
+    pub(crate) sql_text: String,
+    pub(crate) query_id: Option<String>,
+}
+
+impl CancellableQuery {
+    /// Update the ids of the current cancellable call, no-op outside of it
+    pub(crate) fn record(f: impl FnOnce(&mut Self)) {
+        let _ = CURRENT_QUERY.try_with(|query| f(&mut query.lock().unwrap()));
+    }
+}
+
+impl SnowflakeApi {
+    /// Same as [`SnowflakeApi::exec`], but once `token` is cancelled the statement is aborted
+    /// on the server and [`SnowflakeApiError::Cancelled`] is returned.
+    /// Abort is best-effort, its failure is only logged.
+    pub async fn exec_cancellable(
+        &self,
+        sql: &str,
+        token: CancellationToken,
+    ) -> Result<QueryResult, SnowflakeApiError> {
+        let options = ExecOptions::default().with_cancellation_token(token);
+        self.exec_with_options(sql, &options).await
+    }
+
+    /// Drive the future until it completes or the token is cancelled,
+    /// in which case the statement it has sent is aborted
+    pub(crate) async fn run_cancellable<T>(
+        &self,
+        token: &CancellationToken,
+        exec: impl Future<Output = Result<T, SnowflakeApiError>>,
+    ) -> Result<T, SnowflakeApiError> {
+        let query = Arc::new(Mutex::new(CancellableQuery::default()));
+        tokio::select! {
+            res = CURRENT_QUERY.scope(Arc::clone(&query), exec) => return res,
+            () = token.cancelled() => {}
+        }
+        // request future is dropped by now, so its concurrency permit is free for the abort
+        let query = std::mem::take(&mut *query.lock().unwrap());
+        self.abort_cancelled(query).await;
+        Err(SnowflakeApiError::Cancelled)
+    }
+
+    async fn abort_cancelled(&self, query: CancellableQuery) {
+        // query id is known once the query outlives the request, before that it's the request id
+        let res = match query {
+            CancellableQuery {
+                query_id: Some(query_id),
+                ..
+            } => self.cancel_query(&query_id).await,
+            CancellableQuery {
+                request_id: Some(request_id),
+                sql_text,
+                ..
+            } => self.abort_request(request_id, sql_text).await,
+            _ => {
+                log::debug!("Query was cancelled before it was sent");
+                Ok(())
+            }
+        };
+        if let Err(e) = res {
+            log::warn!("Failed to abort the cancelled query: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use mockito::Matcher;
+    use serde_json::json;
+    use url::Url;
+
+    use super::*;
+    use crate::mock;
+
+    const SQL: &str = "CALL SYSTEM$WAIT(10)";
+    const QUERY_ID: &str = "01b2c3d4-0000-1111-0000-000000000001";
+
+    fn cancel_soon() -> CancellationToken {
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancel.cancel();
+        });
+        token
+    }
+
+    #[tokio::test]
+    async fn pending_request_is_aborted_by_its_request_id() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let request_id = Arc::new(Mutex::new(String::new()));
+
+        let captured = Arc::clone(&request_id);
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_request(move |request| {
+                let url = Url::parse(&format!("http://localhost{}", request.path_and_query()));
+                if let Some((_, id)) = url.unwrap().query_pairs().find(|(k, _)| k == "requestId") {
+                    *captured.lock().unwrap() = id.into_owned();
+                }
+                true
+            })
+            .with_chunked_body(|w| {
+                std::thread::sleep(Duration::from_secs(2));
+                w.write_all(mock::query_body(&json!({})).as_bytes())
+            })
+            .create_async()
+            .await;
+        let aborted_id = Arc::new(Mutex::new(String::new()));
+        let captured = Arc::clone(&aborted_id);
+        let abort = server
+            .mock("POST", "/queries/v1/abort-request")
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({ "sqlText": SQL })))
+            .with_body_from_request(move |request| {
+                let body: serde_json::Value =
+                    serde_json::from_slice(request.body().unwrap()).unwrap();
+                *captured.lock().unwrap() = body["requestId"].as_str().unwrap().to_string();
+                json!({"success": true, "code": null, "message": null})
+                    .to_string()
+                    .into_bytes()
+            })
+            .create_async()
+            .await;
+
+        let err = mock::api(&server)
+            .exec_cancellable(SQL, cancel_soon())
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, SnowflakeApiError::Cancelled), "{err}");
+        abort.assert_async().await;
+        let request_id = request_id.lock().unwrap().clone();
+        assert!(!request_id.is_empty());
+        assert_eq!(*aborted_id.lock().unwrap(), request_id);
+    }
+
+    #[tokio::test]
+    async fn running_query_is_cancelled_by_its_query_id() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let result_path = format!("/queries/{QUERY_ID}/result");
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({ "sqlText": SQL })))
+            .with_body(
+                json!({
+                    "code": "333333",
+                    "message": "Asynchronous execution in progress.",
+                    "success": true,
+                    "data": {"queryId": QUERY_ID, "getResultUrl": result_path}
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let cancel = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({
+                "sqlText": format!("SELECT SYSTEM$CANCEL_QUERY('{QUERY_ID}')")
+            })))
+            .with_body(mock::query_body(&json!({})))
+            .create_async()
+            .await;
+
+        let err = mock::api(&server)
+            .exec_cancellable(SQL, cancel_soon())
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, SnowflakeApiError::Cancelled), "{err}");
+        cancel.assert_async().await;
+    }
+}
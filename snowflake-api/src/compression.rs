@@ -0,0 +1,191 @@
+use std::io::{self, Read};
+
+use bytes::Bytes;
+use flate2::read::MultiGzDecoder;
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CompressionError {
+    #[error("Failed to decompress {format:?} chunk #{index}")]
+    Decompression {
+        index: usize,
+        format: CompressionFormat,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error(transparent)]
+    TokioTaskJoinError(#[from] tokio::task::JoinError),
+}
+
+/// Arrow IPC messages start with it, see <https://arrow.apache.org/docs/format/Columnar.html#encapsulated-message-format>
+const ARROW_CONTINUATION: [u8; 4] = [0xff; 4];
+
+/// Compression of the downloaded result chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionFormat {
+    Identity,
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+impl CompressionFormat {
+    /// Guess the format by the magic number. Brotli streams don't have one, so chunks which
+    /// are neither Arrow IPC (continuation marker) nor JSON rows (`[`) are taken for Brotli.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.is_empty() || bytes.starts_with(&ARROW_CONTINUATION) || bytes[0] == b'[' {
+            Self::Identity
+        } else if bytes.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Self::Zstd
+        } else {
+            Self::Brotli
+        }
+    }
+
+    /// Format by the `Content-Encoding` header value
+    pub fn from_content_encoding(encoding: &str) -> Option<Self> {
+        match encoding.trim().to_lowercase().as_str() {
+            "identity" => Some(Self::Identity),
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "br" => Some(Self::Brotli),
+            "zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    fn decompress(self, bytes: &[u8]) -> io::Result<Bytes> {
+        let mut buf = Vec::with_capacity(bytes.len() * 4);
+        match self {
+            Self::Identity => return Ok(Bytes::copy_from_slice(bytes)),
+            Self::Gzip => MultiGzDecoder::new(bytes).read_to_end(&mut buf)?,
+            Self::Brotli => {
+                brotli_decompressor::Decompressor::new(bytes, 64 * 1024).read_to_end(&mut buf)?
+            }
+            Self::Zstd => zstd::stream::read::Decoder::new(bytes)?.read_to_end(&mut buf)?,
+        };
+        Ok(Bytes::from(buf))
+    }
+}
+
+/// Decompress the chunks on the rayon thread pool, order of the chunks is preserved.
+/// This is CPU-bound, call it from `spawn_blocking` when in async context.
+pub fn decompress_chunks_parallel(
+    chunks: Vec<Bytes>,
+    format: CompressionFormat,
+) -> Result<Vec<Bytes>, CompressionError> {
+    if format == CompressionFormat::Identity {
+        return Ok(chunks);
+    }
+
+    chunks
+        .into_par_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            format
+                .decompress(&chunk)
+                .map_err(|source| CompressionError::Decompression {
+                    index,
+                    format,
+                    source,
+                })
+        })
+        .collect()
+}
+
+/// Decompress the chunks stored compressed, chunks of the single result share the format.
+/// Transport level compression is handled by the HTTP client already.
+pub(crate) async fn decompress_chunks(chunks: Vec<Bytes>) -> Result<Vec<Bytes>, CompressionError> {
+    let format = chunks.first().map_or(CompressionFormat::Identity, |c| {
+        CompressionFormat::detect(c)
+    });
+    if format == CompressionFormat::Identity {
+        return Ok(chunks);
+    }
+
+    log::debug!("Decompressing {} {format:?} chunks", chunks.len());
+    tokio::task::spawn_blocking(move || decompress_chunks_parallel(chunks, format)).await?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const ROWS: &[u8] = br#"["1","a"],["2","b"]"#;
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn brotli(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+        encoder.write_all(bytes).unwrap();
+        encoder.into_inner()
+    }
+
+    #[test]
+    fn detects_uncompressed_chunks() {
+        assert_eq!(
+            CompressionFormat::detect(b"[\"1\"]"),
+            CompressionFormat::Identity
+        );
+        assert_eq!(
+            CompressionFormat::detect(&[0xff, 0xff, 0xff, 0xff, 0x10, 0, 0, 0]),
+            CompressionFormat::Identity
+        );
+        assert_eq!(CompressionFormat::detect(b""), CompressionFormat::Identity);
+    }
+
+    #[test]
+    fn detects_compressed_chunks() {
+        assert_eq!(
+            CompressionFormat::detect(&gzip(ROWS)),
+            CompressionFormat::Gzip
+        );
+        assert_eq!(
+            CompressionFormat::detect(&zstd::encode_all(ROWS, 1).unwrap()),
+            CompressionFormat::Zstd
+        );
+        assert_eq!(
+            CompressionFormat::detect(&brotli(ROWS)),
+            CompressionFormat::Brotli
+        );
+    }
+
+    #[tokio::test]
+    async fn decompresses_in_order() {
+        for compress in [
+            gzip as fn(&[u8]) -> Vec<u8>,
+            |b: &[u8]| zstd::encode_all(b, 1).unwrap(),
+            brotli,
+        ] {
+            let chunks = (0..8)
+                .map(|i| Bytes::from(compress(format!("[\"{i}\"]").as_bytes())))
+                .collect();
+
+            let chunks = decompress_chunks(chunks).await.unwrap();
+            let expected: Vec<Bytes> = (0..8).map(|i| Bytes::from(format!("[\"{i}\"]"))).collect();
+            assert_eq!(chunks, expected);
+        }
+    }
+
+    #[test]
+    fn reports_corrupted_chunk() {
+        let mut corrupted = gzip(ROWS);
+        corrupted.truncate(12);
+        let chunks = vec![Bytes::from(gzip(ROWS)), Bytes::from(corrupted)];
+
+        let err = decompress_chunks_parallel(chunks, CompressionFormat::Gzip).unwrap_err();
+        assert!(matches!(
+            err,
+            CompressionError::Decompression { index: 1, .. }
+        ));
+    }
+}
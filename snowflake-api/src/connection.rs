@@ -1,20 +1,37 @@
+use base64::Engine;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::future::try_join_all;
+use futures::stream::{self, Stream, StreamExt};
 use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use reqwest::Method;
 use reqwest_middleware::ClientWithMiddleware;
-use reqwest_retry::policies::ExponentialBackoff;
-use reqwest_retry::RetryTransientMiddleware;
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use url::Url;
 use uuid::Uuid;
 
+use crate::middleware::{
+    CircuitBreakerMiddleware, CircuitOpenError, LoggingMiddleware, SnowflakeRetryPolicy,
+};
+use crate::{AccountIdentifier, ConfigError};
+
 #[derive(Error, Debug)]
 pub enum ConnectionError {
     #[error(transparent)]
     RequestError(#[from] reqwest::Error),
 
     #[error(transparent)]
-    RequestMiddlewareError(#[from] reqwest_middleware::Error),
+    RequestMiddlewareError(reqwest_middleware::Error),
+
+    #[error("Downloaded chunk is corrupted, expected checksum `{expected}`, got `{actual}`")]
+    ChecksumMismatch { expected: String, actual: String },
+
+    #[error("Endpoint keeps failing, requests are rejected until the circuit breaker closes")]
+    CircuitOpen,
 
     #[error(transparent)]
     UrlParsing(#[from] url::ParseError),
@@ -24,45 +41,161 @@ pub enum ConnectionError {
 
     #[error(transparent)]
     InvalidHeader(#[from] header::InvalidHeaderValue),
+
+    #[error("Chunk header name `{0}` is invalid")]
+    InvalidHeaderName(String),
+
+    #[error("Value of the chunk header `{0}` is invalid")]
+    InvalidHeaderValue(String),
+
+    #[error(transparent)]
+    Compression(#[from] std::io::Error),
+
+    #[error(transparent)]
+    InvalidAccount(#[from] ConfigError),
+}
+
+impl From<reqwest_middleware::Error> for ConnectionError {
+    fn from(e: reqwest_middleware::Error) -> Self {
+        match &e {
+            reqwest_middleware::Error::Middleware(inner) if inner.is::<CircuitOpenError>() => {
+                Self::CircuitOpen
+            }
+            _ => Self::RequestMiddlewareError(e),
+        }
+    }
+}
+
+/// Chunks are several MiB each, default 64 KiB windows would throttle their download
+const H2_STREAM_WINDOW_SIZE: u32 = 4 * 1024 * 1024;
+const H2_CONNECTION_WINDOW_SIZE: u32 = 16 * 1024 * 1024;
+const H2_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Smaller bodies are sent as is, compression wouldn't save a round trip on them
+const COMPRESSION_THRESHOLD: usize = 8 * 1024;
+
+fn gzip(body: &[u8]) -> Result<Vec<u8>, ConnectionError> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(body.len() / 4), Compression::fast());
+    encoder.write_all(body)?;
+    Ok(encoder.finish()?)
+}
+
+/// Later pairs override the earlier ones with the same name
+fn build_header_map(pairs: &[(HeaderName, HeaderValue)]) -> HeaderMap {
+    let mut headers = HeaderMap::with_capacity(pairs.len());
+    for (name, value) in pairs {
+        headers.insert(name.clone(), value.clone());
+    }
+    headers
+}
+
+/// `Authorization` value, marked as sensitive to keep it out of debug output
+fn build_auth_header(token: &str) -> Result<HeaderValue, ConnectionError> {
+    let mut value = HeaderValue::from_str(token)?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+/// Base64 encoded big-endian CRC32 of the object as stored in S3.
+/// Checksum covers the stored bytes, so it's skipped if the body was transparently decompressed,
+/// which is signalled by the stripped `Content-Length` header.
+fn expected_crc32(headers: &HeaderMap) -> Option<String> {
+    if !headers.contains_key(header::CONTENT_LENGTH) {
+        return None;
+    }
+    headers
+        .get("x-amz-checksum-crc32")
+        .and_then(|v| v.to_str().ok())
+        // checksums of multipart uploads are composite, `<checksum of checksums>-<parts>`
+        .filter(|v| !v.contains('-'))
+        .map(ToString::to_string)
 }
 
 /// Container for query parameters
 /// This API has different endpoints and MIME types for different requests
 struct QueryContext {
-    path: &'static str,
+    path: String,
     accept_mime: &'static str,
+    method: Method,
 }
 
+#[derive(Debug, Clone)]
 pub enum QueryType {
     LoginRequest,
     TokenRequest,
     CloseSession,
+    Heartbeat,
     JsonQuery,
     ArrowQuery,
+    /// Result of the previously executed query, eg child statement of multi-statement query
+    QueryResult {
+        query_id: String,
+    },
+    /// Abort the query by the id of the request which has submitted it
+    AbortRequest,
+    /// Execution status of the query
+    QueryMonitoring {
+        query_id: String,
+    },
+    /// Result of the query which is still running, by the `getResultUrl` of the previous response
+    ResultUrl {
+        path: String,
+    },
 }
 
 impl QueryType {
-    const fn query_context(&self) -> QueryContext {
+    fn query_context(&self) -> QueryContext {
         match self {
             Self::LoginRequest => QueryContext {
-                path: "session/v1/login-request",
+                path: "session/v1/login-request".to_string(),
                 accept_mime: "application/json",
+                method: Method::POST,
             },
             Self::TokenRequest => QueryContext {
-                path: "/session/token-request",
+                path: "/session/token-request".to_string(),
                 accept_mime: "application/snowflake",
+                method: Method::POST,
             },
             Self::CloseSession => QueryContext {
-                path: "session",
+                path: "session".to_string(),
+                accept_mime: "application/snowflake",
+                // deletion is requested by `delete=true`, same as the official drivers do
+                method: Method::POST,
+            },
+            Self::Heartbeat => QueryContext {
+                path: "session/heartbeat".to_string(),
                 accept_mime: "application/snowflake",
+                method: Method::POST,
             },
             Self::JsonQuery => QueryContext {
-                path: "queries/v1/query-request",
+                path: "queries/v1/query-request".to_string(),
                 accept_mime: "application/json",
+                method: Method::POST,
             },
             Self::ArrowQuery => QueryContext {
-                path: "queries/v1/query-request",
+                path: "queries/v1/query-request".to_string(),
+                accept_mime: "application/snowflake",
+                method: Method::POST,
+            },
+            Self::QueryResult { query_id } => QueryContext {
+                path: format!("queries/{query_id}/result"),
+                accept_mime: "application/snowflake",
+                method: Method::GET,
+            },
+            Self::AbortRequest => QueryContext {
+                path: "queries/v1/abort-request".to_string(),
+                accept_mime: "application/json",
+                method: Method::POST,
+            },
+            Self::QueryMonitoring { query_id } => QueryContext {
+                path: format!("monitoring/queries/{query_id}"),
+                accept_mime: "application/json",
+                method: Method::GET,
+            },
+            Self::ResultUrl { path } => QueryContext {
+                path: path.trim_start_matches('/').to_string(),
                 accept_mime: "application/snowflake",
+                method: Method::GET,
             },
         }
     }
@@ -73,11 +206,22 @@ impl QueryType {
 pub struct Connection {
     // no need for Arc as it's already inside the reqwest client
     client: ClientWithMiddleware,
+    compress_requests: bool,
+    /// Replaces the account URL, see [`Connection::with_base_url`]
+    base_url: Option<Url>,
 }
 
 impl Connection {
     pub fn new() -> Result<Self, ConnectionError> {
-        let client = Self::default_client_builder()?;
+        let client = Self::default_client_builder(None)?;
+
+        Ok(Self::new_with_middware(client.build()))
+    }
+
+    /// Same as [`Connection::new`], with the user agent identifying the embedding application,
+    /// eg `my-etl-tool/2.3.1`
+    pub fn with_user_agent(user_agent: &str) -> Result<Self, ConnectionError> {
+        let client = Self::default_client_builder(Some(user_agent))?;
 
         Ok(Self::new_with_middware(client.build()))
     }
@@ -87,35 +231,87 @@ impl Connection {
     /// Users can provide their own middleware to the connection like this:
     /// ```rust
     /// use snowflake_api::connection::Connection;
-    /// let mut client = Connection::default_client_builder();
+    /// let mut client = Connection::default_client_builder(None);
     ///  // modify the client builder here
     /// let connection = Connection::new_with_middware(client.unwrap().build());
     /// ```
     /// This is not intended to be called directly, but is used by `SnowflakeApiBuilder::with_client`
     pub fn new_with_middware(client: ClientWithMiddleware) -> Self {
-        Self { client }
+        Self {
+            client,
+            compress_requests: true,
+            base_url: None,
+        }
     }
 
-    pub fn default_client_builder() -> Result<reqwest_middleware::ClientBuilder, ConnectionError> {
-        let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
+    /// Send the requests to this URL instead of the one derived from the account identifier,
+    /// eg a `PrivateLink` endpoint or a proxy
+    #[must_use]
+    pub fn with_base_url(mut self, base_url: Url) -> Self {
+        self.base_url = Some(base_url);
+        self
+    }
 
-        let client = reqwest::ClientBuilder::new()
-            .user_agent("Rust/0.0.1")
-            .gzip(true)
-            .referer(false);
+    /// Gzip request bodies larger than 8 KiB, eg long SQL text, enabled by default
+    #[must_use]
+    pub fn with_request_compression(mut self, compress_requests: bool) -> Self {
+        self.compress_requests = compress_requests;
+        self
+    }
 
-        #[cfg(debug_assertions)]
-        let client = client.connection_verbose(true);
+    /// Client with the retries, circuit breaker and logging middleware, the user agent
+    /// defaults to `snowflake-rs/<version>`
+    pub fn default_client_builder(
+        user_agent: Option<&str>,
+    ) -> Result<reqwest_middleware::ClientBuilder, ConnectionError> {
+        let client = Self::base_client_builder(user_agent).http1_only();
+
+        Ok(Self::with_default_middleware(client.build()?))
+    }
 
-        let client = client.build()?;
+    /// Same as [`Connection::default_client_builder`], but HTTP/2 is negotiated with the servers
+    /// which support it, so concurrent chunk downloads share a connection.
+    /// Protocol is picked by ALPN rather than assumed, as chunk storage could be HTTP/1.1 only.
+    pub fn default_client_builder_h2(
+        user_agent: Option<&str>,
+    ) -> Result<reqwest_middleware::ClientBuilder, ConnectionError> {
+        let client = Self::base_client_builder(user_agent)
+            .http2_initial_stream_window_size(H2_STREAM_WINDOW_SIZE)
+            .http2_initial_connection_window_size(H2_CONNECTION_WINDOW_SIZE)
+            .http2_keep_alive_interval(H2_KEEP_ALIVE_INTERVAL)
+            .http2_keep_alive_while_idle(true);
 
-        Ok(reqwest_middleware::ClientBuilder::new(client)
-            .with(RetryTransientMiddleware::new_with_policy(retry_policy)))
+        Ok(Self::with_default_middleware(client.build()?))
+    }
+
+    fn base_client_builder(user_agent: Option<&str>) -> reqwest::ClientBuilder {
+        let user_agent = user_agent.map_or_else(
+            || format!("snowflake-rs/{}", env!("CARGO_PKG_VERSION")),
+            ToString::to_string,
+        );
+        reqwest::ClientBuilder::new()
+            .user_agent(user_agent)
+            .gzip(true)
+            .referer(false)
+    }
+
+    fn with_default_middleware(client: reqwest::Client) -> reqwest_middleware::ClientBuilder {
+        reqwest_middleware::ClientBuilder::new(client)
+            .with(SnowflakeRetryPolicy::default())
+            .with(CircuitBreakerMiddleware::default())
+            .with(LoggingMiddleware::default())
+    }
+
+    /// Scheme and host of the REST API for the given account, see [`AccountIdentifier::parse`]
+    pub fn base_rest_url(account_identifier: &str) -> Result<Url, ConnectionError> {
+        let account_identifier = AccountIdentifier::parse(account_identifier)?;
+        Ok(Url::parse(&format!(
+            "https://{account_identifier}.snowflakecomputing.com/"
+        ))?)
     }
 
     /// Perform request of given query type with extra body or parameters
     // todo: implement soft error handling
-    // todo: is there better way to not repeat myself?
     pub async fn request<R: serde::de::DeserializeOwned>(
         &self,
         query_type: QueryType,
@@ -124,9 +320,118 @@ impl Connection {
         auth: Option<&str>,
         body: impl serde::Serialize,
     ) -> Result<R, ConnectionError> {
+        let (method, url, headers) =
+            self.prepare_request(&query_type, account_identifier, extra_get_params, auth)?;
+        let resp = self.send(method, url, headers, &body).await?;
+
+        Ok(resp.json::<R>().await?)
+    }
+
+    /// Same as [`Connection::request`], but the JSON body is returned undecoded,
+    /// so the caller could look at the envelope before deserializing it into the target type
+    pub async fn request_body(
+        &self,
+        query_type: QueryType,
+        account_identifier: &str,
+        extra_get_params: &[(&str, &str)],
+        auth: Option<&str>,
+        body: impl serde::Serialize,
+    ) -> Result<bytes::Bytes, ConnectionError> {
+        let (method, url, headers) =
+            self.prepare_request(&query_type, account_identifier, extra_get_params, auth)?;
+        let resp = self.send(method, url, headers, &body).await?;
+
+        Ok(resp.bytes().await?)
+    }
+
+    /// Same as [`Connection::request`], but the response body is returned as is instead of
+    /// being deserialized from JSON, for the endpoints which answer with binary data.
+    /// Non-success statuses are reported as errors, since there is no envelope to carry them.
+    pub async fn request_bytes(
+        &self,
+        query_type: QueryType,
+        account_identifier: &str,
+        extra_get_params: &[(&str, &str)],
+        auth: Option<&str>,
+        body: impl serde::Serialize,
+    ) -> Result<bytes::Bytes, ConnectionError> {
+        let (method, url, mut headers) =
+            self.prepare_request(&query_type, account_identifier, extra_get_params, auth)?;
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/octet-stream"),
+        );
+        let resp = self.send(method, url, headers, &body).await?;
+
+        Ok(resp.error_for_status()?.bytes().await?)
+    }
+
+    /// Send the request with the method of the query type, body is only sent with the methods
+    /// other than GET
+    async fn send(
+        &self,
+        method: Method,
+        url: Url,
+        headers: HeaderMap,
+        body: &impl serde::Serialize,
+    ) -> Result<reqwest::Response, ConnectionError> {
+        if method == Method::GET {
+            return Ok(self.client.get(url).headers(headers).send().await?);
+        }
+
+        let mut body = serde_json::to_vec(body)?;
+        let mut request = self.client.request(method, url).headers(headers);
+        if self.compress_requests && body.len() > COMPRESSION_THRESHOLD {
+            body = gzip(&body)?;
+            request = request.header(header::CONTENT_ENCODING, "gzip");
+        }
+
+        // body is kept in memory, so the retry middleware is able to send it again
+        Ok(request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await?)
+    }
+
+    /// Same as [`Connection::request`] without the body, for the query types sent with GET
+    pub async fn get_request<R: serde::de::DeserializeOwned>(
+        &self,
+        query_type: QueryType,
+        account_identifier: &str,
+        extra_get_params: &[(&str, &str)],
+        auth: Option<&str>,
+    ) -> Result<R, ConnectionError> {
+        self.request(query_type, account_identifier, extra_get_params, auth, ())
+            .await
+    }
+
+    /// Post the form to the third party endpoint, eg the identity provider, returns the body
+    pub async fn post_form(
+        &self,
+        url: Url,
+        form: &[(&str, &str)],
+    ) -> Result<String, ConnectionError> {
+        let resp = self
+            .client
+            .post(url)
+            .form(form)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(resp.text().await?)
+    }
+
+    fn prepare_request(
+        &self,
+        query_type: &QueryType,
+        account_identifier: &str,
+        extra_get_params: &[(&str, &str)],
+        auth: Option<&str>,
+    ) -> Result<(Method, Url, HeaderMap), ConnectionError> {
         let context = query_type.query_context();
 
-        let request_id = Uuid::new_v4();
         let request_guid = Uuid::new_v4();
         let client_start_time = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -134,66 +439,347 @@ impl Connection {
             .as_secs()
             .to_string();
         // fixme: update uuid's on the retry
-        let request_id = request_id.to_string();
         let request_guid = request_guid.to_string();
 
         let mut get_params = vec![
             ("clientStartTime", client_start_time.as_str()),
-            ("requestId", request_id.as_str()),
             ("request_guid", request_guid.as_str()),
         ];
+        // caller could pick the request id to be able to abort the request later
+        let request_id = Uuid::new_v4().to_string();
+        if !extra_get_params.iter().any(|(k, _)| *k == "requestId") {
+            get_params.push(("requestId", request_id.as_str()));
+        }
         get_params.extend_from_slice(extra_get_params);
 
-        let url = format!(
-            "https://{}.snowflakecomputing.com/{}",
-            &account_identifier, context.path
-        );
-        let url = Url::parse_with_params(&url, get_params)?;
+        let base_url = match &self.base_url {
+            Some(base_url) => base_url.clone(),
+            None => Self::base_rest_url(account_identifier)?,
+        };
+        let mut url = base_url.join(&context.path)?;
+        url.query_pairs_mut().extend_pairs(get_params);
 
-        let mut headers = HeaderMap::new();
-
-        headers.append(
+        let mut headers = vec![(
             header::ACCEPT,
             HeaderValue::from_static(context.accept_mime),
-        );
+        )];
         if let Some(auth) = auth {
-            let mut auth_val = HeaderValue::from_str(auth)?;
-            auth_val.set_sensitive(true);
-            headers.append(header::AUTHORIZATION, auth_val);
+            headers.push((header::AUTHORIZATION, build_auth_header(auth)?));
         }
 
-        // todo: persist client to use connection polling
-        let resp = self
-            .client
-            .post(url)
-            .headers(headers)
-            .json(&body)
-            .send()
-            .await?;
-
-        Ok(resp.json::<R>().await?)
+        Ok((context.method, url, build_header_map(&headers)))
     }
 
+    // chunk URLs are pre-signed, so they are kept out of the span
+    #[tracing::instrument(name = "snowflake.chunk", skip_all, fields(bytes = tracing::field::Empty))]
     pub async fn get_chunk(
         &self,
         url: &str,
         headers: &HashMap<String, String>,
     ) -> Result<bytes::Bytes, ConnectionError> {
-        let mut header_map = HeaderMap::new();
-        for (k, v) in headers {
-            header_map.insert(
-                HeaderName::from_bytes(k.as_bytes()).unwrap(),
-                HeaderValue::from_bytes(v.as_bytes()).unwrap(),
-            );
+        let resp = self.send_chunk_request(url, headers).await?;
+        let expected_checksum = expected_crc32(resp.headers());
+        let bytes = resp.bytes().await?;
+        tracing::Span::current().record("bytes", bytes.len());
+
+        if let Some(expected) = expected_checksum {
+            let actual = base64::engine::general_purpose::STANDARD
+                .encode(crc32fast::hash(&bytes).to_be_bytes());
+            if expected != actual {
+                return Err(ConnectionError::ChecksumMismatch { expected, actual });
+            }
         }
-        let bytes = self
+        Ok(bytes)
+    }
+
+    /// Same as [`Connection::get_chunk`], but the body is yielded as it arrives instead of
+    /// being buffered. Checksum is verified once the body ends, the last item is an error
+    /// if it doesn't match, so consumers have to treat the data as unverified until then.
+    /// Result readers of the crate don't use it, they need the whole chunk to decompress it.
+    pub async fn stream_chunk(
+        &self,
+        url: &str,
+        headers: &HashMap<String, String>,
+    ) -> Result<impl Stream<Item = Result<bytes::Bytes, ConnectionError>>, ConnectionError> {
+        let resp = self.send_chunk_request(url, headers).await?;
+        let expected_checksum = expected_crc32(resp.headers());
+        let body = resp.bytes_stream();
+
+        let state = (body, crc32fast::Hasher::new(), expected_checksum, false);
+        Ok(stream::unfold(
+            state,
+            |(mut body, mut hasher, expected, done)| async move {
+                if done {
+                    return None;
+                }
+                match body.next().await {
+                    Some(Ok(bytes)) => {
+                        hasher.update(&bytes);
+                        Some((Ok(bytes), (body, hasher, expected, false)))
+                    }
+                    Some(Err(e)) => Some((Err(e.into()), (body, hasher, expected, true))),
+                    None => {
+                        let expected = expected?;
+                        let actual = base64::engine::general_purpose::STANDARD
+                            .encode(hasher.finalize().to_be_bytes());
+                        (expected != actual).then(|| {
+                            let err = ConnectionError::ChecksumMismatch { expected, actual };
+                            (Err(err), (body, crc32fast::Hasher::new(), None, true))
+                        })
+                    }
+                }
+            },
+        ))
+    }
+
+    async fn send_chunk_request(
+        &self,
+        url: &str,
+        headers: &HashMap<String, String>,
+    ) -> Result<reqwest::Response, ConnectionError> {
+        let headers = headers
+            .iter()
+            .map(|(k, v)| {
+                let name = HeaderName::from_bytes(k.as_bytes())
+                    .map_err(|_| ConnectionError::InvalidHeaderName(k.clone()))?;
+                // values could be encryption keys, so only the name is reported
+                let value = HeaderValue::from_bytes(v.as_bytes())
+                    .map_err(|_| ConnectionError::InvalidHeaderValue(k.clone()))?;
+                Ok((name, value))
+            })
+            .collect::<Result<Vec<_>, ConnectionError>>()?;
+        Ok(self
             .client
             .get(url)
-            .headers(header_map)
+            .headers(build_header_map(&headers))
             .send()
-            .await?
-            .bytes()
-            .await?;
-        Ok(bytes)
+            .await?)
+    }
+
+    /// Download multiple chunks concurrently, preserving the order of `urls` in the result
+    pub async fn get_chunks_parallel<'a>(
+        &self,
+        urls: impl IntoIterator<Item = &'a str>,
+        headers: &HashMap<String, String>,
+    ) -> Result<Vec<bytes::Bytes>, ConnectionError> {
+        self.get_chunks_with_progress(urls, headers, &|_, _| {})
+            .await
+    }
+
+    /// Same as [`Connection::get_chunks_parallel`], `progress` is called with
+    /// `(chunks_completed, chunks_total)` every time one of the chunks is downloaded
+    pub async fn get_chunks_with_progress<'a>(
+        &self,
+        urls: impl IntoIterator<Item = &'a str>,
+        headers: &HashMap<String, String>,
+        progress: &(dyn Fn(usize, usize) + Send + Sync),
+    ) -> Result<Vec<bytes::Bytes>, ConnectionError> {
+        let urls: Vec<_> = urls.into_iter().collect();
+        let total = urls.len();
+        let completed = AtomicUsize::new(0);
+        try_join_all(urls.into_iter().map(|url| async {
+            let chunk = self.get_chunk(url, headers).await?;
+            progress(completed.fetch_add(1, Ordering::Relaxed) + 1, total);
+            Ok(chunk)
+        }))
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+
+    use base64::Engine;
+    use flate2::read::GzDecoder;
+    use futures::{StreamExt, TryStreamExt};
+    use mockito::Matcher;
+    use serde_json::{json, Value};
+
+    use super::{ConnectionError, QueryType};
+    use crate::mock;
+
+    /// Runs the statement against the mock server, returns the request body as sent
+    async fn sent_body(sql: &str, content_encoding: Matcher) -> Vec<u8> {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let sent = Arc::new(Mutex::new(vec![]));
+        let captured = Arc::clone(&sent);
+        let query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_header("content-encoding", content_encoding)
+            .match_header("content-type", "application/json")
+            .with_body_from_request(move |request| {
+                captured.lock().unwrap().clone_from(request.body().unwrap());
+                mock::query_body(&json!({})).into_bytes()
+            })
+            .create_async()
+            .await;
+
+        mock::api(&server).exec(sql).await.unwrap();
+        query.assert_async().await;
+        let body = sent.lock().unwrap().clone();
+        body
+    }
+
+    #[tokio::test]
+    async fn large_body_is_gzipped() {
+        let sql = format!("SELECT '{}'", "x".repeat(16 * 1024));
+        let body = sent_body(&sql, Matcher::Exact("gzip".to_string())).await;
+
+        let mut json = vec![];
+        GzDecoder::new(body.as_slice())
+            .read_to_end(&mut json)
+            .unwrap();
+        let json: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["sqlText"], sql);
+    }
+
+    #[tokio::test]
+    async fn small_body_is_sent_as_is() {
+        let body = sent_body("SELECT 1", Matcher::Missing).await;
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["sqlText"], "SELECT 1");
+    }
+
+    /// Chunk of the mock server with the `crc32` checksum header
+    async fn chunk(server: &mut mockito::ServerGuard, body: &[u8], crc32: &str) -> mockito::Mock {
+        server
+            .mock("GET", "/chunk")
+            .with_header("x-amz-checksum-crc32", crc32)
+            .with_body(body)
+            .create_async()
+            .await
+    }
+
+    #[tokio::test]
+    async fn streamed_chunk_is_verified_at_the_end() {
+        let mut server = mockito::Server::new_async().await;
+        let body = b"chunk of the result".repeat(1024);
+        let checksum =
+            base64::engine::general_purpose::STANDARD.encode(crc32fast::hash(&body).to_be_bytes());
+        let _chunk = chunk(&mut server, &body, &checksum).await;
+        let connection = mock::connection(&server);
+        let url = format!("{}/chunk", server.url());
+
+        let parts: Vec<_> = connection
+            .stream_chunk(&url, &HashMap::new())
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(parts.concat(), body);
+        let bytes = connection.get_chunk(&url, &HashMap::new()).await.unwrap();
+        assert_eq!(bytes, body);
+    }
+
+    #[tokio::test]
+    async fn streamed_chunk_with_wrong_checksum_ends_with_error() {
+        let mut server = mockito::Server::new_async().await;
+        let body = b"chunk of the result".repeat(1024);
+        let _chunk = chunk(&mut server, &body, "AAAAAA==").await;
+        let connection = mock::connection(&server);
+        let url = format!("{}/chunk", server.url());
+
+        let mut items: Vec<_> = connection
+            .stream_chunk(&url, &HashMap::new())
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        let last = items.pop().unwrap();
+        assert!(
+            matches!(&last, Err(ConnectionError::ChecksumMismatch { expected, .. }) if expected == "AAAAAA=="),
+            "{last:?}"
+        );
+        // data is still yielded before the checksum is known
+        let parts: Vec<_> = items.into_iter().map(Result::unwrap).collect();
+        assert_eq!(parts.concat(), body);
+
+        let err = connection
+            .get_chunk(&url, &HashMap::new())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, ConnectionError::ChecksumMismatch { .. }),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn binary_response_is_returned_as_is() {
+        let mut server = mockito::Server::new_async().await;
+        let body = [0xff, 0x00, b'A', b'R', b'R', b'O', b'W', b'1'];
+        let result = server
+            .mock(
+                "GET",
+                "/queries/01b2c3d4-0000-1111-0000-000000000001/result",
+            )
+            .match_query(Matcher::Any)
+            .match_header("accept", "application/octet-stream")
+            .match_header("authorization", "Snowflake Token=\"token\"")
+            .with_header("content-type", "application/octet-stream")
+            .with_body(body)
+            .create_async()
+            .await;
+        let query_type = |query_id: &str| QueryType::QueryResult {
+            query_id: query_id.to_string(),
+        };
+        let connection = mock::connection(&server);
+
+        let bytes = connection
+            .request_bytes(
+                query_type("01b2c3d4-0000-1111-0000-000000000001"),
+                "XY12345",
+                &[],
+                Some("Snowflake Token=\"token\""),
+                (),
+            )
+            .await
+            .unwrap();
+        result.assert_async().await;
+        assert_eq!(bytes.as_ref(), body);
+
+        // no envelope to report the failure in
+        let err = connection
+            .request_bytes(
+                query_type("01b2c3d4-0000-1111-0000-000000000002"),
+                "XY12345",
+                &[],
+                None,
+                (),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, ConnectionError::RequestError(e) if e.status() == Some(reqwest::StatusCode::NOT_IMPLEMENTED)),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_chunk_headers_are_errors() {
+        let server = mockito::Server::new_async().await;
+        let connection = mock::connection(&server);
+        let url = format!("{}/chunk", server.url());
+
+        let headers = HashMap::from([("x-amz-schlüssel".to_string(), "key".to_string())]);
+        let err = connection.get_chunk(&url, &headers).await.unwrap_err();
+        assert!(
+            matches!(&err, ConnectionError::InvalidHeaderName(name) if name == "x-amz-schlüssel"),
+            "{err}"
+        );
+
+        let headers = HashMap::from([("x-amz-key".to_string(), "secret\nkey".to_string())]);
+        let err = connection.get_chunk(&url, &headers).await.unwrap_err();
+        // the value isn't part of the error
+        assert!(
+            matches!(&err, ConnectionError::InvalidHeaderValue(name) if name == "x-amz-key"),
+            "{err}"
+        );
     }
 }
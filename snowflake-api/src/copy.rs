@@ -0,0 +1,724 @@
+use std::fmt::Write;
+
+use serde::Deserialize;
+
+use crate::connection::QueryType;
+use crate::metadata::qualified_identifier;
+use crate::quote::{quote_literal, quote_stage_path};
+use crate::responses::ExecResponse;
+use crate::rows::Rows;
+use crate::warehouse::sql_bool;
+use crate::{query_response, SnowflakeApi, SnowflakeApiError};
+
+/// Statement type id of `COPY INTO <table>`, unloading into a stage has its own type
+const COPY_STATEMENT_TYPE: i64 = 0x3600;
+
+/// Load status of the single file
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum CopyStatus {
+    Loaded,
+    /// Some of the rows were skipped, eg with `ON_ERROR = CONTINUE`
+    PartiallyLoaded,
+    LoadFailed,
+    /// File was already loaded and skipped
+    LoadSkipped,
+    Other(String),
+}
+
+impl From<String> for CopyStatus {
+    fn from(status: String) -> Self {
+        match status.to_uppercase().as_str() {
+            "LOADED" => Self::Loaded,
+            "PARTIALLY_LOADED" => Self::PartiallyLoaded,
+            "LOAD_FAILED" => Self::LoadFailed,
+            "LOAD_SKIPPED" => Self::LoadSkipped,
+            _ => Self::Other(status),
+        }
+    }
+}
+
+/// Row of the `COPY INTO <table>` result, one per file
+#[derive(Debug, Clone, Deserialize)]
+pub struct CopyFileResult {
+    pub file: String,
+    pub status: CopyStatus,
+    #[serde(default)]
+    pub rows_parsed: u64,
+    #[serde(default)]
+    pub rows_loaded: u64,
+    #[serde(default)]
+    pub error_limit: u64,
+    #[serde(default)]
+    pub errors_seen: u64,
+    #[serde(default)]
+    pub first_error: Option<String>,
+    #[serde(default)]
+    pub first_error_line: Option<u64>,
+    #[serde(default)]
+    pub first_error_character: Option<u64>,
+    #[serde(default)]
+    pub first_error_column_name: Option<String>,
+}
+
+/// Row of the `VALIDATION_MODE = RETURN_ERRORS` or `RETURN_ALL_ERRORS` result
+#[derive(Debug, Clone, Deserialize)]
+pub struct CopyValidationError {
+    pub error: String,
+    pub file: String,
+    #[serde(default)]
+    pub line: Option<u64>,
+    #[serde(default)]
+    pub character: Option<u64>,
+    #[serde(default)]
+    pub byte_offset: Option<u64>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub sql_state: Option<String>,
+    #[serde(default)]
+    pub column_name: Option<String>,
+    #[serde(default)]
+    pub row_number: Option<u64>,
+    #[serde(default)]
+    pub row_start_line: Option<u64>,
+    #[serde(default)]
+    pub rejected_record: Option<String>,
+}
+
+/// Outcome of `COPY INTO <table>`, either the files which were loaded,
+/// or the errors found by the validation run
+#[derive(Debug, Clone, Default)]
+pub struct CopyResult {
+    /// Empty if there were no new files to load
+    pub files: Vec<CopyFileResult>,
+    pub validation_errors: Vec<CopyValidationError>,
+}
+
+impl CopyResult {
+    fn from_rows(rows: Rows) -> Result<Self, SnowflakeApiError> {
+        let has_column = |name: &str| {
+            rows.schema()
+                .iter()
+                .any(|f| f.name.eq_ignore_ascii_case(name))
+        };
+        let mut res = Self::default();
+        if has_column("rows_loaded") {
+            res.files = rows
+                .map(|row| row.deserialize())
+                .collect::<Result<_, _>>()?;
+        } else if has_column("error") && has_column("file") {
+            res.validation_errors = rows
+                .map(|row| row.deserialize())
+                .collect::<Result<_, _>>()?;
+        } else if rows.schema().len() == 1 && has_column("status") {
+            // `Copy executed with 0 files processed.`
+            log::debug!("No files were loaded");
+        } else {
+            return Err(SnowflakeApiError::UnexpectedResponse);
+        }
+        Ok(res)
+    }
+
+    pub fn rows_loaded(&self) -> u64 {
+        self.files.iter().map(|f| f.rows_loaded).sum()
+    }
+
+    pub fn errors_seen(&self) -> u64 {
+        self.files.iter().map(|f| f.errors_seen).sum()
+    }
+
+    /// All files were loaded without errors and the validation didn't find any
+    pub fn is_fully_loaded(&self) -> bool {
+        self.validation_errors.is_empty()
+            && self
+                .files
+                .iter()
+                .all(|f| matches!(f.status, CopyStatus::Loaded | CopyStatus::LoadSkipped))
+    }
+}
+
+/// Format of the staged files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CopyFileFormat {
+    Csv,
+    Json,
+    Avro,
+    Orc,
+    Parquet,
+    Xml,
+    /// Named file format created with `CREATE FILE FORMAT`
+    Named(String),
+}
+
+impl CopyFileFormat {
+    pub(crate) fn to_sql(&self) -> Result<String, SnowflakeApiError> {
+        let type_ = match self {
+            Self::Csv => "CSV",
+            Self::Json => "JSON",
+            Self::Avro => "AVRO",
+            Self::Orc => "ORC",
+            Self::Parquet => "PARQUET",
+            Self::Xml => "XML",
+            Self::Named(name) => {
+                return Ok(format!("FORMAT_NAME = {}", qualified_identifier(name)?));
+            }
+        };
+        Ok(format!("TYPE = {type_}"))
+    }
+}
+
+/// What to do with the file once an error is found in it, the statement is aborted by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyOnError {
+    /// Skip the bad rows and load the rest
+    Continue,
+    SkipFile,
+    AbortStatement,
+}
+
+impl CopyOnError {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Continue => "CONTINUE",
+            Self::SkipFile => "SKIP_FILE",
+            Self::AbortStatement => "ABORT_STATEMENT",
+        }
+    }
+}
+
+/// Check the files without loading them, the errors end up in [`CopyResult::validation_errors`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyValidationMode {
+    /// Errors of the first file with errors
+    ReturnErrors,
+    ReturnAllErrors,
+}
+
+impl CopyValidationMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::ReturnErrors => "RETURN_ERRORS",
+            Self::ReturnAllErrors => "RETURN_ALL_ERRORS",
+        }
+    }
+}
+
+/// Options of [`SnowflakeApi::copy_into_table`], unset ones keep the defaults of the table and stage
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct CopyOptions {
+    file_format: Option<CopyFileFormat>,
+    files: Vec<String>,
+    pattern: Option<String>,
+    on_error: Option<CopyOnError>,
+    match_by_column_name: bool,
+    purge: Option<bool>,
+    force: Option<bool>,
+    validation_mode: Option<CopyValidationMode>,
+}
+
+impl CopyOptions {
+    pub fn with_file_format(mut self, file_format: CopyFileFormat) -> Self {
+        self.file_format = Some(file_format);
+        self
+    }
+
+    /// Load only these files, paths are relative to the stage location
+    pub fn with_files(mut self, files: &[&str]) -> Self {
+        self.files = files.iter().map(ToString::to_string).collect();
+        self
+    }
+
+    /// Load only the files whose path matches the regular expression
+    pub fn with_pattern(mut self, pattern: &str) -> Self {
+        self.pattern = Some(pattern.to_string());
+        self
+    }
+
+    pub fn with_on_error(mut self, on_error: CopyOnError) -> Self {
+        self.on_error = Some(on_error);
+        self
+    }
+
+    /// Load the columns by their name instead of the position, names are matched case-insensitively
+    pub fn with_match_by_column_name(mut self, match_by_column_name: bool) -> Self {
+        self.match_by_column_name = match_by_column_name;
+        self
+    }
+
+    /// Remove the files from the stage once they are loaded
+    pub fn with_purge(mut self, purge: bool) -> Self {
+        self.purge = Some(purge);
+        self
+    }
+
+    /// Load the files again even if they were loaded before, this could duplicate the rows
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = Some(force);
+        self
+    }
+
+    pub fn with_validation_mode(mut self, validation_mode: CopyValidationMode) -> Self {
+        self.validation_mode = Some(validation_mode);
+        self
+    }
+
+    fn to_sql(&self, table: &str, stage: &str) -> Result<String, SnowflakeApiError> {
+        let mut sql = format!(
+            "COPY INTO {} FROM {}",
+            qualified_identifier(table)?,
+            quote_stage_path(stage)?
+        );
+        if !self.files.is_empty() {
+            let files = self
+                .files
+                .iter()
+                .map(|file| quote_literal(file))
+                .collect::<Vec<_>>();
+            let _ = write!(sql, " FILES = ({})", files.join(", "));
+        }
+        if let Some(pattern) = &self.pattern {
+            let _ = write!(sql, " PATTERN = {}", quote_literal(pattern));
+        }
+        if let Some(file_format) = &self.file_format {
+            let _ = write!(sql, " FILE_FORMAT = ({})", file_format.to_sql()?);
+        }
+        if let Some(on_error) = self.on_error {
+            let _ = write!(sql, " ON_ERROR = {}", on_error.as_str());
+        }
+        if self.match_by_column_name {
+            sql.push_str(" MATCH_BY_COLUMN_NAME = CASE_INSENSITIVE");
+        }
+        if let Some(purge) = self.purge {
+            let _ = write!(sql, " PURGE = {}", sql_bool(purge));
+        }
+        if let Some(force) = self.force {
+            let _ = write!(sql, " FORCE = {}", sql_bool(force));
+        }
+        if let Some(validation_mode) = self.validation_mode {
+            let _ = write!(sql, " VALIDATION_MODE = {}", validation_mode.as_str());
+        }
+        Ok(sql)
+    }
+}
+
+impl SnowflakeApi {
+    /// Load the files of the stage location, eg `@my_stage/2024/`, into the table.
+    /// Table name is resolved case-insensitively unless quoted, see [`CopyOptions`] for the rest.
+    pub async fn copy_into_table(
+        &self,
+        table: &str,
+        stage: &str,
+        options: CopyOptions,
+    ) -> Result<CopyResult, SnowflakeApiError> {
+        self.copy_into(&options.to_sql(table, stage)?).await
+    }
+
+    /// Execute `COPY INTO <table>` and return the per-file outcome. Other statements,
+    /// and `VALIDATION_MODE = RETURN_<n>_ROWS` which returns the table rows, are reported
+    /// as [`SnowflakeApiError::UnexpectedResponse`].
+    pub async fn copy_into(&self, sql: &str) -> Result<CopyResult, SnowflakeApiError> {
+        let resp = self
+            .run_sql::<ExecResponse>(sql, QueryType::JsonQuery)
+            .await?;
+        let resp = query_response(resp)?;
+        if resp.data.statement_type_id != COPY_STATEMENT_TYPE {
+            log::debug!(
+                "Statement type {:#x} is not COPY INTO <table>",
+                resp.data.statement_type_id
+            );
+            return Err(SnowflakeApiError::UnexpectedResponse);
+        }
+
+        CopyResult::from_rows(self.query_rows(resp).await?)
+    }
+}
+
+/// Options of the CSV files written by [`SnowflakeApi::copy_into_location`]
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct CsvOptions {
+    field_delimiter: Option<String>,
+    enclosed_by: Option<char>,
+    null_if: Option<String>,
+    header: bool,
+}
+
+impl CsvOptions {
+    /// `,` by default
+    pub fn with_field_delimiter(mut self, field_delimiter: &str) -> Self {
+        self.field_delimiter = Some(field_delimiter.to_string());
+        self
+    }
+
+    /// Quote character of the values containing delimiters, eg `"`, values aren't quoted by default
+    pub fn with_enclosed_by(mut self, enclosed_by: char) -> Self {
+        self.enclosed_by = Some(enclosed_by);
+        self
+    }
+
+    /// Text written for `NULL`s, `\N` by default
+    pub fn with_null_if(mut self, null_if: &str) -> Self {
+        self.null_if = Some(null_if.to_string());
+        self
+    }
+
+    /// Write the column names as the first line of every file
+    pub fn with_header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    fn to_sql(&self) -> String {
+        let mut sql = "TYPE = CSV".to_string();
+        if let Some(field_delimiter) = &self.field_delimiter {
+            let _ = write!(sql, " FIELD_DELIMITER = {}", quote_literal(field_delimiter));
+        }
+        if let Some(enclosed_by) = self.enclosed_by {
+            let _ = write!(
+                sql,
+                " FIELD_OPTIONALLY_ENCLOSED_BY = {}",
+                quote_literal(&enclosed_by.to_string())
+            );
+        }
+        if let Some(null_if) = &self.null_if {
+            let _ = write!(sql, " NULL_IF = ({})", quote_literal(null_if));
+        }
+        sql
+    }
+}
+
+/// Format of the files written by [`SnowflakeApi::copy_into_location`]
+#[derive(Debug, Clone)]
+pub enum UnloadFileFormat {
+    Csv(CsvOptions),
+    /// Newline delimited JSON, the query has to return a single `VARIANT` or `OBJECT` column
+    Json,
+    Parquet,
+}
+
+impl UnloadFileFormat {
+    fn to_sql(&self) -> String {
+        match self {
+            Self::Csv(options) => options.to_sql(),
+            Self::Json => "TYPE = JSON".to_string(),
+            Self::Parquet => "TYPE = PARQUET".to_string(),
+        }
+    }
+}
+
+/// Options of [`SnowflakeApi::copy_into_location`], CSV files are written by default
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct CopyOutOptions {
+    file_format: UnloadFileFormat,
+    overwrite: bool,
+    single: bool,
+    max_file_size: Option<u64>,
+    detailed_output: bool,
+}
+
+impl Default for CopyOutOptions {
+    fn default() -> Self {
+        Self {
+            file_format: UnloadFileFormat::Csv(CsvOptions::default()),
+            overwrite: false,
+            single: false,
+            max_file_size: None,
+            detailed_output: true,
+        }
+    }
+}
+
+impl CopyOutOptions {
+    pub fn with_file_format(mut self, file_format: UnloadFileFormat) -> Self {
+        self.file_format = file_format;
+        self
+    }
+
+    /// Replace the files of the same name, otherwise the statement fails if any of them exists
+    pub fn with_overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Write a single file named after the last part of the location instead of several ones
+    pub fn with_single(mut self, single: bool) -> Self {
+        self.single = single;
+        self
+    }
+
+    /// Upper bound of the file size in bytes, `16 MB` by default
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = Some(max_file_size);
+        self
+    }
+
+    /// List every written file in [`CopyOutResult::files`], enabled by default.
+    /// Only the totals are returned otherwise.
+    pub fn with_detailed_output(mut self, detailed_output: bool) -> Self {
+        self.detailed_output = detailed_output;
+        self
+    }
+
+    fn to_sql(&self, query: &str, stage: &str) -> Result<String, SnowflakeApiError> {
+        let mut sql = format!(
+            "COPY INTO {} FROM ({query}) FILE_FORMAT = ({})",
+            quote_stage_path(stage)?,
+            self.file_format.to_sql()
+        );
+        if let UnloadFileFormat::Csv(CsvOptions { header: true, .. }) = self.file_format {
+            sql.push_str(" HEADER = TRUE");
+        }
+        let _ = write!(
+            sql,
+            " OVERWRITE = {} SINGLE = {} DETAILED_OUTPUT = {}",
+            sql_bool(self.overwrite),
+            sql_bool(self.single),
+            sql_bool(self.detailed_output)
+        );
+        if let Some(max_file_size) = self.max_file_size {
+            let _ = write!(sql, " MAX_FILE_SIZE = {max_file_size}");
+        }
+        Ok(sql)
+    }
+}
+
+/// Row of the `COPY INTO <location>` result with `DETAILED_OUTPUT = TRUE`, one per file
+#[derive(Debug, Clone, Deserialize)]
+pub struct UnloadedFile {
+    /// Path relative to the location
+    pub file_name: String,
+    /// Size in bytes after the compression
+    pub file_size: u64,
+    pub row_count: u64,
+}
+
+#[derive(Deserialize)]
+struct UnloadSummary {
+    rows_unloaded: u64,
+    #[serde(default)]
+    input_bytes: u64,
+    #[serde(default)]
+    output_bytes: u64,
+}
+
+/// Outcome of `COPY INTO <location>`
+#[derive(Debug, Clone, Default)]
+pub struct CopyOutResult {
+    /// Empty unless [`CopyOutOptions::with_detailed_output`] is set
+    pub files: Vec<UnloadedFile>,
+    pub rows_unloaded: u64,
+    /// Total size of the written files
+    pub output_bytes: u64,
+}
+
+impl CopyOutResult {
+    fn from_rows(rows: Rows) -> Result<Self, SnowflakeApiError> {
+        let has_column = |name: &str| {
+            rows.schema()
+                .iter()
+                .any(|f| f.name.eq_ignore_ascii_case(name))
+        };
+        if has_column("file_name") {
+            let files: Vec<UnloadedFile> = rows
+                .map(|row| row.deserialize())
+                .collect::<Result<_, _>>()?;
+            Ok(Self {
+                rows_unloaded: files.iter().map(|f| f.row_count).sum(),
+                output_bytes: files.iter().map(|f| f.file_size).sum(),
+                files,
+            })
+        } else if has_column("rows_unloaded") {
+            let mut res = Self::default();
+            for row in rows {
+                let summary: UnloadSummary = row.deserialize()?;
+                log::debug!(
+                    "Unloaded {} rows, {} bytes compressed to {}",
+                    summary.rows_unloaded,
+                    summary.input_bytes,
+                    summary.output_bytes
+                );
+                res.rows_unloaded += summary.rows_unloaded;
+                res.output_bytes += summary.output_bytes;
+            }
+            Ok(res)
+        } else {
+            Err(SnowflakeApiError::UnexpectedResponse)
+        }
+    }
+}
+
+impl SnowflakeApi {
+    /// Write the result of the query into the files of the stage location, eg `@my_stage/export/`,
+    /// the counterpart of [`SnowflakeApi::copy_into_table`]
+    pub async fn copy_into_location(
+        &self,
+        query: &str,
+        stage: &str,
+        options: CopyOutOptions,
+    ) -> Result<CopyOutResult, SnowflakeApiError> {
+        let rows = self.query(&options.to_sql(query, stage)?).await?;
+        CopyOutResult::from_rows(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+
+    use super::*;
+    use crate::mock;
+
+    /// Runs `COPY INTO` against the mock server answering with the given response body
+    async fn copy_into(body: &str) -> Result<CopyResult, SnowflakeApiError> {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(body)
+            .create_async()
+            .await;
+        mock::api(&server)
+            .copy_into("COPY INTO events FROM @landing")
+            .await
+    }
+
+    #[tokio::test]
+    async fn csv_load_with_partially_loaded_and_failed_files() {
+        let res = copy_into(include_str!("fixtures/copy_csv.json"))
+            .await
+            .unwrap();
+
+        let statuses: Vec<_> = res.files.iter().map(|f| f.status.clone()).collect();
+        assert_eq!(
+            statuses,
+            [
+                CopyStatus::Loaded,
+                CopyStatus::PartiallyLoaded,
+                CopyStatus::LoadFailed
+            ]
+        );
+        assert_eq!(res.rows_loaded(), 1998);
+        assert_eq!(res.errors_seen(), 3);
+        assert!(!res.is_fully_loaded());
+
+        let partial = &res.files[1];
+        assert_eq!(partial.file, "events/2024-06-10/part_001.csv.gz");
+        assert_eq!(partial.rows_parsed, 1000);
+        assert_eq!(
+            partial.first_error.as_deref(),
+            Some("Numeric value 'n/a' is not recognized")
+        );
+        assert_eq!(partial.first_error_line, Some(17));
+        assert_eq!(partial.first_error_character, Some(12));
+        assert_eq!(
+            partial.first_error_column_name.as_deref(),
+            Some("\"EVENTS\"[\"AMOUNT\":4]")
+        );
+        assert_eq!(res.files[0].first_error, None);
+    }
+
+    #[tokio::test]
+    async fn copy_into_table_reports_loaded_and_failed_files() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let copy = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "sqlText": "COPY INTO analytics.events FROM '@landing/events/2024-06-10/' \
+                            PATTERN = '.*\\\\.csv\\\\.gz' FILE_FORMAT = (TYPE = CSV) \
+                            ON_ERROR = CONTINUE"
+            })))
+            .with_body(include_str!("fixtures/copy_csv.json"))
+            .create_async()
+            .await;
+
+        let options = CopyOptions::default()
+            .with_pattern(r".*\.csv\.gz")
+            .with_file_format(CopyFileFormat::Csv)
+            .with_on_error(CopyOnError::Continue);
+        let res = mock::api(&server)
+            .copy_into_table("analytics.events", "@landing/events/2024-06-10/", options)
+            .await
+            .unwrap();
+        copy.assert_async().await;
+
+        let loaded: Vec<_> = res
+            .files
+            .iter()
+            .map(|f| (f.status.clone(), f.rows_loaded, f.errors_seen))
+            .collect();
+        assert_eq!(
+            loaded,
+            [
+                (CopyStatus::Loaded, 1000, 0),
+                (CopyStatus::PartiallyLoaded, 998, 2),
+                (CopyStatus::LoadFailed, 0, 1)
+            ]
+        );
+        assert_eq!(res.files[2].first_error_line, Some(2));
+        assert!(!res.is_fully_loaded());
+    }
+
+    #[tokio::test]
+    async fn parquet_load_without_line_numbers() {
+        let res = copy_into(include_str!("fixtures/copy_parquet.json"))
+            .await
+            .unwrap();
+
+        assert_eq!(res.files.len(), 3);
+        assert_eq!(res.rows_loaded(), 52310 + 49876 + 50111);
+        let partial = &res.files[2];
+        assert_eq!(partial.status, CopyStatus::PartiallyLoaded);
+        assert_eq!(partial.first_error_line, None);
+        assert_eq!(partial.first_error_character, None);
+        assert!(res.validation_errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn validation_errors() {
+        let res = copy_into(include_str!("fixtures/copy_validation.json"))
+            .await
+            .unwrap();
+
+        assert!(res.files.is_empty());
+        assert!(!res.is_fully_loaded());
+        assert_eq!(res.validation_errors.len(), 2);
+        let error = &res.validation_errors[0];
+        assert_eq!(error.file, "events/2024-06-10/part_001.csv.gz");
+        assert_eq!(error.line, Some(17));
+        assert_eq!(error.byte_offset, Some(1733));
+        assert_eq!(error.category.as_deref(), Some("conversion"));
+        assert_eq!(error.code.as_deref(), Some("100038"));
+        assert_eq!(error.row_number, Some(16));
+        assert_eq!(
+            error.rejected_record.as_deref(),
+            Some("42,2024-06-10,click,web,n/a")
+        );
+    }
+
+    #[tokio::test]
+    async fn no_new_files() {
+        let res = copy_into(include_str!("fixtures/copy_no_files.json"))
+            .await
+            .unwrap();
+        assert!(res.files.is_empty());
+        assert!(res.is_fully_loaded());
+    }
+
+    #[tokio::test]
+    async fn other_statements_are_rejected() {
+        let body = include_str!("fixtures/copy_csv.json").replace("13824", "4096");
+        let err = copy_into(&body).await.unwrap_err();
+        assert!(
+            matches!(err, SnowflakeApiError::UnexpectedResponse),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,793 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use serde::de::value::{SeqDeserializer, StrDeserializer};
+use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::forward_to_deserialize_any;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::responses::SnowflakeType;
+use crate::rows::{FromSnowflakeValue, Row, TypeError};
+use crate::FieldSchema;
+
+#[derive(Error, Debug)]
+pub enum DeserializeError {
+    #[error(transparent)]
+    TypeError(#[from] TypeError),
+
+    #[error("Column `{0}` contains invalid semi-structured value: {1}")]
+    InvalidVariant(String, serde_json::Error),
+
+    #[error("Row has no value for column `{0}`")]
+    MissingValue(String),
+
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl DeserializeError {
+    /// Errors raised by the target type, eg `invalid type: string`, don't know the column
+    fn in_column(self, column: &str) -> Self {
+        match self {
+            Self::Custom(msg) => Self::Custom(format!("Column `{column}`: {msg}")),
+            other => other,
+        }
+    }
+}
+
+impl de::Error for DeserializeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+impl Row {
+    /// Deserialize row into a struct, columns are matched to fields case-insensitively.
+    /// Tuples and sequences are filled in column order.
+    pub fn deserialize<T: de::DeserializeOwned>(&self) -> Result<T, DeserializeError> {
+        T::deserialize(RowDeserializer::new(self))
+    }
+}
+
+/// Deserializer over the whole row, see [`Row::deserialize`]
+pub struct RowDeserializer<'a> {
+    row: &'a Row,
+}
+
+impl<'a> RowDeserializer<'a> {
+    pub fn new(row: &'a Row) -> Self {
+        Self { row }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for RowDeserializer<'_> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(RowMapAccess {
+            row: self.row,
+            fields: &[],
+            idx: 0,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(RowMapAccess {
+            row: self.row,
+            fields,
+            idx: 0,
+        })
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(RowSeqAccess {
+            row: self.row,
+            idx: 0,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct enum identifier ignored_any
+    }
+}
+
+struct RowMapAccess<'a> {
+    row: &'a Row,
+    /// struct field names, used to map column names case-insensitively
+    fields: &'static [&'static str],
+    idx: usize,
+}
+
+impl<'de> MapAccess<'de> for RowMapAccess<'_> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let Some(field) = self.row.schema().get(self.idx) else {
+            return Ok(None);
+        };
+        let key = self
+            .fields
+            .iter()
+            .find(|f| f.eq_ignore_ascii_case(&field.name))
+            .copied()
+            .unwrap_or(field.name.as_str());
+        let key: StrDeserializer<'_, DeserializeError> = key.into_deserializer();
+        seed.deserialize(key).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let idx = self.idx;
+        self.idx += 1;
+        let field = &self.row.schema()[idx];
+        let value = self
+            .row
+            .values()
+            .get(idx)
+            .ok_or_else(|| DeserializeError::MissingValue(field.name.clone()))?;
+        seed.deserialize(ValueDeserializer::new(value, field))
+            .map_err(|e| e.in_column(&field.name))
+    }
+}
+
+struct RowSeqAccess<'a> {
+    row: &'a Row,
+    idx: usize,
+}
+
+impl<'de> SeqAccess<'de> for RowSeqAccess<'_> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        let (Some(value), Some(field)) = (
+            self.row.values().get(self.idx),
+            self.row.schema().get(self.idx),
+        ) else {
+            return Ok(None);
+        };
+        self.idx += 1;
+        seed.deserialize(ValueDeserializer::new(value, field))
+            .map(Some)
+            .map_err(|e| e.in_column(&field.name))
+    }
+}
+
+/// Deserializer of a single column value, coerces JSON-encoded Snowflake values
+/// to the type requested by the target field
+struct ValueDeserializer<'a> {
+    value: &'a Value,
+    field: &'a FieldSchema,
+}
+
+impl<'a> ValueDeserializer<'a> {
+    fn new(value: &'a Value, field: &'a FieldSchema) -> Self {
+        Self { value, field }
+    }
+
+    fn get<T: FromSnowflakeValue>(&self) -> Result<T, DeserializeError> {
+        Ok(T::from_snowflake_value(self.value, self.field)?)
+    }
+
+    fn is_semi_structured(&self) -> bool {
+        matches!(
+            self.field.type_,
+            SnowflakeType::Variant | SnowflakeType::Object | SnowflakeType::Array
+        )
+    }
+
+    /// Semi-structured values are sent as JSON encoded strings
+    fn variant(&self) -> Result<Value, DeserializeError> {
+        match self.value {
+            Value::String(s) => serde_json::from_str(s)
+                .map_err(|e| DeserializeError::InvalidVariant(self.field.name.clone(), e)),
+            other => Ok(other.clone()),
+        }
+    }
+
+    /// Binary values are hex-encoded
+    fn binary(&self) -> Result<Vec<u8>, DeserializeError> {
+        let text = self.get::<String>()?;
+        Ok(decode_hex(&text).ok_or_else(|| TypeError::InvalidValue {
+            column: self.field.name.clone(),
+            value: text.clone(),
+            target: "bytes",
+        })?)
+    }
+
+    /// Temporal values are converted to the ISO format expected by chrono and most other crates
+    fn text(&self) -> Result<String, DeserializeError> {
+        match self.field.type_ {
+            SnowflakeType::Date => Ok(self.get::<NaiveDate>()?.to_string()),
+            // timestamps without timezone are kept naive, eg for `NaiveDateTime` fields
+            SnowflakeType::TimestampNtz => Ok(self
+                .get::<DateTime<Utc>>()?
+                .naive_utc()
+                .format("%Y-%m-%dT%H:%M:%S%.f")
+                .to_string()),
+            SnowflakeType::TimestampLtz | SnowflakeType::TimestampTz => {
+                Ok(self.get::<DateTime<Utc>>()?.to_rfc3339())
+            }
+            SnowflakeType::Time => {
+                let text = self.get::<String>()?;
+                let (secs, _) = text.split_once('.').unwrap_or((&text, ""));
+                let time = secs
+                    .parse::<u32>()
+                    .ok()
+                    .and_then(|secs| NaiveTime::from_num_seconds_from_midnight_opt(secs, 0))
+                    .ok_or_else(|| TypeError::InvalidValue {
+                        column: self.field.name.clone(),
+                        value: text.clone(),
+                        target: "NaiveTime",
+                    })?;
+                // keep the fraction as is
+                Ok(match text.split_once('.') {
+                    Some((_, fraction)) => format!("{}.{fraction}", time.format("%H:%M:%S")),
+                    None => time.to_string(),
+                })
+            }
+            _ => self.get::<String>(),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.value.is_null() {
+            return visitor.visit_unit();
+        }
+        match self.field.type_ {
+            SnowflakeType::Fixed if self.field.scale.unwrap_or(0) == 0 => match self.get::<i64>() {
+                Ok(value) => visitor.visit_i64(value),
+                // integers beyond `i64`, eg of `NUMBER(38, 0)`
+                Err(_) if cfg!(feature = "decimal") => visitor.visit_string(self.get()?),
+                Err(e) => Err(e),
+            },
+            // exact decimal text, so `Decimal` fields don't go through `f64`
+            SnowflakeType::Fixed if cfg!(feature = "decimal") => visitor.visit_string(self.get()?),
+            SnowflakeType::Fixed | SnowflakeType::Real => visitor.visit_f64(self.get()?),
+            SnowflakeType::Boolean => visitor.visit_bool(self.get()?),
+            SnowflakeType::Variant | SnowflakeType::Object | SnowflakeType::Array => {
+                de::Deserializer::deserialize_any(self.variant()?, visitor)
+                    .map_err(|e| DeserializeError::InvalidVariant(self.field.name.clone(), e))
+            }
+            _ => visitor.visit_string(self.text()?),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_bool(self.get()?)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i64(self.get()?)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let text = self.get::<String>()?;
+        let value = text.parse::<u64>().map_err(|_| TypeError::InvalidValue {
+            column: self.field.name.clone(),
+            value: text.clone(),
+            target: "u64",
+        })?;
+        visitor.visit_u64(value)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_f64(self.get()?)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_string(self.text()?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.field.type_ {
+            SnowflakeType::Binary => visitor.visit_byte_buf(self.binary()?),
+            _ => visitor.visit_byte_buf(self.get::<String>()?.into_bytes()),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.value.is_null() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        // `Vec<u8>` fields ask for a sequence rather than bytes
+        if matches!(self.field.type_, SnowflakeType::Binary) && !self.value.is_null() {
+            return visitor.visit_seq(SeqDeserializer::new(self.binary()?.into_iter()));
+        }
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if self.is_semi_structured() {
+            self.variant()?
+                .deserialize_enum(name, variants, visitor)
+                .map_err(|e| DeserializeError::InvalidVariant(self.field.name.clone(), e))
+        } else {
+            // unit variants are matched by their textual representation
+            visitor.visit_enum(self.text()?.into_deserializer())
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use chrono::NaiveDateTime;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    fn field(name: &str, type_: SnowflakeType, scale: Option<i64>) -> FieldSchema {
+        FieldSchema {
+            name: name.to_string(),
+            type_,
+            scale,
+            precision: None,
+            nullable: true,
+        }
+    }
+
+    fn row(columns: Vec<(FieldSchema, Value)>) -> Row {
+        let (schema, values): (Vec<_>, Vec<_>) = columns.into_iter().unzip();
+        Row::new(values, Arc::new(schema))
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Status {
+        Active,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Event {
+        id: i64,
+        small: u8,
+        amount: f64,
+        exact_amount: String,
+        name: Option<String>,
+        missing: Option<String>,
+        is_active: bool,
+        status: Status,
+        day: NaiveDate,
+        at: NaiveTime,
+        created_ntz: NaiveDateTime,
+        created_ltz: DateTime<Utc>,
+        created_tz: DateTime<Utc>,
+        payload: HashMap<String, i64>,
+        tags: Vec<String>,
+        raw: Vec<u8>,
+    }
+
+    #[test]
+    fn mixed_type_struct() {
+        let row = row(vec![
+            (field("ID", SnowflakeType::Fixed, Some(0)), json!("42")),
+            (field("SMALL", SnowflakeType::Fixed, Some(0)), json!("7")),
+            (
+                field("AMOUNT", SnowflakeType::Fixed, Some(2)),
+                json!("12.50"),
+            ),
+            (
+                field("EXACT_AMOUNT", SnowflakeType::Fixed, Some(2)),
+                json!("12.50"),
+            ),
+            (field("NAME", SnowflakeType::Text, None), json!("alice")),
+            (field("MISSING", SnowflakeType::Text, None), Value::Null),
+            (field("IS_ACTIVE", SnowflakeType::Boolean, None), json!("1")),
+            (field("STATUS", SnowflakeType::Text, None), json!("Active")),
+            (field("DAY", SnowflakeType::Date, None), json!("19723")),
+            (field("AT", SnowflakeType::Time, None), json!("45296.5")),
+            (
+                field("CREATED_NTZ", SnowflakeType::TimestampNtz, Some(9)),
+                json!("1704067200.123000000"),
+            ),
+            (
+                field("CREATED_LTZ", SnowflakeType::TimestampLtz, Some(9)),
+                json!("1704067200.000000000"),
+            ),
+            (
+                field("CREATED_TZ", SnowflakeType::TimestampTz, Some(9)),
+                json!("1704067200.000000000 1500"),
+            ),
+            (
+                field("PAYLOAD", SnowflakeType::Object, None),
+                json!("{\"a\": 1}"),
+            ),
+            (
+                field("TAGS", SnowflakeType::Array, None),
+                json!("[\"x\", \"y\"]"),
+            ),
+            (field("RAW", SnowflakeType::Binary, None), json!("CAFE")),
+            (field("EXTRA", SnowflakeType::Text, None), json!("ignored")),
+        ]);
+
+        let event: Event = row.deserialize().unwrap();
+        assert_eq!(
+            event,
+            Event {
+                id: 42,
+                small: 7,
+                amount: 12.5,
+                exact_amount: "12.50".to_string(),
+                name: Some("alice".to_string()),
+                missing: None,
+                is_active: true,
+                status: Status::Active,
+                day: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                at: NaiveTime::from_hms_milli_opt(12, 34, 56, 500).unwrap(),
+                created_ntz: NaiveDate::from_ymd_opt(2024, 1, 1)
+                    .unwrap()
+                    .and_hms_milli_opt(0, 0, 0, 123)
+                    .unwrap(),
+                created_ltz: DateTime::from_timestamp(1_704_067_200, 0).unwrap(),
+                created_tz: DateTime::from_timestamp(1_704_067_200, 0).unwrap(),
+                payload: HashMap::from([("a".to_string(), 1)]),
+                tags: vec!["x".to_string(), "y".to_string()],
+                raw: vec![0xCA, 0xFE],
+            }
+        );
+    }
+
+    #[test]
+    fn tuple_in_column_order() {
+        let row = row(vec![
+            (field("A", SnowflakeType::Fixed, Some(0)), json!("1")),
+            (field("B", SnowflakeType::Text, None), json!("two")),
+        ]);
+
+        let (a, b): (i64, String) = row.deserialize().unwrap();
+        assert_eq!((a, b), (1, "two".to_string()));
+    }
+
+    #[test]
+    fn missing_field_is_named() {
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Target {
+            id: i64,
+            name: String,
+        }
+        let row = row(vec![(
+            field("ID", SnowflakeType::Fixed, Some(0)),
+            json!("1"),
+        )]);
+
+        let err = row.deserialize::<Target>().unwrap_err();
+        assert_eq!(err.to_string(), "missing field `name`");
+    }
+
+    #[test]
+    fn null_into_non_optional_field_names_the_column() {
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Target {
+            name: String,
+        }
+        let row = row(vec![(
+            field("NAME", SnowflakeType::Text, None),
+            Value::Null,
+        )]);
+
+        let err = row.deserialize::<Target>().unwrap_err();
+        assert!(err.to_string().contains("NAME"), "{err}");
+    }
+
+    #[test]
+    fn short_row_is_an_error() {
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Target {
+            a: i64,
+            b: i64,
+        }
+        let row = Row::new(
+            vec![json!("1")],
+            Arc::new(vec![
+                field("A", SnowflakeType::Fixed, Some(0)),
+                field("B", SnowflakeType::Fixed, Some(0)),
+            ]),
+        );
+
+        let err = row.deserialize::<Target>().unwrap_err();
+        assert!(matches!(err, DeserializeError::MissingValue(ref column) if column == "B"));
+        assert_eq!(err.to_string(), "Row has no value for column `B`");
+    }
+
+    #[test]
+    fn incompatible_values_name_the_column() {
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Counter {
+            count: u32,
+        }
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Flag {
+            enabled: bool,
+        }
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Labeled {
+            status: Status,
+        }
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Document {
+            payload: HashMap<String, i64>,
+        }
+
+        let err = row(vec![(
+            field("COUNT", SnowflakeType::Fixed, Some(0)),
+            json!("-1"),
+        )])
+        .deserialize::<Counter>()
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Can not convert value `-1` of column `COUNT` to `u64`"
+        );
+
+        let err = row(vec![(
+            field("ENABLED", SnowflakeType::Boolean, None),
+            json!("maybe"),
+        )])
+        .deserialize::<Flag>()
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Can not convert value `\"maybe\"` of column `ENABLED` to `bool`"
+        );
+
+        let err = row(vec![(
+            field("STATUS", SnowflakeType::Text, None),
+            json!("Archived"),
+        )])
+        .deserialize::<Labeled>()
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Column `STATUS`: unknown variant `Archived`, expected `Active`"
+        );
+
+        let err = row(vec![(
+            field("PAYLOAD", SnowflakeType::Object, None),
+            json!("{\"a\": "),
+        )])
+        .deserialize::<Document>()
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Column `PAYLOAD` contains invalid semi-structured value"),
+            "{err}"
+        );
+
+        let err = row(vec![(
+            field("PAYLOAD", SnowflakeType::Object, None),
+            json!("{\"a\": \"one\"}"),
+        )])
+        .deserialize::<Document>()
+        .unwrap_err();
+        assert!(err.to_string().contains("`PAYLOAD`"), "{err}");
+    }
+
+    #[test]
+    fn null_into_non_optional_field_error_message() {
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Target {
+            id: i64,
+        }
+        let err = row(vec![(
+            field("ID", SnowflakeType::Fixed, Some(0)),
+            Value::Null,
+        )])
+        .deserialize::<Target>()
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Column `ID` is null, use `Option<T>` to read nullable columns"
+        );
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn exact_decimals() {
+        use rust_decimal::Decimal;
+
+        #[derive(Deserialize, Debug)]
+        struct Payment {
+            amount: Decimal,
+            big: Decimal,
+            refund: Option<Decimal>,
+            rate: f64,
+        }
+        let row = row(vec![
+            (
+                field("AMOUNT", SnowflakeType::Fixed, Some(10)),
+                json!("1234567890123456.0123456789"),
+            ),
+            (
+                field("BIG", SnowflakeType::Fixed, Some(0)),
+                json!("12345678901234567890123"),
+            ),
+            (field("REFUND", SnowflakeType::Fixed, Some(2)), Value::Null),
+            (field("RATE", SnowflakeType::Fixed, Some(2)), json!("0.25")),
+        ]);
+
+        let payment: Payment = row.deserialize().unwrap();
+        assert_eq!(payment.amount.to_string(), "1234567890123456.0123456789");
+        assert_eq!(payment.big.to_string(), "12345678901234567890123");
+        assert_eq!(payment.refund, None);
+        assert!((payment.rate - 0.25).abs() < f64::EPSILON);
+
+        let amount: Decimal = row.get("AMOUNT").unwrap();
+        assert_eq!(amount, payment.amount);
+    }
+}
@@ -0,0 +1,250 @@
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, Field};
+
+use crate::bind;
+use crate::connection::QueryType;
+use crate::requests::ExecRequest;
+use crate::responses::{ExecResponse, ExecResponseRowType, SnowflakeType};
+use crate::{query_response, BindValue, SnowflakeApi, SnowflakeApiError};
+
+/// Largest precision of `NUMBER` which always fits into `Int64`
+const INT64_MAX_PRECISION: i64 = 18;
+
+/// Largest scale of `TIMESTAMP_NTZ` and `TIMESTAMP_LTZ` which are sent as a single `Int64`
+const EPOCH_ONLY_MAX_SCALE: i64 = 7;
+
+/// Largest scale of `TIMESTAMP_TZ` which is sent without the separate fraction
+const TZ_EPOCH_ONLY_MAX_SCALE: i64 = 3;
+
+/// Column of the statement result, as reported by the server without executing the statement
+#[derive(Debug, Clone)]
+pub struct ColumnDescription {
+    pub name: String,
+    pub type_: SnowflakeType,
+    /// Type name as sent by the server, eg `FIXED`, `TEXT`, `GEOGRAPHY` or `VECTOR(FLOAT, 768)`,
+    /// useful for the types without special handling, see [`SnowflakeType::Other`]
+    pub type_name: String,
+    pub nullable: bool,
+    pub precision: Option<i64>,
+    pub scale: Option<i64>,
+    /// Maximum number of characters for text columns
+    pub length: Option<i64>,
+    /// Maximum number of bytes for text and binary columns
+    pub byte_length: Option<i64>,
+    /// Type of the column in the Arrow record batches, see [`SnowflakeApi::describe`]
+    pub data_type: DataType,
+}
+
+impl From<ExecResponseRowType> for ColumnDescription {
+    fn from(value: ExecResponseRowType) -> Self {
+        let data_type = arrow_type(&value);
+        Self {
+            name: value.name,
+            type_: value.type_,
+            type_name: value.type_name,
+            nullable: value.nullable,
+            precision: value.precision,
+            scale: value.scale,
+            length: value.length,
+            byte_length: value.byte_length,
+            data_type,
+        }
+    }
+}
+
+/// Arrow type of the column in the record batches, which carry the native encoding of Snowflake
+/// rather than the logical type: `NUMBER` is the unscaled integer, `TIME` and timestamps are
+/// integers in units of the column scale, timestamps with higher precision or with time zone are
+/// structs of the epoch seconds, fraction and time zone offset.
+///
+/// For `NUMBER` up to 18 digits and `TIME` the widest integer type is reported,
+/// the server sends narrower integers when the values of the chunk fit into them.
+fn arrow_type(column: &ExecResponseRowType) -> DataType {
+    let scale = column.scale.unwrap_or(0);
+    match column.type_ {
+        SnowflakeType::Fixed => {
+            let precision = column.precision.unwrap_or(38);
+            if precision <= INT64_MAX_PRECISION {
+                DataType::Int64
+            } else {
+                DataType::Decimal128(
+                    u8::try_from(precision).unwrap_or(38),
+                    i8::try_from(scale).unwrap_or(0),
+                )
+            }
+        }
+        SnowflakeType::Real => DataType::Float64,
+        SnowflakeType::Text
+        | SnowflakeType::Variant
+        | SnowflakeType::Object
+        | SnowflakeType::Array => DataType::Utf8,
+        SnowflakeType::Date => DataType::Date32,
+        SnowflakeType::Time => DataType::Int64,
+        SnowflakeType::TimestampNtz | SnowflakeType::TimestampLtz => {
+            if scale <= EPOCH_ONLY_MAX_SCALE {
+                DataType::Int64
+            } else {
+                timestamp_struct(&[("epoch", DataType::Int64), ("fraction", DataType::Int32)])
+            }
+        }
+        SnowflakeType::TimestampTz => {
+            if scale <= TZ_EPOCH_ONLY_MAX_SCALE {
+                timestamp_struct(&[("epoch", DataType::Int64), ("timezone", DataType::Int32)])
+            } else {
+                timestamp_struct(&[
+                    ("epoch", DataType::Int64),
+                    ("fraction", DataType::Int32),
+                    ("timezone", DataType::Int32),
+                ])
+            }
+        }
+        SnowflakeType::Binary => DataType::Binary,
+        SnowflakeType::Boolean => DataType::Boolean,
+        SnowflakeType::Other => {
+            let element = if column.type_name.starts_with("VECTOR(INT") {
+                Some(DataType::Int32)
+            } else if column.type_name.starts_with("VECTOR(FLOAT") {
+                Some(DataType::Float32)
+            } else {
+                None
+            };
+            match (element, column.vector_dimension.map(i32::try_from)) {
+                (Some(element), Some(Ok(dimension))) => {
+                    DataType::FixedSizeList(Arc::new(Field::new("item", element, false)), dimension)
+                }
+                // eg `GEOGRAPHY`, which is sent as GeoJSON text by default
+                _ => DataType::Utf8,
+            }
+        }
+    }
+}
+
+fn timestamp_struct(fields: &[(&str, DataType)]) -> DataType {
+    DataType::Struct(
+        fields
+            .iter()
+            .map(|(name, data_type)| Field::new(*name, data_type.clone(), false))
+            .collect(),
+    )
+}
+
+impl SnowflakeApi {
+    /// Columns of the statement result without running it. Statement is only compiled
+    /// by the cloud services layer, so the warehouse isn't used and doesn't have to be running.
+    ///
+    /// [`ColumnDescription::data_type`] is the type of the column in the returned record batches,
+    /// which keep the native encoding of Snowflake, eg `NUMBER(10, 2)` is the `Int64` of
+    /// hundredths and `TIMESTAMP_NTZ(9)` is the struct of the epoch seconds and nanoseconds.
+    pub async fn describe(&self, sql: &str) -> Result<Vec<ColumnDescription>, SnowflakeApiError> {
+        self.describe_with_binds(sql, &[]).await
+    }
+
+    /// Same as [`SnowflakeApi::describe`] for the statement with `?` placeholders,
+    /// types of the bound values could affect the types of the result columns
+    pub async fn describe_with_binds(
+        &self,
+        sql: &str,
+        params: &[BindValue],
+    ) -> Result<Vec<ColumnDescription>, SnowflakeApiError> {
+        let request = ExecRequest {
+            describe_only: true,
+            bindings: bind::positional_bindings(params),
+            ..ExecRequest::new(sql)
+        };
+        let resp = self
+            .run_exec_request::<ExecResponse>(request, QueryType::JsonQuery)
+            .await?;
+        let resp = query_response(resp)?;
+
+        Ok(resp.data.rowtype.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::datatypes::Fields;
+    use serde_json::json;
+
+    use super::*;
+
+    fn column(type_: &str, precision: Option<i64>, scale: Option<i64>) -> ExecResponseRowType {
+        serde_json::from_value(json!({
+            "name": "C",
+            "type": type_,
+            "precision": precision,
+            "scale": scale,
+            "nullable": true,
+            "byteLength": null,
+            "length": null,
+            "vectorDimension": null,
+        }))
+        .unwrap()
+    }
+
+    fn fields(names: &[&str]) -> DataType {
+        DataType::Struct(Fields::from(
+            names
+                .iter()
+                .map(|name| {
+                    let data_type = if *name == "epoch" {
+                        DataType::Int64
+                    } else {
+                        DataType::Int32
+                    };
+                    Field::new(*name, data_type, false)
+                })
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    #[test]
+    fn numbers_keep_native_encoding() {
+        assert_eq!(
+            arrow_type(&column("fixed", Some(18), Some(0))),
+            DataType::Int64
+        );
+        assert_eq!(
+            arrow_type(&column("fixed", Some(10), Some(2))),
+            DataType::Int64
+        );
+        assert_eq!(
+            arrow_type(&column("fixed", Some(38), Some(2))),
+            DataType::Decimal128(38, 2)
+        );
+        assert_eq!(arrow_type(&column("real", None, None)), DataType::Float64);
+    }
+
+    #[test]
+    fn timestamps_keep_native_encoding() {
+        assert_eq!(arrow_type(&column("time", None, Some(9))), DataType::Int64);
+        assert_eq!(
+            arrow_type(&column("timestamp_ntz", None, Some(3))),
+            DataType::Int64
+        );
+        assert_eq!(
+            arrow_type(&column("timestamp_ntz", None, Some(9))),
+            fields(&["epoch", "fraction"])
+        );
+        assert_eq!(
+            arrow_type(&column("timestamp_ltz", None, Some(9))),
+            fields(&["epoch", "fraction"])
+        );
+        assert_eq!(
+            arrow_type(&column("timestamp_tz", None, Some(3))),
+            fields(&["epoch", "timezone"])
+        );
+        assert_eq!(
+            arrow_type(&column("timestamp_tz", None, Some(9))),
+            fields(&["epoch", "fraction", "timezone"])
+        );
+    }
+
+    #[test]
+    fn other_types() {
+        assert_eq!(arrow_type(&column("date", None, None)), DataType::Date32);
+        assert_eq!(arrow_type(&column("variant", None, None)), DataType::Utf8);
+        assert_eq!(arrow_type(&column("geography", None, None)), DataType::Utf8);
+        assert_eq!(arrow_type(&column("binary", None, None)), DataType::Binary);
+    }
+}
@@ -0,0 +1,249 @@
+use arrow::array::{Array, UInt64Array};
+use arrow::datatypes::DataType;
+
+use crate::connection::QueryType;
+use crate::responses::ExecResponse;
+use crate::{query_response, QueryResult, SnowflakeApi, SnowflakeApiError};
+
+/// Statement type ids of DML statements: insert, update, delete, merge and multi-table insert
+const DML_STATEMENT_TYPES: std::ops::Range<i64> = 0x3000..0x4000;
+
+const ROWS_INSERTED: &str = "number of rows inserted";
+const ROWS_UPDATED: &str = "number of rows updated";
+const ROWS_DELETED: &str = "number of rows deleted";
+const MULTI_JOINED_ROWS_UPDATED: &str = "number of multi-joined rows updated";
+
+/// Affected row counts reported by `INSERT`, `UPDATE`, `DELETE` and `MERGE`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DmlResult {
+    pub rows_inserted: u64,
+    pub rows_updated: u64,
+    pub rows_deleted: u64,
+    /// Rows of `UPDATE ... FROM` which were matched by more than one joined row
+    pub multi_joined_rows_updated: u64,
+    /// All of the count columns in order, eg multi-table insert has a column per table
+    pub counts: Vec<(String, u64)>,
+}
+
+impl DmlResult {
+    fn from_counts(counts: Vec<(String, u64)>) -> Option<Self> {
+        if counts.is_empty() || !counts.iter().all(|(name, _)| is_count_column(name)) {
+            return None;
+        }
+
+        let mut res = Self::default();
+        for (name, count) in &counts {
+            let name = name.to_lowercase();
+            if name.starts_with(ROWS_INSERTED) {
+                res.rows_inserted += count;
+            } else if name.starts_with(ROWS_UPDATED) {
+                res.rows_updated += count;
+            } else if name.starts_with(ROWS_DELETED) {
+                res.rows_deleted += count;
+            } else if name.starts_with(MULTI_JOINED_ROWS_UPDATED) {
+                res.multi_joined_rows_updated += count;
+            }
+        }
+        res.counts = counts;
+        Some(res)
+    }
+
+    /// Total number of inserted, updated and deleted rows
+    pub fn total(&self) -> u64 {
+        self.rows_inserted + self.rows_updated + self.rows_deleted
+    }
+}
+
+fn is_count_column(name: &str) -> bool {
+    name.to_lowercase().starts_with("number of ")
+}
+
+impl QueryResult {
+    /// Affected row counts if this is the result of DML statement, recognized by its columns
+    pub fn affected_rows(&self) -> Option<DmlResult> {
+        let counts = match self {
+            QueryResult::Arrow(batches) => {
+                let batch = batches.iter().find(|b| b.num_rows() > 0)?;
+                let mut counts = vec![];
+                for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+                    let column = arrow::compute::cast(column, &DataType::UInt64).ok()?;
+                    let column = column.as_any().downcast_ref::<UInt64Array>()?;
+                    counts.push((field.name().clone(), column.value(0)));
+                }
+                counts
+            }
+            QueryResult::Json(json) => {
+                let row = json.value.as_array()?.first()?.as_array()?;
+                let mut counts = vec![];
+                for (field, value) in json.schema.iter().zip(row) {
+                    let count = match value {
+                        serde_json::Value::String(s) => s.parse().ok()?,
+                        other => other.as_u64()?,
+                    };
+                    counts.push((field.name.clone(), count));
+                }
+                counts
+            }
+            QueryResult::Empty => return None,
+        };
+        DmlResult::from_counts(counts)
+    }
+}
+
+impl SnowflakeApi {
+    /// Execute a single DML statement and return the affected row counts,
+    /// statements of other types are reported as [`SnowflakeApiError::UnexpectedResponse`]
+    pub async fn exec_dml(&self, sql: &str) -> Result<DmlResult, SnowflakeApiError> {
+        let resp = self
+            .run_sql::<ExecResponse>(sql, QueryType::JsonQuery)
+            .await?;
+        let resp = query_response(resp)?;
+        if !DML_STATEMENT_TYPES.contains(&resp.data.statement_type_id) {
+            log::debug!(
+                "Statement type {:#x} is not DML",
+                resp.data.statement_type_id
+            );
+            return Err(SnowflakeApiError::UnexpectedResponse);
+        }
+
+        let result = self.raw_query_result(resp).await?.deserialize_arrow()?;
+        Ok(result.affected_rows().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::mock;
+
+    const INSERT: i64 = 0x3100;
+    const UPDATE: i64 = 0x3200;
+    const DELETE: i64 = 0x3300;
+    const MERGE: i64 = 0x3400;
+    const MULTI_TABLE_INSERT: i64 = 0x3500;
+    const SELECT: i64 = 0x1000;
+
+    /// Single row result of the statement with a count column per `(name, count)`
+    fn dml_body(statement_type_id: i64, counts: &[(&str, u64)]) -> String {
+        let rowtype: Vec<Value> = counts
+            .iter()
+            .map(|(name, _)| {
+                json!({
+                    "name": name, "type": "fixed", "precision": 19, "scale": 0,
+                    "nullable": false, "byteLength": null, "length": null
+                })
+            })
+            .collect();
+        let row: Vec<String> = counts.iter().map(|(_, count)| count.to_string()).collect();
+        mock::query_body(&json!({
+            "statementTypeId": statement_type_id,
+            "rowtype": rowtype,
+            "rowset": [row],
+            "total": 1,
+            "returned": 1
+        }))
+    }
+
+    async fn exec_dml(body: String) -> Result<DmlResult, SnowflakeApiError> {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(body)
+            .create_async()
+            .await;
+        mock::api(&server).exec_dml("DML").await
+    }
+
+    #[tokio::test]
+    async fn insert() {
+        let res = exec_dml(dml_body(INSERT, &[("number of rows inserted", 3)]))
+            .await
+            .unwrap();
+        assert_eq!(res.rows_inserted, 3);
+        assert_eq!(res.total(), 3);
+    }
+
+    #[tokio::test]
+    async fn update_with_multi_joined_rows() {
+        let res = exec_dml(dml_body(
+            UPDATE,
+            &[
+                ("number of rows updated", 5),
+                ("number of multi-joined rows updated", 2),
+            ],
+        ))
+        .await
+        .unwrap();
+        assert_eq!(res.rows_updated, 5);
+        assert_eq!(res.multi_joined_rows_updated, 2);
+        assert_eq!(res.total(), 5);
+    }
+
+    #[tokio::test]
+    async fn delete() {
+        let res = exec_dml(dml_body(DELETE, &[("number of rows deleted", 7)]))
+            .await
+            .unwrap();
+        assert_eq!(res.rows_deleted, 7);
+    }
+
+    #[tokio::test]
+    async fn merge() {
+        let res = exec_dml(dml_body(
+            MERGE,
+            &[
+                ("number of rows inserted", 1),
+                ("number of rows updated", 2),
+                ("number of rows deleted", 3),
+            ],
+        ))
+        .await
+        .unwrap();
+        assert_eq!(
+            (res.rows_inserted, res.rows_updated, res.rows_deleted),
+            (1, 2, 3)
+        );
+        assert_eq!(res.total(), 6);
+    }
+
+    #[tokio::test]
+    async fn multi_table_insert_sums_tables() {
+        let res = exec_dml(dml_body(
+            MULTI_TABLE_INSERT,
+            &[
+                ("number of rows inserted into T1", 4),
+                ("number of rows inserted into T2", 6),
+            ],
+        ))
+        .await
+        .unwrap();
+        assert_eq!(res.rows_inserted, 10);
+        assert_eq!(res.counts.len(), 2);
+        assert_eq!(
+            res.counts[1],
+            ("number of rows inserted into T2".to_string(), 6)
+        );
+    }
+
+    #[tokio::test]
+    async fn other_statements_are_rejected() {
+        let err = exec_dml(dml_body(SELECT, &[("number of rows inserted", 1)]))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, SnowflakeApiError::UnexpectedResponse),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn query_result_without_count_columns_is_not_dml() {
+        assert_eq!(DmlResult::from_counts(vec![("N".to_string(), 1)]), None);
+        assert_eq!(DmlResult::from_counts(vec![]), None);
+    }
+}
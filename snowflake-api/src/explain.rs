@@ -0,0 +1,448 @@
+use std::fmt::Write;
+
+use serde::Deserialize;
+
+use crate::{SnowflakeApi, SnowflakeApiError};
+
+/// Output format of the `EXPLAIN` statement,
+/// see <https://docs.snowflake.com/en/sql-reference/sql/explain>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExplainType {
+    Tabular,
+    Json,
+    Text,
+}
+
+impl ExplainType {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Tabular => "TABULAR",
+            Self::Json => "JSON",
+            Self::Text => "TEXT",
+        }
+    }
+}
+
+/// Statistics of the whole plan
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanStats {
+    pub partitions_total: Option<i64>,
+    pub partitions_assigned: Option<i64>,
+    pub bytes_assigned: Option<i64>,
+}
+
+/// Single operator of the plan, operators reference their parents by id
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanOperation {
+    #[serde(default)]
+    pub step: Option<i64>,
+    pub id: i64,
+    #[serde(default)]
+    pub parent_operators: Vec<i64>,
+    pub operation: String,
+    #[serde(default)]
+    pub objects: Vec<String>,
+    #[serde(default)]
+    pub alias: Option<String>,
+    #[serde(default)]
+    pub expressions: Vec<String>,
+    #[serde(default)]
+    pub partitions_total: Option<i64>,
+    #[serde(default)]
+    pub partitions_assigned: Option<i64>,
+    #[serde(default)]
+    pub bytes_assigned: Option<i64>,
+}
+
+/// Query plan as a tree of operators. Plan in `Text` format isn't parsed,
+/// only the `text` is available.
+#[derive(Debug, Clone, Default)]
+pub struct QueryPlan {
+    pub global_stats: Option<PlanStats>,
+    pub operations: Vec<PlanOperation>,
+    pub text: Option<String>,
+    /// Plan document as returned by the server, only for the `Json` format
+    pub raw: Option<serde_json::Value>,
+}
+
+impl QueryPlan {
+    /// Operators without parents, usually the single `Result` operator
+    pub fn roots(&self) -> impl Iterator<Item = &PlanOperation> {
+        self.operations
+            .iter()
+            .filter(|op| op.parent_operators.is_empty())
+    }
+
+    pub fn children(&self, id: i64) -> impl Iterator<Item = &PlanOperation> {
+        self.operations
+            .iter()
+            .filter(move |op| op.parent_operators.contains(&id))
+    }
+
+    /// Graphviz representation of the plan, edges point from parent to child operator
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph plan {\n    node [shape=box];\n");
+        for op in &self.operations {
+            let mut label = op.operation.clone();
+            for object in &op.objects {
+                let _ = write!(label, "\\n{object}");
+            }
+            if let (Some(assigned), Some(total)) = (op.partitions_assigned, op.partitions_total) {
+                let _ = write!(label, "\\npartitions: {assigned}/{total}");
+            }
+            let _ = writeln!(
+                dot,
+                "    {} [label=\"{}\"];",
+                node_id(op),
+                label.replace('"', "\\\"")
+            );
+        }
+        for op in &self.operations {
+            for parent in &op.parent_operators {
+                let parent = self
+                    .operations
+                    .iter()
+                    .find(|p| p.id == *parent && p.step == op.step);
+                if let Some(parent) = parent {
+                    let _ = writeln!(dot, "    {} -> {};", node_id(parent), node_id(op));
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Ids are unique within the single step only
+fn node_id(op: &PlanOperation) -> String {
+    format!("op_{}_{}", op.step.unwrap_or(0), op.id)
+}
+
+#[derive(Deserialize)]
+struct JsonPlan {
+    #[serde(rename = "GlobalStats")]
+    global_stats: Option<PlanStats>,
+    #[serde(rename = "Operations", default)]
+    operations: Vec<Vec<PlanOperation>>,
+}
+
+impl SnowflakeApi {
+    /// Explain the query plan without running the query
+    pub async fn explain(
+        &self,
+        sql: &str,
+        explain_type: ExplainType,
+    ) -> Result<QueryPlan, SnowflakeApiError> {
+        let rows = self
+            .query(&format!("EXPLAIN USING {} {sql}", explain_type.as_str()))
+            .await
+            .map_err(compilation_error)?;
+
+        match explain_type {
+            ExplainType::Text => {
+                let mut text = String::new();
+                for row in rows {
+                    text.push_str(&row.get_by_index::<String>(0)?);
+                }
+                Ok(QueryPlan {
+                    text: Some(text),
+                    ..QueryPlan::default()
+                })
+            }
+            ExplainType::Json => {
+                let mut json = String::new();
+                for row in rows {
+                    json.push_str(&row.get_by_index::<String>(0)?);
+                }
+                let raw: serde_json::Value = serde_json::from_str(&json)?;
+                let plan = JsonPlan::deserialize(&raw)?;
+                let operations = plan
+                    .operations
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(|(step, ops)| {
+                        ops.into_iter().map(move |mut op| {
+                            op.step = op.step.or(i64::try_from(step + 1).ok());
+                            op
+                        })
+                    })
+                    .collect();
+                Ok(QueryPlan {
+                    global_stats: plan.global_stats,
+                    operations,
+                    text: None,
+                    raw: Some(raw),
+                })
+            }
+            ExplainType::Tabular => {
+                let mut plan = QueryPlan::default();
+                for row in rows {
+                    let operation = row.get::<String>("operation")?;
+                    let partitions_total = row.get::<Option<i64>>("partitionsTotal")?;
+                    let partitions_assigned = row.get::<Option<i64>>("partitionsAssigned")?;
+                    let bytes_assigned = row.get::<Option<i64>>("bytesAssigned")?;
+
+                    // first row describes the whole plan
+                    if operation == "GlobalStats" {
+                        plan.global_stats = Some(PlanStats {
+                            partitions_total,
+                            partitions_assigned,
+                            bytes_assigned,
+                        });
+                        continue;
+                    }
+
+                    plan.operations.push(PlanOperation {
+                        step: row.get("step")?,
+                        id: row.get("id")?,
+                        parent_operators: row
+                            .get::<Option<String>>("parent")?
+                            .map(|p| parse_list(&p).filter_map(|id| id.parse().ok()).collect())
+                            .unwrap_or_default(),
+                        operation,
+                        objects: row
+                            .get::<Option<String>>("objects")?
+                            .map(|o| parse_list(&o).map(ToString::to_string).collect())
+                            .unwrap_or_default(),
+                        alias: row.get("alias")?,
+                        expressions: row
+                            .get::<Option<String>>("expressions")?
+                            .map(|e| vec![e])
+                            .unwrap_or_default(),
+                        partitions_total,
+                        partitions_assigned,
+                        bytes_assigned,
+                    });
+                }
+                Ok(plan)
+            }
+        }
+    }
+}
+
+/// Invalid statement fails to compile, the position of the error is extracted from the message,
+/// eg `SQL compilation error: syntax error line 1 at position 7 unexpected 'FORM'.`
+fn compilation_error(err: SnowflakeApiError) -> SnowflakeApiError {
+    let SnowflakeApiError::ApiError {
+        code,
+        message,
+        query_id,
+    } = &err
+    else {
+        return err;
+    };
+    if !message.contains("SQL compilation error") {
+        return err;
+    }
+
+    let number_after = |marker: &str| {
+        let (_, rest) = message.split_once(marker)?;
+        let digits: String = rest
+            .trim_start()
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect();
+        digits.parse().ok()
+    };
+    SnowflakeApiError::CompilationError {
+        code: code.clone(),
+        line: number_after(" line "),
+        position: number_after(" at position "),
+        message: message.clone(),
+        query_id: query_id.clone(),
+    }
+}
+
+/// Tabular plan encodes lists as `[a, b]`, single values are taken as is
+fn parse_list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+
+    async fn explain(
+        body: String,
+        explain_type: ExplainType,
+    ) -> Result<QueryPlan, SnowflakeApiError> {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::Regex("EXPLAIN USING ".to_string()))
+            .with_body(body)
+            .create_async()
+            .await;
+        mock::api(&server)
+            .explain("SELECT * FROM z1 JOIN z2 USING (id)", explain_type)
+            .await
+    }
+
+    fn summary(plan: &QueryPlan) -> Vec<(Option<i64>, i64, Vec<i64>, &str)> {
+        plan.operations
+            .iter()
+            .map(|op| {
+                (
+                    op.step,
+                    op.id,
+                    op.parent_operators.clone(),
+                    op.operation.as_str(),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn tabular_plan() {
+        let plan = explain(
+            include_str!("fixtures/explain_tabular.json").to_string(),
+            ExplainType::Tabular,
+        )
+        .await
+        .unwrap();
+
+        let stats = plan.global_stats.as_ref().unwrap();
+        assert_eq!(
+            (
+                stats.partitions_total,
+                stats.partitions_assigned,
+                stats.bytes_assigned
+            ),
+            (Some(2), Some(2), Some(1024))
+        );
+        assert_eq!(
+            summary(&plan),
+            [
+                (Some(1), 0, vec![], "Result"),
+                (Some(1), 1, vec![0], "InnerJoin"),
+                (Some(1), 2, vec![1], "TableScan"),
+                (Some(1), 3, vec![1], "JoinFilter"),
+                (Some(1), 4, vec![3], "TableScan"),
+            ]
+        );
+        let scan = &plan.operations[2];
+        assert_eq!(scan.objects, ["TESTDB.PUBLIC.Z2"]);
+        assert_eq!(scan.alias.as_deref(), Some("Z2"));
+        assert_eq!(scan.expressions, ["ID, NAME"]);
+        assert_eq!(scan.partitions_assigned, Some(1));
+
+        assert_eq!(plan.roots().count(), 1);
+        let children: Vec<_> = plan.children(1).map(|op| op.id).collect();
+        assert_eq!(children, [2, 3]);
+        let dot = plan.to_dot();
+        assert!(dot.contains("op_1_2 [label=\"TableScan\\nTESTDB.PUBLIC.Z2\\npartitions: 1/1\"];"));
+        assert!(dot.contains("op_1_1 -> op_1_3;"), "{dot}");
+    }
+
+    #[tokio::test]
+    async fn json_plan_steps_are_flattened() {
+        let plan = explain(
+            include_str!("fixtures/explain_json.json").to_string(),
+            ExplainType::Json,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            plan.global_stats.as_ref().unwrap().partitions_total,
+            Some(3)
+        );
+        assert_eq!(
+            summary(&plan),
+            [
+                (Some(1), 0, vec![], "Result"),
+                (Some(1), 1, vec![0], "Aggregate"),
+                (Some(1), 2, vec![1], "TableScan"),
+                (Some(2), 0, vec![], "Result"),
+                (Some(2), 1, vec![0], "Filter"),
+                (Some(2), 2, vec![1], "TableScan"),
+            ]
+        );
+        assert_eq!(plan.roots().count(), 2);
+        assert!(plan.raw.as_ref().unwrap()["Operations"].is_array());
+        // same ids of different steps are different nodes
+        let dot = plan.to_dot();
+        assert!(dot.contains("op_1_1 -> op_1_2;") && dot.contains("op_2_1 -> op_2_2;"));
+        assert!(!dot.contains("op_1_1 -> op_2_2;"), "{dot}");
+    }
+
+    #[tokio::test]
+    async fn text_plan() {
+        let body = mock::query_body(&json!({
+            "rowtype": [{
+                "name": "content", "type": "text", "nullable": true,
+                "byteLength": null, "length": null, "precision": null, "scale": null
+            }],
+            "rowset": [["GlobalStats:\n    partitionsTotal=2\nResult\n"]],
+            "total": 1,
+            "returned": 1
+        }));
+        let plan = explain(body, ExplainType::Text).await.unwrap();
+        assert_eq!(
+            plan.text.as_deref(),
+            Some("GlobalStats:\n    partitionsTotal=2\nResult\n")
+        );
+        assert!(plan.operations.is_empty());
+    }
+
+    #[tokio::test]
+    async fn compilation_error_keeps_the_query_id() {
+        let body = mock::error_body(
+            "001003",
+            "SQL compilation error:\nsyntax error line 1 at position 7 unexpected 'FORM'.",
+            &json!({}),
+        );
+        let err = explain(body, ExplainType::Tabular).await.unwrap_err();
+        let SnowflakeApiError::CompilationError {
+            code,
+            line,
+            position,
+            query_id,
+            ..
+        } = &err
+        else {
+            panic!("{err}");
+        };
+        assert_eq!(code, "001003");
+        assert_eq!((*line, *position), (Some(1), Some(7)));
+        assert_eq!(
+            query_id.as_deref(),
+            Some("01b2c3d4-0000-1111-0000-00000000ffff")
+        );
+        assert!(err
+            .to_string()
+            .ends_with("Query id: `01b2c3d4-0000-1111-0000-00000000ffff`"));
+    }
+
+    #[test]
+    fn other_errors_are_kept() {
+        let err = compilation_error(SnowflakeApiError::ApiError {
+            code: "002003".to_string(),
+            message: "Object 'Z1' does not exist or not authorized.".to_string(),
+            query_id: None,
+        });
+        assert!(matches!(err, SnowflakeApiError::ApiError { .. }), "{err}");
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(parse_list("[0, 1]").collect::<Vec<_>>(), ["0", "1"]);
+        assert_eq!(parse_list(" 3 ").collect::<Vec<_>>(), ["3"]);
+        assert_eq!(parse_list("[]").count(), 0);
+        assert_eq!(parse_list("").count(), 0);
+    }
+}
@@ -0,0 +1,241 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use object_store::limit::LimitStore;
+use object_store::ObjectStore;
+use tokio::task;
+
+use crate::put::s3_store;
+use crate::responses::{EncryptionMaterialVariant, PutGetExecResponse, PutGetStageInfo};
+use crate::SnowflakeApiError;
+
+/// Outcome of downloading the single file, failure of one file doesn't stop the others
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileStatus {
+    Downloaded,
+    Failed(String),
+}
+
+/// Per-file result of the GET command
+#[derive(Debug, Clone)]
+pub struct GetFileResult {
+    /// Path of the file relative to the stage location
+    pub file: String,
+    /// Number of bytes written, `0` if the download has failed
+    pub size: u64,
+    pub status: FileStatus,
+}
+
+/// Download the files listed by the server, the server resolves `PATTERN` and the stage path.
+/// Files are stored as is, eg gzip compressed files keep their compression, same as PUT does.
+/// Subdirectories of the stage location are kept, so files with the same name don't overwrite
+/// each other. Client-side encrypted files, eg of the internal stages, aren't supported yet.
+pub async fn get(resp: PutGetExecResponse) -> Result<Vec<GetFileResult>, SnowflakeApiError> {
+    check_unencrypted(resp.data.encryption_material.as_ref())?;
+
+    let local_location = resp
+        .data
+        .local_location
+        .ok_or_else(|| SnowflakeApiError::InvalidLocalPath(String::new()))?;
+    let local_location = PathBuf::from(local_location.trim_start_matches("file://"));
+
+    match resp.data.stage_info {
+        PutGetStageInfo::Aws(info) => {
+            let (s3, bucket_path) = s3_store(info)?;
+            let limit_store = LimitStore::new(s3, resp.data.parallel.max(1));
+            get_files_par(
+                resp.data.src_locations,
+                &bucket_path,
+                &local_location,
+                limit_store,
+            )
+            .await
+        }
+        PutGetStageInfo::Azure(_) => Err(SnowflakeApiError::Unimplemented(
+            "GET file requests for Azure".to_string(),
+        )),
+        PutGetStageInfo::Gcs(_) => Err(SnowflakeApiError::Unimplemented(
+            "GET file requests for GCS".to_string(),
+        )),
+    }
+}
+
+async fn get_files_par<T: ObjectStore>(
+    files: Vec<String>,
+    bucket_path: &str,
+    local_location: &Path,
+    limit_store: LimitStore<T>,
+) -> Result<Vec<GetFileResult>, SnowflakeApiError> {
+    tokio::fs::create_dir_all(local_location).await?;
+
+    let limit_store = Arc::new(limit_store);
+    let mut tasks = task::JoinSet::new();
+    for (index, file) in files.into_iter().enumerate() {
+        let bucket_path = bucket_path.to_owned();
+        let local_location = local_location.to_owned();
+        let limit_store = Arc::clone(&limit_store);
+        tasks.spawn(async move {
+            let res = get_file(limit_store.as_ref(), &file, &bucket_path, &local_location).await;
+            let result = match res {
+                Ok(size) => GetFileResult {
+                    file,
+                    size,
+                    status: FileStatus::Downloaded,
+                },
+                Err(e) => {
+                    log::warn!("Failed to download {file}: {e}");
+                    GetFileResult {
+                        file,
+                        size: 0,
+                        status: FileStatus::Failed(e.to_string()),
+                    }
+                }
+            };
+            (index, result)
+        });
+    }
+
+    let mut results = Vec::with_capacity(tasks.len());
+    while let Some(result) = tasks.join_next().await {
+        results.push(result?);
+    }
+    // keep the order of the files as listed by the server
+    results.sort_by_key(|(index, _)| *index);
+
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+/// Files are encrypted with the key derived from the encryption material, which isn't implemented,
+/// writing the ciphertext as the downloaded file would silently corrupt the data
+fn check_unencrypted(
+    material: Option<&EncryptionMaterialVariant>,
+) -> Result<(), SnowflakeApiError> {
+    let encrypted = match material {
+        None => false,
+        Some(EncryptionMaterialVariant::Single(_)) => true,
+        Some(EncryptionMaterialVariant::Multiple(materials)) => !materials.is_empty(),
+    };
+    if encrypted {
+        return Err(SnowflakeApiError::Unimplemented(
+            "GET of client-side encrypted files".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Local path of the file, the path relative to the stage location is kept.
+/// Path is sent by the server, but anything escaping the local location is still rejected.
+fn local_path(local_location: &Path, file: &str) -> Result<PathBuf, SnowflakeApiError> {
+    let relative = Path::new(file);
+    let is_normal = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if !is_normal || relative.file_name().is_none() {
+        return Err(SnowflakeApiError::InvalidLocalPath(file.to_owned()));
+    }
+    Ok(local_location.join(relative))
+}
+
+async fn get_file<T: ObjectStore>(
+    store: &T,
+    file: &str,
+    bucket_path: &str,
+    local_location: &Path,
+) -> Result<u64, SnowflakeApiError> {
+    let local_path = local_path(local_location, file)?;
+
+    let src_path = object_store::path::Path::parse(format!("{bucket_path}{file}"))?;
+    let bytes = store.get(&src_path).await?.bytes().await?;
+    if let Some(parent) = local_path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(local_path, &bytes).await?;
+
+    Ok(bytes.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use object_store::memory::InMemory;
+
+    use super::*;
+    use crate::responses::PutGetEncryptionMaterial;
+
+    fn local_location() -> PathBuf {
+        std::env::temp_dir().join(format!("snowflake-get-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn same_file_names_in_subpaths_are_kept_apart() {
+        let store = InMemory::new();
+        for (path, content) in [("stage/a/data.csv", "a"), ("stage/b/data.csv", "bb")] {
+            store
+                .put(&object_store::path::Path::from(path), Bytes::from(content))
+                .await
+                .unwrap();
+        }
+
+        let local = local_location();
+        let files = vec!["a/data.csv".to_string(), "b/data.csv".to_string()];
+        let results = get_files_par(files, "stage/", &local, LimitStore::new(store, 2))
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.status == FileStatus::Downloaded));
+        assert_eq!(
+            std::fs::read_to_string(local.join("a/data.csv")).unwrap(),
+            "a"
+        );
+        assert_eq!(
+            std::fs::read_to_string(local.join("b/data.csv")).unwrap(),
+            "bb"
+        );
+        std::fs::remove_dir_all(local).unwrap();
+    }
+
+    #[test]
+    fn paths_escaping_local_location_are_rejected() {
+        let local = Path::new("/tmp/download");
+        assert_eq!(
+            local_path(local, "a/b/data.csv").unwrap(),
+            local.join("a/b/data.csv")
+        );
+        for file in [
+            "../data.csv",
+            "a/../../data.csv",
+            "/etc/passwd",
+            "./data.csv",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    local_path(local, file),
+                    Err(SnowflakeApiError::InvalidLocalPath(_))
+                ),
+                "{file}"
+            );
+        }
+    }
+
+    #[test]
+    fn encrypted_files_are_not_downloaded() {
+        let material = || PutGetEncryptionMaterial {
+            query_stage_master_key: "a2V5".to_string(),
+            query_id: "01b2c3d4-0000-1111-0000-000000000001".to_string(),
+            smk_id: 1,
+        };
+        assert!(check_unencrypted(None).is_ok());
+        assert!(check_unencrypted(Some(&EncryptionMaterialVariant::Multiple(vec![]))).is_ok());
+        for material in [
+            EncryptionMaterialVariant::Single(material()),
+            EncryptionMaterialVariant::Multiple(vec![material()]),
+        ] {
+            assert!(matches!(
+                check_unencrypted(Some(&material)),
+                Err(SnowflakeApiError::Unimplemented(_))
+            ));
+        }
+    }
+}
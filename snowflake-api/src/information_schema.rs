@@ -0,0 +1,200 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::metadata::{catalog_name, identifier, identifier_parts, yes_no};
+use crate::quote::quote_literal;
+use crate::{SnowflakeApi, SnowflakeApiError, TableInfo};
+
+/// Row of `INFORMATION_SCHEMA.COLUMNS`, columns which aren't listed here are ignored
+#[derive(Debug, Clone, Deserialize)]
+pub struct ColumnInfo {
+    pub name: String,
+    pub table: String,
+    pub schema: String,
+    /// 1-based position of the column in the table
+    pub ordinal_position: u32,
+    /// Type without the parameters, eg `NUMBER` or `TEXT`
+    pub data_type: String,
+    #[serde(default, deserialize_with = "yes_no")]
+    pub nullable: bool,
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub character_maximum_length: Option<u64>,
+    #[serde(default)]
+    pub numeric_precision: Option<u32>,
+    #[serde(default)]
+    pub numeric_scale: Option<u32>,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+/// Row of `INFORMATION_SCHEMA.VIEWS`, columns which aren't listed here are ignored
+#[derive(Debug, Clone, Deserialize)]
+pub struct ViewInfo {
+    pub name: String,
+    pub schema: String,
+    /// `CREATE VIEW` statement, only visible to the owner role
+    #[serde(default)]
+    pub definition: Option<String>,
+    #[serde(default, deserialize_with = "yes_no")]
+    pub is_secure: bool,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub created_on: Option<DateTime<Utc>>,
+}
+
+/// Row of `INFORMATION_SCHEMA.FUNCTIONS` or `PROCEDURES`, columns which aren't listed here are ignored
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoutineInfo {
+    pub name: String,
+    pub schema: String,
+    /// Names and types of the arguments, eg `(A NUMBER, B VARCHAR)`
+    pub argument_signature: String,
+    /// Return type, eg `NUMBER(38,0)` or `TABLE (ID NUMBER)`
+    pub data_type: String,
+    /// `SQL`, `JAVASCRIPT`, `PYTHON`, etc
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub created_on: Option<DateTime<Utc>>,
+}
+
+/// Queries of the `INFORMATION_SCHEMA` of the database, see [`SnowflakeApi::information_schema`].
+/// Only the objects the current role has access to are listed.
+pub struct InformationSchema<'a> {
+    api: &'a SnowflakeApi,
+    database: String,
+}
+
+impl SnowflakeApi {
+    /// Catalog of the database, unquoted name is resolved case-insensitively
+    pub fn information_schema(&self, database: &str) -> InformationSchema<'_> {
+        InformationSchema {
+            api: self,
+            database: database.to_string(),
+        }
+    }
+}
+
+impl InformationSchema<'_> {
+    /// Tables and views of the database, or only of the given schema.
+    /// [`TableInfo::kind`] is `BASE TABLE`, `TEMPORARY TABLE`, `VIEW`, etc.
+    pub async fn tables(&self, schema: Option<&str>) -> Result<Vec<TableInfo>, SnowflakeApiError> {
+        self.select(
+            "TABLE_NAME AS \"name\", TABLE_CATALOG AS \"database_name\", TABLE_SCHEMA AS \"schema_name\", \
+            TABLE_TYPE AS \"kind\", ROW_COUNT AS \"rows\", BYTES AS \"bytes\", TABLE_OWNER AS \"owner\", \
+            COMMENT AS \"comment\", CLUSTERING_KEY AS \"cluster_by\", CREATED AS \"created_on\"",
+            "TABLES",
+            &schema_filter("TABLE_SCHEMA", schema)?,
+            "TABLE_SCHEMA, TABLE_NAME",
+        )
+        .await
+    }
+
+    /// Columns of the table in order, the name may be qualified with the schema
+    pub async fn columns(&self, table: &str) -> Result<Vec<ColumnInfo>, SnowflakeApiError> {
+        let parts = identifier_parts(table)?;
+        let (schema, name) = match parts.as_slice() {
+            [name] => (None, *name),
+            [schema, name] => (Some(*schema), *name),
+            _ => return Err(SnowflakeApiError::InvalidIdentifier(table.to_string())),
+        };
+        let mut filter = schema_filter("TABLE_SCHEMA", schema)?;
+        let _ = write!(
+            filter,
+            " AND TABLE_NAME = {}",
+            quote_literal(&catalog_name(name))
+        );
+        self.select(
+            "COLUMN_NAME AS \"name\", TABLE_NAME AS \"table\", TABLE_SCHEMA AS \"schema\", \
+            ORDINAL_POSITION AS \"ordinal_position\", DATA_TYPE AS \"data_type\", IS_NULLABLE AS \"nullable\", \
+            COLUMN_DEFAULT AS \"default\", CHARACTER_MAXIMUM_LENGTH AS \"character_maximum_length\", \
+            NUMERIC_PRECISION AS \"numeric_precision\", NUMERIC_SCALE AS \"numeric_scale\", \
+            COMMENT AS \"comment\"",
+            "COLUMNS",
+            &filter,
+            "TABLE_SCHEMA, ORDINAL_POSITION",
+        )
+        .await
+    }
+
+    pub async fn views(&self, schema: Option<&str>) -> Result<Vec<ViewInfo>, SnowflakeApiError> {
+        self.select(
+            "TABLE_NAME AS \"name\", TABLE_SCHEMA AS \"schema\", VIEW_DEFINITION AS \"definition\", \
+            IS_SECURE AS \"is_secure\", TABLE_OWNER AS \"owner\", COMMENT AS \"comment\", CREATED AS \"created_on\"",
+            "VIEWS",
+            &schema_filter("TABLE_SCHEMA", schema)?,
+            "TABLE_SCHEMA, TABLE_NAME",
+        )
+        .await
+    }
+
+    /// User-defined functions, overloads are listed separately
+    pub async fn functions(
+        &self,
+        schema: Option<&str>,
+    ) -> Result<Vec<RoutineInfo>, SnowflakeApiError> {
+        self.select(
+            "FUNCTION_NAME AS \"name\", FUNCTION_SCHEMA AS \"schema\", \
+            ARGUMENT_SIGNATURE AS \"argument_signature\", DATA_TYPE AS \"data_type\", \
+            FUNCTION_LANGUAGE AS \"language\", FUNCTION_OWNER AS \"owner\", COMMENT AS \"comment\", \
+            CREATED AS \"created_on\"",
+            "FUNCTIONS",
+            &schema_filter("FUNCTION_SCHEMA", schema)?,
+            "FUNCTION_SCHEMA, FUNCTION_NAME",
+        )
+        .await
+    }
+
+    /// Stored procedures, overloads are listed separately
+    pub async fn procedures(
+        &self,
+        schema: Option<&str>,
+    ) -> Result<Vec<RoutineInfo>, SnowflakeApiError> {
+        self.select(
+            "PROCEDURE_NAME AS \"name\", PROCEDURE_SCHEMA AS \"schema\", \
+            ARGUMENT_SIGNATURE AS \"argument_signature\", DATA_TYPE AS \"data_type\", \
+            PROCEDURE_LANGUAGE AS \"language\", PROCEDURE_OWNER AS \"owner\", COMMENT AS \"comment\", \
+            CREATED AS \"created_on\"",
+            "PROCEDURES",
+            &schema_filter("PROCEDURE_SCHEMA", schema)?,
+            "PROCEDURE_SCHEMA, PROCEDURE_NAME",
+        )
+        .await
+    }
+
+    async fn select<T: serde::de::DeserializeOwned>(
+        &self,
+        columns: &str,
+        view: &str,
+        filter: &str,
+        order_by: &str,
+    ) -> Result<Vec<T>, SnowflakeApiError> {
+        let sql = format!(
+            "SELECT {columns} FROM {}.INFORMATION_SCHEMA.{view} WHERE {filter} ORDER BY {order_by}",
+            identifier(&self.database)?
+        );
+        self.api.query_as(&sql).await
+    }
+}
+
+/// Condition on the schema column, the information schema itself is always left out
+fn schema_filter(column: &str, schema: Option<&str>) -> Result<String, SnowflakeApiError> {
+    Ok(match schema {
+        Some(schema) => format!(
+            "{column} = {}",
+            quote_literal(&catalog_name(identifier(schema)?))
+        ),
+        None => format!("{column} <> 'INFORMATION_SCHEMA'"),
+    })
+}
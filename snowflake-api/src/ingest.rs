@@ -0,0 +1,504 @@
+use crate::metadata::{identifier, qualified_identifier};
+use crate::quote::quote_stage_path;
+use crate::{BindValue, CopyOnError, SnowflakeApi, SnowflakeApiError};
+
+const DEFAULT_BATCH_SIZE: usize = 10_000;
+const DEFAULT_STAGE_THRESHOLD: usize = 1_000;
+
+/// Options of [`SnowflakeApi::insert_rows`]
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct IngestOptions {
+    table: String,
+    columns: Vec<String>,
+    batch_size: usize,
+    stage_threshold: usize,
+    on_error: CopyOnError,
+}
+
+impl IngestOptions {
+    /// Rows are inserted into the table, the name may be qualified with the database and schema
+    pub fn new(table: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            columns: vec![],
+            batch_size: DEFAULT_BATCH_SIZE,
+            stage_threshold: DEFAULT_STAGE_THRESHOLD,
+            on_error: CopyOnError::AbortStatement,
+        }
+    }
+
+    /// Columns the row values are inserted into, all columns of the table in order by default
+    pub fn with_columns(mut self, columns: &[&str]) -> Self {
+        self.columns = columns.iter().map(ToString::to_string).collect();
+        self
+    }
+
+    /// Number of rows sent with the single statement, `10_000` by default
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Batches of at least this many rows are uploaded to the temporary stage as gzipped CSV
+    /// and loaded with `COPY INTO`, smaller ones are inserted with bind values. `1_000` by default,
+    /// `usize::MAX` always inserts.
+    pub fn with_stage_threshold(mut self, stage_threshold: usize) -> Self {
+        self.stage_threshold = stage_threshold;
+        self
+    }
+
+    /// What to do with the bad rows. [`CopyOnError::AbortStatement`], the default, stops at the
+    /// first failed batch. Otherwise the remaining batches are still sent, and staged batches
+    /// also skip the bad rows, or files with [`CopyOnError::SkipFile`].
+    pub fn with_on_error(mut self, on_error: CopyOnError) -> Self {
+        self.on_error = on_error;
+        self
+    }
+
+    fn column_list(&self) -> Result<String, SnowflakeApiError> {
+        if self.columns.is_empty() {
+            return Ok(String::new());
+        }
+        let columns = self
+            .columns
+            .iter()
+            .map(|column| identifier(column))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!(" ({})", columns.join(", ")))
+    }
+}
+
+/// How the batch was sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IngestMethod {
+    Insert,
+    Stage,
+}
+
+/// Outcome of the single batch of [`SnowflakeApi::insert_rows`]
+#[derive(Debug)]
+pub struct BatchOutcome {
+    pub index: usize,
+    pub method: IngestMethod,
+    pub rows: usize,
+    pub rows_loaded: u64,
+    /// Rows rejected by `COPY INTO`, only staged batches skip the bad rows
+    pub errors_seen: u64,
+    /// First error of the staged load, or the error of the whole batch
+    pub error: Option<String>,
+}
+
+/// Totals of [`SnowflakeApi::insert_rows`]
+#[derive(Debug, Default)]
+pub struct IngestResult {
+    pub rows_loaded: u64,
+    pub batches: Vec<BatchOutcome>,
+}
+
+impl IngestResult {
+    pub fn has_errors(&self) -> bool {
+        self.batches
+            .iter()
+            .any(|batch| batch.error.is_some() || batch.errors_seen > 0)
+    }
+}
+
+impl SnowflakeApi {
+    /// Insert the rows in batches, see [`IngestOptions`]. Rows are consumed lazily, so only
+    /// the current batch is kept in memory. Values of each column have to be of the same type,
+    /// use [`BindValue::Null`] for `NULL`s.
+    ///
+    /// Failures of the batches are reported in [`IngestResult::batches`], errors are only
+    /// returned for invalid options. Temporary stage files are removed whether the load
+    /// has succeeded or not.
+    pub async fn insert_rows<I>(
+        &self,
+        options: &IngestOptions,
+        rows: I,
+    ) -> Result<IngestResult, SnowflakeApiError>
+    where
+        I: IntoIterator<Item = Vec<BindValue>>,
+    {
+        let table = qualified_identifier(&options.table)?;
+        let columns = options.column_list()?;
+
+        let mut result = IngestResult::default();
+        let mut rows = rows.into_iter().peekable();
+        while rows.peek().is_some() {
+            let batch: Vec<_> = rows.by_ref().take(options.batch_size).collect();
+            let index = result.batches.len();
+            let outcome = if batch.len() >= options.stage_threshold {
+                self.ingest_staged(&table, &columns, options.on_error, index, &batch)
+                    .await
+            } else {
+                self.ingest_inserted(&table, &columns, index, batch).await
+            };
+            log::debug!(
+                "Batch {index} of {} rows loaded {} rows",
+                outcome.rows,
+                outcome.rows_loaded
+            );
+
+            result.rows_loaded += outcome.rows_loaded;
+            let failed = outcome.error.is_some();
+            result.batches.push(outcome);
+            if failed && options.on_error == CopyOnError::AbortStatement {
+                log::warn!("Batch {index} has failed, the remaining rows are not loaded");
+                break;
+            }
+        }
+        Ok(result)
+    }
+
+    async fn ingest_inserted(
+        &self,
+        table: &str,
+        columns: &str,
+        index: usize,
+        batch: Vec<Vec<BindValue>>,
+    ) -> BatchOutcome {
+        let rows = batch.len();
+        let placeholders = vec!["?"; batch[0].len()].join(", ");
+        let sql = format!("INSERT INTO {table}{columns} VALUES ({placeholders})");
+        let (rows_loaded, error) = match self.exec_batch(&sql, batch).await {
+            Ok(res) => (res.total(), None),
+            Err(e) => (0, Some(e.to_string())),
+        };
+        BatchOutcome {
+            index,
+            method: IngestMethod::Insert,
+            rows,
+            rows_loaded,
+            errors_seen: 0,
+            error,
+        }
+    }
+
+    async fn ingest_staged(
+        &self,
+        table: &str,
+        columns: &str,
+        on_error: CopyOnError,
+        index: usize,
+        batch: &[Vec<BindValue>],
+    ) -> BatchOutcome {
+        let mut outcome = BatchOutcome {
+            index,
+            method: IngestMethod::Stage,
+            rows: batch.len(),
+            rows_loaded: 0,
+            errors_seen: 0,
+            error: None,
+        };
+        let location = match self.upload_bind_stage(batch).await {
+            Ok(location) => location,
+            Err(e) => {
+                outcome.error = Some(e.to_string());
+                return outcome;
+            }
+        };
+
+        self.load_staged(table, columns, on_error, &location, &mut outcome)
+            .await;
+        outcome
+    }
+
+    /// Load the staged rows and remove them. `PURGE` would keep the files which weren't loaded,
+    /// eg skipped ones, so the location is removed regardless of the outcome.
+    async fn load_staged(
+        &self,
+        table: &str,
+        columns: &str,
+        on_error: CopyOnError,
+        location: &str,
+        outcome: &mut BatchOutcome,
+    ) {
+        let res = match copy_sql(table, columns, location, on_error) {
+            Ok(sql) => self.copy_into(&sql).await,
+            Err(e) => Err(e),
+        };
+        match res {
+            Ok(copy) => {
+                outcome.rows_loaded = copy.rows_loaded();
+                outcome.errors_seen = copy.errors_seen();
+                outcome.error = copy.files.into_iter().find_map(|file| file.first_error);
+            }
+            Err(e) => outcome.error = Some(e.to_string()),
+        }
+        if let Err(e) = self.remove_staged(location).await {
+            log::warn!("Failed to remove staged rows {location}: {e}");
+        }
+    }
+
+    async fn remove_staged(&self, location: &str) -> Result<(), SnowflakeApiError> {
+        self.execute_internal(&format!("REMOVE {}", quote_stage_path(location)?))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Bind stage file format matches the CSV written for the rows: values are quoted,
+/// unquoted empty fields are `NULL`s
+fn copy_sql(
+    table: &str,
+    columns: &str,
+    location: &str,
+    on_error: CopyOnError,
+) -> Result<String, SnowflakeApiError> {
+    Ok(format!(
+        "COPY INTO {table}{columns} FROM {} \
+        FILE_FORMAT = (TYPE = CSV FIELD_OPTIONALLY_ENCLOSED_BY = '\"' EMPTY_FIELD_AS_NULL = TRUE) \
+        ON_ERROR = {}",
+        quote_stage_path(location)?,
+        on_error.as_str()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Matcher, Mock, ServerGuard};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{bind, mock, BindType};
+
+    const LOCATION: &str = "@SNOWFLAKE_TEMP_BIND_STAGE/batch";
+
+    fn rows(ids: std::ops::RangeInclusive<i64>) -> Vec<Vec<BindValue>> {
+        ids.map(|id| vec![BindValue::Fixed(id), format!("name {id}").into()])
+            .collect()
+    }
+
+    /// Inserts succeed unless the batch has the row with id `failing`
+    async fn inserts(server: &mut ServerGuard, failing: i64) -> Mock {
+        server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({
+                "sqlText": "INSERT INTO analytics.events (id, name) VALUES (?, ?)"
+            })))
+            .with_body_from_request(move |request| {
+                let body: Value = serde_json::from_slice(request.body().unwrap()).unwrap();
+                let ids = body["bindings"]["1"]["value"].as_array().unwrap().clone();
+                if ids.contains(&json!(failing.to_string())) {
+                    mock::error_body("100038", "Numeric value 'x' is not recognized", &json!({}))
+                        .into_bytes()
+                } else {
+                    mock::query_body(&json!({
+                        "statementTypeId": 12544,
+                        "stats": { "numRowsInserted": ids.len() }
+                    }))
+                    .into_bytes()
+                }
+            })
+            .create_async()
+            .await
+    }
+
+    fn options(on_error: CopyOnError) -> IngestOptions {
+        IngestOptions::new("analytics.events")
+            .with_columns(&["id", "name"])
+            .with_batch_size(2)
+            .with_stage_threshold(usize::MAX)
+            .with_on_error(on_error)
+    }
+
+    #[tokio::test]
+    async fn rows_are_inserted_in_batches() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let insert = inserts(&mut server, 0).await.expect(3);
+
+        let api = mock::api(&server);
+        let res = api
+            .insert_rows(&options(CopyOnError::AbortStatement), rows(1..=5))
+            .await
+            .unwrap();
+        insert.assert_async().await;
+
+        assert_eq!(res.rows_loaded, 5);
+        assert!(!res.has_errors());
+        let batches: Vec<_> = res
+            .batches
+            .iter()
+            .map(|batch| (batch.index, batch.method, batch.rows, batch.rows_loaded))
+            .collect();
+        assert_eq!(
+            batches,
+            [
+                (0, IngestMethod::Insert, 2, 2),
+                (1, IngestMethod::Insert, 2, 2),
+                (2, IngestMethod::Insert, 1, 1)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_batch_stops_the_load() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _insert = inserts(&mut server, 3).await;
+
+        let api = mock::api(&server);
+        let res = api
+            .insert_rows(&options(CopyOnError::AbortStatement), rows(1..=5))
+            .await
+            .unwrap();
+
+        assert_eq!(res.rows_loaded, 2);
+        assert_eq!(res.batches.len(), 2);
+        let error = res.batches[1].error.as_deref().unwrap();
+        assert!(
+            error.contains("Numeric value 'x' is not recognized"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn remaining_batches_are_sent_after_failed_one() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _insert = inserts(&mut server, 3).await;
+
+        let api = mock::api(&server);
+        let res = api
+            .insert_rows(&options(CopyOnError::Continue), rows(1..=5))
+            .await
+            .unwrap();
+
+        assert_eq!(res.rows_loaded, 3);
+        assert!(res.has_errors());
+        let failed: Vec<_> = res.batches.iter().map(|b| b.error.is_some()).collect();
+        assert_eq!(failed, [false, true, false]);
+    }
+
+    async fn remove(server: &mut ServerGuard) -> Mock {
+        server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({
+                "sqlText": format!("REMOVE '{LOCATION}'"),
+                "isInternal": true
+            })))
+            .with_body(mock::query_body(&json!({})))
+            .create_async()
+            .await
+    }
+
+    async fn load_staged(server: &ServerGuard) -> BatchOutcome {
+        let mut outcome = BatchOutcome {
+            index: 0,
+            method: IngestMethod::Stage,
+            rows: 2003,
+            rows_loaded: 0,
+            errors_seen: 0,
+            error: None,
+        };
+        mock::api(server)
+            .load_staged("events", "", CopyOnError::SkipFile, LOCATION, &mut outcome)
+            .await;
+        outcome
+    }
+
+    #[tokio::test]
+    async fn staged_rows_are_removed_after_partial_load() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _copy = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::Regex("COPY INTO events".to_string()))
+            .with_body(include_str!("fixtures/copy_csv.json"))
+            .create_async()
+            .await;
+        let remove = remove(&mut server).await;
+
+        let outcome = load_staged(&server).await;
+        remove.assert_async().await;
+        assert_eq!(outcome.rows_loaded, 1998);
+        assert_eq!(outcome.errors_seen, 3);
+        assert_eq!(
+            outcome.error.as_deref(),
+            Some("Numeric value 'n/a' is not recognized")
+        );
+    }
+
+    #[tokio::test]
+    async fn staged_rows_are_removed_after_failed_load() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _copy = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::Regex("COPY INTO events".to_string()))
+            .with_body(mock::error_body(
+                "002003",
+                "Table 'EVENTS' does not exist",
+                &json!({}),
+            ))
+            .create_async()
+            .await;
+        let remove = remove(&mut server).await;
+
+        let outcome = load_staged(&server).await;
+        remove.assert_async().await;
+        assert_eq!(outcome.rows_loaded, 0);
+        assert!(outcome.error.unwrap().contains("does not exist"));
+    }
+
+    #[test]
+    fn staged_load_keeps_files_for_removal() {
+        let sql = copy_sql(
+            "analytics.events",
+            " (id, \"Name\")",
+            "@stage/o'brien",
+            CopyOnError::SkipFile,
+        )
+        .unwrap();
+        assert_eq!(
+            sql,
+            "COPY INTO analytics.events (id, \"Name\") FROM '@stage/o\\'brien' \
+             FILE_FORMAT = (TYPE = CSV FIELD_OPTIONALLY_ENCLOSED_BY = '\"' \
+             EMPTY_FIELD_AS_NULL = TRUE) ON_ERROR = SKIP_FILE"
+        );
+    }
+
+    #[test]
+    fn invalid_names_are_rejected() {
+        let options = IngestOptions::new("events").with_columns(&["id", "bad name"]);
+        assert!(options.column_list().is_err());
+        let options = IngestOptions::new("events").with_columns(&["id", "\"Quoted \"\"Name\"\"\""]);
+        assert_eq!(
+            options.column_list().unwrap(),
+            " (id, \"Quoted \"\"Name\"\"\")"
+        );
+    }
+
+    #[test]
+    fn staged_values_are_escaped() {
+        let csv = bind::to_csv(&[
+            vec![
+                BindValue::Fixed(1),
+                "plain".into(),
+                BindValue::Null(BindType::Text),
+            ],
+            vec![
+                BindValue::Fixed(-2),
+                "comma, \"quote\"\nnewline".into(),
+                "".into(),
+            ],
+            vec![
+                BindValue::Decimal("12345678901234567890.5".to_string()),
+                "\\N".into(),
+                "naïve ☃".into(),
+            ],
+        ]);
+        assert_eq!(
+            csv,
+            "\"1\",\"plain\",\n\
+             \"-2\",\"comma, \"\"quote\"\"\nnewline\",\"\"\n\
+             \"12345678901234567890.5\",\"\\N\",\"naïve ☃\"\n"
+        );
+    }
+}
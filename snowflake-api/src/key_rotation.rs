@@ -0,0 +1,132 @@
+use crate::quote::quote_ident;
+use crate::{SnowflakeApi, SnowflakeApiError};
+
+const PEM_PUBLIC_KEY_BEGIN: &str = "-----BEGIN PUBLIC KEY-----";
+const PEM_PUBLIC_KEY_END: &str = "-----END PUBLIC KEY-----";
+
+/// Key which can be registered for the key-pair authentication of the user
+pub trait KeyPair {
+    /// Base64 encoded DER of the public key, as expected by `ALTER USER ... SET RSA_PUBLIC_KEY`
+    fn public_key(&self) -> Result<String, SnowflakeApiError>;
+}
+
+/// PEM encoded public key, eg `rsa_key.pub` generated with `openssl rsa -pubout`
+#[derive(Debug, Clone)]
+pub struct PublicKeyPem(pub String);
+
+impl KeyPair for PublicKeyPem {
+    fn public_key(&self) -> Result<String, SnowflakeApiError> {
+        let pem = self.0.trim();
+        let body = pem
+            .strip_prefix(PEM_PUBLIC_KEY_BEGIN)
+            .and_then(|rest| rest.strip_suffix(PEM_PUBLIC_KEY_END))
+            .ok_or_else(|| SnowflakeApiError::InvalidPublicKey("missing PEM header".to_string()))?;
+        let body: String = body.split_whitespace().collect();
+        if body.is_empty()
+            || !body
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+/=".contains(c))
+        {
+            return Err(SnowflakeApiError::InvalidPublicKey(
+                "body is not base64".to_string(),
+            ));
+        }
+        Ok(body)
+    }
+}
+
+/// Zero-downtime rotation of the key-pair of the current user, which relies on
+/// Snowflake accepting either of the two registered public keys.
+///
+/// [`KeyRotation::begin`] registers the new key next to the current one, clients are then
+/// switched to the new private key, and [`KeyRotation::complete`] removes the old key.
+/// The session of `api` keeps working, but it has to be rebuilt with the new private key
+/// before it logs in again after `complete`.
+pub struct KeyRotation;
+
+impl KeyRotation {
+    /// Register `new_key` as `RSA_PUBLIC_KEY_2`, fails with
+    /// [`SnowflakeApiError::KeyRotationInProgress`] if the user already has two keys
+    pub async fn begin(
+        api: &SnowflakeApi,
+        new_key: &impl KeyPair,
+    ) -> Result<(), SnowflakeApiError> {
+        let public_key = new_key.public_key()?;
+        let user = current_user(api).await?;
+        if user_property(api, &user, "RSA_PUBLIC_KEY_2_FP")
+            .await?
+            .is_some()
+        {
+            return Err(SnowflakeApiError::KeyRotationInProgress(user));
+        }
+
+        log::info!("Registering the new public key of {user}");
+        api.execute(&format!(
+            "ALTER USER {} SET RSA_PUBLIC_KEY_2 = '{public_key}'",
+            quote_ident(&user)?
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Remove the old key, the new key is moved to `RSA_PUBLIC_KEY` so the next rotation
+    /// can use the second slot again
+    pub async fn complete(api: &SnowflakeApi) -> Result<(), SnowflakeApiError> {
+        let user = current_user(api).await?;
+        let new_key = user_property(api, &user, "RSA_PUBLIC_KEY_2")
+            .await?
+            .ok_or(SnowflakeApiError::NoKeyRotation(user.clone()))?;
+
+        log::info!("Completing the key rotation of {user}");
+        let user = quote_ident(&user)?;
+        // both slots hold the new key in between, so its clients can log in at any moment
+        api.execute(&format!(
+            "ALTER USER {user} SET RSA_PUBLIC_KEY = '{}'",
+            new_key.replace('\'', "")
+        ))
+        .await?;
+        api.execute(&format!("ALTER USER {user} UNSET RSA_PUBLIC_KEY_2"))
+            .await?;
+        Ok(())
+    }
+
+    /// Remove the new key, the old one stays in use
+    pub async fn rollback(api: &SnowflakeApi) -> Result<(), SnowflakeApiError> {
+        let user = current_user(api).await?;
+        log::info!("Rolling back the key rotation of {user}");
+        api.execute(&format!(
+            "ALTER USER {} UNSET RSA_PUBLIC_KEY_2",
+            quote_ident(&user)?
+        ))
+        .await?;
+        Ok(())
+    }
+}
+
+async fn current_user(api: &SnowflakeApi) -> Result<String, SnowflakeApiError> {
+    let row = api
+        .query_internal("SELECT CURRENT_USER()")
+        .await?
+        .next()
+        .ok_or(SnowflakeApiError::EmptyResponse)?;
+    Ok(row.get_by_index(0)?)
+}
+
+/// Value of the `DESC USER` property, unset properties are reported as `null`
+async fn user_property(
+    api: &SnowflakeApi,
+    user: &str,
+    property: &str,
+) -> Result<Option<String>, SnowflakeApiError> {
+    let rows = api
+        .query(&format!("DESC USER {}", quote_ident(user)?))
+        .await?;
+    for row in rows {
+        let name: String = row.get("property")?;
+        if name.eq_ignore_ascii_case(property) {
+            let value: Option<String> = row.get("value")?;
+            return Ok(value.filter(|v| !v.is_empty() && v != "null"));
+        }
+    }
+    Ok(None)
+}
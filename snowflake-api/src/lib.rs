@@ -10,39 +10,164 @@ clippy::missing_errors_doc,
 clippy::module_name_repetitions,
 clippy::struct_field_names,
 clippy::future_not_send, // This one seems like something we should eventually fix
-clippy::missing_panics_doc
+clippy::missing_panics_doc,
 )]
 
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::io;
+use std::num::NonZeroUsize;
 use std::sync::Arc;
+use std::time::Duration;
 
 use arrow::error::ArrowError;
 use arrow::ipc::reader::StreamReader;
 use arrow::record_batch::RecordBatch;
 use base64::Engine;
 use bytes::{Buf, Bytes};
-use futures::future::try_join_all;
-use regex::Regex;
+use reqwest::header::HeaderValue;
 use reqwest_middleware::ClientWithMiddleware;
 use thiserror::Error;
-
-use responses::ExecResponse;
-use session::{AuthError, Session};
-
+use tracing::Instrument;
+use url::Url;
+use uuid::Uuid;
+
+pub use account::AccountIdentifier;
+pub use account_usage::{
+    AccountUsage, LoginHistoryRow, QueryHistoryRow, StorageUsageRow, WarehouseMeteringRow,
+};
+pub use bind::{BatchResult, BindType, BindValue};
+pub use call::CallResult;
+pub use compression::{decompress_chunks_parallel, CompressionError, CompressionFormat};
+pub use copy::{
+    CopyFileFormat, CopyFileResult, CopyOnError, CopyOptions, CopyOutOptions, CopyOutResult,
+    CopyResult, CopyStatus, CopyValidationError, CopyValidationMode, CsvOptions, UnloadFileFormat,
+    UnloadedFile,
+};
+pub use de::{DeserializeError, RowDeserializer};
+pub use describe::ColumnDescription;
+pub use dml::DmlResult;
+pub use explain::{ExplainType, PlanOperation, PlanStats, QueryPlan};
+pub use get::{FileStatus, GetFileResult};
+pub use information_schema::{ColumnInfo, InformationSchema, RoutineInfo, ViewInfo};
+pub use ingest::{BatchOutcome, IngestMethod, IngestOptions, IngestResult};
+pub use key_rotation::{KeyPair, KeyRotation, PublicKeyPem};
+pub use many::{ExecManyError, ExecManyOptions, StatementOutcome};
+pub use messages::{MessageSeverity, ServerMessage};
+pub use metadata::{SchemaInfo, TableColumn, TableInfo};
+pub use mfa::{ConsoleMfaHandler, MfaHandler};
+pub use options::{ExecOptions, ResultFormat};
+#[cfg(feature = "polars")]
+pub use polars::PolarsCastError;
+pub use prepared::PreparedStatement;
+pub use query_handle::{PollPolicy, QueryDetails, QueryHandle, QueryStatus};
+pub use registry::SnowflakeRegistry;
+pub use requests::{SessionParameters, SessionParametersBuilder};
+pub use resource_monitor::{MonitorFrequency, MonitorTrigger, ResourceMonitorSpec, TriggerAction};
+pub use responses::ExecResponse;
+pub use retry::QueryRetryPolicy;
+pub use rows::{FromSnowflakeValue, Row, Rows, TypeError};
+pub use schema_evolution::{ColumnDescriptor, SchemaEvolution, SchemaMigration};
+use session::Session;
+pub use session::{AuthError, SecondaryRoles};
+pub use sso::ProgrammaticSsoAuth;
+pub use stage::{AwsCredentials, AzureAuth, S3Auth, StageFileInfo, StageLocation, StageSpec};
+pub use task::{TaskInfo, TaskSchedule, TaskSpec, TaskState};
+#[cfg(feature = "test-utils")]
+pub use test_utils::{AssertionError, QueryResultSnapshot};
+pub use tokio_util::sync::CancellationToken;
+pub use transaction::Transaction;
+pub use warehouse::{WarehouseSize, WarehouseSpec};
+
+use crate::cancellable::CancellableQuery;
 use crate::connection::QueryType;
 use crate::connection::{Connection, ConnectionError};
-use crate::requests::ExecRequest;
-use crate::responses::{ExecResponseRowType, SnowflakeType};
+use crate::quote::SqlBuilder;
+use crate::requests::{ExecRequest, ExecRequestParameters};
+use crate::responses::{
+    CommandType, ExecErrorResponse, ExecResponseRowType, QueryExecResponse, QueryExecResponseData,
+    ResponseEnvelope, ResponseEnvelopeData, SnowflakeType,
+};
 use crate::session::AuthError::MissingEnvArgument;
-
+use crate::session::SESSION_EXPIRED;
+use crate::statement::{redact_secrets, FileTransfer, StatementKind};
+
+/// Number of bind values after which they are uploaded to the stage, unless set by the server
+const DEFAULT_STAGE_BINDING_THRESHOLD: u64 = 65_280;
+/// Temporary stage for the bind values of large batches
+const BIND_STAGE: &str = "SYSTEM$BIND";
+
+/// Result of the query is older than the retention window
+const RESULT_EXPIRED: &str = "000612";
+/// Statement with the given id doesn't exist
+const QUERY_NOT_FOUND: &str = "000709";
+/// Query is still running, its result has to be polled by `getResultUrl`
+const QUERY_IN_PROGRESS: &str = "333333";
+const QUERY_IN_PROGRESS_ASYNC: &str = "333334";
+/// Matches the default `STATEMENT_TIMEOUT_IN_SECONDS` of the server
+const DEFAULT_RESULT_POLL_TIMEOUT: Duration = Duration::from_hours(48);
+const RESULT_POLL_INITIAL_DELAY: Duration = Duration::from_millis(500);
+const RESULT_POLL_MAX_DELAY: Duration = Duration::from_secs(5);
+
+mod account;
+mod account_usage;
+mod arrow_rows;
+mod bind;
+mod call;
+mod cancellable;
+mod compression;
 pub mod connection;
+mod copy;
+mod de;
+mod describe;
+mod dml;
+mod explain;
+mod get;
+mod information_schema;
+mod ingest;
+mod key_rotation;
+mod many;
+mod messages;
+mod metadata;
+mod mfa;
+pub mod middleware;
+#[cfg(test)]
+mod mock;
+mod options;
+mod parameters;
 #[cfg(feature = "polars")]
 mod polars;
+mod prepared;
 mod put;
+mod query_context;
+mod query_handle;
+pub mod quote;
+mod registry;
 mod requests;
+mod resource_monitor;
 mod responses;
+mod retry;
+mod rows;
+mod schema_evolution;
 mod session;
+mod sso;
+mod stage;
+mod statement;
+mod stream;
+mod task;
+#[cfg(feature = "test-utils")]
+mod test_utils;
+mod transaction;
+mod warehouse;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Required field `{0}` is missing")]
+    MissingField(&'static str),
+
+    #[error("Field `{field}` is invalid: {reason}")]
+    InvalidField { field: &'static str, reason: String },
+}
 
 #[derive(Error, Debug)]
 pub enum SnowflakeApiError {
@@ -52,12 +177,34 @@ pub enum SnowflakeApiError {
     #[error(transparent)]
     AuthError(#[from] AuthError),
 
+    #[error(transparent)]
+    ConfigError(#[from] ConfigError),
+
     #[error(transparent)]
     ResponseDeserializationError(#[from] base64::DecodeError),
 
     #[error(transparent)]
     ArrowError(#[from] arrow::error::ArrowError),
 
+    #[error(transparent)]
+    JsonDeserializationError(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    TypeError(#[from] TypeError),
+
+    #[error(transparent)]
+    DeserializeError(#[from] DeserializeError),
+
+    #[cfg(feature = "polars")]
+    #[error(transparent)]
+    PolarsError(#[from] PolarsCastError),
+
+    #[error(transparent)]
+    CompressionError(#[from] CompressionError),
+
+    #[error("Bind values are invalid: {0}")]
+    InvalidBindings(String),
+
     #[error("S3 bucket path in PUT request is invalid: `{0}`")]
     InvalidBucketPath(String),
 
@@ -76,8 +223,23 @@ pub enum SnowflakeApiError {
     #[error(transparent)]
     TokioTaskJoinError(#[from] tokio::task::JoinError),
 
-    #[error("Snowflake API error. Code: `{0}`. Message: `{1}`")]
-    ApiError(String, String),
+    #[error("Snowflake API error. Code: `{code}`. Message: `{message}`{}", query_id_suffix(.query_id.as_deref()))]
+    ApiError {
+        code: String,
+        message: String,
+        /// Id of the failed query to look it up in the query history, when the server reported it
+        query_id: Option<String>,
+    },
+
+    #[error("Statement failed to compile. Code: `{code}`. Message: `{message}`{}", query_id_suffix(.query_id.as_deref()))]
+    CompilationError {
+        code: String,
+        message: String,
+        line: Option<u32>,
+        position: Option<u32>,
+        /// Id of the failed query, see [`SnowflakeApiError::ApiError`]
+        query_id: Option<String>,
+    },
 
     #[error("Snowflake API empty response could mean that query wasn't executed correctly or API call was faulty")]
     EmptyResponse,
@@ -85,12 +247,66 @@ pub enum SnowflakeApiError {
     #[error("No usable rowsets were included in the response")]
     BrokenResponse,
 
+    #[error("Query handle `{0}` belongs to another account or has malformed id")]
+    InvalidQueryHandle(String),
+
+    #[error("Result of the query `{0}` has expired")]
+    ResultExpired(String),
+
+    #[error("Query `{0}` doesn't exist or its status has expired")]
+    QueryNotFound(String),
+
+    #[error("Gave up waiting for the result of the query `{0}`")]
+    ResultPollTimeout(String),
+
+    #[error("Query was cancelled by the caller")]
+    Cancelled,
+
+    #[error("No query has been executed by this client yet")]
+    NoPreviousQuery,
+
+    #[error("Shutdown didn't finish within the deadline")]
+    ShutdownTimeout,
+
+    #[error("Options can't be used together: {0}")]
+    InvalidExecOptions(String),
+
+    #[error("Can't quote {value:?}: {reason}")]
+    Unquotable { value: String, reason: &'static str },
+
+    #[error("Identifier `{0}` must be quoted")]
+    InvalidIdentifier(String),
+
+    #[error("Public key is invalid: {0}")]
+    InvalidPublicKey(String),
+
+    #[error("User `{0}` already has two public keys, complete or roll back the previous rotation")]
+    KeyRotationInProgress(String),
+
+    #[error("User `{0}` has no key rotation in progress")]
+    NoKeyRotation(String),
+
+    #[error("Transaction is already open, nested transactions are not supported")]
+    TransactionInProgress,
+
+    #[error("Connection `{0}` is not registered")]
+    UnknownConnection(String),
+
     #[error("Following feature is not implemented yet: {0}")]
     Unimplemented(String),
 
     #[error("Unexpected API response")]
     UnexpectedResponse,
 
+    #[error("Statement {index} failed: {source}")]
+    StatementError {
+        index: usize,
+        source: Box<SnowflakeApiError>,
+    },
+
+    #[error("Expected {expected} statements to be run, server has run {actual}")]
+    StatementCountMismatch { expected: usize, actual: usize },
+
     #[error(transparent)]
     GlobPatternError(#[from] glob::PatternError),
 
@@ -114,6 +330,7 @@ impl Display for JsonResult {
 }
 
 /// Based on the [`ExecResponseRowType`]
+#[derive(Debug)]
 pub struct FieldSchema {
     pub name: String,
     // todo: is it a good idea to expose internal response struct to the user?
@@ -144,6 +361,34 @@ pub enum QueryResult {
     Empty,
 }
 
+/// Query result along with the id of the statement which has produced it,
+/// the id could be used to find the query in the history or in the web interface
+pub struct QueryResultWithId {
+    pub query_id: String,
+    pub result: QueryResult,
+    /// Result columns, also for [`QueryResult::Empty`] results
+    pub columns: Vec<ColumnDescription>,
+}
+
+/// Raw query result along with the id of the statement and the result columns,
+/// see [`QueryResultWithId`]
+pub struct RawQueryResultWithId {
+    pub query_id: String,
+    pub result: RawQueryResult,
+    /// Result columns, also for [`RawQueryResult::Empty`] results
+    pub columns: Vec<ColumnDescription>,
+}
+
+impl RawQueryResultWithId {
+    pub fn deserialize_arrow(self) -> Result<QueryResultWithId, ArrowError> {
+        Ok(QueryResultWithId {
+            query_id: self.query_id,
+            result: self.result.deserialize_arrow()?,
+            columns: self.columns,
+        })
+    }
+}
+
 /// Raw query result
 /// Can be transformed into [`QueryResult`]
 pub enum RawQueryResult {
@@ -182,6 +427,7 @@ impl RawQueryResult {
     }
 }
 
+#[derive(Clone)]
 pub struct AuthArgs {
     pub account_identifier: String,
     pub warehouse: Option<String>,
@@ -197,7 +443,10 @@ impl AuthArgs {
         let auth_type = if let Ok(password) = std::env::var("SNOWFLAKE_PASSWORD") {
             Ok(AuthType::Password(PasswordArgs { password }))
         } else if let Ok(private_key_pem) = std::env::var("SNOWFLAKE_PRIVATE_KEY") {
-            Ok(AuthType::Certificate(CertificateArgs { private_key_pem }))
+            Ok(AuthType::Certificate(CertificateArgs {
+                private_key_pem,
+                passphrase: std::env::var("SNOWFLAKE_PRIVATE_KEY_PASSPHRASE").ok(),
+            }))
         } else {
             Err(MissingEnvArgument(
                 "SNOWFLAKE_PASSWORD or SNOWFLAKE_PRIVATE_KEY".to_owned(),
@@ -218,28 +467,93 @@ impl AuthArgs {
     }
 }
 
+#[derive(Clone)]
 pub enum AuthType {
     Password(PasswordArgs),
     Certificate(CertificateArgs),
+    ExternalOAuth(ExternalOAuthArgs),
+    /// IdP-initiated SAML, user is taken from the identity provider credentials
+    ProgrammaticSso(ProgrammaticSsoAuth),
 }
 
+/// Access token issued by the identity provider configured as Snowflake External OAuth integration
+#[derive(Clone)]
+pub struct ExternalOAuthArgs {
+    pub token: String,
+    pub token_type: ExternalOAuthTokenType,
+}
+
+/// Identity provider which has issued the token, the token itself is validated by Snowflake.
+/// It's only logged, the login request is the same for all of them: Snowflake tells the issuer
+/// from the token and the External OAuth integration it matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExternalOAuthTokenType {
+    Okta,
+    AzureAd,
+    PingFederate,
+    Custom(String),
+}
+
+#[derive(Clone)]
 pub struct PasswordArgs {
     pub password: String,
 }
 
+#[derive(Clone)]
 pub struct CertificateArgs {
+    /// PKCS#1, PKCS#8 or encrypted PKCS#8 PEM
     pub private_key_pem: String,
+    /// Only needed for `ENCRYPTED PRIVATE KEY`
+    pub passphrase: Option<String>,
 }
 
 #[must_use]
 pub struct SnowflakeApiBuilder {
     pub auth: AuthArgs,
     client: Option<ClientWithMiddleware>,
+    secondary_roles: Option<SecondaryRoles>,
+    session_parameters: Option<SessionParameters>,
+    idle_timeout: Option<Duration>,
+    result_poll_timeout: Option<Duration>,
+    query_retry_policy: Option<QueryRetryPolicy>,
+    mfa_handler: Option<Arc<dyn MfaHandler>>,
+    request_compression: bool,
+    max_concurrent_queries: Option<NonZeroUsize>,
+    user_agent: Option<String>,
+}
+
+/// Builder without any of the fields set, fill them in with the `with_*` methods
+impl Default for SnowflakeApiBuilder {
+    fn default() -> Self {
+        Self::new(AuthArgs {
+            account_identifier: String::new(),
+            warehouse: None,
+            database: None,
+            schema: None,
+            username: String::new(),
+            role: None,
+            auth_type: AuthType::Password(PasswordArgs {
+                password: String::new(),
+            }),
+        })
+    }
 }
 
 impl SnowflakeApiBuilder {
     pub fn new(auth: AuthArgs) -> Self {
-        Self { auth, client: None }
+        Self {
+            auth,
+            client: None,
+            secondary_roles: None,
+            session_parameters: None,
+            idle_timeout: None,
+            result_poll_timeout: None,
+            query_retry_policy: None,
+            mfa_handler: None,
+            request_compression: true,
+            max_concurrent_queries: None,
+            user_agent: None,
+        }
     }
 
     pub fn with_client(mut self, client: ClientWithMiddleware) -> Self {
@@ -247,11 +561,179 @@ impl SnowflakeApiBuilder {
         self
     }
 
-    pub fn build(self) -> Result<SnowflakeApi, SnowflakeApiError> {
+    /// Account identifier, eg `xy12345.us-east-2.aws` or `myorg-myaccount`
+    pub fn with_account(mut self, account_identifier: &str) -> Self {
+        self.auth.account_identifier = account_identifier.to_string();
+        self
+    }
+
+    pub fn with_user(mut self, username: &str) -> Self {
+        self.auth.username = username.to_string();
+        self
+    }
+
+    /// Use password authentication
+    pub fn with_password(mut self, password: &str) -> Self {
+        self.auth.auth_type = AuthType::Password(PasswordArgs {
+            password: password.to_string(),
+        });
+        self
+    }
+
+    /// Use key-pair authentication, passphrase is only needed for the encrypted key
+    pub fn with_private_key(mut self, private_key_pem: &str, passphrase: Option<&str>) -> Self {
+        self.auth.auth_type = AuthType::Certificate(CertificateArgs {
+            private_key_pem: private_key_pem.to_string(),
+            passphrase: passphrase.map(ToString::to_string),
+        });
+        self
+    }
+
+    /// Use External OAuth, eg Okta or Azure AD access token
+    pub fn with_external_oauth(
+        mut self,
+        token: impl Into<String>,
+        token_type: ExternalOAuthTokenType,
+    ) -> Self {
+        self.auth.auth_type = AuthType::ExternalOAuth(ExternalOAuthArgs {
+            token: token.into(),
+            token_type,
+        });
+        self
+    }
+
+    pub fn with_authenticator(mut self, auth_type: AuthType) -> Self {
+        self.auth.auth_type = auth_type;
+        self
+    }
+
+    pub fn with_warehouse(mut self, warehouse: &str) -> Self {
+        self.auth.warehouse = Some(warehouse.to_string());
+        self
+    }
+
+    pub fn with_database(mut self, database: &str) -> Self {
+        self.auth.database = Some(database.to_string());
+        self
+    }
+
+    pub fn with_schema(mut self, schema: &str) -> Self {
+        self.auth.schema = Some(schema.to_string());
+        self
+    }
+
+    pub fn with_role(mut self, role: &str) -> Self {
+        self.auth.role = Some(role.to_string());
+        self
+    }
+
+    /// Check that the required fields are set and the account identifier looks valid
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        AccountIdentifier::parse(&self.auth.account_identifier)?;
+
+        // with OAuth the user is taken from the token, with SSO from the IdP credentials
+        if self.auth.username.is_empty()
+            && !matches!(
+                self.auth.auth_type,
+                AuthType::ExternalOAuth(_) | AuthType::ProgrammaticSso(_)
+            )
+        {
+            return Err(ConfigError::MissingField("username"));
+        }
+
+        match &self.auth.auth_type {
+            AuthType::ExternalOAuth(args) if args.token.is_empty() => {
+                Err(ConfigError::MissingField("token"))
+            }
+            AuthType::Password(args) if args.password.is_empty() => {
+                Err(ConfigError::MissingField("password"))
+            }
+            AuthType::Certificate(args) if args.private_key_pem.is_empty() => {
+                Err(ConfigError::MissingField("private_key_pem"))
+            }
+            AuthType::ProgrammaticSso(args) if args.username.is_empty() => {
+                Err(ConfigError::MissingField("username"))
+            }
+            AuthType::ProgrammaticSso(args) if args.password.is_empty() => {
+                Err(ConfigError::MissingField("password"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Issue `USE SECONDARY ROLES` right after login, including any automatic re-login
+    pub fn with_secondary_roles(mut self, secondary_roles: SecondaryRoles) -> Self {
+        self.secondary_roles = Some(secondary_roles);
+        self
+    }
+
+    /// User agent of the HTTP requests, eg `my-etl-tool/2.3.1`, so the traffic of the tool
+    /// can be told apart. Ignored with a custom client, see [`SnowflakeApiBuilder::with_client`]
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Gzip large request bodies, eg statements with long SQL text, enabled by default
+    pub fn with_request_compression(mut self, request_compression: bool) -> Self {
+        self.request_compression = request_compression;
+        self
+    }
+
+    /// Set session parameters at login instead of running `ALTER SESSION` after it,
+    /// see [`SessionParameters::builder`]
+    pub fn with_session_parameters(mut self, session_parameters: SessionParameters) -> Self {
+        self.session_parameters = Some(session_parameters);
+        self
+    }
+
+    /// Validate the session with a heartbeat before running a query if it was idle for this long,
+    /// defaults to slightly less than the 4 hours Snowflake keeps idle sessions for
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Stop waiting for the result of the long running query after this long,
+    /// defaults to 48 hours which is the default statement timeout of the server
+    pub fn with_result_poll_timeout(mut self, result_poll_timeout: Duration) -> Self {
+        self.result_poll_timeout = Some(result_poll_timeout);
+        self
+    }
+
+    /// Limit the number of statements running at once, the rest wait in FIFO order.
+    /// Server rejects too many concurrent statements of the same session, unlimited by default
+    pub fn with_max_concurrent_queries(mut self, max_concurrent_queries: NonZeroUsize) -> Self {
+        self.max_concurrent_queries = Some(max_concurrent_queries);
+        self
+    }
+
+    /// Run the statements failed with transient Snowflake errors again, disabled by default
+    pub fn with_query_retry_policy(mut self, query_retry_policy: QueryRetryPolicy) -> Self {
+        self.query_retry_policy = Some(query_retry_policy);
+        self
+    }
+
+    /// Provide the Duo passcode or approve the push when password login requires MFA,
+    /// see [`ConsoleMfaHandler`] for command line tools
+    pub fn with_mfa_handler(mut self, mfa_handler: Arc<dyn MfaHandler>) -> Self {
+        self.mfa_handler = Some(mfa_handler);
+        self
+    }
+
+    pub fn build(mut self) -> Result<SnowflakeApi, SnowflakeApiError> {
+        self.validate()?;
+        self.auth.account_identifier =
+            AccountIdentifier::parse(&self.auth.account_identifier)?.to_string();
+
         let connection = match self.client {
-            Some(client) => Arc::new(Connection::new_with_middware(client)),
-            None => Arc::new(Connection::new()?),
+            Some(client) => Connection::new_with_middware(client),
+            None => match &self.user_agent {
+                Some(user_agent) => Connection::with_user_agent(user_agent)?,
+                None => Connection::new()?,
+            },
         };
+        let connection = Arc::new(connection.with_request_compression(self.request_compression));
 
         let session = match self.auth.auth_type {
             AuthType::Password(args) => Session::password_auth(
@@ -264,33 +746,109 @@ impl SnowflakeApiBuilder {
                 self.auth.role.as_deref(),
                 &args.password,
             ),
-            AuthType::Certificate(args) => Session::cert_auth(
+            AuthType::Certificate(args) => {
+                let session = Session::cert_auth(
+                    Arc::clone(&connection),
+                    &self.auth.account_identifier,
+                    self.auth.warehouse.as_deref(),
+                    self.auth.database.as_deref(),
+                    self.auth.schema.as_deref(),
+                    &self.auth.username,
+                    self.auth.role.as_deref(),
+                    &args.private_key_pem,
+                );
+                match args.passphrase {
+                    Some(passphrase) => session.with_private_key_passphrase(&passphrase),
+                    None => session,
+                }
+            }
+            AuthType::ExternalOAuth(args) => {
+                log::debug!("Using {:?} External OAuth token", args.token_type);
+                Session::oauth_auth(
+                    Arc::clone(&connection),
+                    &self.auth.account_identifier,
+                    self.auth.warehouse.as_deref(),
+                    self.auth.database.as_deref(),
+                    self.auth.schema.as_deref(),
+                    &self.auth.username,
+                    self.auth.role.as_deref(),
+                    &args.token,
+                )
+            }
+            AuthType::ProgrammaticSso(args) => Session::sso_auth(
                 Arc::clone(&connection),
                 &self.auth.account_identifier,
                 self.auth.warehouse.as_deref(),
                 self.auth.database.as_deref(),
                 self.auth.schema.as_deref(),
-                &self.auth.username,
                 self.auth.role.as_deref(),
-                &args.private_key_pem,
+                args,
             ),
         };
+        let session = match self.secondary_roles {
+            Some(secondary_roles) => session.with_secondary_roles(secondary_roles),
+            None => session,
+        };
+        let session = match self.session_parameters {
+            Some(session_parameters) => session.with_session_parameters(session_parameters),
+            None => session,
+        };
+        let session = match self.idle_timeout {
+            Some(idle_timeout) => session.with_idle_timeout(idle_timeout),
+            None => session,
+        };
+        let session = match self.mfa_handler {
+            Some(mfa_handler) => session.with_mfa_handler(mfa_handler),
+            None => session,
+        };
 
         let account_identifier = self.auth.account_identifier.to_uppercase();
 
-        Ok(SnowflakeApi::new(
-            Arc::clone(&connection),
-            session,
-            account_identifier,
-        ))
+        let mut api = SnowflakeApi::new(Arc::clone(&connection), session, account_identifier);
+        if let Some(result_poll_timeout) = self.result_poll_timeout {
+            api.result_poll_timeout = result_poll_timeout;
+        }
+        api.query_retry_policy = self.query_retry_policy;
+        api.query_limiter = self
+            .max_concurrent_queries
+            .map(|limit| tokio::sync::Semaphore::new(limit.get()));
+        Ok(api)
+    }
+}
+
+/// Number of statements expected in the multi-statement request, the request is rejected
+/// by the server if the script has a different number of statements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultiStatementCount {
+    Exact(usize),
+    Any,
+}
+
+impl MultiStatementCount {
+    /// `MULTI_STATEMENT_COUNT` value, `0` allows any number of statements
+    pub(crate) fn as_parameter(self) -> usize {
+        match self {
+            Self::Exact(n) => n,
+            Self::Any => 0,
+        }
     }
 }
 
 /// Snowflake API, keeps connection pool and manages session for you
 pub struct SnowflakeApi {
     connection: Arc<Connection>,
-    session: Session,
+    session: Arc<Session>,
     account_identifier: String,
+    /// SQL text of the requests waiting for the response by their request id, used to abort them
+    in_flight: std::sync::Mutex<HashMap<String, String>>,
+    last_query_id: std::sync::Mutex<Option<String>>,
+    last_warnings: std::sync::Mutex<Vec<ServerMessage>>,
+    result_poll_timeout: Duration,
+    query_retry_policy: Option<QueryRetryPolicy>,
+    /// Permits for the statements of the session, see [`SnowflakeApiBuilder::with_max_concurrent_queries`]
+    query_limiter: Option<tokio::sync::Semaphore>,
+    /// Rollback of the dropped [`Transaction`], awaited before the next transaction begins
+    abandoned_rollback: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>>,
 }
 
 impl SnowflakeApi {
@@ -298,8 +856,15 @@ impl SnowflakeApi {
     pub fn new(connection: Arc<Connection>, session: Session, account_identifier: String) -> Self {
         Self {
             connection,
-            session,
+            session: Arc::new(session),
             account_identifier,
+            in_flight: std::sync::Mutex::default(),
+            last_query_id: std::sync::Mutex::default(),
+            last_warnings: std::sync::Mutex::default(),
+            result_poll_timeout: DEFAULT_RESULT_POLL_TIMEOUT,
+            query_retry_policy: None,
+            query_limiter: None,
+            abandoned_rollback: std::sync::Mutex::default(),
         }
     }
     /// Initialize object with password auth. Authentication happens on the first request.
@@ -368,10 +933,76 @@ impl SnowflakeApi {
         SnowflakeApiBuilder::new(AuthArgs::from_env()?).build()
     }
 
+    /// Current value of the session parameter as announced by the server, eg `TIMEZONE`.
+    /// Names are case-insensitive, values are kept as raw JSON.
+    pub fn session_parameter(&self, name: &str) -> Option<serde_json::Value> {
+        self.session.parameters().get(name)
+    }
+
+    /// Timezone of the session as announced by the server, `TIMESTAMP_LTZ` values are shown in it
+    pub fn session_timezone(&self) -> Option<String> {
+        self.session.parameters().timezone()
+    }
+
+    /// Secondary roles which are activated for every new session
+    pub fn secondary_roles(&self) -> Option<&SecondaryRoles> {
+        self.session.secondary_roles()
+    }
+
+    /// Switch autocommit mode of the session. The mode is re-applied if the session
+    /// has to be recreated, eg after the master token expiry, so batches keep their semantics.
+    pub async fn set_autocommit(&self, autocommit: bool) -> Result<(), SnowflakeApiError> {
+        self.execute(&session::autocommit_statement(autocommit))
+            .await?;
+        self.session.record_autocommit(autocommit);
+        Ok(())
+    }
+
+    /// Autocommit mode set with [`SnowflakeApi::set_autocommit`], `None` means the server default
+    pub fn autocommit(&self) -> Option<bool> {
+        self.session.autocommit()
+    }
+
+    /// Authorization header of the current session, token is renewed first if needed.
+    /// Allows calling REST endpoints which aren't wrapped by the library within the same session.
+    pub async fn authorization_header(&self) -> Result<HeaderValue, SnowflakeApiError> {
+        let parts = self.session.get_token().await?;
+        let mut header = HeaderValue::from_str(&parts.session_token_auth_header)
+            .map_err(ConnectionError::from)?;
+        header.set_sensitive(true);
+        Ok(header)
+    }
+
+    /// Scheme and host used for the REST API requests of this account
+    pub fn base_rest_url(&self) -> Result<Url, SnowflakeApiError> {
+        Ok(Connection::base_rest_url(&self.account_identifier)?)
+    }
+
+    /// Switch the warehouse of the session, the name is case-sensitive, see [`quote::quote_ident`]
+    pub async fn set_warehouse(&self, name: &str) -> Result<(), SnowflakeApiError> {
+        self.execute(&SqlBuilder::new("USE WAREHOUSE ").ident(name)?.build())
+            .await?;
+        Ok(())
+    }
+
+    /// Switch the database of the session, the name is case-sensitive, see [`quote::quote_ident`]
+    pub async fn set_database(&self, name: &str) -> Result<(), SnowflakeApiError> {
+        self.execute(&SqlBuilder::new("USE DATABASE ").ident(name)?.build())
+            .await?;
+        Ok(())
+    }
+
+    /// Switch the schema of the session, the name is case-sensitive, see [`quote::quote_ident`]
+    pub async fn set_schema(&self, name: &str) -> Result<(), SnowflakeApiError> {
+        self.execute(&SqlBuilder::new("USE SCHEMA ").ident(name)?.build())
+            .await?;
+        Ok(())
+    }
+
     /// Closes the current session, this is necessary to clean up temporary objects (tables, functions, etc)
     /// which are Snowflake session dependent.
     /// If another request is made the new session will be initiated.
-    pub async fn close_session(&mut self) -> Result<(), SnowflakeApiError> {
+    pub async fn close_session(&self) -> Result<(), SnowflakeApiError> {
         self.session.close().await?;
         Ok(())
     }
@@ -379,103 +1010,702 @@ impl SnowflakeApi {
     /// Execute a single query against API.
     /// If statement is PUT, then file will be uploaded to the Snowflake-managed storage
     pub async fn exec(&self, sql: &str) -> Result<QueryResult, SnowflakeApiError> {
-        let raw = self.exec_raw(sql).await?;
-        let res = raw.deserialize_arrow()?;
-        Ok(res)
+        self.exec_with_options(sql, &ExecOptions::default()).await
+    }
+
+    /// Same as [`SnowflakeApi::exec`], along with the query id of the statement.
+    /// PUT statements don't have a query id and should be run with [`SnowflakeApi::exec`].
+    pub async fn exec_with_query_id(
+        &self,
+        sql: &str,
+    ) -> Result<QueryResultWithId, SnowflakeApiError> {
+        Ok(self
+            .exec_raw_with_query_id(sql)
+            .await?
+            .deserialize_arrow()?)
+    }
+
+    /// Same as [`SnowflakeApi::exec_with_query_id`], the Arrow result is left undecoded
+    pub async fn exec_raw_with_query_id(
+        &self,
+        sql: &str,
+    ) -> Result<RawQueryResultWithId, SnowflakeApiError> {
+        let resp = self
+            .run_sql::<ExecResponse>(sql, QueryType::ArrowQuery)
+            .await?;
+        self.raw_query_result_with_id(query_response(resp)?).await
+    }
+
+    /// Sequence id of the last request, assigned automatically and unique within the session
+    pub fn current_sequence_id(&self) -> u64 {
+        self.session.sequence_id()
+    }
+
+    /// Id of the most recent statement sent by this client, including the failed ones.
+    /// With concurrent queries it's the one which has finished last.
+    pub fn last_query_id(&self) -> Option<String> {
+        self.last_query_id.lock().unwrap().clone()
+    }
+
+    /// Warnings and info messages of the most recent statement, empty if there were none.
+    /// They are also logged, warnings at `warn` level.
+    pub fn last_warnings(&self) -> Vec<ServerMessage> {
+        self.last_warnings.lock().unwrap().clone()
+    }
+
+    /// Execute a single query with the per-query options applied, see [`ExecOptions`].
+    /// Conflicting options are reported as [`SnowflakeApiError::InvalidExecOptions`]
+    /// before anything is sent.
+    pub async fn exec_with_options(
+        &self,
+        sql: &str,
+        options: &ExecOptions,
+    ) -> Result<QueryResult, SnowflakeApiError> {
+        Ok(self
+            .exec_with_options_and_query_id(sql, options)
+            .await?
+            .result)
+    }
+
+    /// Same as [`SnowflakeApi::exec_with_options`], along with the query id of the statement
+    /// and the result columns. For multi-statement requests they are of the last statement.
+    /// PUT and GET statements don't have a query id, it's empty for them along with the columns.
+    pub async fn exec_with_options_and_query_id(
+        &self,
+        sql: &str,
+        options: &ExecOptions,
+    ) -> Result<QueryResultWithId, SnowflakeApiError> {
+        let is_file_transfer = StatementKind::of(sql).is_file_transfer();
+        options.validate(is_file_transfer)?;
+
+        let exec = async {
+            if is_file_transfer {
+                return Ok(QueryResultWithId {
+                    query_id: String::new(),
+                    result: self.exec_raw(sql).await?.deserialize_arrow()?,
+                    columns: vec![],
+                });
+            }
+            self.exec_request_with_options(sql, options).await
+        };
+        match &options.cancellation_token {
+            Some(token) => self.run_cancellable(token, exec).await,
+            None => exec.await,
+        }
+    }
+
+    async fn exec_request_with_options(
+        &self,
+        sql: &str,
+        options: &ExecOptions,
+    ) -> Result<QueryResultWithId, SnowflakeApiError> {
+        let format = options.result_format.unwrap_or(ResultFormat::Arrow);
+        let request = ExecRequest {
+            parameters: options.request_parameters(),
+            bindings: bind::positional_bindings(&options.binds),
+            is_internal: options.internal,
+            ..ExecRequest::new(sql)
+        };
+        let resp = self
+            .run_exec_request::<ExecResponse>(request, format.query_type())
+            .await?;
+        let resp = multi_statement_response(resp)?;
+
+        // parent of the multi-statement request only carries the ids of its statements
+        if let Some(result_ids) = resp.data.result_ids.as_deref() {
+            let result_ids = split_result_ids(result_ids);
+            let Some(index) = result_ids.len().checked_sub(1) else {
+                return Ok(QueryResultWithId {
+                    query_id: resp.data.query_id,
+                    result: QueryResult::Empty,
+                    columns: vec![],
+                });
+            };
+            return self
+                .fetch_query_result_with_id(result_ids[index])
+                .await
+                .and_then(|raw| Ok(raw.deserialize_arrow()?))
+                .map_err(|e| SnowflakeApiError::StatementError {
+                    index,
+                    source: Box::new(e),
+                });
+        }
+        let raw = self.raw_query_result_with_id(resp).await?;
+        Ok(raw.deserialize_arrow()?)
+    }
+
+    /// Execute a single statement with `?` placeholders replaced by the bind values in order.
+    /// Values are sent separately from the SQL text, so they can't alter the statement.
+    pub async fn exec_with_binds(
+        &self,
+        sql: &str,
+        params: &[BindValue],
+    ) -> Result<QueryResult, SnowflakeApiError> {
+        let request = ExecRequest {
+            bindings: bind::positional_bindings(params),
+            ..ExecRequest::new(sql)
+        };
+        let resp = self
+            .run_exec_request::<ExecResponse>(request, QueryType::ArrowQuery)
+            .await?;
+        let raw = self.raw_query_result(query_response(resp)?).await?;
+        Ok(raw.deserialize_arrow()?)
+    }
+
+    /// Execute the DML statement once for every row of bind values, eg `INSERT INTO t VALUES (?, ?)`,
+    /// returns the affected row counts of the batch. Small batches are sent inline as array bindings,
+    /// batches over `CLIENT_STAGE_ARRAY_BINDING_THRESHOLD` values are uploaded to the temporary stage.
+    pub async fn exec_batch(
+        &self,
+        sql: &str,
+        rows: Vec<Vec<BindValue>>,
+    ) -> Result<BatchResult, SnowflakeApiError> {
+        if rows.is_empty() {
+            return Ok(BatchResult::default());
+        }
+
+        let types = bind::column_types(&rows)?;
+        let threshold = self
+            .session
+            .parameters()
+            .stage_array_binding_threshold()
+            .unwrap_or(DEFAULT_STAGE_BINDING_THRESHOLD);
+        let values = (rows.len() * types.len()) as u64;
+
+        // threshold of 0 disables the stage binding
+        let request = if threshold > 0 && values >= threshold {
+            ExecRequest {
+                bind_stage: Some(self.upload_bind_stage(&rows).await?),
+                ..ExecRequest::new(sql)
+            }
+        } else {
+            ExecRequest {
+                bindings: bind::array_bindings(&rows, &types),
+                ..ExecRequest::new(sql)
+            }
+        };
+
+        let resp = self
+            .run_exec_request::<ExecResponse>(request, QueryType::JsonQuery)
+            .await?;
+        let resp = query_response(resp)?;
+
+        let stats = resp.data.stats.unwrap_or_default();
+        Ok(BatchResult {
+            rows: rows.len(),
+            rows_inserted: stats.num_rows_inserted,
+            rows_updated: stats.num_rows_updated,
+            rows_deleted: stats.num_rows_deleted,
+        })
+    }
+
+    /// Upload bind values as CSV to the temporary stage, returns the stage location
+    pub(crate) async fn upload_bind_stage(
+        &self,
+        rows: &[Vec<BindValue>],
+    ) -> Result<String, SnowflakeApiError> {
+        self.execute_internal(&format!(
+            "CREATE TEMPORARY STAGE IF NOT EXISTS {BIND_STAGE} \
+            FILE_FORMAT = (TYPE = CSV FIELD_OPTIONALLY_ENCLOSED_BY = '\"')"
+        ))
+        .await?;
+
+        let id = Uuid::new_v4();
+        let path = std::env::temp_dir().join(format!("snowflake-binds-{id}.csv"));
+        tokio::fs::write(&path, bind::to_csv(rows)).await?;
+        log::debug!("Uploading {} rows of bind values to the stage", rows.len());
+
+        let sql = SqlBuilder::new("PUT ")
+            .literal(&format!("file://{}", path.to_string_lossy()))
+            .sql(" ")
+            .stage_path(&format!("@{BIND_STAGE}/{id}"))?
+            .build();
+        let res = self.exec_put(&sql).await;
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            log::warn!("Failed to remove bind values file {}: {e}", path.display());
+        }
+        res?;
+
+        Ok(format!("@{BIND_STAGE}/{id}"))
     }
 
     /// Executes a single query against API.
-    /// If statement is PUT, then file will be uploaded to the Snowflake-managed storage
+    /// If statement is PUT, then file will be uploaded to the Snowflake-managed storage,
+    /// GET downloads the files from the stage, use [`SnowflakeApi::exec_get`] for per-file results
     /// Returns raw bytes in the Arrow response
     pub async fn exec_raw(&self, sql: &str) -> Result<RawQueryResult, SnowflakeApiError> {
-        let put_re = Regex::new(r"(?i)^(?:/\*.*\*/\s*)*put\s+").unwrap();
-
         // put commands go through a different flow and result is side-effect
-        if put_re.is_match(sql) {
-            log::info!("Detected PUT query");
-            self.exec_put(sql).await.map(|()| RawQueryResult::Empty)
-        } else {
-            self.exec_arrow_raw(sql).await
+        let kind = StatementKind::of(sql);
+        if kind.is_file_transfer() {
+            match FileTransfer::parse(sql) {
+                Some(transfer) => log::info!(
+                    "Detected {kind:?} query of {} and {}",
+                    transfer.local,
+                    transfer.stage
+                ),
+                None => log::info!("Detected {kind:?} query"),
+            }
+        }
+        match kind {
+            StatementKind::Put => self.exec_put(sql).await.map(|()| RawQueryResult::Empty),
+            StatementKind::Get => self.exec_get(sql).await.map(|_| RawQueryResult::Empty),
+            StatementKind::Other => self.exec_arrow_raw(sql).await,
+        }
+    }
+
+    /// Execute a single SELECT query and decode the Arrow response into record batches.
+    /// Inline result page and all of the referenced chunks are decoded in order,
+    /// chunks are downloaded in parallel.
+    pub async fn query_arrow(&self, sql: &str) -> Result<Vec<RecordBatch>, SnowflakeApiError> {
+        match self.exec_arrow_raw(sql).await? {
+            RawQueryResult::Bytes(bytes) => Ok(RawQueryResult::flat_bytes_to_batches(bytes)?),
+            RawQueryResult::Empty => Ok(vec![]),
+            // non-SELECT statements are answered with JSON even if Arrow was requested
+            RawQueryResult::Json(_) => Err(SnowflakeApiError::UnexpectedResponse),
+        }
+    }
+
+    /// Same as [`SnowflakeApi::query_arrow`], `progress` is called with
+    /// `(chunks_completed, chunks_total)` as the result chunks are downloaded, eg to show a progress bar
+    pub async fn query_arrow_with_progress(
+        &self,
+        sql: &str,
+        progress: impl Fn(usize, usize) + Send + Sync,
+    ) -> Result<Vec<RecordBatch>, SnowflakeApiError> {
+        let resp = self
+            .run_sql::<ExecResponse>(sql, QueryType::ArrowQuery)
+            .await?;
+        match self
+            .raw_query_result_with_progress(query_response(resp)?, &progress)
+            .await?
+        {
+            RawQueryResult::Bytes(bytes) => Ok(RawQueryResult::flat_bytes_to_batches(bytes)?),
+            RawQueryResult::Empty => Ok(vec![]),
+            RawQueryResult::Json(_) => Err(SnowflakeApiError::UnexpectedResponse),
+        }
+    }
+
+    /// Execute a single DML or DDL statement and return the number of affected rows,
+    /// which is the sum of inserted, updated and deleted rows. DDL statements affect 0 rows.
+    pub async fn execute(&self, sql: &str) -> Result<u64, SnowflakeApiError> {
+        self.execute_request(ExecRequest::new(sql)).await
+    }
+
+    /// [`SnowflakeApi::execute`] of the statement issued by the library itself
+    pub(crate) async fn execute_internal(&self, sql: &str) -> Result<u64, SnowflakeApiError> {
+        self.execute_request(ExecRequest::internal(sql)).await
+    }
+
+    async fn execute_request(&self, request: ExecRequest) -> Result<u64, SnowflakeApiError> {
+        let resp = self
+            .run_exec_request::<ExecResponse>(request, QueryType::JsonQuery)
+            .await?;
+        let resp = query_response(resp)?;
+
+        Ok(resp.data.stats.map_or(0, |stats| {
+            stats.num_rows_inserted + stats.num_rows_updated + stats.num_rows_deleted
+        }))
+    }
+
+    /// Execute a single query and return its rows, result is requested in JSON format.
+    /// Chunked results are downloaded in parallel and concatenated in order.
+    pub async fn query(&self, sql: &str) -> Result<Rows, SnowflakeApiError> {
+        let resp = self
+            .run_sql::<ExecResponse>(sql, QueryType::JsonQuery)
+            .await?;
+        self.query_rows(query_response(resp)?).await
+    }
+
+    /// Execute a single query and deserialize its rows into structs, tuples or maps,
+    /// see [`Row::deserialize`]. Columns are matched to the fields case-insensitively,
+    /// extra columns are ignored and missing ones are reported by their field name.
+    /// The result is requested in Arrow format, JSON results are read the same way.
+    ///
+    /// Nullable columns need `Option<T>` fields, `DATE`, `TIME` and `TIMESTAMP_*` columns are read
+    /// into chrono types and `VARIANT`, `OBJECT` and `ARRAY` into `serde_json::Value` or any type
+    /// deserialized from their JSON. `NUMBER` with scale is read as `f64` by numeric fields,
+    /// `String` fields get its exact decimal text, eg for the decimal types parsed from strings.
+    /// With the `decimal` feature `rust_decimal::Decimal` fields get the exact value.
+    ///
+    /// ```no_run
+    /// # async fn run(api: &snowflake_api::SnowflakeApi) -> Result<(), snowflake_api::SnowflakeApiError> {
+    /// #[derive(serde::Deserialize)]
+    /// struct User {
+    ///     id: i64,
+    ///     name: Option<String>,
+    ///     created_at: chrono::DateTime<chrono::Utc>,
+    /// }
+    ///
+    /// let users: Vec<User> = api.query_as("SELECT id, name, created_at FROM users").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_as<T: serde::de::DeserializeOwned>(
+        &self,
+        sql: &str,
+    ) -> Result<Vec<T>, SnowflakeApiError> {
+        let resp = self
+            .run_sql::<ExecResponse>(sql, QueryType::ArrowQuery)
+            .await?;
+        let resp = query_response(resp)?;
+        let schema = resp.data.rowtype.iter().cloned().map(Into::into).collect();
+        let rows = match self.raw_query_result(resp).await? {
+            RawQueryResult::Bytes(bytes) => {
+                arrow_rows::batch_rows(&RawQueryResult::flat_bytes_to_batches(bytes)?, schema)?
+            }
+            RawQueryResult::Json(json) => {
+                Rows::new(serde_json::from_value(json.value)?, json.schema)
+            }
+            RawQueryResult::Empty => return Ok(vec![]),
+        };
+        Ok(rows
+            .map(|row| row.deserialize())
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// [`SnowflakeApi::query`] of the statement issued by the library itself
+    pub(crate) async fn query_internal(&self, sql: &str) -> Result<Rows, SnowflakeApiError> {
+        let resp = self
+            .run_exec_request::<ExecResponse>(ExecRequest::internal(sql), QueryType::JsonQuery)
+            .await?;
+        self.query_rows(query_response(resp)?).await
+    }
+
+    /// Rows of the JSON result, including the ones in chunks
+    pub(crate) async fn query_rows(
+        &self,
+        mut resp: QueryExecResponse,
+    ) -> Result<Rows, SnowflakeApiError> {
+        let schema = std::mem::take(&mut resp.data.rowtype)
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        let mut rows: Vec<Vec<serde_json::Value>> = match resp.data.rowset.take() {
+            Some(rowset) => serde_json::from_value(rowset)?,
+            None => vec![],
+        };
+
+        rows.append(&mut self.json_chunk_rows(&resp.data).await?);
+
+        Ok(Rows::new(rows, schema))
+    }
+
+    /// Download rows of the JSON result which aren't inlined in the response
+    async fn json_chunk_rows(
+        &self,
+        data: &QueryExecResponseData,
+    ) -> Result<Vec<Vec<serde_json::Value>>, SnowflakeApiError> {
+        let chunks = self
+            .connection
+            .get_chunks_parallel(
+                data.chunks.iter().map(|chunk| chunk.url.as_str()),
+                &data.chunk_headers,
+            )
+            .instrument(data.span.clone())
+            .await?;
+        let chunks = compression::decompress_chunks(chunks).await?;
+
+        let mut rows = vec![];
+        for chunk in chunks {
+            // JSON chunks are comma-separated row arrays without the enclosing brackets
+            let mut buf = Vec::with_capacity(chunk.len() + 2);
+            buf.push(b'[');
+            buf.extend_from_slice(&chunk);
+            buf.push(b']');
+            let mut chunk_rows: Vec<Vec<serde_json::Value>> = serde_json::from_slice(&buf)?;
+            rows.append(&mut chunk_rows);
         }
+        Ok(rows)
     }
 
     async fn exec_put(&self, sql: &str) -> Result<(), SnowflakeApiError> {
         let resp = self
             .run_sql::<ExecResponse>(sql, QueryType::JsonQuery)
             .await?;
-        log::debug!("Got PUT response: {:?}", resp);
+        log::debug!("Got PUT response: {resp:?}");
 
         match resp {
             ExecResponse::Query(_) => Err(SnowflakeApiError::UnexpectedResponse),
             ExecResponse::PutGet(pg) => put::put(pg).await,
-            ExecResponse::Error(e) => Err(SnowflakeApiError::ApiError(
-                e.data.error_code,
-                e.message.unwrap_or_default(),
-            )),
+            ExecResponse::Error(e) => Err(exec_error(e)),
+        }
+    }
+
+    /// Download the files from the stage, eg `GET @my_stage/data/ file:///tmp/ PATTERN='.*csv.gz'`.
+    /// Files which failed to download are reported in the result instead of failing the call.
+    pub async fn exec_get(&self, sql: &str) -> Result<Vec<GetFileResult>, SnowflakeApiError> {
+        let resp = self
+            .run_sql::<ExecResponse>(sql, QueryType::JsonQuery)
+            .await?;
+        log::debug!("Got GET response: {resp:?}");
+
+        match resp {
+            ExecResponse::PutGet(pg) if matches!(pg.data.command, CommandType::Download) => {
+                get::get(pg).await
+            }
+            ExecResponse::Query(_) | ExecResponse::PutGet(_) => {
+                Err(SnowflakeApiError::UnexpectedResponse)
+            }
+            ExecResponse::Error(e) => Err(exec_error(e)),
         }
     }
 
-    /// Useful for debugging to get the straight query response
-    #[cfg(debug_assertions)]
-    pub async fn exec_response(&mut self, sql: &str) -> Result<ExecResponse, SnowflakeApiError> {
+    /// Typed response of the query request, without downloading chunks or decoding Arrow.
+    /// Useful for debugging and for the statement types the client doesn't handle yet.
+    pub async fn exec_response(&self, sql: &str) -> Result<ExecResponse, SnowflakeApiError> {
         self.run_sql::<ExecResponse>(sql, QueryType::ArrowQuery)
             .await
     }
 
-    /// Useful for debugging to get raw JSON response
-    #[cfg(debug_assertions)]
-    pub async fn exec_json(&mut self, sql: &str) -> Result<serde_json::Value, SnowflakeApiError> {
+    /// Untouched JSON body of the query response, including the chunk URLs and the fields
+    /// the client doesn't know about. Failed statements are returned as is, not as errors.
+    pub async fn exec_json(&self, sql: &str) -> Result<serde_json::Value, SnowflakeApiError> {
         self.run_sql::<serde_json::Value>(sql, QueryType::JsonQuery)
             .await
     }
 
+    /// Execute multiple statements in a single request, results are returned in statement order.
+    /// Failure of the individual statement is reported as [`SnowflakeApiError::StatementError`]
+    /// with the index of the statement.
+    pub async fn execute_batch(
+        &self,
+        statements: &[&str],
+    ) -> Result<Vec<QueryResult>, SnowflakeApiError> {
+        if statements.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let sql = statements
+            .iter()
+            .map(|s| s.trim().trim_end_matches(';'))
+            .collect::<Vec<_>>()
+            .join(";\n");
+        self.exec_multi(&sql, MultiStatementCount::Exact(statements.len()))
+            .await
+    }
+
+    /// Execute the script of several `;` separated statements, the server does the splitting,
+    /// so semicolons inside string literals and `$$` blocks are handled correctly.
+    /// Results are returned in statement order, failure of the individual statement is reported
+    /// as [`SnowflakeApiError::StatementError`] with the index of the statement.
+    /// With [`MultiStatementCount::Exact`] a different number of results is reported as
+    /// [`SnowflakeApiError::StatementCountMismatch`].
+    pub async fn exec_multi(
+        &self,
+        sql: &str,
+        count: MultiStatementCount,
+    ) -> Result<Vec<QueryResult>, SnowflakeApiError> {
+        let request = ExecRequest {
+            parameters: ExecRequestParameters {
+                multi_statement_count: Some(count.as_parameter()),
+                ..ExecRequestParameters::default()
+            },
+            ..ExecRequest::new(sql)
+        };
+        let resp = self
+            .run_exec_request::<ExecResponse>(request, QueryType::ArrowQuery)
+            .await?;
+        let resp = multi_statement_response(resp)?;
+
+        // single statement script is executed as a regular query
+        let Some(result_ids) = resp.data.result_ids.clone() else {
+            return Ok(vec![self
+                .raw_query_result(resp)
+                .await?
+                .deserialize_arrow()?]);
+        };
+        // parent statement only carries the ids of child statements, each has its own result
+        let result_ids = split_result_ids(&result_ids);
+        log::debug!(
+            "Multi-statement request has run {} statements",
+            result_ids.len()
+        );
+        if let MultiStatementCount::Exact(expected) = count {
+            if result_ids.len() != expected {
+                return Err(SnowflakeApiError::StatementCountMismatch {
+                    expected,
+                    actual: result_ids.len(),
+                });
+            }
+        }
+
+        let mut results = Vec::with_capacity(result_ids.len());
+        for (index, query_id) in result_ids.into_iter().enumerate() {
+            let result = self
+                .fetch_query_result(query_id)
+                .await
+                .and_then(|raw| Ok(raw.deserialize_arrow()?))
+                .map_err(|e| SnowflakeApiError::StatementError {
+                    index,
+                    source: Box::new(e),
+                })?;
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+
+    /// Fetch result of the previously executed query without running it again,
+    /// eg the one submitted with [`SnowflakeApi::exec_async`] or found in the query history.
+    /// Results are kept by Snowflake for 24 hours, older ones are reported as
+    /// [`SnowflakeApiError::ResultExpired`].
+    pub async fn fetch_result(&self, query_id: &str) -> Result<QueryResult, SnowflakeApiError> {
+        let raw = self
+            .fetch_query_result(query_id)
+            .await
+            .map_err(|e| match e {
+                SnowflakeApiError::ApiError { code, .. } if code == RESULT_EXPIRED => {
+                    SnowflakeApiError::ResultExpired(query_id.to_string())
+                }
+                SnowflakeApiError::ApiError { code, .. } if code == QUERY_NOT_FOUND => {
+                    SnowflakeApiError::QueryNotFound(query_id.to_string())
+                }
+                e => e,
+            })?;
+        Ok(raw.deserialize_arrow()?)
+    }
+
+    /// Run a query over the result of the previous one on the server, eg to aggregate it
+    /// instead of downloading it again. `projection_sql` refers to that result as `previous`,
+    /// eg `SELECT COUNT(*) FROM previous WHERE amount > 0`, without it all rows are returned.
+    pub async fn result_scan(
+        &self,
+        query_id: &str,
+        projection_sql: Option<&str>,
+    ) -> Result<QueryResult, SnowflakeApiError> {
+        // query ids are UUIDs, anything else is rejected before it gets into the SQL
+        if Uuid::parse_str(query_id).is_err() {
+            return Err(SnowflakeApiError::InvalidQueryHandle(query_id.to_string()));
+        }
+        let scan = format!("SELECT * FROM TABLE(RESULT_SCAN('{query_id}'))");
+        let sql = match projection_sql {
+            Some(projection) => format!("WITH previous AS ({scan}) {projection}"),
+            None => scan,
+        };
+        self.exec(&sql).await
+    }
+
+    /// [`SnowflakeApi::result_scan`] of the most recent statement, see [`SnowflakeApi::last_query_id`]
+    pub async fn result_scan_last(&self) -> Result<QueryResult, SnowflakeApiError> {
+        let query_id = self
+            .last_query_id()
+            .ok_or(SnowflakeApiError::NoPreviousQuery)?;
+        self.result_scan(&query_id, None).await
+    }
+
+    /// Fetch result of the previously executed query by its id
+    pub(crate) async fn fetch_query_result(
+        &self,
+        query_id: &str,
+    ) -> Result<RawQueryResult, SnowflakeApiError> {
+        Ok(self.fetch_query_result_with_id(query_id).await?.result)
+    }
+
+    async fn fetch_query_result_with_id(
+        &self,
+        query_id: &str,
+    ) -> Result<RawQueryResultWithId, SnowflakeApiError> {
+        log::debug!("Fetching result of the query {query_id}");
+        let parts = self.session.get_token().await?;
+        let resp = self
+            .connection
+            .get_request::<ExecResponse>(
+                QueryType::QueryResult {
+                    query_id: query_id.to_string(),
+                },
+                &self.account_identifier,
+                &[],
+                Some(&parts.session_token_auth_header),
+            )
+            .await?;
+
+        self.raw_query_result_with_id(query_response(resp)?).await
+    }
+
     async fn exec_arrow_raw(&self, sql: &str) -> Result<RawQueryResult, SnowflakeApiError> {
         let resp = self
             .run_sql::<ExecResponse>(sql, QueryType::ArrowQuery)
             .await?;
-        log::debug!("Got query response: {:?}", resp);
-
-        let resp = match resp {
-            // processable response
-            ExecResponse::Query(qr) => Ok(qr),
-            ExecResponse::PutGet(_) => Err(SnowflakeApiError::UnexpectedResponse),
-            ExecResponse::Error(e) => Err(SnowflakeApiError::ApiError(
-                e.data.error_code,
-                e.message.unwrap_or_default(),
-            )),
-        }?;
+        log::debug!("Got query response: {resp:?}");
 
+        self.raw_query_result(query_response(resp)?).await
+    }
+
+    /// [`SnowflakeApi::raw_query_result`] along with the query id and the result columns
+    async fn raw_query_result_with_id(
+        &self,
+        resp: QueryExecResponse,
+    ) -> Result<RawQueryResultWithId, SnowflakeApiError> {
+        let query_id = resp.data.query_id.clone();
+        let columns = resp.data.rowtype.iter().cloned().map(Into::into).collect();
+        Ok(RawQueryResultWithId {
+            query_id,
+            result: self.raw_query_result(resp).await?,
+            columns,
+        })
+    }
+
+    /// Download the referenced chunks, if any
+    async fn raw_query_result(
+        &self,
+        resp: QueryExecResponse,
+    ) -> Result<RawQueryResult, SnowflakeApiError> {
+        self.raw_query_result_with_progress(resp, &|_, _| {}).await
+    }
+
+    async fn raw_query_result_with_progress(
+        &self,
+        mut resp: QueryExecResponse,
+        progress: &(dyn Fn(usize, usize) + Send + Sync),
+    ) -> Result<RawQueryResult, SnowflakeApiError> {
         // if response was empty, base64 data is empty string
         // todo: still return empty arrow batch with proper schema? (schema always included)
+        let is_json = resp.data.is_json();
         if resp.data.returned == 0 {
             log::debug!("Got response with 0 rows");
             Ok(RawQueryResult::Empty)
-        } else if let Some(value) = resp.data.rowset {
+        } else if is_json {
             log::debug!("Got JSON response");
-            // NOTE: json response could be chunked too. however, go clients should receive arrow by-default,
-            // unless user sets session variable to return json. This case was added for debugging and status
-            // information being passed through that fields.
+            // go clients should receive arrow by-default, unless user sets session variable
+            // to return json, or the result was produced by the JSON query and is fetched later
+            let mut value = resp
+                .data
+                .rowset
+                .take()
+                .unwrap_or_else(|| serde_json::Value::Array(vec![]));
+            if let serde_json::Value::Array(rows) = &mut value {
+                for row in self.json_chunk_rows(&resp.data).await? {
+                    rows.push(serde_json::Value::from(row));
+                }
+            }
             Ok(RawQueryResult::Json(JsonResult {
                 value,
                 schema: resp.data.rowtype.into_iter().map(Into::into).collect(),
             }))
         } else if let Some(base64) = resp.data.rowset_base64 {
-            // fixme: is it possible to give streaming interface?
-            let mut chunks = try_join_all(resp.data.chunks.iter().map(|chunk| {
-                self.connection
-                    .get_chunk(&chunk.url, &resp.data.chunk_headers)
-            }))
-            .await?;
-
-            // fixme: should base64 chunk go first?
-            // fixme: if response is chunked is it both base64 + chunks or just chunks?
+            // first page of the result is inlined, the rest (if any) is referenced by chunk urls
+            let mut res = vec![];
             if !base64.is_empty() {
                 log::debug!("Got base64 encoded response");
                 let bytes = Bytes::from(base64::engine::general_purpose::STANDARD.decode(base64)?);
-                chunks.push(bytes);
+                res.push(bytes);
             }
 
-            Ok(RawQueryResult::Bytes(chunks))
+            // fixme: is it possible to give streaming interface?
+            let chunks = self
+                .connection
+                .get_chunks_with_progress(
+                    resp.data.chunks.iter().map(|chunk| chunk.url.as_str()),
+                    &resp.data.chunk_headers,
+                    progress,
+                )
+                .instrument(resp.data.span.clone())
+                .await?;
+            log::debug!("Downloaded {} chunks", chunks.len());
+            let mut chunks = compression::decompress_chunks(chunks).await?;
+            res.append(&mut chunks);
+
+            Ok(RawQueryResult::Bytes(res))
         } else {
             Err(SnowflakeApiError::BrokenResponse)
         }
@@ -486,28 +1716,918 @@ impl SnowflakeApi {
         sql_text: &str,
         query_type: QueryType,
     ) -> Result<R, SnowflakeApiError> {
-        log::debug!("Executing: {}", sql_text);
+        self.run_exec_request(ExecRequest::new(sql_text), query_type)
+            .await
+    }
+
+    #[tracing::instrument(
+        name = "snowflake.query",
+        skip_all,
+        fields(
+            sql = %span_sql(&request.sql_text),
+            request_id = tracing::field::Empty,
+            query_id = tracing::field::Empty,
+            statement_type_id = tracing::field::Empty,
+            rows = tracing::field::Empty,
+            chunks = tracing::field::Empty,
+            duration_ms = tracing::field::Empty,
+            queue_ms = tracing::field::Empty,
+            timezone = tracing::field::Empty,
+        )
+    )]
+    pub(crate) async fn run_exec_request<R: serde::de::DeserializeOwned>(
+        &self,
+        request: ExecRequest,
+        query_type: QueryType,
+    ) -> Result<R, SnowflakeApiError> {
+        log::debug!("Executing: {}", redact_secrets(&request.sql_text));
+        let started = std::time::Instant::now();
+
+        let mut attempt = 1;
+        let body = loop {
+            let body = self.exec_once(&request, query_type.clone()).await?;
+            let delay = match &self.query_retry_policy {
+                Some(policy) => serde_json::from_slice::<ResponseEnvelope>(&body)?
+                    .error_code()
+                    .and_then(|code| {
+                        let delay = policy.retry_delay(&request.sql_text, code, attempt)?;
+                        Some((code.to_string(), delay))
+                    }),
+                None => None,
+            };
+            let Some((code, delay)) = delay else {
+                break body;
+            };
+
+            tracing::warn!(
+                code = code.as_str(),
+                attempt,
+                delay_ms = u64::try_from(delay.as_millis()).unwrap_or(u64::MAX),
+                "Query failed with retryable code, retrying"
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        };
+
+        let envelope = serde_json::from_slice::<ResponseEnvelope>(&body)?;
+        let query_id = envelope.query_id().map(ToString::to_string);
+        if let Some(query_id) = &query_id {
+            *self.last_query_id.lock().unwrap() = Some(query_id.clone());
+        }
+        if let Some(data) = envelope.data {
+            let warnings = ServerMessage::from_response(&data);
+            for warning in &warnings {
+                warning.log(query_id.as_deref());
+            }
+            *self.last_warnings.lock().unwrap() = warnings;
+            record_span_fields(&data, started.elapsed());
+
+            // server announces session parameter changes with every query response
+            self.session.parameters().apply(&data.parameters);
+            if let Some(timezone) = self.session.parameters().timezone() {
+                tracing::Span::current().record("timezone", timezone);
+            }
+            if let Some(context) = data.query_context {
+                self.session.query_context().merge(context);
+            }
+        } else {
+            self.last_warnings.lock().unwrap().clear();
+            record_span_duration(started.elapsed());
+        }
+
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Send the request and wait for the query to finish, returns the undecoded response body
+    async fn exec_once(
+        &self,
+        request: &ExecRequest,
+        query_type: QueryType,
+    ) -> Result<Bytes, SnowflakeApiError> {
+        // permit is held until the query finishes, dropping the future releases it
+        let _permit = self.acquire_query_permit().await;
+        // request stays registered while its result is polled, so it could still be aborted
+        let (mut body, mut _in_flight) = self.request_sql(request, query_type.clone()).await?;
+        // session could expire on the server before token validity runs out locally,
+        // in that case the token is renewed and request is replayed once
+        if serde_json::from_slice::<ResponseEnvelope>(&body)?
+            .code
+            .as_deref()
+            == Some(SESSION_EXPIRED)
+        {
+            log::info!("Session has expired, renewing token and replaying the request");
+            self.session.expire_session_token().await;
+            (body, _in_flight) = self.request_sql(request, query_type).await?;
+        }
+
+        // async requests are answered with in-progress code by design, handle is polled instead
+        if !request.async_exec {
+            body = self.poll_result(body).await?;
+        }
+        Ok(body)
+    }
+
+    /// Time spent waiting for the permit is recorded as `queue_ms` of the query span
+    async fn acquire_query_permit(&self) -> Option<tokio::sync::SemaphorePermit<'_>> {
+        let limiter = self.query_limiter.as_ref()?;
+        if let Ok(permit) = limiter.try_acquire() {
+            tracing::Span::current().record("queue_ms", 0);
+            return Some(permit);
+        }
+        let started = std::time::Instant::now();
+        // semaphore is never closed
+        let permit = limiter.acquire().await.ok()?;
+        let waited = started.elapsed();
+        log::debug!("Query waited {waited:?} for the concurrency limit");
+        tracing::Span::current().record(
+            "queue_ms",
+            u64::try_from(waited.as_millis()).unwrap_or(u64::MAX),
+        );
+        Some(permit)
+    }
+
+    /// Long running queries are answered with the in-progress code after ~45 seconds,
+    /// the result has to be polled by the URL from the response until the query finishes
+    async fn poll_result(&self, mut body: Bytes) -> Result<Bytes, SnowflakeApiError> {
+        let started = std::time::Instant::now();
+        let mut delay = RESULT_POLL_INITIAL_DELAY;
+        loop {
+            let envelope = serde_json::from_slice::<ResponseEnvelope>(&body)?;
+            let code = envelope.code.as_deref();
+            if code != Some(QUERY_IN_PROGRESS) && code != Some(QUERY_IN_PROGRESS_ASYNC) {
+                return Ok(body);
+            }
+            let Some(data) = envelope.data else {
+                return Ok(body);
+            };
+            let Some(path) = data.get_result_url.map(String::from) else {
+                return Ok(body);
+            };
+            let query_id = data.query_id.map(String::from).unwrap_or_default();
+            if started.elapsed() >= self.result_poll_timeout {
+                return Err(SnowflakeApiError::ResultPollTimeout(query_id));
+            }
+            CancellableQuery::record(|query| query.query_id = Some(query_id.clone()));
+
+            log::debug!("Query {query_id} is still running, polling its result");
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(RESULT_POLL_MAX_DELAY);
+
+            let parts = self.session.get_token().await?;
+            body = self
+                .connection
+                .request_body(
+                    QueryType::ResultUrl { path },
+                    &self.account_identifier,
+                    &[],
+                    Some(&parts.session_token_auth_header),
+                    (),
+                )
+                .await?;
+        }
+    }
 
+    async fn request_sql(
+        &self,
+        request: &ExecRequest,
+        query_type: QueryType,
+    ) -> Result<(Bytes, InFlightRequest<'_>), SnowflakeApiError> {
         let parts = self.session.get_token().await?;
 
         let body = ExecRequest {
-            sql_text: sql_text.to_string(),
-            async_exec: false,
             sequence_id: parts.sequence_id,
-            is_internal: false,
+            query_context_dto: self.session.query_context().to_dto(),
+            ..request.clone()
         };
 
+        let request_id = Uuid::new_v4().to_string();
+        let in_flight = InFlightRequest::register(&self.in_flight, &request_id, &body.sql_text);
+        tracing::Span::current().record("request_id", request_id.as_str());
+        CancellableQuery::record(|query| {
+            query.request_id = Some(request_id.clone());
+            query.sql_text.clone_from(&body.sql_text);
+        });
         let resp = self
             .connection
-            .request::<R>(
+            .request_body(
                 query_type,
                 &self.account_identifier,
-                &[],
+                &[("requestId", &request_id)],
                 Some(&parts.session_token_auth_header),
                 body,
             )
             .await?;
 
-        Ok(resp)
+        Ok((resp, in_flight))
+    }
+}
+
+/// Longer SQL text is cut in the `snowflake.query` span, it's there to recognize the statement
+const SPAN_SQL_LENGTH: usize = 200;
+
+/// Secrets are masked before the text is cut, so a cut clause can't leak its beginning
+fn span_sql(sql: &str) -> String {
+    let sql = redact_secrets(sql);
+    match sql.char_indices().nth(SPAN_SQL_LENGTH) {
+        Some((end, _)) => sql[..end].to_string(),
+        None => sql.into_owned(),
+    }
+}
+
+/// Fill in the fields of the `snowflake.query` span known once the response arrives
+fn record_span_fields(data: &ResponseEnvelopeData, duration: Duration) {
+    let span = tracing::Span::current();
+    if let Some(query_id) = data.query_id.as_deref() {
+        span.record("query_id", query_id);
+    }
+    if let Some(statement_type_id) = data.statement_type_id {
+        span.record("statement_type_id", statement_type_id);
+    }
+    if let Some(rows) = data.total {
+        span.record("rows", rows);
+    }
+    if let Some(chunks) = &data.chunks {
+        span.record("chunks", chunks.len());
+    }
+    record_span_duration(duration);
+}
+
+fn record_span_duration(duration: Duration) {
+    tracing::Span::current().record(
+        "duration_ms",
+        u64::try_from(duration.as_millis()).unwrap_or(u64::MAX),
+    );
+}
+
+/// Keeps the request registered for [`SnowflakeApi::cancel_all`] until its query finishes
+/// or the request future is dropped
+struct InFlightRequest<'a> {
+    registry: &'a std::sync::Mutex<HashMap<String, String>>,
+    request_id: String,
+}
+
+impl<'a> InFlightRequest<'a> {
+    fn register(
+        registry: &'a std::sync::Mutex<HashMap<String, String>>,
+        request_id: &str,
+        sql_text: &str,
+    ) -> Self {
+        registry
+            .lock()
+            .unwrap()
+            .insert(request_id.to_string(), sql_text.to_string());
+        Self {
+            registry,
+            request_id: request_id.to_string(),
+        }
+    }
+}
+
+impl Drop for InFlightRequest<'_> {
+    fn drop(&mut self) {
+        self.registry.lock().unwrap().remove(&self.request_id);
+    }
+}
+
+/// Extract processable query response, errors are mapped to [`SnowflakeApiError::ApiError`]
+pub(crate) fn query_response(resp: ExecResponse) -> Result<QueryExecResponse, SnowflakeApiError> {
+    match resp {
+        ExecResponse::Query(qr) => Ok(qr),
+        ExecResponse::PutGet(_) => Err(SnowflakeApiError::UnexpectedResponse),
+        ExecResponse::Error(e) => Err(exec_error(e)),
+    }
+}
+
+/// Ids of the child statements of the multi-statement request, in statement order
+fn split_result_ids(result_ids: &str) -> Vec<&str> {
+    result_ids.split(',').filter(|id| !id.is_empty()).collect()
+}
+
+/// Same as [`query_response`] for the parent of the multi-statement request. The statements
+/// after the failed one aren't run, so the failure is attributed to the statement following
+/// the completed ones when the server lists them.
+fn multi_statement_response(resp: ExecResponse) -> Result<QueryExecResponse, SnowflakeApiError> {
+    match resp {
+        ExecResponse::Error(mut e) => match e.data.result_ids.take() {
+            Some(completed) => Err(SnowflakeApiError::StatementError {
+                index: split_result_ids(&completed).len(),
+                source: Box::new(exec_error(e)),
+            }),
+            None => Err(exec_error(e)),
+        },
+        resp => query_response(resp),
+    }
+}
+
+/// Error of the failed statement with the query id to look it up in the history
+pub(crate) fn exec_error(e: ExecErrorResponse) -> SnowflakeApiError {
+    SnowflakeApiError::ApiError {
+        code: e.data.error_code,
+        message: e.message.unwrap_or_default(),
+        query_id: Some(e.data.query_id).filter(|id| !id.is_empty()),
+    }
+}
+
+fn query_id_suffix(query_id: Option<&str>) -> String {
+    query_id
+        .map(|id| format!(" Query id: `{id}`"))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+
+    const FIRST: &str = "01b2c3d4-0000-1111-0000-000000000011";
+    const SECOND: &str = "01b2c3d4-0000-1111-0000-000000000012";
+
+    #[tokio::test]
+    async fn running_query_is_polled_until_it_finishes() {
+        let result_path = format!("/queries/{FIRST}/result");
+        let in_progress = json!({
+            "code": QUERY_IN_PROGRESS,
+            "message": "Asynchronous execution in progress.",
+            "success": true,
+            "data": {"queryId": FIRST, "getResultUrl": result_path}
+        })
+        .to_string();
+
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(&in_progress)
+            .create_async()
+            .await;
+        let still_running = server
+            .mock("GET", result_path.as_str())
+            .match_query(Matcher::Any)
+            .with_body(&in_progress)
+            .expect(2)
+            .create_async()
+            .await;
+        let finished = server
+            .mock("GET", result_path.as_str())
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(&json!({
+                "queryId": FIRST,
+                "rowtype": [{
+                    "name": "N", "type": "fixed", "precision": 1, "scale": 0,
+                    "nullable": false, "byteLength": null, "length": null
+                }],
+                "rowset": [["1"]],
+                "total": 1,
+                "returned": 1
+            })))
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let QueryResult::Json(result) = api.exec("CALL long_running()").await.unwrap() else {
+            panic!("expected JSON result");
+        };
+        assert_eq!(result.value, json!([["1"]]));
+        query.assert_async().await;
+        still_running.assert_async().await;
+        finished.assert_async().await;
+    }
+
+    async fn retried_query(sql: &str, expected_attempts: usize) {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(mock::error_body(
+                "000604",
+                "SQL execution canceled",
+                &json!({}),
+            ))
+            .expect(expected_attempts)
+            .create_async()
+            .await;
+
+        let mut api = mock::api(&server);
+        api.query_retry_policy = Some(
+            QueryRetryPolicy::default()
+                .with_backoff(Duration::from_millis(1), Duration::from_millis(1)),
+        );
+        let err = api.exec(sql).await.err().unwrap();
+        assert!(
+            matches!(err, SnowflakeApiError::ApiError { ref code, .. } if code == "000604"),
+            "{err}"
+        );
+        query.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn read_only_query_is_retried() {
+        retried_query("SELECT 1", 3).await;
+    }
+
+    #[tokio::test]
+    async fn dml_is_not_retried_by_default() {
+        retried_query("INSERT INTO t VALUES (1)", 1).await;
+    }
+
+    #[tokio::test]
+    async fn queue_wait_is_recorded_on_query_span() {
+        let spans = mock::Spans::default();
+        let _subscriber = tracing::subscriber::set_default(spans.clone());
+
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_chunked_body(|w| {
+                std::thread::sleep(Duration::from_millis(200));
+                w.write_all(mock::query_body(&json!({})).as_bytes())
+            })
+            .expect(2)
+            .create_async()
+            .await;
+
+        let mut api = mock::api(&server);
+        api.query_limiter = Some(tokio::sync::Semaphore::new(1));
+        let (first, second) = tokio::join!(api.exec("SELECT 1"), api.exec("SELECT 2"));
+        first.unwrap();
+        second.unwrap();
+
+        let mut queued: Vec<u64> = spans
+            .named("snowflake.query")
+            .iter()
+            .map(|span| span.fields["queue_ms"].parse().unwrap())
+            .collect();
+        queued.sort_unstable();
+        assert_eq!(queued.len(), 2);
+        assert_eq!(queued[0], 0);
+        assert!(queued[1] >= 150, "{queued:?}");
+    }
+
+    #[tokio::test]
+    async fn session_timezone_follows_the_server() {
+        let spans = mock::Spans::default();
+        let _subscriber = tracing::subscriber::set_default(spans.clone());
+
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(&json!({
+                "parameters": [{"name": "TIMEZONE", "value": "Europe/Berlin"}]
+            })))
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        assert_eq!(api.session_timezone(), None);
+        api.exec("ALTER SESSION SET TIMEZONE = 'Europe/Berlin'")
+            .await
+            .unwrap();
+        assert_eq!(api.session_timezone().as_deref(), Some("Europe/Berlin"));
+        let span = &spans.named("snowflake.query")[0];
+        assert_eq!(span.fields["timezone"], "Europe/Berlin");
+    }
+
+    #[tokio::test]
+    async fn library_statements_are_marked_internal() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let sent = Arc::new(std::sync::Mutex::new(vec![]));
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body_from_request({
+                let sent = Arc::clone(&sent);
+                move |request| {
+                    let body: serde_json::Value =
+                        serde_json::from_slice(request.body().unwrap()).unwrap();
+                    sent.lock().unwrap().push((
+                        body["sqlText"].as_str().unwrap().to_string(),
+                        body["isInternal"].as_bool().unwrap(),
+                    ));
+                    mock::query_body(&json!({
+                        "rowtype": [{
+                            "name": "ID", "type": "text", "nullable": true,
+                            "byteLength": null, "length": null, "precision": null, "scale": null
+                        }],
+                        "rowset": [["1718031337001000000"]],
+                        "total": 1,
+                        "returned": 1
+                    }))
+                    .into_bytes()
+                }
+            })
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        // autocommit mode is re-applied by the session once it logs in
+        api.session.record_autocommit(false);
+        api.exec("SELECT 1").await.unwrap();
+        api.execute("INSERT INTO t VALUES (1)").await.unwrap();
+        api.begin_transaction().await.unwrap().leave_open();
+        api.exec_with_options("SELECT 2", &ExecOptions::default().with_internal(true))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *sent.lock().unwrap(),
+            [
+                ("ALTER SESSION SET AUTOCOMMIT = FALSE".to_string(), true),
+                ("SELECT 1".to_string(), false),
+                ("INSERT INTO t VALUES (1)".to_string(), false),
+                ("BEGIN".to_string(), false),
+                ("SELECT CURRENT_TRANSACTION()".to_string(), true),
+                ("SELECT 2".to_string(), true),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn chunks_are_downloaded_within_query_span() {
+        let spans = mock::Spans::default();
+        let _subscriber = tracing::subscriber::set_default(spans.clone());
+
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(&json!({
+                "rowtype": [{
+                    "name": "N", "type": "fixed", "precision": 1, "scale": 0,
+                    "nullable": false, "byteLength": null, "length": null
+                }],
+                "rowset": [["1"]],
+                "total": 3,
+                "returned": 1,
+                "chunks": [
+                    {"url": format!("{}/chunks/0", server.url()), "rowCount": 1, "uncompressedSize": 16},
+                    {"url": format!("{}/chunks/1", server.url()), "rowCount": 1, "uncompressedSize": 16}
+                ]
+            })))
+            .expect(2)
+            .create_async()
+            .await;
+        let _first = server
+            .mock("GET", "/chunks/0")
+            .with_body(r#"["2"]"#)
+            .expect(2)
+            .create_async()
+            .await;
+        let _second = server
+            .mock("GET", "/chunks/1")
+            .with_body(r#"["3"]"#)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let rows = api.query("SELECT n FROM t").await.unwrap();
+        assert_eq!(rows.len(), 3);
+        let QueryResult::Json(result) = api.exec("SELECT n FROM t").await.unwrap() else {
+            panic!("expected JSON result");
+        };
+        assert_eq!(result.value, json!([["1"], ["2"], ["3"]]));
+
+        let queries: Vec<u64> = spans
+            .named("snowflake.query")
+            .iter()
+            .map(|span| span.id)
+            .collect();
+        let chunks = spans.named("snowflake.chunk");
+        assert_eq!(chunks.len(), 4);
+        for chunk in chunks {
+            assert!(
+                chunk.parent.is_some_and(|parent| queries.contains(&parent)),
+                "{chunk:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn query_as_maps_arrow_results() {
+        use std::collections::HashMap;
+        use std::sync::Arc;
+
+        use arrow::array::{Int64Array, StringArray};
+        use arrow::datatypes::{DataType, Field, Schema};
+        use arrow::ipc::writer::StreamWriter;
+
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Item {
+            id: i64,
+            price: f64,
+            name: Option<String>,
+        }
+
+        let metadata = |logical_type: &str, scale: &str| {
+            HashMap::from([
+                ("logicalType".to_string(), logical_type.to_string()),
+                ("scale".to_string(), scale.to_string()),
+            ])
+        };
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("ID", DataType::Int64, false).with_metadata(metadata("FIXED", "0")),
+            Field::new("PRICE", DataType::Int64, false).with_metadata(metadata("FIXED", "2")),
+            Field::new("NAME", DataType::Utf8, true).with_metadata(metadata("TEXT", "0")),
+        ]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2])),
+                Arc::new(Int64Array::from(vec![1250, 99])),
+                Arc::new(StringArray::from(vec![Some("apple"), None])),
+            ],
+        )
+        .unwrap();
+        let mut writer = StreamWriter::try_new(Vec::new(), &schema).unwrap();
+        writer.write(&batch).unwrap();
+        let ipc = writer.into_inner().unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_header("accept", "application/snowflake")
+            .with_body(mock::query_body(&json!({
+                "queryResultFormat": "arrow",
+                "rowtype": [
+                    {"name": "ID", "type": "fixed", "precision": 38, "scale": 0,
+                     "nullable": false, "byteLength": null, "length": null},
+                    {"name": "PRICE", "type": "fixed", "precision": 10, "scale": 2,
+                     "nullable": false, "byteLength": null, "length": null},
+                    {"name": "NAME", "type": "text", "precision": null, "scale": null,
+                     "nullable": true, "byteLength": 64, "length": 16}
+                ],
+                "rowset": null,
+                "rowsetBase64": base64::engine::general_purpose::STANDARD.encode(ipc),
+                "total": 2,
+                "returned": 2
+            })))
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let items: Vec<Item> = api
+            .query_as("SELECT id, price, name FROM items")
+            .await
+            .unwrap();
+        query.assert_async().await;
+        assert_eq!(
+            items,
+            vec![
+                Item {
+                    id: 1,
+                    price: 12.5,
+                    name: Some("apple".to_string())
+                },
+                Item {
+                    id: 2,
+                    price: 0.99,
+                    name: None
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_query_reports_query_id() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(mock::error_body(
+                "002003",
+                "Table 'MISSING' does not exist",
+                &json!({}),
+            ))
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let err = api.exec("SELECT * FROM missing").await.err().unwrap();
+        let SnowflakeApiError::ApiError {
+            code,
+            message,
+            query_id,
+        } = &err
+        else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(code, "002003");
+        assert_eq!(message, "Table 'MISSING' does not exist");
+        assert_eq!(
+            query_id.as_deref(),
+            Some("01b2c3d4-0000-1111-0000-00000000ffff")
+        );
+        assert!(err
+            .to_string()
+            .ends_with("Query id: `01b2c3d4-0000-1111-0000-00000000ffff`"));
+    }
+
+    #[tokio::test]
+    async fn multi_statement_result_count_is_validated() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(
+                &json!({"resultIds": format!("{FIRST},{SECOND}")}),
+            ))
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let err = api
+            .exec_multi(
+                "SELECT 1; SELECT 2; SELECT 3",
+                MultiStatementCount::Exact(3),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(
+                err,
+                SnowflakeApiError::StatementCountMismatch {
+                    expected: 3,
+                    actual: 2
+                }
+            ),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_statement_of_the_script_is_attributed() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(mock::error_body(
+                "002003",
+                "Table 'MISSING' does not exist",
+                &json!({"resultIds": FIRST}),
+            ))
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let err = api
+            .exec_multi("SELECT 1; SELECT * FROM missing", MultiStatementCount::Any)
+            .await
+            .err()
+            .unwrap();
+        let SnowflakeApiError::StatementError { index, source } = err else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(index, 1);
+        assert!(
+            matches!(*source, SnowflakeApiError::ApiError { ref code, .. } if code == "002003")
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_child_result_is_attributed() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(
+                &json!({"resultIds": format!("{FIRST},{SECOND}")}),
+            ))
+            .create_async()
+            .await;
+        let _first = server
+            .mock("GET", format!("/queries/{FIRST}/result").as_str())
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(&json!({"queryId": FIRST})))
+            .create_async()
+            .await;
+        let _second = server
+            .mock("GET", format!("/queries/{SECOND}/result").as_str())
+            .match_query(Matcher::Any)
+            .with_body(mock::error_body("000612", "Result expired", &json!({})))
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let err = api
+            .execute_batch(&["SELECT 1", "SELECT 2"])
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err, SnowflakeApiError::StatementError { index: 1, .. }),
+            "{err}"
+        );
+    }
+
+    fn geography_column() -> serde_json::Value {
+        json!({
+            "name": "AREA", "type": "geography", "precision": null, "scale": null,
+            "nullable": true, "byteLength": null, "length": null
+        })
+    }
+
+    #[tokio::test]
+    async fn columns_are_returned_along_with_raw_and_optioned_results() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(&json!({
+                "queryId": FIRST,
+                "rowtype": [geography_column()],
+                "total": 0,
+                "returned": 0
+            })))
+            .expect(2)
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let raw = api
+            .exec_raw_with_query_id("SELECT area FROM regions WHERE false")
+            .await
+            .unwrap();
+        assert_eq!(raw.query_id, FIRST);
+        assert!(matches!(raw.result, RawQueryResult::Empty));
+        let columns: Vec<_> = raw
+            .columns
+            .iter()
+            .map(|column| {
+                (
+                    column.name.as_str(),
+                    column.type_,
+                    column.type_name.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(columns, [("AREA", SnowflakeType::Other, "GEOGRAPHY")]);
+
+        let optioned = api
+            .exec_with_options_and_query_id(
+                "SELECT area FROM regions WHERE false",
+                &ExecOptions::default().with_query_tag("regions"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(optioned.query_id, FIRST);
+        assert!(matches!(optioned.result, QueryResult::Empty));
+        assert_eq!(optioned.columns.len(), 1);
+        assert_eq!(optioned.columns[0].type_name, "GEOGRAPHY");
+    }
+
+    #[tokio::test]
+    async fn columns_of_multi_statement_request_are_of_the_last_statement() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(
+                &json!({"resultIds": format!("{FIRST},{SECOND}")}),
+            ))
+            .create_async()
+            .await;
+        let _second = server
+            .mock("GET", format!("/queries/{SECOND}/result").as_str())
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(&json!({
+                "queryId": SECOND,
+                "rowtype": [geography_column()],
+                "total": 0,
+                "returned": 0
+            })))
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let result = api
+            .exec_with_options_and_query_id(
+                "SELECT 1; SELECT area FROM regions WHERE false",
+                &ExecOptions::default().with_multi_statement_count(MultiStatementCount::Exact(2)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.query_id, SECOND);
+        assert_eq!(result.columns.len(), 1);
+        assert_eq!(result.columns[0].name, "AREA");
     }
 }
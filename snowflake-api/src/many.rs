@@ -0,0 +1,206 @@
+use std::fmt::{Debug, Formatter};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::connection::QueryType;
+use crate::requests::{ExecRequest, ExecRequestParameters};
+use crate::responses::ExecResponse;
+use crate::{query_response, QueryResult, SnowflakeApi, SnowflakeApiError, Transaction};
+
+/// Options of [`SnowflakeApi::exec_many`]
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct ExecManyOptions {
+    transaction: bool,
+    rollback_on_error: bool,
+    query_tag: Option<String>,
+}
+
+impl Default for ExecManyOptions {
+    fn default() -> Self {
+        Self {
+            transaction: false,
+            rollback_on_error: true,
+            query_tag: None,
+        }
+    }
+}
+
+impl ExecManyOptions {
+    /// Run the statements between `BEGIN` and `COMMIT`.
+    /// DDL statements commit the open transaction implicitly, so they can't be rolled back.
+    pub fn with_transaction(mut self, transaction: bool) -> Self {
+        self.transaction = transaction;
+        self
+    }
+
+    /// Roll the transaction back when one of the statements fails, enabled by default.
+    /// Otherwise the transaction is left open on the server, finish it with `COMMIT` or `ROLLBACK`.
+    pub fn with_rollback_on_error(mut self, rollback_on_error: bool) -> Self {
+        self.rollback_on_error = rollback_on_error;
+        self
+    }
+
+    /// Tag every statement with `<tag>#<index>`, so they can be found in the query history
+    pub fn with_query_tag(mut self, query_tag: &str) -> Self {
+        self.query_tag = Some(query_tag.to_string());
+        self
+    }
+}
+
+/// Result of the single statement run by [`SnowflakeApi::exec_many`]
+pub struct StatementOutcome {
+    pub index: usize,
+    pub query_id: String,
+    /// Sum of inserted, updated and deleted rows, `None` for statements which aren't DML
+    pub affected_rows: Option<u64>,
+    pub result: QueryResult,
+    pub elapsed: Duration,
+}
+
+impl Debug for StatementOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StatementOutcome")
+            .field("index", &self.index)
+            .field("query_id", &self.query_id)
+            .field("affected_rows", &self.affected_rows)
+            .field("elapsed", &self.elapsed)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Failure of [`SnowflakeApi::exec_many`], along with the statements which have succeeded
+#[derive(Error, Debug)]
+#[error("Statement {index} failed: {source}")]
+pub struct ExecManyError {
+    /// Index of the failed statement, `stmts.len()` if the final `COMMIT` has failed
+    pub index: usize,
+    pub source: SnowflakeApiError,
+    pub completed: Vec<StatementOutcome>,
+    /// Transaction was rolled back, changes of the completed statements are discarded
+    pub rolled_back: bool,
+}
+
+impl From<ExecManyError> for SnowflakeApiError {
+    fn from(value: ExecManyError) -> Self {
+        SnowflakeApiError::StatementError {
+            index: value.index,
+            source: Box::new(value.source),
+        }
+    }
+}
+
+impl SnowflakeApi {
+    /// Execute the statements one by one on the same session and stop at the first failure.
+    /// With [`ExecManyOptions::with_transaction`] the transaction is started with
+    /// [`SnowflakeApi::begin_transaction`], so it fails if there is one open already.
+    pub async fn exec_many(
+        &self,
+        stmts: &[&str],
+        opts: &ExecManyOptions,
+    ) -> Result<Vec<StatementOutcome>, ExecManyError> {
+        let fail = |index, source, completed| ExecManyError {
+            index,
+            source,
+            completed,
+            rolled_back: false,
+        };
+
+        let transaction = if opts.transaction {
+            match self.begin_transaction().await {
+                Ok(transaction) => Some(transaction),
+                Err(e) => return Err(fail(0, e, vec![])),
+            }
+        } else {
+            None
+        };
+
+        let mut completed = Vec::with_capacity(stmts.len());
+        for (index, sql) in stmts.iter().enumerate() {
+            let tag = opts.query_tag.as_ref().map(|tag| format!("{tag}#{index}"));
+            match self.exec_tagged(sql, tag).await {
+                Ok(outcome) => completed.push(StatementOutcome { index, ..outcome }),
+                Err(e) => {
+                    log::warn!("Statement {index} failed, aborting the remaining statements");
+                    let mut err = fail(index, e, completed);
+                    if let Some(transaction) = transaction {
+                        err.rolled_back = finish_failed(transaction, opts).await;
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        if let Some(transaction) = transaction {
+            if let Err(e) = transaction.commit().await {
+                let mut err = fail(stmts.len(), e, completed);
+                if opts.rollback_on_error {
+                    err.rolled_back = self.rollback().await;
+                }
+                return Err(err);
+            }
+        }
+        Ok(completed)
+    }
+
+    async fn exec_tagged(
+        &self,
+        sql: &str,
+        query_tag: Option<String>,
+    ) -> Result<StatementOutcome, SnowflakeApiError> {
+        let started = Instant::now();
+        let request = ExecRequest {
+            parameters: ExecRequestParameters {
+                query_tag,
+                ..ExecRequestParameters::default()
+            },
+            ..ExecRequest::new(sql)
+        };
+        let resp = self
+            .run_exec_request::<ExecResponse>(request, QueryType::ArrowQuery)
+            .await?;
+        let resp = query_response(resp)?;
+        let query_id = resp.data.query_id.clone();
+        let affected_rows =
+            resp.data.stats.as_ref().map(|stats| {
+                stats.num_rows_inserted + stats.num_rows_updated + stats.num_rows_deleted
+            });
+        let result = self.raw_query_result(resp).await?.deserialize_arrow()?;
+
+        Ok(StatementOutcome {
+            index: 0,
+            query_id,
+            affected_rows,
+            result,
+            elapsed: started.elapsed(),
+        })
+    }
+
+    /// Failure to roll back is logged, the original error is more relevant to the caller.
+    /// Used once the transaction guard is consumed by the failed commit.
+    async fn rollback(&self) -> bool {
+        match self.exec_tagged("ROLLBACK", None).await {
+            Ok(_) => true,
+            Err(e) => {
+                log::error!("Failed to roll back the transaction: {e}");
+                false
+            }
+        }
+    }
+}
+
+/// Roll back the transaction of the failed statement, or leave it open if asked to
+async fn finish_failed(transaction: Transaction<'_>, opts: &ExecManyOptions) -> bool {
+    if !opts.rollback_on_error {
+        transaction.leave_open();
+        return false;
+    }
+    match transaction.rollback().await {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("Failed to roll back the transaction: {e}");
+            false
+        }
+    }
+}
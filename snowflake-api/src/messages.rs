@@ -0,0 +1,186 @@
+use serde::Deserialize;
+
+use crate::responses::ResponseEnvelopeData;
+
+/// Severity of a [`ServerMessage`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum MessageSeverity {
+    #[serde(alias = "WARN")]
+    Warning,
+    Info,
+}
+
+/// Non-fatal message attached to a successful response, eg a truncated value or a deprecation notice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerMessage {
+    pub code: Option<String>,
+    pub severity: MessageSeverity,
+    pub text: String,
+}
+
+// messages are either plain strings or objects, code is sent as a string or a number
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawMessage {
+    Text(String),
+    Object {
+        #[serde(default)]
+        code: Option<serde_json::Value>,
+        #[serde(default)]
+        severity: Option<MessageSeverity>,
+        #[serde(alias = "message")]
+        text: String,
+    },
+}
+
+impl ServerMessage {
+    /// Collect the `warnings` and `messages` arrays of the response `data`,
+    /// entries which don't look like a message are skipped
+    pub(crate) fn from_response(data: &ResponseEnvelopeData) -> Vec<Self> {
+        let parse = |entries: Option<&serde_json::Value>, severity: MessageSeverity| {
+            entries
+                .and_then(serde_json::Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(move |entry| {
+                    let message = RawMessage::deserialize(entry)
+                        .inspect_err(|e| log::debug!("Skipping server message {entry}: {e}"))
+                        .ok()?;
+                    Some(match message {
+                        RawMessage::Text(text) => Self {
+                            code: None,
+                            severity,
+                            text,
+                        },
+                        RawMessage::Object {
+                            code,
+                            severity: explicit,
+                            text,
+                        } => Self {
+                            code: code.filter(|code| !code.is_null()).map(|code| match code {
+                                serde_json::Value::String(code) => code,
+                                code => code.to_string(),
+                            }),
+                            severity: explicit.unwrap_or(severity),
+                            text,
+                        },
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        parse(data.warnings.as_ref(), MessageSeverity::Warning)
+            .into_iter()
+            .chain(parse(data.messages.as_ref(), MessageSeverity::Info))
+            .collect()
+    }
+
+    pub(crate) fn log(&self, query_id: Option<&str>) {
+        let query_id = query_id.unwrap_or("-");
+        let code = self.code.as_deref().unwrap_or("-");
+        match self.severity {
+            MessageSeverity::Warning => {
+                log::warn!("Query {query_id} warning {code}: {}", self.text);
+            }
+            MessageSeverity::Info => log::info!("Query {query_id} message {code}: {}", self.text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+
+    use super::*;
+    use crate::mock;
+    use crate::responses::ResponseEnvelope;
+
+    fn messages(body: &str) -> Vec<ServerMessage> {
+        let envelope: ResponseEnvelope = serde_json::from_str(body).unwrap();
+        ServerMessage::from_response(&envelope.data.unwrap())
+    }
+
+    #[test]
+    fn numeric_truncation_warning() {
+        assert_eq!(
+            messages(include_str!("fixtures/warnings_truncation.json")),
+            vec![ServerMessage {
+                code: Some("100039".to_string()),
+                severity: MessageSeverity::Warning,
+                text: "Numeric value '123.4567' was truncated to fit column AMOUNT of type \
+                       NUMBER(10,2)"
+                    .to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn multiple_warnings_and_messages() {
+        assert_eq!(
+            messages(include_str!("fixtures/warnings_multiple.json")),
+            vec![
+                ServerMessage {
+                    code: Some("100039".to_string()),
+                    severity: MessageSeverity::Warning,
+                    text: "Numeric value '9.999' was truncated to fit column RATE of type \
+                           NUMBER(4,2)"
+                        .to_string(),
+                },
+                ServerMessage {
+                    code: None,
+                    severity: MessageSeverity::Warning,
+                    text: "Function SYSTEM$LEGACY_STATS is deprecated and will be removed in a \
+                           future release"
+                        .to_string(),
+                },
+                ServerMessage {
+                    code: None,
+                    severity: MessageSeverity::Warning,
+                    text: "Clustering key has no effect on tables with fewer than 1000 rows"
+                        .to_string(),
+                },
+                // `42` isn't a message and is skipped
+                ServerMessage {
+                    code: Some("1".to_string()),
+                    severity: MessageSeverity::Info,
+                    text: "Warehouse COMPUTE_WH was resumed to run the query".to_string(),
+                },
+                ServerMessage {
+                    code: None,
+                    severity: MessageSeverity::Warning,
+                    text: "Result exceeds the configured CLIENT_RESULT_CHUNK_SIZE".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn warnings_of_last_query_are_kept() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _insert = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(include_str!("fixtures/warnings_truncation.json"))
+            .expect(1)
+            .create_async()
+            .await;
+        let _select = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(&serde_json::json!({})))
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        api.exec("INSERT INTO payments (amount) VALUES (123.4567)")
+            .await
+            .unwrap();
+        let warnings = api.last_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code.as_deref(), Some("100039"));
+
+        api.exec("SELECT 1").await.unwrap();
+        assert!(api.last_warnings().is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer};
+
+use crate::quote::quote_literal;
+use crate::{SnowflakeApi, SnowflakeApiError};
+
+/// Row of `SHOW TABLES`, columns which aren't listed here are ignored
+#[derive(Debug, Clone, Deserialize)]
+pub struct TableInfo {
+    pub name: String,
+    #[serde(rename = "database_name")]
+    pub database: String,
+    #[serde(rename = "schema_name")]
+    pub schema: String,
+    /// `TABLE`, `TEMPORARY`, `TRANSIENT`, etc
+    #[serde(default)]
+    pub kind: String,
+    #[serde(default)]
+    pub rows: Option<u64>,
+    #[serde(default)]
+    pub bytes: Option<u64>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub cluster_by: Option<String>,
+    #[serde(default)]
+    pub created_on: Option<DateTime<Utc>>,
+}
+
+/// Row of `SHOW SCHEMAS`, columns which aren't listed here are ignored
+#[derive(Debug, Clone, Deserialize)]
+pub struct SchemaInfo {
+    pub name: String,
+    #[serde(rename = "database_name")]
+    pub database: String,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default, deserialize_with = "yes_no")]
+    pub is_current: bool,
+    #[serde(default)]
+    pub created_on: Option<DateTime<Utc>>,
+}
+
+/// Row of `DESC TABLE`, columns which aren't listed here are ignored
+#[derive(Debug, Clone, Deserialize)]
+pub struct TableColumn {
+    pub name: String,
+    /// Snowflake type of the column, eg `NUMBER(38,0)` or `VARCHAR(16777216)`
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// `COLUMN` for regular columns, `VIRTUAL` for computed ones
+    #[serde(default)]
+    pub kind: String,
+    #[serde(rename = "null?", default, deserialize_with = "yes_no")]
+    pub nullable: bool,
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(rename = "primary key", default, deserialize_with = "yes_no")]
+    pub primary_key: bool,
+    #[serde(rename = "unique key", default, deserialize_with = "yes_no")]
+    pub unique_key: bool,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+impl SnowflakeApi {
+    /// List tables visible to the current role, `like` is the case-insensitive pattern
+    /// matched against table names and `in_` is the database or `database.schema` to look in
+    pub async fn show_tables(
+        &self,
+        like: Option<&str>,
+        in_: Option<&str>,
+    ) -> Result<Vec<TableInfo>, SnowflakeApiError> {
+        let like = like
+            .map(|like| format!(" LIKE {}", quote_literal(like)))
+            .unwrap_or_default();
+        let in_ = match in_ {
+            Some(in_) => format!(" IN {}", qualified_identifier(in_)?),
+            None => String::new(),
+        };
+        self.query_as(&format!("SHOW TABLES{like}{in_}")).await
+    }
+
+    /// List schemas of the current database, or of `database` if given
+    pub async fn show_schemas(
+        &self,
+        database: Option<&str>,
+    ) -> Result<Vec<SchemaInfo>, SnowflakeApiError> {
+        let sql = match database {
+            Some(database) => format!("SHOW SCHEMAS IN DATABASE {}", identifier(database)?),
+            None => "SHOW SCHEMAS".to_string(),
+        };
+        self.query_as(&sql).await
+    }
+
+    /// Columns of the table, `name` may be qualified with the database and schema
+    pub async fn describe_table(&self, name: &str) -> Result<Vec<TableColumn>, SnowflakeApiError> {
+        self.query_as(&format!("DESC TABLE {}", qualified_identifier(name)?))
+            .await
+    }
+}
+
+/// Snowflake reports flags of metadata commands as `Y`/`N` or `true`/`false` strings
+pub(crate) fn yes_no<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    let value = Option::<String>::deserialize(deserializer)?;
+    Ok(value.is_some_and(|v| {
+        v.eq_ignore_ascii_case("y")
+            || v.eq_ignore_ascii_case("yes")
+            || v.eq_ignore_ascii_case("true")
+    }))
+}
+
+/// Unquoted identifiers are passed as is to keep their case-insensitive resolution,
+/// anything else has to be quoted by the caller
+pub(crate) fn identifier(name: &str) -> Result<&str, SnowflakeApiError> {
+    let is_unquoted = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    let is_quoted = name.len() >= 2
+        && name.starts_with('"')
+        && name.ends_with('"')
+        && !name[1..name.len() - 1].replace("\"\"", "").contains('"');
+
+    if is_unquoted || is_quoted {
+        Ok(name)
+    } else {
+        Err(SnowflakeApiError::InvalidIdentifier(name.to_string()))
+    }
+}
+
+pub(crate) fn qualified_identifier(name: &str) -> Result<String, SnowflakeApiError> {
+    Ok(identifier_parts(name)?.join("."))
+}
+
+/// Parts of the name qualified with up to the database and schema, each one checked by [`identifier`]
+pub(crate) fn identifier_parts(name: &str) -> Result<Vec<&str>, SnowflakeApiError> {
+    let mut parts = vec![];
+    let mut rest = name;
+    while !rest.is_empty() {
+        // dots are allowed within the quoted parts
+        let end = if rest.starts_with('"') {
+            let mut end = 1;
+            loop {
+                match rest[end..].find('"') {
+                    Some(i) if rest[end + i + 1..].starts_with('"') => end += i + 2,
+                    Some(i) => break end + i + 1,
+                    None => return Err(SnowflakeApiError::InvalidIdentifier(name.to_string())),
+                }
+            }
+        } else {
+            rest.find('.').unwrap_or(rest.len())
+        };
+        parts.push(identifier(&rest[..end])?);
+        rest = &rest[end..];
+        if let Some(next) = rest.strip_prefix('.') {
+            if next.is_empty() {
+                return Err(SnowflakeApiError::InvalidIdentifier(name.to_string()));
+            }
+            rest = next;
+        } else if !rest.is_empty() {
+            return Err(SnowflakeApiError::InvalidIdentifier(name.to_string()));
+        }
+    }
+    if parts.is_empty() || parts.len() > 3 {
+        return Err(SnowflakeApiError::InvalidIdentifier(name.to_string()));
+    }
+    Ok(parts)
+}
+
+/// Name the way it's stored in the catalog: unquoted identifiers are upper case,
+/// quoted ones are taken as is
+pub(crate) fn catalog_name(identifier: &str) -> String {
+    match identifier
+        .strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+    {
+        Some(name) => name.replace("\"\"", "\""),
+        None => identifier.to_uppercase(),
+    }
+}
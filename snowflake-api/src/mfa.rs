@@ -0,0 +1,58 @@
+use std::io::{self, BufRead, Write};
+
+use async_trait::async_trait;
+
+use crate::session::AuthError;
+
+/// Login response `nextAction` asking the client to retry with Duo push
+pub(crate) const DUO_ALL: &str = "EXT_AUTHN_DUO_ALL";
+/// Login response `nextAction` asking the client to retry with Duo push or passcode
+pub(crate) const DUO_PUSH_N_PASSCODE: &str = "EXT_AUTHN_DUO_PUSH_N_PASSCODE";
+
+/// Second factor sent along with the retried login
+pub(crate) enum DuoFactor {
+    Push,
+    Passcode(String),
+}
+
+impl DuoFactor {
+    pub(crate) fn method(&self) -> &'static str {
+        match self {
+            Self::Push => "push",
+            Self::Passcode(_) => "passcode",
+        }
+    }
+}
+
+/// Second factor of the password login, called when the server asks for Duo MFA
+#[async_trait]
+pub trait MfaHandler: Send + Sync {
+    /// Return `None` to approve the login with a Duo push notification,
+    /// or `Some(passcode)` with the code from the Duo app or a hardware token
+    async fn handle_mfa(&self, prompt: &str) -> Result<Option<String>, AuthError>;
+}
+
+/// Asks for the passcode on the terminal, empty input falls back to the push notification
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConsoleMfaHandler;
+
+#[async_trait]
+impl MfaHandler for ConsoleMfaHandler {
+    async fn handle_mfa(&self, prompt: &str) -> Result<Option<String>, AuthError> {
+        let prompt = prompt.to_string();
+        tokio::task::spawn_blocking(move || -> io::Result<Option<String>> {
+            let mut stderr = io::stderr();
+            writeln!(stderr, "{prompt}")?;
+            write!(stderr, "Duo passcode (leave empty for push notification): ")?;
+            stderr.flush()?;
+
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            let passcode = line.trim();
+            Ok((!passcode.is_empty()).then(|| passcode.to_string()))
+        })
+        .await
+        .map_err(|e| AuthError::MfaFailed(e.to_string()))?
+        .map_err(|e| AuthError::MfaFailed(e.to_string()))
+    }
+}
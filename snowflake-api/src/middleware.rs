@@ -0,0 +1,695 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use http::Extensions;
+use reqwest::header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{Middleware, Next, Result};
+use reqwest_retry::{RetryPolicy, Retryable};
+use retry_policies::RetryDecision;
+use thiserror::Error;
+
+/// Paths which carry credentials (password, JWT, master token) in the request body
+const CREDENTIAL_PATHS: [&str; 2] = ["session/v1/login-request", "session/token-request"];
+
+/// Logs every request made to Snowflake along with the response status and latency.
+/// Authorization header and credentials are redacted by default, so it's safe to use in production.
+///
+/// Added to the default client, use it with a custom one like this:
+/// ```rust
+/// use snowflake_api::connection::Connection;
+/// use snowflake_api::middleware::LoggingMiddleware;
+/// let client = Connection::default_client_builder(None)
+///     .unwrap()
+///     .with(LoggingMiddleware::default().with_log_level(log::Level::Info));
+/// ```
+#[derive(Debug, Clone)]
+pub struct LoggingMiddleware {
+    log_level: log::Level,
+    redact_auth: bool,
+    max_body_size: usize,
+}
+
+impl Default for LoggingMiddleware {
+    fn default() -> Self {
+        Self {
+            log_level: log::Level::Debug,
+            redact_auth: true,
+            max_body_size: 4096,
+        }
+    }
+}
+
+impl LoggingMiddleware {
+    #[must_use]
+    pub fn with_log_level(mut self, log_level: log::Level) -> Self {
+        self.log_level = log_level;
+        self
+    }
+
+    /// Replace `Authorization` header and login request bodies with `[REDACTED]`
+    #[must_use]
+    pub fn with_redact_auth(mut self, redact_auth: bool) -> Self {
+        self.redact_auth = redact_auth;
+        self
+    }
+
+    /// Request bodies longer than this are truncated in logs
+    #[must_use]
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    fn log_request(&self, req: &Request) {
+        let headers = req
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = if self.redact_auth && name == AUTHORIZATION {
+                    "[REDACTED]"
+                } else {
+                    value.to_str().unwrap_or("[binary]")
+                };
+                format!("{name}: {value}")
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        // forms are only posted to the identity provider, with the user's credentials
+        let carries_credentials = CREDENTIAL_PATHS
+            .iter()
+            .any(|path| req.url().path().ends_with(path))
+            || req
+                .headers()
+                .get(CONTENT_TYPE)
+                .is_some_and(|v| v == "application/x-www-form-urlencoded");
+        let body = match req.body().and_then(reqwest::Body::as_bytes) {
+            None => String::new(),
+            Some(_) if self.redact_auth && carries_credentials => "[REDACTED]".to_string(),
+            Some(bytes) if req.headers().contains_key(CONTENT_ENCODING) => {
+                format!("[{} bytes compressed]", bytes.len())
+            }
+            Some(bytes) if bytes.len() > self.max_body_size => format!(
+                "{}[truncated]",
+                String::from_utf8_lossy(&bytes[..self.max_body_size])
+            ),
+            Some(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+        };
+
+        log::log!(
+            self.log_level,
+            "Request: {} {} headers: [{headers}] body: {body}",
+            req.method(),
+            req.url()
+        );
+    }
+}
+
+#[async_trait]
+impl Middleware for LoggingMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        if !log::log_enabled!(self.log_level) {
+            return next.run(req, extensions).await;
+        }
+
+        self.log_request(&req);
+        let method = req.method().clone();
+        let url = req.url().clone();
+        let started = Instant::now();
+        let resp = next.run(req, extensions).await;
+        let elapsed = started.elapsed();
+
+        match &resp {
+            Ok(r) => log::log!(
+                self.log_level,
+                "Response: {method} {url} status: {} in {elapsed:?}",
+                r.status()
+            ),
+            Err(e) => log::log!(
+                self.log_level,
+                "Request failed: {method} {url} in {elapsed:?}: {e}"
+            ),
+        }
+        resp
+    }
+}
+
+const QUERY_REQUEST_PATH: &str = "/queries/v1/query-request";
+/// Aborts are sent once, retrying them would only delay the shutdown they are part of
+const ABORT_REQUEST_PATH: &str = "/queries/v1/abort-request";
+
+/// Retries transient failures with exponential backoff. Rate limited requests (HTTP 429)
+/// are retried after the delay requested by the server in `Retry-After` header instead.
+/// Total time spent waiting between the attempts is capped by `max_total_wait`.
+///
+/// Works as a middleware on its own, as `RetryTransientMiddleware` has no access
+/// to the response when making the decision.
+#[derive(Debug, Clone)]
+pub struct SnowflakeRetryPolicy {
+    max_retries: u32,
+    min_interval: Duration,
+    max_interval: Duration,
+    max_total_wait: Duration,
+}
+
+impl Default for SnowflakeRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            min_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(30),
+            max_total_wait: Duration::from_mins(5),
+        }
+    }
+}
+
+impl SnowflakeRetryPolicy {
+    #[must_use]
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Bounds of the exponential backoff interval
+    #[must_use]
+    pub fn with_retry_bounds(mut self, min_interval: Duration, max_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self.max_interval = max_interval;
+        self
+    }
+
+    /// Give up if the next attempt would make the total wait longer than this
+    #[must_use]
+    pub fn with_max_total_wait(mut self, max_total_wait: Duration) -> Self {
+        self.max_total_wait = max_total_wait;
+        self
+    }
+
+    /// Delay before the next attempt, `None` when the request shouldn't be retried
+    fn retry_delay(
+        &self,
+        result: &Result<Response>,
+        start_time: DateTime<Utc>,
+        n_past_retries: u32,
+    ) -> Option<Duration> {
+        let rate_limited = result
+            .as_ref()
+            .ok()
+            .filter(|r| r.status() == StatusCode::TOO_MANY_REQUESTS);
+        match rate_limited.and_then(retry_after) {
+            Some(delay) => {
+                let waited = (Utc::now() - start_time).to_std().unwrap_or_default();
+                (n_past_retries < self.max_retries && waited + delay <= self.max_total_wait)
+                    .then_some(delay)
+            }
+            None => match self.should_retry(start_time, n_past_retries) {
+                RetryDecision::Retry { execute_after } => {
+                    Some((execute_after - Utc::now()).to_std().unwrap_or_default())
+                }
+                RetryDecision::DoNotRetry => None,
+            },
+        }
+    }
+}
+
+impl RetryPolicy for SnowflakeRetryPolicy {
+    fn should_retry(
+        &self,
+        request_start_time: DateTime<Utc>,
+        n_past_retries: u32,
+    ) -> RetryDecision {
+        if n_past_retries >= self.max_retries {
+            return RetryDecision::DoNotRetry;
+        }
+
+        let delay = self
+            .min_interval
+            .saturating_mul(2_u32.saturating_pow(n_past_retries))
+            .min(self.max_interval);
+        let waited = (Utc::now() - request_start_time)
+            .to_std()
+            .unwrap_or_default();
+        if waited + delay > self.max_total_wait {
+            return RetryDecision::DoNotRetry;
+        }
+
+        match chrono::Duration::from_std(delay) {
+            Ok(delay) => RetryDecision::Retry {
+                execute_after: Utc::now() + delay,
+            },
+            Err(_) => RetryDecision::DoNotRetry,
+        }
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let value = resp.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[async_trait]
+impl Middleware for SnowflakeRetryPolicy {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        if req.url().path().ends_with(ABORT_REQUEST_PATH) {
+            return next.run(req, extensions).await;
+        }
+        let start_time = Utc::now();
+        let mut n_past_retries = 0;
+        let mut retry_reason = 0;
+        loop {
+            // streaming bodies can't be replayed, such requests are sent once
+            let Some(mut attempt) = req.try_clone() else {
+                return next.run(req, extensions).await;
+            };
+            // retries keep the `requestId` of the original request, so the server can tell
+            // them apart from the new queries, and are annotated the way other drivers do it
+            if n_past_retries > 0 && attempt.url().path().ends_with(QUERY_REQUEST_PATH) {
+                attempt
+                    .url_mut()
+                    .query_pairs_mut()
+                    .append_pair("retryCount", &n_past_retries.to_string())
+                    .append_pair("retryReason", &retry_reason.to_string());
+            }
+
+            let result = next.clone().run(attempt, extensions).await;
+            let transient = match &result {
+                Ok(resp) => reqwest_retry::default_on_request_success(resp),
+                Err(e) => reqwest_retry::default_on_request_failure(e),
+            } == Some(Retryable::Transient);
+            if !transient {
+                return result;
+            }
+
+            let Some(delay) = self.retry_delay(&result, start_time, n_past_retries) else {
+                return result;
+            };
+            // HTTP status of the failed attempt, 0 for the network failures
+            retry_reason = result.as_ref().map_or(0, |r| r.status().as_u16());
+            log::warn!(
+                "Retry attempt #{n_past_retries}. Sleeping {delay:?} before the next attempt"
+            );
+            tokio::time::sleep(delay).await;
+            n_past_retries += 1;
+        }
+    }
+}
+
+const CIRCUIT_CLOSED: u8 = 0;
+const CIRCUIT_OPEN: u8 = 1;
+const CIRCUIT_HALF_OPEN: u8 = 2;
+
+/// Request was rejected without being sent, surfaced as [`crate::connection::ConnectionError::CircuitOpen`]
+#[derive(Error, Debug)]
+#[error("Circuit breaker is open")]
+pub struct CircuitOpenError;
+
+/// Stops sending requests to a host after `failure_threshold` consecutive server errors
+/// (5xx or network failures). While the circuit is open requests fail immediately,
+/// after `open_duration` single probe request is let through, its outcome either closes
+/// the circuit or opens it again.
+///
+/// Every host has its own circuit, so an unavailable account, or chunk storage,
+/// doesn't block the requests to the others sharing the client.
+/// Placed after the retry middleware, so retries stop as soon as the circuit opens.
+#[derive(Debug)]
+pub struct CircuitBreakerMiddleware {
+    failure_threshold: u32,
+    open_duration: Duration,
+    circuits: Mutex<HashMap<String, Arc<Circuit>>>,
+}
+
+impl Default for CircuitBreakerMiddleware {
+    fn default() -> Self {
+        Self::new(5, Duration::from_secs(30))
+    }
+}
+
+impl CircuitBreakerMiddleware {
+    pub fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            open_duration,
+            circuits: Mutex::default(),
+        }
+    }
+
+    /// Whether the requests to the host are rejected, eg `xy12345.snowflakecomputing.com`
+    pub fn is_open(&self, host: &str) -> bool {
+        self.circuits
+            .lock()
+            .unwrap()
+            .get(host)
+            .is_some_and(|circuit| circuit.is_open())
+    }
+
+    fn circuit(&self, host: &str) -> Arc<Circuit> {
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Circuit::new(self.failure_threshold, self.open_duration)));
+        Arc::clone(circuit)
+    }
+}
+
+/// Circuit of the single host, see [`CircuitBreakerMiddleware`]
+#[derive(Debug)]
+struct Circuit {
+    failure_threshold: u32,
+    open_duration: Duration,
+
+    state: AtomicU8,
+    consecutive_failures: AtomicU32,
+    /// milliseconds since `created_at`
+    opened_at: AtomicU64,
+    created_at: Instant,
+}
+
+impl Circuit {
+    fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            failure_threshold,
+            open_duration,
+            state: AtomicU8::new(CIRCUIT_CLOSED),
+            consecutive_failures: AtomicU32::new(0),
+            opened_at: AtomicU64::new(0),
+            created_at: Instant::now(),
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.state.load(Ordering::Acquire) != CIRCUIT_CLOSED
+    }
+
+    fn now_millis(&self) -> u64 {
+        u64::try_from(self.created_at.elapsed().as_millis()).unwrap_or(u64::MAX)
+    }
+
+    /// Whether the request could be sent, `Some(true)` for the probe, which is the first
+    /// request after `open_duration`
+    fn acquire(&self) -> Option<bool> {
+        match self.state.load(Ordering::Acquire) {
+            CIRCUIT_CLOSED => Some(false),
+            CIRCUIT_OPEN => {
+                let open_for = self
+                    .now_millis()
+                    .saturating_sub(self.opened_at.load(Ordering::Acquire));
+                let probe = open_for
+                    >= u64::try_from(self.open_duration.as_millis()).unwrap_or(u64::MAX)
+                    && self
+                        .state
+                        .compare_exchange(
+                            CIRCUIT_OPEN,
+                            CIRCUIT_HALF_OPEN,
+                            Ordering::AcqRel,
+                            Ordering::Acquire,
+                        )
+                        .is_ok();
+                probe.then_some(true)
+            }
+            // probe is already in flight
+            _ => None,
+        }
+    }
+
+    fn on_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Release);
+        if self.state.swap(CIRCUIT_CLOSED, Ordering::AcqRel) != CIRCUIT_CLOSED {
+            log::info!("Circuit breaker closed");
+        }
+    }
+
+    fn on_failure(&self) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::AcqRel) + 1;
+        let probe_failed = self.state.load(Ordering::Acquire) == CIRCUIT_HALF_OPEN;
+        if probe_failed || failures >= self.failure_threshold {
+            self.opened_at.store(self.now_millis(), Ordering::Release);
+            if self.state.swap(CIRCUIT_OPEN, Ordering::AcqRel) == CIRCUIT_CLOSED {
+                log::warn!(
+                    "Circuit breaker opened after {failures} consecutive failures, \
+                    rejecting requests for {:?}",
+                    self.open_duration
+                );
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for CircuitBreakerMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let host = match (req.url().host_str(), req.url().port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (host, None) => host.unwrap_or_default().to_string(),
+            (None, Some(_)) => String::new(),
+        };
+        let circuit = self.circuit(&host);
+        let Some(probe) = circuit.acquire() else {
+            return Err(reqwest_middleware::Error::middleware(CircuitOpenError));
+        };
+
+        let result = next.run(req, extensions).await;
+        let failed = match &result {
+            Ok(resp) => resp.status().is_server_error(),
+            // circuit is only closed by the probe which has actually succeeded
+            Err(_) if probe => true,
+            Err(reqwest_middleware::Error::Reqwest(e)) => e.is_connect() || e.is_timeout(),
+            Err(reqwest_middleware::Error::Middleware(_)) => false,
+        };
+        if failed {
+            circuit.on_failure();
+        } else {
+            circuit.on_success();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use mockito::Matcher;
+    use url::Url;
+
+    use super::*;
+    use crate::connection::Connection;
+    use crate::mock;
+
+    const OPEN_DURATION: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let circuit = Circuit::new(3, OPEN_DURATION);
+
+        circuit.on_failure();
+        circuit.on_failure();
+        assert!(!circuit.is_open());
+        assert_eq!(circuit.acquire(), Some(false));
+
+        circuit.on_failure();
+        assert!(circuit.is_open());
+        assert_eq!(circuit.acquire(), None);
+    }
+
+    #[test]
+    fn success_resets_failure_count() {
+        let circuit = Circuit::new(2, OPEN_DURATION);
+
+        circuit.on_failure();
+        circuit.on_success();
+        circuit.on_failure();
+        assert!(!circuit.is_open());
+    }
+
+    #[test]
+    fn single_probe_after_open_duration() {
+        let circuit = Circuit::new(1, OPEN_DURATION);
+        circuit.on_failure();
+        assert_eq!(circuit.acquire(), None);
+
+        std::thread::sleep(OPEN_DURATION);
+        assert_eq!(circuit.acquire(), Some(true));
+        assert_eq!(circuit.state.load(Ordering::Acquire), CIRCUIT_HALF_OPEN);
+        // the rest is rejected while the probe is in flight
+        assert_eq!(circuit.acquire(), None);
+    }
+
+    #[test]
+    fn successful_probe_closes_circuit() {
+        let circuit = Circuit::new(1, OPEN_DURATION);
+        circuit.on_failure();
+        std::thread::sleep(OPEN_DURATION);
+        assert_eq!(circuit.acquire(), Some(true));
+
+        circuit.on_success();
+        assert!(!circuit.is_open());
+        assert_eq!(circuit.acquire(), Some(false));
+    }
+
+    #[test]
+    fn failed_probe_opens_circuit_again() {
+        let circuit = Circuit::new(3, OPEN_DURATION);
+        for _ in 0..3 {
+            circuit.on_failure();
+        }
+        std::thread::sleep(OPEN_DURATION);
+        assert_eq!(circuit.acquire(), Some(true));
+
+        circuit.on_failure();
+        assert_eq!(circuit.state.load(Ordering::Acquire), CIRCUIT_OPEN);
+        assert_eq!(circuit.acquire(), None);
+    }
+
+    #[tokio::test]
+    async fn hosts_have_separate_circuits() {
+        let mut failing = mockito::Server::new_async().await;
+        let mut healthy = mockito::Server::new_async().await;
+        let failing_mock = failing
+            .mock("GET", "/")
+            .with_status(503)
+            .expect(2)
+            .create_async()
+            .await;
+        let _healthy = healthy.mock("GET", "/").create_async().await;
+
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(CircuitBreakerMiddleware::new(2, Duration::from_mins(1)))
+            .build();
+        for _ in 0..2 {
+            let resp = client.get(failing.url()).send().await.unwrap();
+            assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+
+        let err = client.get(failing.url()).send().await.unwrap_err();
+        assert!(
+            matches!(err, reqwest_middleware::Error::Middleware(ref e) if e.is::<CircuitOpenError>())
+        );
+        let resp = client.get(healthy.url()).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        failing_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn probe_failed_in_middleware_keeps_circuit_open() {
+        let breaker = CircuitBreakerMiddleware::new(1, Duration::ZERO);
+        let circuit = breaker.circuit("127.0.0.1:1");
+        circuit.on_failure();
+
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(breaker)
+            .with(FailingMiddleware)
+            .build();
+        let err = client.get("http://127.0.0.1:1/").send().await.unwrap_err();
+
+        assert!(matches!(err, reqwest_middleware::Error::Middleware(_)));
+        assert_eq!(circuit.state.load(Ordering::Acquire), CIRCUIT_OPEN);
+    }
+
+    struct FailingMiddleware;
+
+    #[async_trait]
+    impl Middleware for FailingMiddleware {
+        async fn handle(
+            &self,
+            _req: Request,
+            _extensions: &mut Extensions,
+            _next: Next<'_>,
+        ) -> Result<Response> {
+            Err(reqwest_middleware::Error::middleware(
+                std::io::Error::other("failed"),
+            ))
+        }
+    }
+
+    fn query_pairs(path_and_query: &str) -> HashMap<String, String> {
+        let url = Url::parse(&format!("http://localhost{path_and_query}")).unwrap();
+        url.query_pairs().into_owned().collect()
+    }
+
+    #[tokio::test]
+    async fn retried_query_keeps_its_request_id() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let sent = Arc::new(Mutex::new(vec![]));
+
+        let captured = Arc::clone(&sent);
+        let unavailable = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_status(503)
+            .with_body_from_request(move |request| {
+                captured
+                    .lock()
+                    .unwrap()
+                    .push(request.path_and_query().to_string());
+                vec![]
+            })
+            .create_async()
+            .await;
+        let captured = Arc::clone(&sent);
+        let retried = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("retryCount".into(), "1".into()),
+                Matcher::UrlEncoded("retryReason".into(), "503".into()),
+            ]))
+            .with_body_from_request(move |request| {
+                captured
+                    .lock()
+                    .unwrap()
+                    .push(request.path_and_query().to_string());
+                mock::query_body(&serde_json::json!({})).into_bytes()
+            })
+            .create_async()
+            .await;
+
+        let retry = SnowflakeRetryPolicy::default()
+            .with_retry_bounds(Duration::from_millis(1), Duration::from_millis(1));
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(retry)
+            .build();
+        let connection =
+            Connection::new_with_middware(client).with_base_url(Url::parse(&server.url()).unwrap());
+        mock::api_with_connection(connection)
+            .exec("SELECT 1")
+            .await
+            .unwrap();
+        unavailable.assert_async().await;
+        retried.assert_async().await;
+
+        let sent = sent.lock().unwrap();
+        let (first, second) = (query_pairs(&sent[0]), query_pairs(&sent[1]));
+        assert!(!first.contains_key("retryCount"));
+        assert_eq!(first["requestId"], second["requestId"]);
+    }
+}
@@ -0,0 +1,267 @@
+//! Mock Snowflake server for the tests of the request flows
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use mockito::{Matcher, Mock, ServerGuard};
+use serde_json::{json, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_core::span::Current;
+use url::Url;
+
+use crate::connection::Connection;
+use crate::session::Session;
+use crate::SnowflakeApi;
+
+pub const QUERY_PATH: &str = "/queries/v1/query-request";
+pub const LOGIN_PATH: &str = "/session/v1/login-request";
+
+/// Connection sending every request to the mock server, without the retry middleware
+pub fn connection(server: &ServerGuard) -> Connection {
+    let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build();
+    Connection::new_with_middware(client).with_base_url(Url::parse(&server.url()).unwrap())
+}
+
+/// Password authenticated client of the mock server, login has to be mocked with [`login`]
+pub fn api(server: &ServerGuard) -> SnowflakeApi {
+    api_with_connection(connection(server))
+}
+
+pub fn api_with_connection(connection: Connection) -> SnowflakeApi {
+    let connection = Arc::new(connection);
+    let session = Session::password_auth(
+        Arc::clone(&connection),
+        "xy12345",
+        None,
+        None,
+        None,
+        "user",
+        None,
+        "password",
+    );
+    SnowflakeApi::new(connection, session, "XY12345".to_string())
+}
+
+/// Successful login issuing tokens valid for an hour
+pub async fn login(server: &mut ServerGuard) -> Mock {
+    login_with_validity(server, 3600).await
+}
+
+/// Successful login issuing the session token valid for the given number of seconds
+pub async fn login_with_validity(server: &mut ServerGuard, validity_in_seconds: i64) -> Mock {
+    login_response(server, Matcher::Any, validity_in_seconds).await
+}
+
+/// Successful login of the request with the matching body, eg `Matcher::PartialJson`
+pub async fn login_matching(server: &mut ServerGuard, body: Matcher) -> Mock {
+    login_response(server, body, 3600).await
+}
+
+async fn login_response(server: &mut ServerGuard, body: Matcher, validity_in_seconds: i64) -> Mock {
+    server
+        .mock("POST", LOGIN_PATH)
+        .match_query(Matcher::Any)
+        .match_body(body)
+        .with_body(
+            json!({
+                "code": null,
+                "message": null,
+                "success": true,
+                "data": {
+                    "sessionId": 1,
+                    "token": "session-token",
+                    "masterToken": "master-token",
+                    "serverVersion": "8.0.0",
+                    "parameters": [],
+                    "sessionInfo": {
+                        "databaseName": null,
+                        "schemaName": null,
+                        "warehouseName": null,
+                        "roleName": "PUBLIC"
+                    },
+                    "masterValidityInSeconds": 14400,
+                    "validityInSeconds": validity_in_seconds
+                }
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await
+}
+
+/// Body of the successful JSON query response, `data` is merged into the defaults
+pub fn query_body(data: &Value) -> String {
+    let mut body = json!({
+        "code": null,
+        "message": null,
+        "success": true,
+        "data": {
+            "parameters": [],
+            "rowtype": [],
+            "rowset": [],
+            "total": 0,
+            "returned": 0,
+            "queryId": "01b2c3d4-0000-1111-0000-000000000001",
+            "queryResultFormat": "json",
+            "finalRoleName": "PUBLIC",
+            "statementTypeId": 4096,
+            "version": 1
+        }
+    });
+    for (key, value) in data.as_object().unwrap() {
+        body["data"][key] = value.clone();
+    }
+    body.to_string()
+}
+
+/// Body of the failed query response
+pub fn error_body(code: &str, message: &str, data: &Value) -> String {
+    let mut body = json!({
+        "code": code,
+        "message": message,
+        "success": false,
+        "data": {
+            "age": 0,
+            "errorCode": code,
+            "internalError": false,
+            "queryId": "01b2c3d4-0000-1111-0000-00000000ffff",
+            "sqlState": "42000"
+        }
+    });
+    for (key, value) in data.as_object().unwrap() {
+        body["data"][key] = value.clone();
+    }
+    body.to_string()
+}
+
+/// Span as seen by [`Spans`], field values are kept in their `Debug` form
+#[derive(Debug, Clone)]
+pub struct SpanRecord {
+    pub id: u64,
+    /// Explicit parent, or the span entered when this one was created
+    pub parent: Option<u64>,
+    pub name: &'static str,
+    pub fields: HashMap<&'static str, String>,
+}
+
+/// Event as seen by [`Spans`], the message is in the `message` field
+#[derive(Debug, Clone)]
+pub struct EventRecord {
+    pub level: Level,
+    pub fields: HashMap<&'static str, String>,
+}
+
+#[derive(Default)]
+struct SpansState {
+    spans: Vec<SpanRecord>,
+    events: Vec<EventRecord>,
+    metadata: Vec<&'static Metadata<'static>>,
+    entered: Vec<u64>,
+}
+
+/// Subscriber collecting the spans along with their recorded fields, and the events,
+/// install it with [`tracing::subscriber::set_default`] on the current-thread test runtime
+#[derive(Clone, Default)]
+pub struct Spans(Arc<Mutex<SpansState>>);
+
+impl Spans {
+    pub fn named(&self, name: &str) -> Vec<SpanRecord> {
+        let state = self.0.lock().unwrap();
+        state
+            .spans
+            .iter()
+            .filter(|span| span.name == name)
+            .cloned()
+            .collect()
+    }
+
+    pub fn events(&self, level: Level) -> Vec<EventRecord> {
+        let state = self.0.lock().unwrap();
+        state
+            .events
+            .iter()
+            .filter(|event| event.level == level)
+            .cloned()
+            .collect()
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut HashMap<&'static str, String>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name(), format!("{value:?}"));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name(), value.to_string());
+    }
+}
+
+impl Subscriber for Spans {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let mut state = self.0.lock().unwrap();
+        let id = state.spans.len() as u64 + 1;
+        let mut fields = HashMap::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        let parent = match attrs.parent() {
+            Some(parent) => Some(parent.into_u64()),
+            None if attrs.is_contextual() => state.entered.last().copied(),
+            None => None,
+        };
+        state.spans.push(SpanRecord {
+            id,
+            parent,
+            name: attrs.metadata().name(),
+            fields,
+        });
+        state.metadata.push(attrs.metadata());
+        Id::from_u64(id)
+    }
+
+    /// Needed by [`tracing::Span::current`], which is used to record the fields
+    fn current_span(&self) -> Current {
+        let state = self.0.lock().unwrap();
+        match state.entered.last() {
+            Some(&id) => Current::new(
+                Id::from_u64(id),
+                state.metadata[usize::try_from(id).unwrap() - 1],
+            ),
+            None => Current::none(),
+        }
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut state = self.0.lock().unwrap();
+        let index = usize::try_from(span.into_u64()).unwrap() - 1;
+        values.record(&mut FieldVisitor(&mut state.spans[index].fields));
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = HashMap::new();
+        event.record(&mut FieldVisitor(&mut fields));
+        self.0.lock().unwrap().events.push(EventRecord {
+            level: *event.metadata().level(),
+            fields,
+        });
+    }
+
+    fn enter(&self, span: &Id) {
+        self.0.lock().unwrap().entered.push(span.into_u64());
+    }
+
+    fn exit(&self, span: &Id) {
+        let mut state = self.0.lock().unwrap();
+        if let Some(index) = state.entered.iter().rposition(|id| *id == span.into_u64()) {
+            state.entered.remove(index);
+        }
+    }
+}
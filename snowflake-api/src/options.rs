@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+
+use crate::connection::QueryType;
+use crate::requests::ExecRequestParameters;
+use crate::{BindValue, MultiStatementCount, SnowflakeApiError};
+
+/// Format the server should use for the result rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultFormat {
+    Arrow,
+    /// Rows as JSON arrays of strings, cheaper for wide `VARIANT` columns
+    Json,
+}
+
+impl ResultFormat {
+    pub(crate) fn query_type(self) -> QueryType {
+        match self {
+            Self::Arrow => QueryType::ArrowQuery,
+            Self::Json => QueryType::JsonQuery,
+        }
+    }
+
+    /// Value of the `QUERY_RESULT_FORMAT` parameter
+    pub(crate) fn as_parameter(self) -> &'static str {
+        match self {
+            Self::Arrow => "ARROW",
+            Self::Json => "JSON",
+        }
+    }
+}
+
+/// Per-query settings for [`crate::SnowflakeApi::exec_with_options`],
+/// unset options keep the session defaults. Options are `Clone`, so a template could be
+/// built once and reused for many queries. Conflicting options are rejected when executed.
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct ExecOptions {
+    pub(crate) result_format: Option<ResultFormat>,
+    pub(crate) parameters: HashMap<String, serde_json::Value>,
+    pub(crate) query_tag: Option<String>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) binds: Vec<BindValue>,
+    pub(crate) multi_statement_count: Option<MultiStatementCount>,
+    pub(crate) cancellation_token: Option<CancellationToken>,
+    pub(crate) internal: bool,
+}
+
+impl ExecOptions {
+    /// Set any statement level parameter, eg `STATEMENT_TIMEOUT_IN_SECONDS` or `ROWS_PER_RESULTSET`.
+    /// Typed options, like [`ExecOptions::with_result_format`], override the same parameter set here.
+    pub fn with_parameter(mut self, name: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.parameters.insert(name.to_uppercase(), value.into());
+        self
+    }
+
+    pub(crate) fn request_parameters(&self) -> ExecRequestParameters {
+        ExecRequestParameters {
+            multi_statement_count: self
+                .multi_statement_count
+                .map(MultiStatementCount::as_parameter),
+            query_result_format: self.result_format.map(ResultFormat::as_parameter),
+            query_tag: self.query_tag.clone(),
+            statement_timeout_in_seconds: self.timeout.map(|timeout| timeout.as_secs()),
+            ..ExecRequestParameters::default()
+        }
+        .with_extra(&self.parameters)
+    }
+
+    /// Request the result in the given format regardless of the session `QUERY_RESULT_FORMAT`
+    pub fn with_result_format(mut self, result_format: ResultFormat) -> Self {
+        self.result_format = Some(result_format);
+        self
+    }
+
+    /// Tag of the statement, overrides `QUERY_TAG` of the session
+    pub fn with_query_tag(mut self, query_tag: &str) -> Self {
+        self.query_tag = Some(query_tag.to_string());
+        self
+    }
+
+    /// Server cancels the statement after this long, whole seconds of at least 1 are allowed
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Values of the `?` placeholders in order, see [`crate::SnowflakeApi::exec_with_binds`]
+    pub fn with_binds(mut self, binds: Vec<BindValue>) -> Self {
+        self.binds = binds;
+        self
+    }
+
+    /// Run the SQL text as a script of several statements, the result of the last one is returned
+    pub fn with_multi_statement_count(mut self, count: MultiStatementCount) -> Self {
+        self.multi_statement_count = Some(count);
+        self
+    }
+
+    /// Abort the statement on the server once the token is cancelled,
+    /// see [`crate::SnowflakeApi::exec_cancellable`]
+    pub fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Mark the statement as issued by the driver rather than the user, the server keeps
+    /// such statements apart in the query history. Only needed by tools built on top of the client.
+    pub fn with_internal(mut self, internal: bool) -> Self {
+        self.internal = internal;
+        self
+    }
+
+    /// Reject the combinations the server can't run, `is_file_transfer` is set for PUT and GET
+    pub(crate) fn validate(&self, is_file_transfer: bool) -> Result<(), SnowflakeApiError> {
+        let invalid = |reason: &str| Err(SnowflakeApiError::InvalidExecOptions(reason.to_string()));
+        if !self.binds.is_empty() && self.multi_statement_count.is_some() {
+            return invalid("bind values can't be used with multi-statement requests");
+        }
+        if self.timeout.is_some_and(|timeout| timeout.as_secs() == 0) {
+            // `0` would disable the timeout instead
+            return invalid("timeout has to be at least 1 second");
+        }
+        let has_request_options = self.result_format.is_some()
+            || !self.parameters.is_empty()
+            || self.query_tag.is_some()
+            || self.timeout.is_some()
+            || !self.binds.is_empty()
+            || self.multi_statement_count.is_some()
+            || self.internal;
+        if is_file_transfer && has_request_options {
+            return invalid("only the cancellation token applies to PUT and GET statements");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+
+    #[tokio::test]
+    async fn parameters_are_sent_with_the_statement() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({
+                "sqlText": "SELECT 1",
+                "parameters": {
+                    "ROWS_PER_RESULTSET": 10,
+                    "TIMEZONE": "UTC",
+                    "QUERY_TAG": "typed",
+                    "STATEMENT_TIMEOUT_IN_SECONDS": 30,
+                    "QUERY_RESULT_FORMAT": "JSON"
+                }
+            })))
+            .with_body(mock::query_body(&json!({})))
+            .create_async()
+            .await;
+
+        let options = ExecOptions::default()
+            .with_parameter("rows_per_resultset", 10)
+            .with_parameter("TIMEZONE", "UTC")
+            // typed options take precedence over the same untyped parameter
+            .with_parameter("query_tag", "untyped")
+            .with_query_tag("typed")
+            .with_timeout(Duration::from_secs(30))
+            .with_result_format(ResultFormat::Json);
+        mock::api(&server)
+            .exec_with_options("SELECT 1", &options)
+            .await
+            .unwrap();
+        query.assert_async().await;
+    }
+
+    #[test]
+    fn conflicting_options_are_rejected() {
+        let invalid = |options: ExecOptions, is_file_transfer| {
+            matches!(
+                options.validate(is_file_transfer),
+                Err(SnowflakeApiError::InvalidExecOptions(_))
+            )
+        };
+        assert!(invalid(
+            ExecOptions::default()
+                .with_binds(vec![BindValue::from(1)])
+                .with_multi_statement_count(MultiStatementCount::Any),
+            false
+        ));
+        assert!(invalid(
+            ExecOptions::default().with_timeout(Duration::from_millis(500)),
+            false
+        ));
+        assert!(invalid(
+            ExecOptions::default().with_parameter("TIMEZONE", "UTC"),
+            true
+        ));
+        assert!(ExecOptions::default()
+            .with_cancellation_token(CancellationToken::new())
+            .validate(true)
+            .is_ok());
+    }
+}
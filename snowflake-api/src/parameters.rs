@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use serde_json::Value;
+
+use crate::responses::NameValueParameter;
+
+pub const TIMEZONE: &str = "TIMEZONE";
+pub const CLIENT_STAGE_ARRAY_BINDING_THRESHOLD: &str = "CLIENT_STAGE_ARRAY_BINDING_THRESHOLD";
+pub const CLIENT_RESULT_CHUNK_SIZE: &str = "CLIENT_RESULT_CHUNK_SIZE";
+
+/// Session parameters as announced by the server. Login response carries the initial state,
+/// every query response carries the changes, eg after `ALTER SESSION SET TIMEZONE = ...`.
+/// Values are kept as raw JSON, typed accessors are provided for the parameters used by the client.
+#[derive(Debug, Default)]
+pub struct ParameterMap {
+    values: RwLock<HashMap<String, Value>>,
+}
+
+impl ParameterMap {
+    /// Apply parameter updates from the response
+    pub fn apply(&self, parameters: &[NameValueParameter]) {
+        if parameters.is_empty() {
+            return;
+        }
+
+        let mut values = self.values.write().unwrap();
+        for p in parameters {
+            log::trace!("Session parameter update: {} = {}", p.name, p.value);
+            values.insert(p.name.to_uppercase(), p.value.clone());
+        }
+    }
+
+    /// Forget all the parameters, used when a new session replaces the old one
+    pub fn clear(&self) {
+        self.values.write().unwrap().clear();
+    }
+
+    /// Raw value of the parameter, names are case-insensitive
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.values
+            .read()
+            .unwrap()
+            .get(&name.to_uppercase())
+            .cloned()
+    }
+
+    /// Timezone of the session, eg `America/Los_Angeles`, `TIMESTAMP_LTZ` values are shown in it
+    pub fn timezone(&self) -> Option<String> {
+        self.get(TIMEZONE)
+            .and_then(|v| v.as_str().map(ToString::to_string))
+    }
+
+    /// Number of bind values after which bindings should be uploaded to the stage
+    pub fn stage_array_binding_threshold(&self) -> Option<u64> {
+        self.get(CLIENT_STAGE_ARRAY_BINDING_THRESHOLD)
+            .as_ref()
+            .and_then(as_u64)
+    }
+
+    pub fn result_chunk_size(&self) -> Option<u64> {
+        self.get(CLIENT_RESULT_CHUNK_SIZE).as_ref().and_then(as_u64)
+    }
+}
+
+/// Numeric parameters could be sent both as numbers and strings
+fn as_u64(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
@@ -1,15 +1,23 @@
 use std::convert::TryFrom;
 
+use base64::Engine;
 use bytes::{Buf, Bytes};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::stream::{self, BoxStream, Stream, StreamExt, TryStreamExt};
 use polars_core::frame::DataFrame;
+use polars_core::prelude::{DataType, NamedFrom, Series, TimeUnit};
 use polars_io::ipc::IpcStreamReader;
-use polars_io::json::{JsonFormat, JsonReader};
 use polars_io::SerReader;
 use serde::de::Error;
-use serde_json::{Map, Value};
+use serde_json::Value;
 use thiserror::Error;
 
-use crate::{JsonResult, RawQueryResult};
+use crate::connection::QueryType;
+use crate::responses::{ExecResponse, SnowflakeType};
+use crate::rows::{FromSnowflakeValue, TypeError};
+use crate::{
+    query_response, FieldSchema, JsonResult, RawQueryResult, SnowflakeApi, SnowflakeApiError,
+};
 
 #[derive(Error, Debug)]
 pub enum PolarsCastError {
@@ -18,6 +26,9 @@ pub enum PolarsCastError {
 
     #[error(transparent)]
     PolarsError(#[from] polars_core::error::PolarsError),
+
+    #[error(transparent)]
+    TypeError(#[from] TypeError),
 }
 
 impl RawQueryResult {
@@ -30,46 +41,133 @@ impl RawQueryResult {
     }
 }
 
-fn dataframe_from_json(json_result: &JsonResult) -> Result<DataFrame, PolarsCastError> {
-    let objects = arrays_to_objects(json_result)?;
-    // fixme: serializing json again, is it possible to keep bytes? or implement casting?
-    let json_string = serde_json::to_string(&objects)?;
-    let reader = std::io::Cursor::new(json_string.as_bytes());
-    let df = JsonReader::new(reader)
-        .with_json_format(JsonFormat::Json)
-        .infer_schema_len(Some(5))
-        .finish()?;
-    Ok(df)
+impl SnowflakeApi {
+    /// Execute a single query and collect the result into a `DataFrame` chunk by chunk,
+    /// the downloaded chunk is dropped as soon as it's converted. See [`SnowflakeApi::exec_polars_streamed`].
+    pub async fn exec_polars(&self, sql: &str) -> Result<DataFrame, SnowflakeApiError> {
+        let mut frames = self.exec_polars_streamed(sql).await?;
+        let mut df: Option<DataFrame> = None;
+        while let Some(frame) = frames.try_next().await? {
+            match &mut df {
+                Some(df) => {
+                    df.vstack_mut(&frame).map_err(PolarsCastError::from)?;
+                }
+                None => df = Some(frame),
+            }
+        }
+        let mut df = df.unwrap_or_else(DataFrame::empty);
+        df.align_chunks();
+        Ok(df)
+    }
+
+    /// Execute a single query and yield a `DataFrame` per result chunk as the chunks arrive,
+    /// only a couple of chunks are downloaded ahead of the consumer.
+    /// Results of non-SELECT statements, eg `SHOW`, are sent as JSON and yielded as a single frame,
+    /// with the column types taken from the result metadata.
+    pub async fn exec_polars_streamed(
+        &self,
+        sql: &str,
+    ) -> Result<
+        impl Stream<Item = Result<DataFrame, SnowflakeApiError>> + Send + 'static,
+        SnowflakeApiError,
+    > {
+        let resp = self
+            .run_sql::<ExecResponse>(sql, QueryType::ArrowQuery)
+            .await?;
+        let mut resp = query_response(resp)?;
+        if resp.data.returned == 0 {
+            return Ok(stream::empty().boxed());
+        }
+        if resp.data.is_json() {
+            let df = self.raw_query_result(resp).await?.to_polars()?;
+            return Ok(stream::once(async { Ok(df) }).boxed());
+        }
+
+        let inline = match resp.data.rowset_base64.take() {
+            Some(base64) if !base64.is_empty() => {
+                let bytes = Bytes::from(base64::engine::general_purpose::STANDARD.decode(base64)?);
+                Some(Ok(dataframe_from_bytes(vec![bytes])?))
+            }
+            _ => None,
+        };
+        log::debug!("Streaming result of {} chunks", resp.data.chunks.len());
+
+        let chunks = self
+            .chunk_stream(resp.data.chunks, resp.data.chunk_headers, resp.data.span)
+            .and_then(|bytes| async move { Ok(dataframe_from_bytes(bytes)?) });
+        let frames: BoxStream<'static, _> = stream::iter(inline).chain(chunks).boxed();
+        Ok(frames)
+    }
 }
 
-/// This is required because the polars json reader expects an array of objects, and
-/// the snowflake json response is an array of arrays (without real column names).
-///
-/// This is apparent if you run a system query (not a select) like `SHOW DATABASES;`.
-fn arrays_to_objects(json_result: &JsonResult) -> Result<Value, PolarsCastError> {
-    let arrays: &Vec<Value> = json_result
+/// Columns are typed after the result metadata, the values are sent as text
+fn dataframe_from_json(json_result: &JsonResult) -> Result<DataFrame, PolarsCastError> {
+    let rows: &Vec<Value> = json_result
         .value
         .as_array()
-        .ok_or(serde_json::Error::custom("Input must be array an array"))?;
-    let names: Vec<String> = json_result.schema.iter().map(|s| s.name.clone()).collect();
-
-    let objects: Result<Vec<Value>, PolarsCastError> = arrays
+        .ok_or(serde_json::Error::custom("Input must be an array"))?;
+    let columns = json_result
+        .schema
         .iter()
-        .map(|array| {
-            array
-                .as_array()
-                .ok_or(serde_json::Error::custom("Input must be array of array"))
-                .map(|array| {
-                    // fixme: lots of copying
-                    let map: Map<String, Value> =
-                        names.clone().into_iter().zip(array.clone()).collect();
-                    Value::Object(map)
-                })
-                .map_err(PolarsCastError::SerdeError)
+        .enumerate()
+        .map(|(idx, field)| {
+            let values = rows
+                .iter()
+                .map(|row| row.get(idx).unwrap_or(&Value::Null))
+                .collect::<Vec<_>>();
+            json_series(field, &values)
         })
-        .collect();
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Timestamps are UTC, dates are days since epoch; `TIME`, binary and semi-structured
+/// values are kept as text
+fn json_series(field: &FieldSchema, values: &[&Value]) -> Result<Series, PolarsCastError> {
+    fn collect<T: FromSnowflakeValue>(
+        field: &FieldSchema,
+        values: &[&Value],
+    ) -> Result<Vec<Option<T>>, TypeError> {
+        values
+            .iter()
+            .map(|value| Option::<T>::from_snowflake_value(value, field))
+            .collect()
+    }
 
-    objects.map(Value::Array)
+    let name = field.name.as_str();
+    let series = match field.type_ {
+        // `NUMBER(38, 0)` could overflow `i64`
+        SnowflakeType::Fixed if field.scale.unwrap_or(0) == 0 => {
+            match collect::<i64>(field, values) {
+                Ok(ints) => Series::new(name, ints),
+                Err(_) => Series::new(name, collect::<f64>(field, values)?),
+            }
+        }
+        SnowflakeType::Fixed | SnowflakeType::Real => {
+            Series::new(name, collect::<f64>(field, values)?)
+        }
+        SnowflakeType::Boolean => Series::new(name, collect::<bool>(field, values)?),
+        SnowflakeType::Date => {
+            let days = collect::<NaiveDate>(field, values)?
+                .into_iter()
+                .map(|date| {
+                    date.and_then(|date| {
+                        i32::try_from((date - NaiveDate::default()).num_days()).ok()
+                    })
+                })
+                .collect::<Vec<_>>();
+            Series::new(name, days).cast(&DataType::Date)?
+        }
+        SnowflakeType::TimestampNtz | SnowflakeType::TimestampLtz | SnowflakeType::TimestampTz => {
+            let micros = collect::<DateTime<Utc>>(field, values)?
+                .into_iter()
+                .map(|ts| ts.map(|ts| ts.timestamp_micros()))
+                .collect::<Vec<_>>();
+            Series::new(name, micros).cast(&DataType::Datetime(TimeUnit::Microseconds, None))?
+        }
+        _ => Series::new(name, collect::<String>(field, values)?),
+    };
+    Ok(series)
 }
 
 fn dataframe_from_bytes(bytes: Vec<Bytes>) -> Result<DataFrame, PolarsCastError> {
@@ -89,3 +187,103 @@ impl TryFrom<RawQueryResult> for DataFrame {
         value.to_polars()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use arrow::array::Int64Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::ipc::writer::StreamWriter;
+    use arrow::record_batch::RecordBatch;
+    use mockito::Matcher;
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+    use crate::stream::PREFETCH_CHUNKS;
+
+    const CHUNKS: usize = 16;
+    const CHUNK_ROWS: usize = 50_000;
+
+    fn ipc_chunk(first: i64) -> Vec<u8> {
+        let schema = Arc::new(Schema::new(vec![Field::new("N", DataType::Int64, false)]));
+        let values: Vec<i64> = (first..).take(CHUNK_ROWS).collect();
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![Arc::new(Int64Array::from(values))],
+        )
+        .unwrap();
+        let mut writer = StreamWriter::try_new(Vec::new(), &schema).unwrap();
+        writer.write(&batch).unwrap();
+        writer.into_inner().unwrap()
+    }
+
+    #[tokio::test]
+    async fn streamed_frames_hold_only_prefetched_chunks() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let chunks: Vec<_> = (0..CHUNKS)
+            .map(|i| {
+                json!({
+                    "url": format!("{}/chunks/{i}", server.url()),
+                    "rowCount": CHUNK_ROWS,
+                    "uncompressedSize": CHUNK_ROWS * 8
+                })
+            })
+            .collect();
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(&json!({
+                "queryResultFormat": "arrow",
+                "rowset": null,
+                "rowsetBase64": "",
+                "total": CHUNKS * CHUNK_ROWS,
+                "returned": CHUNKS * CHUNK_ROWS,
+                "chunks": chunks
+            })))
+            .create_async()
+            .await;
+
+        let chunk_size = ipc_chunk(0).len();
+        let downloaded = Arc::new(AtomicUsize::new(0));
+        let mut chunk_mocks = vec![];
+        for i in 0..CHUNKS {
+            let downloaded = Arc::clone(&downloaded);
+            let first = i64::try_from(i * CHUNK_ROWS).unwrap();
+            chunk_mocks.push(
+                server
+                    .mock("GET", format!("/chunks/{i}").as_str())
+                    .with_body_from_request(move |_| {
+                        downloaded.fetch_add(1, Ordering::SeqCst);
+                        ipc_chunk(first)
+                    })
+                    .create_async()
+                    .await,
+            );
+        }
+
+        let api = mock::api(&server);
+        let mut frames = api.exec_polars_streamed("SELECT n FROM t").await.unwrap();
+        let mut consumed = 0;
+        let mut peak_held = 0;
+        while let Some(frame) = frames.try_next().await.unwrap() {
+            assert_eq!(frame.height(), CHUNK_ROWS);
+            let first = frame.column("N").unwrap().i64().unwrap().get(0);
+            assert_eq!(first, Some(i64::try_from(consumed * CHUNK_ROWS).unwrap()));
+            consumed += 1;
+            // slow consumer, the downloads could run ahead of it meanwhile
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            // the frame at hand and the chunks downloaded ahead of it
+            let held = downloaded.load(Ordering::SeqCst) - consumed + 1;
+            peak_held = peak_held.max(held);
+        }
+
+        assert_eq!(consumed, CHUNKS);
+        assert!(peak_held <= PREFETCH_CHUNKS + 1, "{peak_held} chunks held");
+        assert!(peak_held * chunk_size < CHUNKS * chunk_size / 4);
+    }
+}
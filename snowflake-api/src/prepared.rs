@@ -0,0 +1,300 @@
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+
+use crate::bind::{BindType, BindValue};
+use crate::connection::QueryType;
+use crate::describe::ColumnDescription;
+use crate::requests::ExecRequest;
+use crate::responses::{BindMetadata, ExecResponse};
+use crate::{query_response, QueryResult, SnowflakeApi, SnowflakeApiError};
+
+/// Statement described once by the server, bind values are checked and coerced
+/// against the placeholder types before every execution.
+/// Clones share the metadata, so they are cheap to hand out to the concurrent tasks.
+#[derive(Clone)]
+pub struct PreparedStatement<'a> {
+    api: &'a SnowflakeApi,
+    inner: Arc<Prepared>,
+}
+
+#[derive(Debug)]
+struct Prepared {
+    sql: String,
+    /// Number of placeholders, `None` if the server didn't report it
+    num_params: Option<usize>,
+    /// Types of the placeholders, `None` for the types the client doesn't check
+    param_types: Vec<Option<BindType>>,
+    columns: Vec<ColumnDescription>,
+}
+
+impl SnowflakeApi {
+    /// Describe the statement once, see [`PreparedStatement`].
+    /// Changes of the underlying objects are not tracked, they surface as server errors.
+    pub async fn prepare(&self, sql: &str) -> Result<PreparedStatement<'_>, SnowflakeApiError> {
+        let request = ExecRequest {
+            describe_only: true,
+            ..ExecRequest::new(sql)
+        };
+        let resp = self
+            .run_exec_request::<ExecResponse>(request, QueryType::JsonQuery)
+            .await?;
+        let data = query_response(resp)?.data;
+
+        let param_types: Vec<_> = data.meta_data_of_binds.iter().map(param_type).collect();
+        let num_params = data
+            .number_of_binds
+            .and_then(|n| usize::try_from(n).ok())
+            .or((!param_types.is_empty()).then_some(param_types.len()));
+        log::debug!("Prepared statement with {num_params:?} placeholders");
+
+        Ok(PreparedStatement {
+            api: self,
+            inner: Arc::new(Prepared {
+                sql: sql.to_string(),
+                num_params,
+                param_types,
+                columns: data.rowtype.into_iter().map(Into::into).collect(),
+            }),
+        })
+    }
+}
+
+impl PreparedStatement<'_> {
+    pub fn sql(&self) -> &str {
+        &self.inner.sql
+    }
+
+    /// Types of the `?` placeholders in order, `None` where the type isn't known
+    pub fn param_types(&self) -> &[Option<BindType>] {
+        &self.inner.param_types
+    }
+
+    /// Columns of the result
+    pub fn columns(&self) -> &[ColumnDescription] {
+        &self.inner.columns
+    }
+
+    /// Execute the statement with the bind values, invalid values are rejected
+    /// with [`SnowflakeApiError::InvalidBindings`] without sending the request
+    pub async fn execute(&self, binds: &[BindValue]) -> Result<QueryResult, SnowflakeApiError> {
+        let binds = self.coerce(binds)?;
+        self.api.exec_with_binds(&self.inner.sql, &binds).await
+    }
+
+    /// Check the number of the values and convert them to the placeholder types,
+    /// text is parsed for the numeric, boolean and date placeholders
+    pub fn coerce(&self, binds: &[BindValue]) -> Result<Vec<BindValue>, SnowflakeApiError> {
+        if let Some(num_params) = self.inner.num_params {
+            if binds.len() != num_params {
+                return Err(SnowflakeApiError::InvalidBindings(format!(
+                    "statement has {num_params} placeholders, got {} values",
+                    binds.len()
+                )));
+            }
+        }
+
+        binds
+            .iter()
+            .enumerate()
+            .map(|(idx, value)| match self.inner.param_types.get(idx) {
+                Some(Some(target)) => coerce_value(value, *target).ok_or_else(|| {
+                    SnowflakeApiError::InvalidBindings(format!(
+                        "value {} of type {:?} can't be bound to {target:?} placeholder",
+                        idx + 1,
+                        value.bind_type()
+                    ))
+                }),
+                _ => Ok(value.clone()),
+            })
+            .collect()
+    }
+}
+
+/// Types the client knows how to check, the rest is left to the server
+fn param_type(meta: &BindMetadata) -> Option<BindType> {
+    let type_ = match meta.type_.to_uppercase().as_str() {
+        "TEXT" => BindType::Text,
+        "FIXED" => BindType::Fixed,
+        "REAL" => BindType::Real,
+        "BOOLEAN" => BindType::Boolean,
+        "DATE" => BindType::Date,
+        "TIME" => BindType::Time,
+        "TIMESTAMP_NTZ" => BindType::TimestampNtz,
+        "TIMESTAMP_LTZ" => BindType::TimestampLtz,
+        "TIMESTAMP_TZ" => BindType::TimestampTz,
+        "BINARY" => BindType::Binary,
+        _ => return None,
+    };
+    Some(type_)
+}
+
+/// Largest integer magnitude `f64` holds exactly
+const MAX_EXACT_F64_INT: i64 = 1 << f64::MANTISSA_DIGITS;
+
+/// Conversions which keep the value only, eg integers beyond 2^53 are rejected for the `REAL`
+/// placeholder. Parsed text is rounded to the nearest `REAL` the way the server would do it.
+/// The server converts between the timestamp types by itself.
+fn coerce_value(value: &BindValue, target: BindType) -> Option<BindValue> {
+    let is_timestamp = |t: BindType| {
+        matches!(
+            t,
+            BindType::TimestampNtz | BindType::TimestampLtz | BindType::TimestampTz
+        )
+    };
+
+    match (value, target) {
+        (BindValue::Null(_), target) => Some(BindValue::Null(target)),
+        (value, target) if value.bind_type() == target => Some(value.clone()),
+        (value, target) if is_timestamp(value.bind_type()) && is_timestamp(target) => {
+            Some(value.clone())
+        }
+        // any value has a text form, the server parses it for the text placeholder as well
+        (value, BindType::Text) if value.bind_type() != BindType::Binary => Some(value.clone()),
+        #[allow(clippy::cast_precision_loss)] // exact within the checked range
+        (BindValue::Fixed(n), BindType::Real) => (-MAX_EXACT_F64_INT..=MAX_EXACT_F64_INT)
+            .contains(n)
+            .then_some(BindValue::Real(*n as f64)),
+        (BindValue::Text(s), BindType::Fixed) => {
+            let s = s.trim();
+            if let Ok(n) = s.parse::<i64>() {
+                Some(BindValue::Fixed(n))
+            } else {
+                is_decimal(s).then(|| BindValue::Decimal(s.to_string()))
+            }
+        }
+        (BindValue::Text(s), BindType::Real) => s.trim().parse().ok().map(BindValue::Real),
+        (BindValue::Text(s), BindType::Boolean) => match s.trim().to_lowercase().as_str() {
+            "true" | "t" | "yes" | "y" | "on" | "1" => Some(BindValue::Boolean(true)),
+            "false" | "f" | "no" | "n" | "off" | "0" => Some(BindValue::Boolean(false)),
+            _ => None,
+        },
+        (BindValue::Text(s), BindType::Date) => NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+            .ok()
+            .map(BindValue::Date),
+        _ => None,
+    }
+}
+
+fn is_decimal(s: &str) -> bool {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    let (int, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    !(int.is_empty() && fraction.is_empty())
+        && int.chars().all(|c| c.is_ascii_digit())
+        && fraction.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn only_exact_values_are_coerced() {
+        assert_eq!(
+            coerce_value(&BindValue::Fixed(1 << 53), BindType::Real),
+            Some(BindValue::Real(9_007_199_254_740_992.0))
+        );
+        assert_eq!(
+            coerce_value(&BindValue::Fixed(-(1 << 53)), BindType::Real),
+            Some(BindValue::Real(-9_007_199_254_740_992.0))
+        );
+        assert_eq!(
+            coerce_value(&BindValue::Fixed((1 << 53) + 1), BindType::Real),
+            None
+        );
+        assert_eq!(
+            coerce_value(&BindValue::Fixed(i64::MAX), BindType::Real),
+            None
+        );
+
+        assert_eq!(
+            coerce_value(&BindValue::Text(" 42 ".to_string()), BindType::Fixed),
+            Some(BindValue::Fixed(42))
+        );
+        assert_eq!(
+            coerce_value(
+                &BindValue::Text("12345678901234567890.5".to_string()),
+                BindType::Fixed
+            ),
+            Some(BindValue::Decimal("12345678901234567890.5".to_string()))
+        );
+        assert_eq!(
+            coerce_value(&BindValue::Text("1e5".to_string()), BindType::Fixed),
+            None
+        );
+        assert_eq!(
+            coerce_value(&BindValue::Text("Yes".to_string()), BindType::Boolean),
+            Some(BindValue::Boolean(true))
+        );
+        assert_eq!(
+            coerce_value(&BindValue::Text("2024-02-30".to_string()), BindType::Date),
+            None
+        );
+        assert_eq!(
+            coerce_value(&BindValue::Binary(vec![1]), BindType::Text),
+            None
+        );
+        assert_eq!(
+            coerce_value(&BindValue::Null(BindType::Text), BindType::Date),
+            Some(BindValue::Null(BindType::Date))
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_binds_are_rejected_before_sending() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let describe = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({ "describeOnly": true })))
+            .with_body(mock::query_body(&json!({
+                "numberOfBinds": 2,
+                "metaDataOfBinds": [
+                    {"type": "FIXED", "precision": 38, "scale": 0, "nullable": true},
+                    {"type": "DATE", "precision": null, "scale": null, "nullable": true}
+                ]
+            })))
+            .expect(1)
+            .create_async()
+            .await;
+        let execute = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .expect(0)
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let statement = api.prepare("INSERT INTO t VALUES (?, ?)").await.unwrap();
+        assert_eq!(
+            statement.param_types(),
+            [Some(BindType::Fixed), Some(BindType::Date)]
+        );
+
+        let Err(err) = statement
+            .execute(&["one".into(), "2024-01-15".into()])
+            .await
+        else {
+            panic!("binds are invalid");
+        };
+        assert_eq!(
+            err.to_string(),
+            "Bind values are invalid: value 1 of type Text can't be bound to Fixed placeholder"
+        );
+        let Err(err) = statement.execute(&[1.into()]).await else {
+            panic!("value is missing");
+        };
+        assert!(
+            matches!(err, SnowflakeApiError::InvalidBindings(_)),
+            "{err}"
+        );
+
+        describe.assert_async().await;
+        execute.assert_async().await;
+    }
+}
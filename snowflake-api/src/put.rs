@@ -4,7 +4,7 @@ use std::sync::Arc;
 
 use futures::stream::FuturesUnordered;
 use futures::TryStreamExt;
-use object_store::aws::AmazonS3Builder;
+use object_store::aws::{AmazonS3, AmazonS3Builder};
 use object_store::limit::LimitStore;
 use object_store::local::LocalFileSystem;
 use object_store::ObjectStore;
@@ -39,6 +39,22 @@ async fn put_to_s3(
     max_parallel_uploads: usize,
     max_file_size_threshold: i64,
 ) -> Result<(), SnowflakeApiError> {
+    let (s3, bucket_path) = s3_store(info)?;
+
+    let files = list_files(src_locations, max_file_size_threshold).await?;
+
+    for src_path in files.large_files {
+        put_file(&s3, &src_path, &bucket_path).await?;
+    }
+
+    let limit_store = LimitStore::new(s3, max_parallel_uploads);
+    put_files_par(files.small_files, &bucket_path, limit_store).await?;
+
+    Ok(())
+}
+
+/// Store with the temporary credentials of the stage, along with the path of the stage in the bucket
+pub(crate) fn s3_store(info: AwsPutGetStageInfo) -> Result<(AmazonS3, String), SnowflakeApiError> {
     // These constants are based on the snowflake website
     let (bucket_name, bucket_path) = info
         .location
@@ -53,16 +69,7 @@ async fn put_to_s3(
         .with_token(info.creds.aws_token)
         .build()?;
 
-    let files = list_files(src_locations, max_file_size_threshold).await?;
-
-    for src_path in files.large_files {
-        put_file(&s3, &src_path, bucket_path).await?;
-    }
-
-    let limit_store = LimitStore::new(s3, max_parallel_uploads);
-    put_files_par(files.small_files, bucket_path, limit_store).await?;
-
-    Ok(())
+    Ok((s3, bucket_path.to_string()))
 }
 
 /// Sorts upload files by whether they are larger or smaller than the threshold
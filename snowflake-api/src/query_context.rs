@@ -0,0 +1,210 @@
+use std::sync::Mutex;
+
+use crate::requests::{QueryContextDto, QueryContextEntryData, QueryContextEntryDto};
+use crate::responses::{QueryContext, QueryContextEntry};
+
+/// Number of entries the server expects the client to keep, matches other drivers
+const QUERY_CONTEXT_CACHE_SIZE: usize = 5;
+
+/// Opaque query context which is echoed back to the server with every query of the session,
+/// hybrid tables rely on it to let reads observe the preceding writes.
+/// Entries are kept by id, newer timestamps replace older ones, lowest priority goes first.
+#[derive(Debug, Default)]
+pub struct QueryContextCache {
+    entries: Mutex<Vec<QueryContextEntry>>,
+}
+
+impl QueryContextCache {
+    /// Merge the context returned with the query response
+    pub fn merge(&self, context: QueryContext) {
+        let mut entries = self.entries.lock().unwrap();
+        for entry in context.entries {
+            match entries.iter_mut().find(|e| e.id == entry.id) {
+                Some(existing) if existing.timestamp < entry.timestamp => *existing = entry,
+                Some(_) => {}
+                None => entries.push(entry),
+            }
+        }
+        entries.sort_by_key(|e| e.priority);
+        entries.truncate(QUERY_CONTEXT_CACHE_SIZE);
+    }
+
+    /// Forget the context, used when a new session replaces the old one
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Context to send with the next request, `None` until the server has sent any
+    pub fn to_dto(&self) -> Option<QueryContextDto> {
+        let entries = self.entries.lock().unwrap();
+        if entries.is_empty() {
+            return None;
+        }
+
+        Some(QueryContextDto {
+            entries: entries
+                .iter()
+                .map(|e| QueryContextEntryDto {
+                    id: e.id,
+                    timestamp: e.timestamp,
+                    priority: e.priority,
+                    context: QueryContextEntryData {
+                        base64_data: e.context.clone(),
+                    },
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use mockito::{Matcher, Mock, ServerGuard};
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+
+    fn entry(id: u64, timestamp: i64, priority: u64, context: &str) -> QueryContextEntry {
+        QueryContextEntry {
+            id,
+            timestamp,
+            priority,
+            context: Some(context.to_string()),
+        }
+    }
+
+    fn sent(cache: &QueryContextCache) -> serde_json::Value {
+        serde_json::to_value(cache.to_dto()).unwrap()
+    }
+
+    #[test]
+    fn newer_entries_replace_older_ones() {
+        let cache = QueryContextCache::default();
+        assert!(cache.to_dto().is_none());
+
+        cache.merge(QueryContext {
+            entries: vec![entry(0, 100, 0, "main"), entry(7, 100, 1, "table-a")],
+        });
+        cache.merge(QueryContext {
+            entries: vec![
+                entry(0, 200, 0, "main-newer"),
+                entry(7, 50, 1, "table-a-older"),
+            ],
+        });
+        assert_eq!(
+            sent(&cache),
+            json!({"entries": [
+                {"id": 0, "timestamp": 200, "priority": 0, "context": {"base64Data": "main-newer"}},
+                {"id": 7, "timestamp": 100, "priority": 1, "context": {"base64Data": "table-a"}}
+            ]})
+        );
+    }
+
+    #[test]
+    fn entries_are_sorted_by_priority_and_capped() {
+        let cache = QueryContextCache::default();
+        cache.merge(QueryContext {
+            entries: (0..7).rev().map(|id| entry(id, 1, id * 10, "x")).collect(),
+        });
+        let dto = cache.to_dto().unwrap();
+        let priorities: Vec<u64> = dto.entries.iter().map(|e| e.priority).collect();
+        assert_eq!(priorities, [0, 10, 20, 30, 40]);
+
+        cache.clear();
+        assert!(cache.to_dto().is_none());
+    }
+
+    fn context_body(context: &str) -> String {
+        mock::query_body(&json!({
+            "queryContext": {"entries": [
+                {"id": 0, "timestamp": 1_700_000_000, "priority": 0, "context": context}
+            ]}
+        }))
+    }
+
+    async fn query_with_context(server: &mut ServerGuard, context: &str, hits: usize) -> Mock {
+        server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({
+                "queryContextDTO": {"entries": [
+                    {"id": 0, "timestamp": 1_700_000_000, "priority": 0,
+                     "context": {"base64Data": context}}
+                ]}
+            })))
+            .with_body(mock::query_body(&json!({})))
+            .expect(hits)
+            .create_async()
+            .await
+    }
+
+    #[tokio::test]
+    async fn context_is_echoed_back_verbatim() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        // matched first, only the request carrying the context gets here
+        let echoed = query_with_context(&mut server, "CNzPwgI=", 1).await;
+        let first = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(context_body("CNzPwgI="))
+            .expect(1)
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        api.exec("INSERT INTO hybrid VALUES (1)").await.unwrap();
+        api.exec("SELECT * FROM hybrid").await.unwrap();
+
+        first.assert_async().await;
+        echoed.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn context_is_cleared_with_the_new_session() {
+        let mut server = mockito::Server::new_async().await;
+        // session token expires right away and the renewal fails, so the session is replaced
+        let login = mock::login_with_validity(&mut server, 0).await.expect(2);
+        let _renew = server
+            .mock("POST", "/session/token-request")
+            .match_query(Matcher::Any)
+            .with_body(
+                json!({
+                    "code": "390114",
+                    "message": "Master token has expired.",
+                    "success": false,
+                    "data": {}
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let sent = Arc::new(Mutex::new(vec![]));
+        let recorded = Arc::clone(&sent);
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body_from_request(move |request| {
+                let body: serde_json::Value =
+                    serde_json::from_slice(request.body().unwrap()).unwrap();
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push(body.get("queryContextDTO").cloned());
+                context_body("CNzPwgI=").into_bytes()
+            })
+            .expect(2)
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        api.exec("INSERT INTO hybrid VALUES (1)").await.unwrap();
+        api.exec("SELECT * FROM hybrid").await.unwrap();
+
+        login.assert_async().await;
+        assert_eq!(*sent.lock().unwrap(), [None, None]);
+    }
+}
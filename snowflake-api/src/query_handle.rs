@@ -0,0 +1,530 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::connection::QueryType;
+use crate::requests::{AbortRequest, ExecRequest};
+use crate::responses::{AbortResponse, AsyncExecResponse, QueryMonitoringResponse};
+use crate::{exec_error, QueryResult, SnowflakeApi, SnowflakeApiError, QUERY_NOT_FOUND};
+
+/// Identified SQL statement is not currently executing
+const QUERY_NOT_EXECUTING: &str = "000605";
+
+const POLL_INITIAL_DELAY: Duration = Duration::from_millis(250);
+const POLL_MAX_DELAY: Duration = Duration::from_secs(5);
+/// Freshly submitted query could be missing from monitoring for a moment
+const NOT_FOUND_POLLS: u32 = 5;
+
+/// Execution status of the asynchronously submitted query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryStatus {
+    /// Waiting for the warehouse, or not yet registered by the server
+    Queued,
+    Running,
+    /// Waiting for the lock held by another transaction
+    Blocked,
+    Success,
+    Failed {
+        code: String,
+        message: String,
+    },
+    Aborted {
+        code: String,
+        message: String,
+    },
+    /// Query doesn't exist, or its monitoring data has expired
+    NotFound,
+    /// Status not known to the client, treated as non-terminal
+    Other(String),
+}
+
+impl QueryStatus {
+    fn new(status: &str, code: String, message: String) -> Self {
+        match status {
+            "QUEUED" | "QUEUED_REPAIRING_WAREHOUSE" | "RESUMING_WAREHOUSE" | "NO_DATA" => {
+                Self::Queued
+            }
+            "RUNNING" | "ABORTING" => Self::Running,
+            "BLOCKED" => Self::Blocked,
+            "SUCCESS" => Self::Success,
+            "FAILED_WITH_ERROR" | "FAILED_WITH_INCIDENT" => Self::Failed { code, message },
+            "ABORTED" | "DISCONNECTED" => Self::Aborted { code, message },
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            Self::Success | Self::Failed { .. } | Self::Aborted { .. }
+        )
+    }
+}
+
+type ProgressCallback = Arc<dyn Fn(Duration, &QueryStatus) + Send + Sync>;
+
+/// How [`QueryHandle::wait_with_policy`] polls the status of the query
+#[derive(Clone)]
+#[must_use]
+pub struct PollPolicy {
+    max_interval: Duration,
+    max_duration: Option<Duration>,
+    cancel_on_timeout: bool,
+    on_progress: Option<ProgressCallback>,
+}
+
+/// Backoff capped at 5 seconds, waits indefinitely
+impl Default for PollPolicy {
+    fn default() -> Self {
+        Self {
+            max_interval: POLL_MAX_DELAY,
+            max_duration: None,
+            cancel_on_timeout: true,
+            on_progress: None,
+        }
+    }
+}
+
+impl Debug for PollPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PollPolicy")
+            .field("max_interval", &self.max_interval)
+            .field("max_duration", &self.max_duration)
+            .field("cancel_on_timeout", &self.cancel_on_timeout)
+            .field("on_progress", &self.on_progress.is_some())
+            .finish()
+    }
+}
+
+impl PollPolicy {
+    /// Upper bound of the exponential backoff between the status polls
+    pub fn with_max_interval(mut self, max_interval: Duration) -> Self {
+        self.max_interval = max_interval.max(POLL_INITIAL_DELAY);
+        self
+    }
+
+    /// Give up waiting with [`SnowflakeApiError::ResultPollTimeout`] after this long
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Whether the query is cancelled once `max_duration` runs out, enabled by default
+    pub fn with_cancel_on_timeout(mut self, cancel_on_timeout: bool) -> Self {
+        self.cancel_on_timeout = cancel_on_timeout;
+        self
+    }
+
+    /// Called after every poll with the time elapsed since the wait started and the status
+    pub fn with_progress(
+        mut self,
+        on_progress: impl Fn(Duration, &QueryStatus) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_progress = Some(Arc::new(on_progress));
+        self
+    }
+}
+
+/// Query status along with the timing reported by the monitoring endpoint
+#[derive(Debug, Clone)]
+pub struct QueryDetails {
+    pub status: QueryStatus,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    /// Time spent so far for running queries
+    pub total_duration: Option<Duration>,
+    pub warehouse_name: Option<String>,
+}
+
+/// Reference to the query running on the server. Handle is just the query id and the account,
+/// so it could be serialized and picked up by another process with its own [`SnowflakeApi`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueryHandle {
+    query_id: String,
+    account_identifier: String,
+}
+
+impl QueryHandle {
+    pub fn new(query_id: &str, account_identifier: &str) -> Self {
+        Self {
+            query_id: query_id.to_string(),
+            account_identifier: account_identifier.to_uppercase(),
+        }
+    }
+
+    pub fn query_id(&self) -> &str {
+        &self.query_id
+    }
+
+    pub fn account_identifier(&self) -> &str {
+        &self.account_identifier
+    }
+
+    /// Current execution status, query has to be submitted by the same user
+    pub async fn status(&self, api: &SnowflakeApi) -> Result<QueryStatus, SnowflakeApiError> {
+        self.check_account(api)?;
+        api.query_status(&self.query_id).await
+    }
+
+    /// Poll the status with exponential backoff until the query finishes.
+    /// Failed and aborted queries are reported as [`SnowflakeApiError::ApiError`].
+    pub async fn wait(&self, api: &SnowflakeApi) -> Result<(), SnowflakeApiError> {
+        self.wait_with_policy(api, &PollPolicy::default()).await
+    }
+
+    /// Same as [`QueryHandle::wait`], with the backoff, timeout and progress reporting
+    /// of the policy. Query is cancelled on timeout unless the policy says otherwise.
+    pub async fn wait_with_policy(
+        &self,
+        api: &SnowflakeApi,
+        policy: &PollPolicy,
+    ) -> Result<(), SnowflakeApiError> {
+        let started = Instant::now();
+        let mut delay = POLL_INITIAL_DELAY;
+        let mut polls = 0;
+        loop {
+            polls += 1;
+            let status = self.status(api).await?;
+            if let Some(on_progress) = &policy.on_progress {
+                on_progress(started.elapsed(), &status);
+            }
+            match status {
+                QueryStatus::Success => return Ok(()),
+                QueryStatus::Failed { code, message } | QueryStatus::Aborted { code, message } => {
+                    return Err(SnowflakeApiError::ApiError {
+                        code,
+                        message,
+                        query_id: Some(self.query_id.clone()),
+                    })
+                }
+                QueryStatus::NotFound if polls > NOT_FOUND_POLLS => {
+                    return Err(SnowflakeApiError::QueryNotFound(self.query_id.clone()))
+                }
+                status => {
+                    log::trace!("Query {} is {status:?}", self.query_id);
+                    if let Some(max_duration) = policy.max_duration {
+                        let remaining = max_duration.saturating_sub(started.elapsed());
+                        if remaining.is_zero() {
+                            return Err(self.timed_out(api, policy).await);
+                        }
+                        delay = delay.min(remaining);
+                    }
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(policy.max_interval);
+                }
+            }
+        }
+    }
+
+    async fn timed_out(&self, api: &SnowflakeApi, policy: &PollPolicy) -> SnowflakeApiError {
+        if policy.cancel_on_timeout {
+            log::info!("Cancelling query {} after the wait timeout", self.query_id);
+            if let Err(e) = self.cancel(api).await {
+                log::warn!("Failed to cancel query {}: {e}", self.query_id);
+            }
+        }
+        SnowflakeApiError::ResultPollTimeout(self.query_id.clone())
+    }
+
+    /// Request cancellation of the query, it doesn't wait for the query to stop
+    pub async fn cancel(&self, api: &SnowflakeApi) -> Result<(), SnowflakeApiError> {
+        self.check_account(api)?;
+        api.cancel_query(&self.query_id).await
+    }
+
+    /// Wait for the query to finish and download its result
+    pub async fn fetch_result(&self, api: &SnowflakeApi) -> Result<QueryResult, SnowflakeApiError> {
+        self.wait(api).await?;
+        api.fetch_result(&self.query_id).await
+    }
+
+    fn check_account(&self, api: &SnowflakeApi) -> Result<(), SnowflakeApiError> {
+        if self.account_identifier == api.account_identifier {
+            Ok(())
+        } else {
+            Err(SnowflakeApiError::InvalidQueryHandle(self.query_id.clone()))
+        }
+    }
+}
+
+impl SnowflakeApi {
+    /// Request cancellation of the query by its id, it doesn't wait for the query to stop.
+    /// Queries which have already finished are not reported as an error.
+    pub async fn cancel_query(&self, query_id: &str) -> Result<(), SnowflakeApiError> {
+        check_query_id(query_id)?;
+        match self
+            .query_internal(&format!("SELECT SYSTEM$CANCEL_QUERY('{query_id}')"))
+            .await
+        {
+            Err(SnowflakeApiError::ApiError { code, .. }) if is_finished_code(&code) => {
+                log::debug!("Query {query_id} has finished before it was cancelled");
+                Ok(())
+            }
+            res => res.map(|_| ()),
+        }
+    }
+
+    /// Abort all the queries of this client which are still waiting for the result,
+    /// eg on shutdown. All of the requests are attempted, the first error is returned.
+    pub async fn cancel_all(&self) -> Result<(), SnowflakeApiError> {
+        let requests: Vec<(String, String)> = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, sql)| (id.clone(), sql.clone()))
+            .collect();
+        if requests.is_empty() {
+            return Ok(());
+        }
+
+        log::info!("Aborting {} in-flight requests", requests.len());
+        let mut result = Ok(());
+        for (request_id, sql_text) in requests {
+            let res = self.abort_request(request_id, sql_text).await;
+            if result.is_ok() {
+                result = res;
+            }
+        }
+        result
+    }
+
+    /// Abort the outstanding queries and close the session, giving up after `deadline`.
+    /// Queries which finish in the meantime are not reported as errors.
+    pub async fn shutdown(&self, deadline: Duration) -> Result<(), SnowflakeApiError> {
+        tokio::time::timeout(deadline, async {
+            let aborted = self.cancel_all().await;
+            let closed = self.close_session().await;
+            aborted.and(closed)
+        })
+        .await
+        .map_err(|_| SnowflakeApiError::ShutdownTimeout)?
+    }
+
+    /// Abort the request by the id it was sent with, finished requests are not reported as an error
+    pub(crate) async fn abort_request(
+        &self,
+        request_id: String,
+        sql_text: String,
+    ) -> Result<(), SnowflakeApiError> {
+        let parts = self.session.get_token().await?;
+        let resp = self
+            .connection
+            .request::<AbortResponse>(
+                QueryType::AbortRequest,
+                &self.account_identifier,
+                &[],
+                Some(&parts.session_token_auth_header),
+                AbortRequest {
+                    sql_text,
+                    request_id,
+                },
+            )
+            .await;
+
+        match resp {
+            Ok(r) if r.success => Ok(()),
+            // request could finish while the abort is on its way
+            Ok(r) if r.code.as_deref().is_some_and(is_finished_code) => Ok(()),
+            Ok(r) => Err(SnowflakeApiError::ApiError {
+                code: r.code.unwrap_or_default(),
+                message: r.message.unwrap_or_default(),
+                query_id: None,
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Execution status of the query submitted by the same user, see [`SnowflakeApi::query_details`]
+    pub async fn query_status(&self, query_id: &str) -> Result<QueryStatus, SnowflakeApiError> {
+        Ok(self.query_details(query_id).await?.status)
+    }
+
+    /// Status and timing of the query as reported by the monitoring endpoint.
+    /// Unknown and expired queries are reported as [`QueryStatus::NotFound`].
+    pub async fn query_details(&self, query_id: &str) -> Result<QueryDetails, SnowflakeApiError> {
+        check_query_id(query_id)?;
+        let parts = self.session.get_token().await?;
+        let resp = self
+            .connection
+            .get_request::<QueryMonitoringResponse>(
+                QueryType::QueryMonitoring {
+                    query_id: query_id.to_string(),
+                },
+                &self.account_identifier,
+                &[],
+                Some(&parts.session_token_auth_header),
+            )
+            .await?;
+
+        if !resp.success {
+            return Err(SnowflakeApiError::ApiError {
+                code: resp.code.unwrap_or_default(),
+                message: resp.message.unwrap_or_default(),
+                query_id: Some(query_id.to_string()),
+            });
+        }
+
+        let Some(entry) = resp.data.and_then(|d| d.queries.into_iter().next()) else {
+            return Ok(QueryDetails {
+                status: QueryStatus::NotFound,
+                start_time: None,
+                end_time: None,
+                total_duration: None,
+                warehouse_name: None,
+            });
+        };
+        let code = match entry.error_code {
+            Some(serde_json::Value::String(s)) => s,
+            Some(serde_json::Value::Null) | None => String::new(),
+            Some(other) => other.to_string(),
+        };
+
+        Ok(QueryDetails {
+            status: QueryStatus::new(&entry.status, code, entry.error_message.unwrap_or_default()),
+            start_time: entry.start_time.and_then(epoch_millis),
+            end_time: entry.end_time.and_then(epoch_millis),
+            total_duration: entry
+                .total_duration
+                .and_then(|ms| u64::try_from(ms).ok())
+                .map(Duration::from_millis),
+            warehouse_name: entry.warehouse_name.filter(|w| !w.is_empty()),
+        })
+    }
+
+    /// Submit the query without waiting for it to finish, use the handle to follow up on it
+    pub async fn exec_async(&self, sql: &str) -> Result<QueryHandle, SnowflakeApiError> {
+        let resp = self
+            .run_exec_request::<AsyncExecResponse>(
+                ExecRequest {
+                    async_exec: true,
+                    ..ExecRequest::new(sql)
+                },
+                QueryType::ArrowQuery,
+            )
+            .await?;
+
+        match resp {
+            AsyncExecResponse::Accepted(r) => {
+                log::debug!("Query {} was submitted", r.data.query_id);
+                Ok(QueryHandle::new(&r.data.query_id, &self.account_identifier))
+            }
+            AsyncExecResponse::Error(e) => Err(exec_error(e)),
+        }
+    }
+
+    /// Submit the query asynchronously, wait for it according to the policy and fetch its result.
+    /// Unlike [`SnowflakeApi::exec`] no request is kept open while the query runs.
+    pub async fn exec_and_wait(
+        &self,
+        sql: &str,
+        poll: &PollPolicy,
+    ) -> Result<QueryResult, SnowflakeApiError> {
+        let handle = self.exec_async(sql).await?;
+        handle.wait_with_policy(self, poll).await?;
+        self.fetch_result(handle.query_id()).await
+    }
+}
+
+/// Query id is interpolated into SQL and URL paths, make sure it can't escape the string literal
+/// or point to another endpoint
+fn check_query_id(query_id: &str) -> Result<(), SnowflakeApiError> {
+    if !query_id.is_empty()
+        && query_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        Ok(())
+    } else {
+        Err(SnowflakeApiError::InvalidQueryHandle(query_id.to_string()))
+    }
+}
+
+/// Monitoring sends `0` for the timestamps which aren't known yet
+fn epoch_millis(ms: i64) -> Option<DateTime<Utc>> {
+    if ms > 0 {
+        DateTime::from_timestamp_millis(ms)
+    } else {
+        None
+    }
+}
+
+/// Query isn't running anymore, or is already gone
+fn is_finished_code(code: &str) -> bool {
+    code == QUERY_NOT_EXECUTING || code == QUERY_NOT_FOUND
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+
+    #[tokio::test]
+    async fn query_details_are_reported() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _monitoring = server
+            .mock(
+                "GET",
+                "/monitoring/queries/01b2c3d4-0000-1111-0000-000000000001",
+            )
+            .match_query(Matcher::Any)
+            .with_body(
+                json!({
+                    "code": null,
+                    "message": null,
+                    "success": true,
+                    "data": {"queries": [{
+                        "id": "01b2c3d4-0000-1111-0000-000000000001",
+                        "status": "FAILED_WITH_ERROR",
+                        "errorCode": 100_038,
+                        "errorMessage": "Numeric value 'x' is not recognized",
+                        "startTime": 1_705_320_000_000_i64,
+                        "endTime": 0,
+                        "totalDuration": 1500,
+                        "warehouseName": ""
+                    }]}
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let details = api
+            .query_details("01b2c3d4-0000-1111-0000-000000000001")
+            .await
+            .unwrap();
+        assert!(
+            matches!(&details.status, QueryStatus::Failed { code, .. } if code == "100038"),
+            "{:?}",
+            details.status
+        );
+        assert_eq!(
+            details.start_time,
+            DateTime::from_timestamp_millis(1_705_320_000_000)
+        );
+        assert_eq!(details.end_time, None);
+        assert_eq!(details.total_duration, Some(Duration::from_millis(1500)));
+        assert_eq!(details.warehouse_name, None);
+    }
+
+    #[tokio::test]
+    async fn invalid_query_ids_are_rejected() {
+        let server = mockito::Server::new_async().await;
+        let api = mock::api(&server);
+        for query_id in ["", "../../session", "01b2c3d4?delete=true"] {
+            let err = api.query_details(query_id).await.unwrap_err();
+            assert!(
+                matches!(&err, SnowflakeApiError::InvalidQueryHandle(id) if id == query_id),
+                "{err}"
+            );
+        }
+    }
+}
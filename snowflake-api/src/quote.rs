@@ -0,0 +1,216 @@
+//! Quoting of the SQL parts which can't be bound, eg object names and stage paths.
+//!
+//! Bind values, see [`crate::BindValue`], are preferred for everything else.
+
+use crate::SnowflakeApiError;
+
+/// Double-quoted identifier, the name is taken as is, so it's case-sensitive:
+/// objects created with unquoted names are stored in upper case, eg `MY_TABLE`
+pub fn quote_ident(name: &str) -> Result<String, SnowflakeApiError> {
+    if name.is_empty() {
+        return Err(unquotable(name, "identifier is empty"));
+    }
+    reject_control_chars(name)?;
+    Ok(format!("\"{}\"", name.replace('"', "\"\"")))
+}
+
+/// Single-quoted string literal, backslashes and quotes are escaped
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Stage location, eg `@my_stage/path with spaces/`, quoted so it could contain any characters.
+/// Location has to start with `@`, `@~` is the user stage and `@%table` the table stage.
+pub fn quote_stage_path(path: &str) -> Result<String, SnowflakeApiError> {
+    if !path.starts_with('@') || path.len() == 1 {
+        return Err(unquotable(
+            path,
+            "stage path has to start with `@` and the stage name",
+        ));
+    }
+    reject_control_chars(path)?;
+    Ok(quote_literal(path))
+}
+
+/// Statement assembled from the typed fragments, the SQL text itself can only be static,
+/// so no user input ends up in it unquoted
+///
+/// ```rust
+/// use snowflake_api::quote::SqlBuilder;
+/// # fn main() -> Result<(), snowflake_api::SnowflakeApiError> {
+/// let sql = SqlBuilder::new("CREATE TABLE ")
+///     .ident("My Table")?
+///     .sql(" (id INT) COMMENT = ")
+///     .literal("it's mine")
+///     .build();
+/// assert_eq!(sql, r#"CREATE TABLE "My Table" (id INT) COMMENT = 'it\'s mine'"#);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct SqlBuilder {
+    sql: String,
+}
+
+impl SqlBuilder {
+    pub fn new(sql: &'static str) -> Self {
+        Self {
+            sql: sql.to_string(),
+        }
+    }
+
+    pub fn sql(mut self, sql: &'static str) -> Self {
+        self.sql.push_str(sql);
+        self
+    }
+
+    /// See [`quote_ident`]
+    pub fn ident(mut self, name: &str) -> Result<Self, SnowflakeApiError> {
+        self.sql.push_str(&quote_ident(name)?);
+        Ok(self)
+    }
+
+    /// Dot-separated name, each part is quoted with [`quote_ident`]
+    pub fn qualified_ident(mut self, parts: &[&str]) -> Result<Self, SnowflakeApiError> {
+        let parts = parts
+            .iter()
+            .map(|part| quote_ident(part))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.sql.push_str(&parts.join("."));
+        Ok(self)
+    }
+
+    /// See [`quote_literal`]
+    pub fn literal(mut self, value: &str) -> Self {
+        self.sql.push_str(&quote_literal(value));
+        self
+    }
+
+    /// See [`quote_stage_path`]
+    pub fn stage_path(mut self, path: &str) -> Result<Self, SnowflakeApiError> {
+        self.sql.push_str(&quote_stage_path(path)?);
+        Ok(self)
+    }
+
+    pub fn build(self) -> String {
+        self.sql
+    }
+}
+
+fn reject_control_chars(value: &str) -> Result<(), SnowflakeApiError> {
+    if value.chars().any(char::is_control) {
+        Err(unquotable(value, "control characters are not allowed"))
+    } else {
+        Ok(())
+    }
+}
+
+fn unquotable(value: &str, reason: &'static str) -> SnowflakeApiError {
+    SnowflakeApiError::Unquotable {
+        value: value.to_string(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_are_quoted_as_is() {
+        assert_eq!(quote_ident("my_table").unwrap(), r#""my_table""#);
+        assert_eq!(quote_ident("My Table").unwrap(), r#""My Table""#);
+        assert_eq!(quote_ident("Zürich_Ümlaut").unwrap(), r#""Zürich_Ümlaut""#);
+        assert_eq!(quote_ident("表格").unwrap(), r#""表格""#);
+    }
+
+    #[test]
+    fn embedded_quotes_of_identifiers_are_doubled() {
+        assert_eq!(quote_ident(r#"a"b"#).unwrap(), r#""a""b""#);
+        assert_eq!(quote_ident(r#""quoted""#).unwrap(), r#""""quoted""""#);
+        assert_eq!(quote_ident("it's").unwrap(), r#""it's""#);
+    }
+
+    #[test]
+    fn unquotable_identifiers_are_rejected() {
+        for name in ["", "a\nb", "a\0b", "tab\there", "\u{7f}", "\u{85}"] {
+            assert!(
+                matches!(quote_ident(name), Err(SnowflakeApiError::Unquotable { .. })),
+                "{name:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn literal_quotes_are_backslash_escaped() {
+        assert_eq!(quote_literal("plain"), "'plain'");
+        assert_eq!(quote_literal(""), "''");
+        assert_eq!(quote_literal("it's"), r"'it\'s'");
+        // doubled quotes are taken literally, not as the escaped quote
+        assert_eq!(quote_literal("it''s"), r"'it\'\'s'");
+        // already escaped quote can't end the literal
+        assert_eq!(quote_literal(r"it\'s"), r"'it\\\'s'");
+        assert_eq!(quote_literal(r"trailing\"), r"'trailing\\'");
+        assert_eq!(quote_literal("naïve ☃"), "'naïve ☃'");
+    }
+
+    #[test]
+    fn stage_paths_are_quoted() {
+        assert_eq!(
+            quote_stage_path("@my_stage/path with spaces/").unwrap(),
+            "'@my_stage/path with spaces/'"
+        );
+        assert_eq!(quote_stage_path("@~").unwrap(), "'@~'");
+        assert_eq!(
+            quote_stage_path("@%orders/o'brien.csv").unwrap(),
+            r"'@%orders/o\'brien.csv'"
+        );
+    }
+
+    #[test]
+    fn invalid_stage_paths_are_rejected() {
+        for path in [
+            "",
+            "@",
+            "my_stage/file.csv",
+            "'@my_stage'",
+            "@stage/a\nb",
+            "@stage/\r",
+        ] {
+            assert!(
+                matches!(
+                    quote_stage_path(path),
+                    Err(SnowflakeApiError::Unquotable { .. })
+                ),
+                "{path:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn builder_quotes_every_fragment() {
+        let sql = SqlBuilder::new("COPY INTO ")
+            .qualified_ident(&["DB", "my schema", r#"t"1"#])
+            .unwrap()
+            .sql(" FROM ")
+            .stage_path("@stage/dir with spaces/")
+            .unwrap()
+            .sql(" PATTERN = ")
+            .literal(r".*\.csv")
+            .build();
+        assert_eq!(
+            sql,
+            r#"COPY INTO "DB"."my schema"."t""1" FROM '@stage/dir with spaces/' PATTERN = '.*\\.csv'"#
+        );
+    }
+
+    #[test]
+    fn builder_fails_on_unquotable_fragment() {
+        assert!(SqlBuilder::new("DROP TABLE ").ident("bad\nname").is_err());
+        assert!(SqlBuilder::new("SELECT 1 FROM ")
+            .qualified_ident(&["db", ""])
+            .is_err());
+        assert!(SqlBuilder::new("LIST ").stage_path("stage").is_err());
+    }
+}
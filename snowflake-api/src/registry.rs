@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::lock::Mutex;
+use reqwest_middleware::ClientWithMiddleware;
+
+use crate::connection::Connection;
+use crate::{AuthArgs, SnowflakeApi, SnowflakeApiBuilder, SnowflakeApiError};
+
+/// Named collection of Snowflake accounts, eg `prod`, `dev`, `partner`.
+/// Clients are created on first use and share the same HTTP client (and connection pool).
+/// Authentication happens on the first request of each client, so failure to log into
+/// one of the accounts doesn't affect the others.
+pub struct SnowflakeRegistry {
+    client: ClientWithMiddleware,
+    configs: HashMap<String, AuthArgs>,
+    clients: Mutex<HashMap<String, Arc<SnowflakeApi>>>,
+}
+
+impl SnowflakeRegistry {
+    pub fn new() -> Result<Self, SnowflakeApiError> {
+        let client = Connection::default_client_builder(None)?.build();
+        Ok(Self::with_client(client))
+    }
+
+    /// Use custom HTTP client for all of the registered accounts
+    pub fn with_client(client: ClientWithMiddleware) -> Self {
+        Self {
+            client,
+            configs: HashMap::new(),
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Register account configuration under the given name, replaces existing one.
+    /// Already created client for the name is kept until [`SnowflakeRegistry::close`].
+    #[must_use]
+    pub fn with_account(mut self, name: impl Into<String>, auth: AuthArgs) -> Self {
+        self.register(name, auth);
+        self
+    }
+
+    pub fn register(&mut self, name: impl Into<String>, auth: AuthArgs) {
+        self.configs.insert(name.into(), auth);
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.configs.keys().map(String::as_str)
+    }
+
+    /// Get the client for the named account, creating it on the first call
+    pub async fn get(&self, name: &str) -> Result<Arc<SnowflakeApi>, SnowflakeApiError> {
+        let mut clients = self.clients.lock().await;
+        if let Some(api) = clients.get(name) {
+            return Ok(Arc::clone(api));
+        }
+
+        let auth = self
+            .configs
+            .get(name)
+            .ok_or_else(|| SnowflakeApiError::UnknownConnection(name.to_string()))?;
+        log::debug!("Creating client for `{name}`");
+        let api = Arc::new(
+            SnowflakeApiBuilder::new(auth.clone())
+                .with_client(self.client.clone())
+                .build()?,
+        );
+        clients.insert(name.to_string(), Arc::clone(&api));
+
+        Ok(api)
+    }
+
+    /// Close sessions of all created clients. Every session is closed even if some of them fail,
+    /// the first error is returned.
+    pub async fn close(&self) -> Result<(), SnowflakeApiError> {
+        let clients: Vec<_> = self.clients.lock().await.drain().collect();
+
+        let mut res = Ok(());
+        for (name, api) in clients {
+            if let Err(e) = api.close_session().await {
+                log::warn!("Failed to close session of `{name}`: {e}");
+                if res.is_ok() {
+                    res = Err(e);
+                }
+            }
+        }
+
+        res
+    }
+}
@@ -1,12 +1,155 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
 use serde::Serialize;
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ExecRequest {
     pub sql_text: String,
     pub async_exec: bool,
     pub sequence_id: u64,
     pub is_internal: bool,
+    /// Only compile the statement and return the result metadata, without any rows
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub describe_only: bool,
+    #[serde(skip_serializing_if = "ExecRequestParameters::is_empty")]
+    pub parameters: ExecRequestParameters,
+    /// Bind values by the 1-based position of the placeholder
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub bindings: BTreeMap<String, Binding>,
+    /// Stage location of the CSV file with bind values, used instead of `bindings` for large batches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bind_stage: Option<String>,
+    /// Query context of the session, see [`crate::query_context::QueryContextCache`]
+    #[serde(rename = "queryContextDTO", skip_serializing_if = "Option::is_none")]
+    pub query_context_dto: Option<QueryContextDto>,
+}
+
+impl ExecRequest {
+    /// Synchronous request without parameters, sequence id is assigned right before sending
+    pub fn new(sql_text: &str) -> Self {
+        Self {
+            sql_text: sql_text.to_string(),
+            async_exec: false,
+            sequence_id: 0,
+            is_internal: false,
+            describe_only: false,
+            parameters: ExecRequestParameters::default(),
+            bindings: BTreeMap::new(),
+            bind_stage: None,
+            query_context_dto: None,
+        }
+    }
+
+    /// Statement the library runs on its own behalf, it's marked as internal in the query history
+    pub fn internal(sql_text: &str) -> Self {
+        Self {
+            is_internal: true,
+            ..Self::new(sql_text)
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct QueryContextDto {
+    pub entries: Vec<QueryContextEntryDto>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct QueryContextEntryDto {
+    pub id: u64,
+    pub timestamp: i64,
+    pub priority: u64,
+    pub context: QueryContextEntryData,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryContextEntryData {
+    /// Opaque to the client, sent back exactly as received
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base64_data: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Binding {
+    #[serde(rename = "type")]
+    pub type_: &'static str,
+    pub value: BindingValue,
+}
+
+/// Array bindings carry the values of the column for all rows of the batch
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum BindingValue {
+    Scalar(Option<String>),
+    Array(Vec<Option<String>>),
+}
+
+/// Statement level parameters, override session parameters for the single request
+#[derive(Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub struct ExecRequestParameters {
+    /// Number of statements in the multi-statement request, `0` allows any number
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multi_statement_count: Option<usize>,
+    /// `ARROW` or `JSON`, overrides the session default for the single query
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_result_format: Option<&'static str>,
+    /// Tag of the single statement, overrides `QUERY_TAG` of the session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_tag: Option<String>,
+    /// Server cancels the single statement after this many seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statement_timeout_in_seconds: Option<u64>,
+    /// Any other statement parameters, eg `ROWS_PER_RESULTSET`.
+    /// Names must not repeat the typed fields above, see [`ExecRequestParameters::with_extra`]
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl ExecRequestParameters {
+    pub fn is_empty(&self) -> bool {
+        self.multi_statement_count.is_none()
+            && self.query_result_format.is_none()
+            && self.query_tag.is_none()
+            && self.statement_timeout_in_seconds.is_none()
+            && self.extra.is_empty()
+    }
+
+    /// Add the untyped parameters, typed fields which are set take precedence
+    pub fn with_extra(mut self, extra: &HashMap<String, serde_json::Value>) -> Self {
+        let typed = [
+            (
+                "MULTI_STATEMENT_COUNT",
+                self.multi_statement_count.is_some(),
+            ),
+            ("QUERY_RESULT_FORMAT", self.query_result_format.is_some()),
+            ("QUERY_TAG", self.query_tag.is_some()),
+            (
+                "STATEMENT_TIMEOUT_IN_SECONDS",
+                self.statement_timeout_in_seconds.is_some(),
+            ),
+        ];
+        for (name, value) in extra {
+            let name = name.to_uppercase();
+            if !typed
+                .iter()
+                .any(|(typed, is_set)| *is_set && *typed == name)
+            {
+                self.extra.insert(name, value.clone());
+            }
+        }
+        self
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AbortRequest {
+    pub sql_text: String,
+    pub request_id: String,
 }
 
 #[derive(Serialize, Debug)]
@@ -17,6 +160,8 @@ pub struct LoginRequest<T> {
 pub type PasswordLoginRequest = LoginRequest<PasswordRequestData>;
 #[cfg(feature = "cert-auth")]
 pub type CertLoginRequest = LoginRequest<CertRequestData>;
+pub type OAuthLoginRequest = LoginRequest<OAuthRequestData>;
+pub type SamlLoginRequest = LoginRequest<SamlRequestData>;
 
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
@@ -30,10 +175,66 @@ pub struct LoginRequestCommon {
     pub client_environment: ClientEnvironment,
 }
 
-#[derive(Serialize, Debug)]
+/// Session parameters sent with the login request, spares the `ALTER SESSION` round trips
+/// after every login, see [`SessionParameters::builder`]
+#[derive(Serialize, Debug, Clone)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub struct SessionParameters {
     pub client_validate_default_parameters: bool,
+    /// Any other session parameters, eg `TIMEZONE` or `QUERY_TAG`
+    #[serde(flatten)]
+    pub extra_parameters: HashMap<String, serde_json::Value>,
+}
+
+impl Default for SessionParameters {
+    fn default() -> Self {
+        Self {
+            client_validate_default_parameters: true,
+            extra_parameters: HashMap::new(),
+        }
+    }
+}
+
+impl SessionParameters {
+    pub fn builder() -> SessionParametersBuilder {
+        SessionParametersBuilder::default()
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+#[must_use]
+pub struct SessionParametersBuilder {
+    parameters: SessionParameters,
+}
+
+impl SessionParametersBuilder {
+    pub fn timezone(self, tz: &str) -> Self {
+        self.parameter("TIMEZONE", tz)
+    }
+
+    pub fn date_output_format(self, fmt: &str) -> Self {
+        self.parameter("DATE_OUTPUT_FORMAT", fmt)
+    }
+
+    pub fn timestamp_output_format(self, fmt: &str) -> Self {
+        self.parameter("TIMESTAMP_OUTPUT_FORMAT", fmt)
+    }
+
+    pub fn query_tag(self, tag: &str) -> Self {
+        self.parameter("QUERY_TAG", tag)
+    }
+
+    /// Any session parameter, names are case-insensitive
+    pub fn parameter(mut self, name: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.parameters
+            .extra_parameters
+            .insert(name.to_uppercase(), value.into());
+        self
+    }
+
+    pub fn build(self) -> SessionParameters {
+        self.parameters
+    }
 }
 
 #[derive(Serialize, Debug)]
@@ -45,12 +246,36 @@ pub struct ClientEnvironment {
     pub ocsp_mode: String,
 }
 
+impl ClientEnvironment {
+    /// Environment of the running process, shown in the login history of the account.
+    /// Detection could spawn `lsb_release` and the like, so it's only done once.
+    pub fn detect() -> Self {
+        static OS: OnceLock<(String, String)> = OnceLock::new();
+        let (os, os_version) = OS.get_or_init(|| {
+            let info = os_info::get();
+            (info.os_type().to_string(), info.version().to_string())
+        });
+
+        Self {
+            application: "Rust".to_string(),
+            os: os.clone(),
+            os_version: os_version.clone(),
+            ocsp_mode: "FAIL_OPEN".to_string(),
+        }
+    }
+}
+
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub struct PasswordRequestData {
     #[serde(flatten)]
     pub login_request_common: LoginRequestCommon,
     pub password: String,
+    /// `push` or `passcode`, set when retrying the login for Duo MFA
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ext_authn_duo_method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passcode: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
@@ -62,6 +287,24 @@ pub struct CertRequestData {
     pub token: String,
 }
 
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub struct OAuthRequestData {
+    #[serde(flatten)]
+    pub login_request_common: LoginRequestCommon,
+    pub authenticator: String,
+    pub token: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub struct SamlRequestData {
+    #[serde(flatten)]
+    pub login_request_common: LoginRequestCommon,
+    pub authenticator: String,
+    pub raw_saml_response: String,
+}
+
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct RenewSessionRequest {
@@ -0,0 +1,357 @@
+use std::fmt::Write;
+
+use serde::Deserialize;
+
+use crate::metadata::identifier;
+use crate::quote::quote_literal;
+use crate::{ConfigError, SnowflakeApi, SnowflakeApiError};
+
+/// How often the used credits are reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonitorFrequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    /// Credits are never reset, the quota is for the lifetime of the monitor
+    Never,
+}
+
+impl MonitorFrequency {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Daily => "DAILY",
+            Self::Weekly => "WEEKLY",
+            Self::Monthly => "MONTHLY",
+            Self::Yearly => "YEARLY",
+            Self::Never => "NEVER",
+        }
+    }
+}
+
+/// What happens once the share of the quota is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerAction {
+    Notify,
+    /// Suspend the warehouses once their running queries finish
+    Suspend,
+    /// Suspend the warehouses and cancel their running queries
+    SuspendImmediate,
+}
+
+impl TriggerAction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Notify => "NOTIFY",
+            Self::Suspend => "SUSPEND",
+            Self::SuspendImmediate => "SUSPEND_IMMEDIATE",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonitorTrigger {
+    /// Share of the credit quota, could be over 100
+    pub percentage: u8,
+    pub action: TriggerAction,
+}
+
+/// Resource monitor, the usage is counted from the start of the current `frequency` interval
+#[derive(Debug, Clone)]
+pub struct ResourceMonitorSpec {
+    pub name: String,
+    /// Credits per `frequency` interval, unlimited if unset on create
+    /// and left as is if unset on alter
+    pub credit_quota: Option<f64>,
+    pub frequency: MonitorFrequency,
+    pub triggers: Vec<MonitorTrigger>,
+}
+
+/// Row of `SHOW RESOURCE MONITORS`, columns which aren't listed here are ignored
+#[derive(Debug, Deserialize)]
+struct MonitorInfo {
+    name: String,
+    frequency: String,
+}
+
+impl ResourceMonitorSpec {
+    /// Properties shared by `CREATE` and `ALTER`. The interval starts anew when `restart`
+    /// is set, which resets the used credits, so `ALTER` only does it when the frequency changes.
+    fn properties(&self, restart: bool) -> Result<String, SnowflakeApiError> {
+        let mut sql = String::new();
+        if let Some(credit_quota) = self.credit_quota {
+            if !credit_quota.is_finite() || credit_quota <= 0.0 {
+                return Err(ConfigError::InvalidField {
+                    field: "credit_quota",
+                    reason: format!("`{credit_quota}` is not a positive number"),
+                }
+                .into());
+            }
+            let _ = write!(sql, " CREDIT_QUOTA = {credit_quota}");
+        }
+        // frequency only applies together with the start of the interval
+        if restart {
+            let _ = write!(
+                sql,
+                " FREQUENCY = {} START_TIMESTAMP = IMMEDIATELY",
+                self.frequency.as_str()
+            );
+        }
+        Ok(sql)
+    }
+
+    /// Triggers of the monitor, an empty list removes them on `ALTER`
+    fn triggers(&self, is_alter: bool) -> String {
+        let mut sql = String::new();
+        if !self.triggers.is_empty() {
+            sql.push_str(" TRIGGERS");
+            for trigger in &self.triggers {
+                let _ = write!(
+                    sql,
+                    " ON {} PERCENT DO {}",
+                    trigger.percentage,
+                    trigger.action.as_str()
+                );
+            }
+        } else if is_alter {
+            sql.push_str(" NOTRIGGERS");
+        }
+        sql
+    }
+}
+
+/// Name of the monitor as stored by Snowflake, unquoted identifiers are upper-cased
+fn stored_name(identifier: &str) -> String {
+    match identifier
+        .strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+    {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => identifier.to_uppercase(),
+    }
+}
+
+impl SnowflakeApi {
+    /// Create the monitor, it has to be assigned to the account or warehouses to take effect.
+    /// Resource monitors can only be managed by `ACCOUNTADMIN`.
+    pub async fn create_resource_monitor(
+        &self,
+        spec: &ResourceMonitorSpec,
+    ) -> Result<(), SnowflakeApiError> {
+        let sql = format!(
+            "CREATE RESOURCE MONITOR {} WITH{}{}",
+            identifier(&spec.name)?,
+            spec.properties(true)?,
+            spec.triggers(false)
+        );
+        self.execute(&sql).await?;
+        Ok(())
+    }
+
+    /// Replace the frequency and triggers of the existing monitor with the ones of `spec`,
+    /// and the quota if it's set. The used credits are only reset when the frequency changes.
+    pub async fn alter_resource_monitor(
+        &self,
+        spec: &ResourceMonitorSpec,
+    ) -> Result<(), SnowflakeApiError> {
+        let name = identifier(&spec.name)?;
+        let frequency = self.resource_monitor_frequency(name).await?;
+        let restart = frequency
+            .is_none_or(|frequency| !frequency.eq_ignore_ascii_case(spec.frequency.as_str()));
+        let properties = spec.properties(restart)?;
+        let set = if properties.is_empty() { "" } else { " SET" };
+        let sql = format!(
+            "ALTER RESOURCE MONITOR {name}{set}{properties}{}",
+            spec.triggers(true)
+        );
+        self.execute(&sql).await?;
+        Ok(())
+    }
+
+    /// Frequency of the monitor as reported by `SHOW RESOURCE MONITORS`, `None` if it's not found
+    async fn resource_monitor_frequency(
+        &self,
+        name: &str,
+    ) -> Result<Option<String>, SnowflakeApiError> {
+        let name = stored_name(name);
+        // `LIKE` patterns may match other monitors, eg `_` matches any character
+        let monitors: Vec<MonitorInfo> = self
+            .query_as(&format!(
+                "SHOW RESOURCE MONITORS LIKE {}",
+                quote_literal(&name)
+            ))
+            .await?;
+        Ok(monitors
+            .into_iter()
+            .find(|monitor| monitor.name == name)
+            .map(|monitor| monitor.frequency))
+    }
+
+    pub async fn drop_resource_monitor(
+        &self,
+        name: &str,
+        if_exists: bool,
+    ) -> Result<(), SnowflakeApiError> {
+        let if_exists = if if_exists { " IF EXISTS" } else { "" };
+        self.execute(&format!(
+            "DROP RESOURCE MONITOR{if_exists} {}",
+            identifier(name)?
+        ))
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Matcher, Mock, ServerGuard};
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+
+    fn spec(credit_quota: Option<f64>, frequency: MonitorFrequency) -> ResourceMonitorSpec {
+        ResourceMonitorSpec {
+            name: "limiter".to_string(),
+            credit_quota,
+            frequency,
+            triggers: vec![MonitorTrigger {
+                percentage: 90,
+                action: TriggerAction::Suspend,
+            }],
+        }
+    }
+
+    async fn statement(server: &mut ServerGuard, sql: &str) -> Mock {
+        server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({ "sqlText": sql })))
+            .with_body(mock::query_body(&json!({ "statementTypeId": 24832 })))
+            .create_async()
+            .await
+    }
+
+    fn text_column(name: &str) -> serde_json::Value {
+        json!({
+            "name": name, "type": "text", "nullable": false,
+            "byteLength": null, "length": null, "precision": null, "scale": null
+        })
+    }
+
+    async fn show(server: &mut ServerGuard, monitors: &[(&str, &str)]) -> Mock {
+        let rowset: Vec<_> = monitors
+            .iter()
+            .map(|(name, frequency)| json!([name, frequency]))
+            .collect();
+        server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({
+                "sqlText": "SHOW RESOURCE MONITORS LIKE 'LIMITER'"
+            })))
+            .with_body(mock::query_body(&json!({
+                "rowtype": [
+                    text_column("name"),
+                    text_column("frequency")
+                ],
+                "rowset": rowset,
+                "total": monitors.len(),
+                "returned": monitors.len()
+            })))
+            .create_async()
+            .await
+    }
+
+    #[tokio::test]
+    async fn create_starts_the_interval() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let create = statement(
+            &mut server,
+            "CREATE RESOURCE MONITOR limiter WITH CREDIT_QUOTA = 100 FREQUENCY = MONTHLY \
+             START_TIMESTAMP = IMMEDIATELY TRIGGERS ON 90 PERCENT DO SUSPEND",
+        )
+        .await;
+
+        let api = mock::api(&server);
+        api.create_resource_monitor(&spec(Some(100.0), MonitorFrequency::Monthly))
+            .await
+            .unwrap();
+        create.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn alter_keeps_the_interval_of_the_same_frequency() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        // `_` of the pattern matches the other monitor too
+        let _show = show(
+            &mut server,
+            &[("LIMITER", "MONTHLY"), ("LIMITER_2", "DAILY")],
+        )
+        .await;
+        let alter = statement(
+            &mut server,
+            "ALTER RESOURCE MONITOR limiter SET CREDIT_QUOTA = 50 \
+             TRIGGERS ON 90 PERCENT DO SUSPEND",
+        )
+        .await;
+
+        let api = mock::api(&server);
+        api.alter_resource_monitor(&spec(Some(50.0), MonitorFrequency::Monthly))
+            .await
+            .unwrap();
+        alter.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn alter_restarts_the_interval_of_changed_frequency() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _show = show(&mut server, &[("LIMITER", "MONTHLY")]).await;
+        let alter = statement(
+            &mut server,
+            "ALTER RESOURCE MONITOR limiter SET FREQUENCY = WEEKLY \
+             START_TIMESTAMP = IMMEDIATELY TRIGGERS ON 90 PERCENT DO SUSPEND",
+        )
+        .await;
+
+        let api = mock::api(&server);
+        api.alter_resource_monitor(&spec(None, MonitorFrequency::Weekly))
+            .await
+            .unwrap();
+        alter.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn alter_without_changed_properties_only_replaces_triggers() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _show = show(&mut server, &[("LIMITER", "MONTHLY")]).await;
+        let alter = statement(&mut server, "ALTER RESOURCE MONITOR limiter NOTRIGGERS").await;
+
+        let api = mock::api(&server);
+        let spec = ResourceMonitorSpec {
+            triggers: vec![],
+            ..spec(None, MonitorFrequency::Monthly)
+        };
+        api.alter_resource_monitor(&spec).await.unwrap();
+        alter.assert_async().await;
+    }
+
+    #[test]
+    fn invalid_quota_is_rejected() {
+        for quota in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(spec(Some(quota), MonitorFrequency::Daily)
+                .properties(true)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn quoted_names_are_matched_as_stored() {
+        assert_eq!(stored_name("limiter"), "LIMITER");
+        assert_eq!(stored_name(r#""My ""Limiter""""#), r#"My "Limiter""#);
+    }
+}
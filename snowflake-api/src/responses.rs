@@ -1,5 +1,7 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 
+use serde::de::IgnoredAny;
 use serde::Deserialize;
 
 #[allow(clippy::large_enum_variant)]
@@ -11,14 +13,101 @@ pub enum ExecResponse {
     Error(ExecErrorResponse),
 }
 
+/// Response to the query submitted with `asyncExec`, query keeps running on the server
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum AsyncExecResponse {
+    Accepted(BaseRestResponse<AsyncExecResponseData>),
+    Error(ExecErrorResponse),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AsyncExecResponseData {
+    pub query_id: String,
+    #[allow(dead_code)]
+    pub get_result_url: String,
+}
+
+// Data is `null`, outcome is in `success` and `code`
+/// Fields of the query response looked at before it's deserialized into the target type,
+/// the rest of it, eg the rowset, is skipped without being decoded
+#[derive(Deserialize, Debug)]
+pub struct ResponseEnvelope<'a> {
+    #[serde(borrow)]
+    pub code: Option<Cow<'a, str>>,
+    #[serde(default)]
+    pub success: bool,
+    #[serde(borrow)]
+    pub data: Option<ResponseEnvelopeData<'a>>,
+}
+
+impl ResponseEnvelope<'_> {
+    /// Error code of the failed request, successful responses could carry a code too
+    pub fn error_code(&self) -> Option<&str> {
+        self.code.as_deref().filter(|_| !self.success)
+    }
+
+    pub fn query_id(&self) -> Option<&str> {
+        self.data
+            .as_ref()
+            .and_then(|data| data.query_id.as_deref())
+            .filter(|id| !id.is_empty())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseEnvelopeData<'a> {
+    #[serde(borrow)]
+    pub query_id: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub get_result_url: Option<Cow<'a, str>>,
+    pub statement_type_id: Option<i64>,
+    pub total: Option<i64>,
+    pub chunks: Option<Vec<IgnoredAny>>,
+    #[serde(default, deserialize_with = "lenient_parameters")]
+    pub parameters: Vec<NameValueParameter>,
+    pub query_context: Option<QueryContext>,
+    pub warnings: Option<serde_json::Value>,
+    pub messages: Option<serde_json::Value>,
+}
+
+pub type AbortResponse = BaseRestResponse<Option<serde_json::Value>>;
+pub type QueryMonitoringResponse = BaseRestResponse<Option<QueryMonitoringResponseData>>;
+
+#[derive(Deserialize, Debug)]
+pub struct QueryMonitoringResponseData {
+    #[serde(default)]
+    pub queries: Vec<QueryMonitoringEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryMonitoringEntry {
+    #[allow(dead_code)]
+    pub id: String,
+    pub status: String,
+    // could be sent both as number and string
+    pub error_code: Option<serde_json::Value>,
+    pub error_message: Option<String>,
+    // timestamps are in milliseconds since epoch, 0 when unknown
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    pub total_duration: Option<i64>,
+    pub warehouse_name: Option<String>,
+}
+
 // todo: add close session response, which should be just empty?
 #[allow(clippy::large_enum_variant)]
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
 pub enum AuthResponse {
     Login(LoginResponse),
+    #[allow(dead_code)]
     Auth(AuthenticatorResponse),
     Renew(RenewSessionResponse),
+    #[allow(dead_code)]
     Close(CloseSessionResponse),
     Error(AuthErrorResponse),
 }
@@ -41,6 +130,8 @@ pub type LoginResponse = BaseRestResponse<LoginResponseData>;
 pub type RenewSessionResponse = BaseRestResponse<RenewSessionResponseData>;
 // Data should be always `null` on successful close session response
 pub type CloseSessionResponse = BaseRestResponse<Option<()>>;
+// Heartbeat response carries no data, outcome is in `success` and `code`
+pub type HeartbeatResponse = BaseRestResponse<Option<serde_json::Value>>;
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -56,13 +147,19 @@ pub struct ExecErrorResponseData {
     // fixme: only valid for exec query response error? present in any exec query response?
     pub query_id: String,
     pub sql_state: String,
+    // failed multi-statement request, ids of the statements which have completed
+    pub result_ids: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthErrorResponseData {
+    #[allow(dead_code)]
     pub authn_method: Option<String>,
+    #[allow(dead_code)]
     pub error_code: Option<String>,
+    /// What the client should do to complete the login, eg provide the second factor
+    pub next_action: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -71,20 +168,43 @@ pub struct NameValueParameter {
     pub value: serde_json::Value,
 }
 
+/// Parameters are only informational for the statement they come with,
+/// a malformed list is logged and skipped instead of failing the successful statement
+fn lenient_parameters<'de, D>(deserializer: D) -> Result<Vec<NameValueParameter>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    if value.is_null() {
+        return Ok(vec![]);
+    }
+    Ok(
+        Vec::<NameValueParameter>::deserialize(&value).unwrap_or_else(|e| {
+            log::warn!("Ignoring malformed session parameters of the response: {e}");
+            vec![]
+        }),
+    )
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct LoginResponseData {
+    #[allow(dead_code)]
     pub session_id: i64,
     pub token: String,
     pub master_token: String,
+    #[allow(dead_code)]
     pub server_version: String,
     #[serde(default)]
     pub parameters: Vec<NameValueParameter>,
+    #[allow(dead_code)]
     pub session_info: SessionInfo,
     pub master_validity_in_seconds: i64,
     pub validity_in_seconds: i64,
 }
 
+// only logged with the response, none of the fields are used
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SessionInfo {
@@ -94,6 +214,8 @@ pub struct SessionInfo {
     pub role_name: String,
 }
 
+// only logged with the response, none of the fields are used
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthenticatorResponseData {
@@ -109,12 +231,14 @@ pub struct RenewSessionResponseData {
     pub validity_in_seconds_s_t: i64,
     pub master_token: String,
     pub validity_in_seconds_m_t: i64,
+    #[allow(dead_code)]
     pub session_id: i64,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct QueryExecResponseData {
+    #[serde(default, deserialize_with = "lenient_parameters")]
     pub parameters: Vec<NameValueParameter>,
     pub rowtype: Vec<ExecResponseRowType>,
     // default for non-SELECT queries
@@ -144,31 +268,148 @@ pub struct QueryExecResponseData {
     pub qrmk: Option<String>,
     #[serde(default)] // chunks are present
     pub chunk_headers: HashMap<String, String>,
+    // span of the query, deserialized within it, chunks are downloaded as its children
+    #[serde(skip, default = "tracing::Span::current")]
+    pub span: tracing::Span,
     // when async query is run (ping pong request?)
     pub get_result_url: Option<String>,
     // multi-statement response, comma-separated
     pub result_ids: Option<String>,
+    // `json` or `arrow`, format the rows were actually sent in
+    pub query_result_format: Option<String>,
+    // only present for DML statements
+    pub stats: Option<ExecResponseStats>,
+    // has to be sent back with the following requests of the session
+    pub query_context: Option<QueryContext>,
+    // only present for describe-only requests
+    #[serde(default)]
+    pub meta_data_of_binds: Vec<BindMetadata>,
     // `progressDesc`, and `queryAbortAfterSecs` are not used but exist in .NET
-    // `sendResultTime`, `queryResultFormat`, `queryContext` also exist
+    // `sendResultTime` also exists
 }
 
-#[derive(Deserialize, Debug)]
+/// Expected type of the `?` placeholder, types are sent in upper case, eg `FIXED`
+#[derive(Deserialize, Debug, Clone)]
+pub struct BindMetadata {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub precision: Option<i64>,
+    pub scale: Option<i64>,
+    #[serde(default)]
+    pub nullable: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct QueryContext {
+    #[serde(default)]
+    pub entries: Vec<QueryContextEntry>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct QueryContextEntry {
+    pub id: u64,
+    pub timestamp: i64,
+    pub priority: u64,
+    // base64 encoded, opaque to the client
+    pub context: Option<String>,
+}
+
+impl QueryExecResponseData {
+    /// Format of the rows is what the server says it is, not what was requested,
+    /// eg non-SELECT statements are answered with JSON even if Arrow was requested
+    pub fn is_json(&self) -> bool {
+        match self.query_result_format.as_deref() {
+            Some(format) => format.eq_ignore_ascii_case("json"),
+            None => self.rowset.is_some(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecResponseStats {
+    #[serde(default)]
+    pub num_rows_inserted: u64,
+    #[serde(default)]
+    pub num_rows_updated: u64,
+    #[serde(default)]
+    pub num_rows_deleted: u64,
+    #[serde(default)]
+    pub num_dml_duplicates: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(from = "RawRowType")]
 pub struct ExecResponseRowType {
     pub name: String,
-    #[serde(rename = "byteLength")]
     pub byte_length: Option<i64>,
     // unused in .NET
     pub length: Option<i64>,
-    #[serde(rename = "type")]
     pub type_: SnowflakeType,
+    /// Type as sent by the server in upper case, `VECTOR` has its element type and dimension appended
+    pub type_name: String,
     pub scale: Option<i64>,
     pub precision: Option<i64>,
     pub nullable: bool,
+    pub vector_dimension: Option<i64>,
 }
 
-// fixme: is it good idea to keep this as an enum if more types could be added in future?
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawRowType {
+    name: String,
+    byte_length: Option<i64>,
+    length: Option<i64>,
+    #[serde(rename = "type")]
+    type_: String,
+    scale: Option<i64>,
+    precision: Option<i64>,
+    nullable: bool,
+    vector_dimension: Option<i64>,
+    // element type of `VECTOR`
+    #[serde(default)]
+    fields: Vec<RawFieldType>,
+}
+
+#[derive(Deserialize)]
+struct RawFieldType {
+    #[serde(rename = "type")]
+    type_: String,
+}
+
+impl From<RawRowType> for ExecResponseRowType {
+    fn from(raw: RawRowType) -> Self {
+        let type_ = SnowflakeType::deserialize(serde_json::Value::String(raw.type_.clone()))
+            .unwrap_or(SnowflakeType::Other);
+        let mut type_name = raw.type_.to_uppercase();
+        if type_name == "VECTOR" {
+            let element = match raw.fields.first().map(|field| field.type_.as_str()) {
+                Some("fixed") => Some("INT"),
+                Some("real") => Some("FLOAT"),
+                _ => None,
+            };
+            if let (Some(element), Some(dimension)) = (element, raw.vector_dimension) {
+                type_name = format!("VECTOR({element}, {dimension})");
+            }
+        }
+        Self {
+            name: raw.name,
+            byte_length: raw.byte_length,
+            length: raw.length,
+            type_,
+            type_name,
+            scale: raw.scale,
+            precision: raw.precision,
+            nullable: raw.nullable,
+            vector_dimension: raw.vector_dimension,
+        }
+    }
+}
+
+/// More types could get special handling later, until then they are [`SnowflakeType::Other`]
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
+#[non_exhaustive]
 pub enum SnowflakeType {
     Fixed,
     Real,
@@ -183,6 +424,9 @@ pub enum SnowflakeType {
     Time,
     Boolean,
     Array,
+    /// Type without special handling, eg `GEOGRAPHY` or `VECTOR`, values are read as text
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Deserialize, Debug)]
@@ -213,7 +457,9 @@ pub struct PutGetResponseData {
     // todo: support different compression formats?
     pub source_compression: String,
     pub stage_info: PutGetStageInfo,
-    pub encryption_material: EncryptionMaterialVariant,
+    /// Missing for the stages without client-side encryption
+    #[serde(default)]
+    pub encryption_material: Option<EncryptionMaterialVariant>,
     // GCS specific. If you request multiple files?
     #[serde(default)]
     pub presigned_urls: Vec<String>,
@@ -304,3 +550,66 @@ pub struct PutGetEncryptionMaterial {
     pub query_id: String,
     pub smk_id: i64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope_skips_the_rowset() {
+        let body = br#"{
+            "code": null,
+            "success": true,
+            "message": null,
+            "data": {
+                "queryId": "01b2c3d4-0000-1111-0000-000000000001",
+                "statementTypeId": 4096,
+                "total": 2,
+                "rowset": [["1", "a\"b"], ["2", null]],
+                "rowsetBase64": "",
+                "chunks": [{"url": "https://example.com/1", "rowCount": 1}],
+                "parameters": [{"name": "TIMEZONE", "value": "UTC"}]
+            }
+        }"#;
+        let envelope = serde_json::from_slice::<ResponseEnvelope>(body).unwrap();
+
+        assert_eq!(envelope.error_code(), None);
+        assert_eq!(
+            envelope.query_id(),
+            Some("01b2c3d4-0000-1111-0000-000000000001")
+        );
+        let data = envelope.data.unwrap();
+        assert_eq!(data.total, Some(2));
+        assert_eq!(data.chunks.map(|chunks| chunks.len()), Some(1));
+        assert_eq!(data.parameters[0].name, "TIMEZONE");
+    }
+
+    #[test]
+    fn malformed_parameters_are_skipped() {
+        let body = br#"{
+            "code": null,
+            "success": true,
+            "data": {
+                "queryId": "01b2c3d4-0000-1111-0000-000000000001",
+                "parameters": [{"name": 42}, "TIMEZONE"]
+            }
+        }"#;
+        let envelope = serde_json::from_slice::<ResponseEnvelope>(body).unwrap();
+
+        assert!(envelope.data.unwrap().parameters.is_empty());
+    }
+
+    #[test]
+    fn envelope_of_failed_request() {
+        let body = br#"{
+            "code": "390112",
+            "success": false,
+            "message": "Your session has expired. Please login again.",
+            "data": null
+        }"#;
+        let envelope = serde_json::from_slice::<ResponseEnvelope>(body).unwrap();
+
+        assert_eq!(envelope.error_code(), Some("390112"));
+        assert_eq!(envelope.query_id(), None);
+    }
+}
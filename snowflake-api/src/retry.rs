@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use crate::statement::leading_keyword;
+
+/// Statement canceled, eg due to the warehouse restart
+const STATEMENT_CANCELED: &str = "000604";
+const STATEMENT_INTERRUPTED: &str = "000630";
+const INTERNAL_ERROR: &str = "010001";
+
+/// Statements which don't change anything and are safe to run again
+const READ_ONLY_KEYWORDS: [&str; 8] = [
+    "SELECT", "WITH", "SHOW", "DESCRIBE", "DESC", "EXPLAIN", "LIST", "LS",
+];
+
+/// Runs the statement again when it fails with a transient Snowflake error code,
+/// eg when the warehouse was restarted. Complements the HTTP level retries of
+/// [`crate::middleware::SnowflakeRetryPolicy`], which only sees transport failures.
+/// DML and other non-idempotent statements are not retried unless enabled with
+/// [`QueryRetryPolicy::with_retry_dml`].
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct QueryRetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    retryable_codes: Vec<String>,
+    retry_dml: bool,
+}
+
+impl Default for QueryRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            retryable_codes: [STATEMENT_CANCELED, STATEMENT_INTERRUPTED, INTERNAL_ERROR]
+                .map(ToString::to_string)
+                .to_vec(),
+            retry_dml: false,
+        }
+    }
+}
+
+impl QueryRetryPolicy {
+    /// Total number of attempts, including the first one
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Delay before the first retry, doubled on every following one up to `max_backoff`
+    pub fn with_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Replace the default list of retryable error codes
+    pub fn with_retryable_codes<S: Into<String>>(
+        mut self,
+        codes: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.retryable_codes = codes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Retry statements which could change the data, eg `INSERT` or `MERGE`,
+    /// only safe if running them twice is harmless
+    pub fn with_retry_dml(mut self, retry_dml: bool) -> Self {
+        self.retry_dml = retry_dml;
+        self
+    }
+
+    /// Delay before the next attempt, `None` if the failed one shouldn't be retried
+    pub(crate) fn retry_delay(&self, sql: &str, code: &str, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_attempts
+            || !self.retryable_codes.iter().any(|c| c == code)
+            || !(self.retry_dml || is_read_only(sql))
+        {
+            return None;
+        }
+        let delay = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt - 1));
+        Some(delay.min(self.max_backoff))
+    }
+}
+
+/// Check the leading keyword of the statement, comments before it are skipped
+fn is_read_only(sql: &str) -> bool {
+    leading_keyword(sql).is_some_and(|keyword| {
+        READ_ONLY_KEYWORDS
+            .iter()
+            .any(|read_only| keyword.eq_ignore_ascii_case(read_only))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_only_statements() {
+        for sql in [
+            "SELECT 1",
+            "  select * from t",
+            "WITH x AS (SELECT 1) SELECT * FROM x",
+            "-- comment\nSHOW TABLES",
+            "// comment\nDESC TABLE t",
+            "/* comment */ explain SELECT 1",
+            "/* a */ -- b\n // c\n LIST @stage",
+            "ls @~",
+        ] {
+            assert!(is_read_only(sql), "{sql:?}");
+        }
+    }
+
+    #[test]
+    fn statements_changing_data() {
+        for sql in [
+            "INSERT INTO t VALUES (1)",
+            "// SELECT\nDELETE FROM t",
+            "-- SELECT\nUPDATE t SET a = 1",
+            "/* SELECT */ MERGE INTO t USING s ON t.id = s.id",
+            "SELECTED_ROWS()",
+            "",
+            "-- SELECT",
+        ] {
+            assert!(!is_read_only(sql), "{sql:?}");
+        }
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_max_backoff() {
+        let policy = QueryRetryPolicy::default()
+            .with_max_attempts(5)
+            .with_backoff(Duration::from_secs(1), Duration::from_secs(3));
+        let delays: Vec<_> = (1..=5)
+            .map(|attempt| policy.retry_delay("SELECT 1", STATEMENT_CANCELED, attempt))
+            .collect();
+        assert_eq!(
+            delays,
+            [
+                Some(Duration::from_secs(1)),
+                Some(Duration::from_secs(2)),
+                Some(Duration::from_secs(3)),
+                Some(Duration::from_secs(3)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn only_retryable_codes_of_read_only_statements_are_retried() {
+        let policy = QueryRetryPolicy::default();
+        assert!(policy.retry_delay("SELECT 1", "002003", 1).is_none());
+        assert!(policy
+            .retry_delay("// cleanup\nDELETE FROM t", INTERNAL_ERROR, 1)
+            .is_none());
+        assert!(policy
+            .with_retry_dml(true)
+            .retry_delay("DELETE FROM t", INTERNAL_ERROR, 1)
+            .is_some());
+    }
+}
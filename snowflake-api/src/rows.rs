@@ -0,0 +1,279 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::responses::SnowflakeType;
+use crate::FieldSchema;
+
+#[derive(Error, Debug)]
+pub enum TypeError {
+    #[error("Column `{0}` is not present in the result")]
+    ColumnNotFound(String),
+
+    #[error("Column index `{0}` is out of bounds")]
+    IndexOutOfBounds(usize),
+
+    #[error("Column `{0}` is null, use `Option<T>` to read nullable columns")]
+    UnexpectedNull(String),
+
+    #[error("Can not convert value `{value}` of column `{column}` to `{target}`")]
+    InvalidValue {
+        column: String,
+        value: String,
+        target: &'static str,
+    },
+
+    #[error("Column `{column}` has unsupported Arrow type `{data_type}`")]
+    UnsupportedArrowType { column: String, data_type: String },
+}
+
+/// Conversion from the JSON encoded Snowflake value into the Rust type.
+/// Snowflake encodes most of the values as strings in JSON responses,
+/// column type is used to pick the right decoding, eg dates are sent as days since epoch.
+pub trait FromSnowflakeValue: Sized {
+    fn from_snowflake_value(value: &Value, field: &FieldSchema) -> Result<Self, TypeError>;
+}
+
+/// Single row of JSON query result
+#[derive(Debug)]
+pub struct Row {
+    values: Vec<Value>,
+    schema: Arc<Vec<FieldSchema>>,
+}
+
+impl Row {
+    pub fn new(values: Vec<Value>, schema: Arc<Vec<FieldSchema>>) -> Self {
+        Self { values, schema }
+    }
+
+    /// Get the column value by name, exact match is preferred over case-insensitive one
+    pub fn get<T: FromSnowflakeValue>(&self, name: &str) -> Result<T, TypeError> {
+        let idx = self
+            .column_index(name)
+            .ok_or_else(|| TypeError::ColumnNotFound(name.to_string()))?;
+        self.get_by_index(idx)
+    }
+
+    /// Get the column value by its position in the result
+    pub fn get_by_index<T: FromSnowflakeValue>(&self, idx: usize) -> Result<T, TypeError> {
+        let value = self
+            .values
+            .get(idx)
+            .ok_or(TypeError::IndexOutOfBounds(idx))?;
+        let field = self
+            .schema
+            .get(idx)
+            .ok_or(TypeError::IndexOutOfBounds(idx))?;
+        T::from_snowflake_value(value, field)
+    }
+
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.schema.iter().position(|f| f.name == name).or_else(|| {
+            self.schema
+                .iter()
+                .position(|f| f.name.eq_ignore_ascii_case(name))
+        })
+    }
+
+    pub fn schema(&self) -> &[FieldSchema] {
+        &self.schema
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// Iterator over the rows of JSON query result, all rows share the same schema
+pub struct Rows {
+    rows: std::vec::IntoIter<Vec<Value>>,
+    schema: Arc<Vec<FieldSchema>>,
+}
+
+impl Rows {
+    pub fn new(rows: Vec<Vec<Value>>, schema: Vec<FieldSchema>) -> Self {
+        Self {
+            rows: rows.into_iter(),
+            schema: Arc::new(schema),
+        }
+    }
+
+    pub fn schema(&self) -> &[FieldSchema] {
+        &self.schema
+    }
+}
+
+impl Iterator for Rows {
+    type Item = Row;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows
+            .next()
+            .map(|values| Row::new(values, Arc::clone(&self.schema)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rows.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Rows {}
+
+fn invalid<T>(value: &Value, field: &FieldSchema) -> TypeError {
+    TypeError::InvalidValue {
+        column: field.name.clone(),
+        value: value.to_string(),
+        target: std::any::type_name::<T>(),
+    }
+}
+
+/// Most of the values are sent as strings, but be lenient and accept native JSON values too
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn not_null(value: &Value, field: &FieldSchema) -> Result<(), TypeError> {
+    if value.is_null() {
+        Err(TypeError::UnexpectedNull(field.name.clone()))
+    } else {
+        Ok(())
+    }
+}
+
+impl<T: FromSnowflakeValue> FromSnowflakeValue for Option<T> {
+    fn from_snowflake_value(value: &Value, field: &FieldSchema) -> Result<Self, TypeError> {
+        if value.is_null() {
+            Ok(None)
+        } else {
+            T::from_snowflake_value(value, field).map(Some)
+        }
+    }
+}
+
+impl FromSnowflakeValue for String {
+    fn from_snowflake_value(value: &Value, field: &FieldSchema) -> Result<Self, TypeError> {
+        not_null(value, field)?;
+        // semi-structured values are kept as their JSON representation
+        Ok(as_text(value).unwrap_or_else(|| value.to_string()))
+    }
+}
+
+impl FromSnowflakeValue for i64 {
+    fn from_snowflake_value(value: &Value, field: &FieldSchema) -> Result<Self, TypeError> {
+        not_null(value, field)?;
+        as_text(value)
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| invalid::<Self>(value, field))
+    }
+}
+
+impl FromSnowflakeValue for f64 {
+    fn from_snowflake_value(value: &Value, field: &FieldSchema) -> Result<Self, TypeError> {
+        not_null(value, field)?;
+        as_text(value)
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| invalid::<Self>(value, field))
+    }
+}
+
+impl FromSnowflakeValue for bool {
+    fn from_snowflake_value(value: &Value, field: &FieldSchema) -> Result<Self, TypeError> {
+        not_null(value, field)?;
+        match as_text(value).as_deref().map(str::to_lowercase).as_deref() {
+            Some("1" | "true") => Ok(true),
+            Some("0" | "false") => Ok(false),
+            _ => Err(invalid::<Self>(value, field)),
+        }
+    }
+}
+
+impl FromSnowflakeValue for NaiveDate {
+    fn from_snowflake_value(value: &Value, field: &FieldSchema) -> Result<Self, TypeError> {
+        not_null(value, field)?;
+        let text = as_text(value).ok_or_else(|| invalid::<Self>(value, field))?;
+        let date = match field.type_ {
+            // dates are encoded as number of days since epoch
+            SnowflakeType::Date => text
+                .parse::<i64>()
+                .ok()
+                .and_then(|days| DateTime::from_timestamp(days * 86_400, 0))
+                .map(|dt| dt.date_naive()),
+            _ => NaiveDate::parse_from_str(&text, "%Y-%m-%d").ok(),
+        };
+        date.ok_or_else(|| invalid::<Self>(value, field))
+    }
+}
+
+impl FromSnowflakeValue for DateTime<Utc> {
+    fn from_snowflake_value(value: &Value, field: &FieldSchema) -> Result<Self, TypeError> {
+        not_null(value, field)?;
+        let text = as_text(value).ok_or_else(|| invalid::<Self>(value, field))?;
+        let datetime = match field.type_ {
+            // timestamps are encoded as `<seconds>.<fraction>`,
+            // TIMESTAMP_TZ has the timezone offset appended after whitespace
+            SnowflakeType::TimestampLtz
+            | SnowflakeType::TimestampNtz
+            | SnowflakeType::TimestampTz => text
+                .split_whitespace()
+                .next()
+                .and_then(parse_epoch_timestamp),
+            _ => DateTime::parse_from_rfc3339(&text)
+                .ok()
+                .map(|dt| dt.with_timezone(&Utc)),
+        };
+        datetime.ok_or_else(|| invalid::<Self>(value, field))
+    }
+}
+
+/// Exact value of `NUMBER`, `FLOAT` values are accepted in the scientific notation too
+#[cfg(feature = "decimal")]
+impl FromSnowflakeValue for rust_decimal::Decimal {
+    fn from_snowflake_value(value: &Value, field: &FieldSchema) -> Result<Self, TypeError> {
+        not_null(value, field)?;
+        as_text(value)
+            .and_then(|s| {
+                Self::from_str_exact(&s)
+                    .or_else(|_| Self::from_scientific(&s))
+                    .ok()
+            })
+            .ok_or_else(|| invalid::<Self>(value, field))
+    }
+}
+
+fn parse_epoch_timestamp(text: &str) -> Option<DateTime<Utc>> {
+    let (secs, fraction) = text.split_once('.').unwrap_or((text, ""));
+    let secs = secs.parse::<i64>().ok()?;
+    let nanos = if fraction.is_empty() {
+        0
+    } else {
+        // right-pad the fraction to nanoseconds precision
+        let digits: String = fraction
+            .chars()
+            .chain(std::iter::repeat('0'))
+            .take(9)
+            .collect();
+        digits.parse::<u32>().ok()?
+    };
+
+    // negative timestamps keep the fraction going forward in time, eg -1.5 is 1.5s before epoch
+    if text.starts_with('-') && nanos > 0 {
+        DateTime::from_timestamp(secs - 1, 1_000_000_000 - nanos)
+    } else {
+        DateTime::from_timestamp(secs, nanos)
+    }
+}
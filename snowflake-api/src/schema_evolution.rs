@@ -0,0 +1,370 @@
+use std::fmt::Write;
+
+use crate::metadata::{catalog_name, identifier, qualified_identifier};
+use crate::quote::quote_ident;
+use crate::{SnowflakeApi, SnowflakeApiError, TableColumn};
+
+/// Column of the table schema, see [`SchemaEvolution`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct ColumnDescriptor {
+    /// Identifier as written in SQL, unquoted names are case-insensitive
+    pub name: String,
+    /// Type as written in SQL, eg `NUMBER(38,0)` or `VARCHAR(100)`
+    pub data_type: String,
+    pub nullable: bool,
+    /// Default expression of the added column, eg `0` or `CURRENT_TIMESTAMP()`
+    pub default: Option<String>,
+}
+
+impl ColumnDescriptor {
+    pub fn new(name: &str, data_type: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            nullable: true,
+            default: None,
+        }
+    }
+
+    pub fn with_nullable(mut self, nullable: bool) -> Self {
+        self.nullable = nullable;
+        self
+    }
+
+    pub fn with_default(mut self, default: &str) -> Self {
+        self.default = Some(default.to_string());
+        self
+    }
+
+    fn key(&self) -> String {
+        catalog_name(&self.name)
+    }
+}
+
+/// Columns of `DESC TABLE` are named as they are stored, names which aren't upper case
+/// are quoted to keep their case
+impl From<TableColumn> for ColumnDescriptor {
+    fn from(column: TableColumn) -> Self {
+        let is_plain =
+            identifier(&column.name).is_ok() && column.name == column.name.to_uppercase();
+        let name = if is_plain {
+            column.name
+        } else {
+            quote_ident(&column.name).unwrap_or(column.name)
+        };
+        Self {
+            name,
+            data_type: column.type_,
+            nullable: column.nullable,
+            default: column.default,
+        }
+    }
+}
+
+/// Single `ALTER TABLE` step
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaMigration {
+    AddColumn(ColumnDescriptor),
+    DropColumn(String),
+    /// Snowflake only allows widening changes, eg a longer `VARCHAR` or a larger `NUMBER` precision
+    AlterColumnType {
+        name: String,
+        new_type: String,
+    },
+    SetNullable {
+        name: String,
+        nullable: bool,
+    },
+    RenameColumn {
+        from: String,
+        to: String,
+    },
+}
+
+impl SchemaMigration {
+    fn to_sql(&self, table: &str) -> Result<String, SnowflakeApiError> {
+        let action = match self {
+            Self::AddColumn(column) => {
+                let mut sql = format!(
+                    "ADD COLUMN {} {}",
+                    identifier(&column.name)?,
+                    column.data_type
+                );
+                if let Some(default) = &column.default {
+                    let _ = write!(sql, " DEFAULT {default}");
+                }
+                if !column.nullable {
+                    sql.push_str(" NOT NULL");
+                }
+                sql
+            }
+            Self::DropColumn(name) => format!("DROP COLUMN {}", identifier(name)?),
+            Self::AlterColumnType { name, new_type } => {
+                format!(
+                    "ALTER COLUMN {} SET DATA TYPE {new_type}",
+                    identifier(name)?
+                )
+            }
+            Self::SetNullable { name, nullable } => {
+                let action = if *nullable { "DROP" } else { "SET" };
+                format!("ALTER COLUMN {} {action} NOT NULL", identifier(name)?)
+            }
+            Self::RenameColumn { from, to } => {
+                format!("RENAME COLUMN {} TO {}", identifier(from)?, identifier(to)?)
+            }
+        };
+        Ok(format!("ALTER TABLE {table} {action}"))
+    }
+}
+
+/// Plans and applies the changes between two versions of the table schema
+pub struct SchemaEvolution;
+
+impl SchemaEvolution {
+    /// Steps turning `current` into `target`, columns are matched by name. Swap the arguments
+    /// for the plan going back. Renames can't be told apart from a dropped and an added column,
+    /// so they are never planned, add [`SchemaMigration::RenameColumn`] by hand instead.
+    ///
+    /// Columns are added first and dropped last, so the table keeps its data the longest.
+    /// Types are compared ignoring case and whitespace, so they have to be written the way
+    /// `DESC TABLE` reports them, eg `NUMBER(38,0)` rather than `INT`.
+    /// Defaults of the existing columns are not compared.
+    pub fn diff(current: &[ColumnDescriptor], target: &[ColumnDescriptor]) -> Vec<SchemaMigration> {
+        let find = |columns: &[ColumnDescriptor], key: &str| {
+            columns.iter().find(|column| column.key() == key).cloned()
+        };
+
+        let mut migrations = vec![];
+        for column in target {
+            if find(current, &column.key()).is_none() {
+                migrations.push(SchemaMigration::AddColumn(column.clone()));
+            }
+        }
+        for column in target {
+            let Some(existing) = find(current, &column.key()) else {
+                continue;
+            };
+            if normalize_type(&existing.data_type) != normalize_type(&column.data_type) {
+                migrations.push(SchemaMigration::AlterColumnType {
+                    name: column.name.clone(),
+                    new_type: column.data_type.clone(),
+                });
+            }
+            if existing.nullable != column.nullable {
+                migrations.push(SchemaMigration::SetNullable {
+                    name: column.name.clone(),
+                    nullable: column.nullable,
+                });
+            }
+        }
+        for column in current {
+            if find(target, &column.key()).is_none() {
+                migrations.push(SchemaMigration::DropColumn(column.name.clone()));
+            }
+        }
+        migrations
+    }
+
+    /// Run the steps in order, each one is a separate statement. DDL commits implicitly,
+    /// so the steps which have succeeded stay applied if a later one fails.
+    pub async fn apply(
+        api: &SnowflakeApi,
+        table: &str,
+        migrations: &[SchemaMigration],
+    ) -> Result<(), SnowflakeApiError> {
+        let table = qualified_identifier(table)?;
+        // validate all of them before anything is changed
+        let statements = migrations
+            .iter()
+            .map(|migration| migration.to_sql(&table))
+            .collect::<Result<Vec<_>, _>>()?;
+        for sql in statements {
+            log::debug!("Migrating: {sql}");
+            api.execute(&sql).await?;
+        }
+        Ok(())
+    }
+}
+
+fn normalize_type(data_type: &str) -> String {
+    data_type
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+
+    fn v1() -> Vec<ColumnDescriptor> {
+        vec![
+            ColumnDescriptor::new("ID", "NUMBER(38,0)").with_nullable(false),
+            ColumnDescriptor::new("NAME", "VARCHAR(50)"),
+            ColumnDescriptor::new("EMAIL", "VARCHAR(100)").with_nullable(false),
+            ColumnDescriptor::new("LEGACY", "VARCHAR(10)"),
+        ]
+    }
+
+    fn v2() -> Vec<ColumnDescriptor> {
+        vec![
+            ColumnDescriptor::new("id", "number(38, 0)").with_nullable(false),
+            ColumnDescriptor::new("NAME", "VARCHAR(200)").with_nullable(false),
+            ColumnDescriptor::new("EMAIL", "VARCHAR(100)"),
+            ColumnDescriptor::new("CREATED_AT", "TIMESTAMP_NTZ(9)")
+                .with_nullable(false)
+                .with_default("CURRENT_TIMESTAMP()"),
+            ColumnDescriptor::new("\"Score\"", "FLOAT"),
+        ]
+    }
+
+    #[test]
+    fn forward_plan_adds_first_and_drops_last() {
+        assert_eq!(
+            SchemaEvolution::diff(&v1(), &v2()),
+            vec![
+                SchemaMigration::AddColumn(
+                    ColumnDescriptor::new("CREATED_AT", "TIMESTAMP_NTZ(9)")
+                        .with_nullable(false)
+                        .with_default("CURRENT_TIMESTAMP()")
+                ),
+                SchemaMigration::AddColumn(ColumnDescriptor::new("\"Score\"", "FLOAT")),
+                SchemaMigration::AlterColumnType {
+                    name: "NAME".to_string(),
+                    new_type: "VARCHAR(200)".to_string(),
+                },
+                SchemaMigration::SetNullable {
+                    name: "NAME".to_string(),
+                    nullable: false,
+                },
+                SchemaMigration::SetNullable {
+                    name: "EMAIL".to_string(),
+                    nullable: true,
+                },
+                SchemaMigration::DropColumn("LEGACY".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn swapped_arguments_plan_going_back() {
+        assert_eq!(
+            SchemaEvolution::diff(&v2(), &v1()),
+            vec![
+                SchemaMigration::AddColumn(ColumnDescriptor::new("LEGACY", "VARCHAR(10)")),
+                SchemaMigration::AlterColumnType {
+                    name: "NAME".to_string(),
+                    new_type: "VARCHAR(50)".to_string(),
+                },
+                SchemaMigration::SetNullable {
+                    name: "NAME".to_string(),
+                    nullable: true,
+                },
+                SchemaMigration::SetNullable {
+                    name: "EMAIL".to_string(),
+                    nullable: false,
+                },
+                SchemaMigration::DropColumn("CREATED_AT".to_string()),
+                SchemaMigration::DropColumn("\"Score\"".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_schema_needs_no_steps() {
+        assert_eq!(SchemaEvolution::diff(&v1(), &v1()), vec![]);
+        assert_eq!(SchemaEvolution::diff(&[], &[]), vec![]);
+
+        // quoted names keep their case, so they don't match the unquoted ones
+        let quoted = [ColumnDescriptor::new("\"score\"", "FLOAT")];
+        let unquoted = [ColumnDescriptor::new("score", "FLOAT")];
+        assert_eq!(
+            SchemaEvolution::diff(&quoted, &unquoted),
+            vec![
+                SchemaMigration::AddColumn(ColumnDescriptor::new("score", "FLOAT")),
+                SchemaMigration::DropColumn("\"score\"".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn described_columns_keep_their_case() {
+        let column = |name: &str| TableColumn {
+            name: name.to_string(),
+            type_: "VARCHAR(16777216)".to_string(),
+            kind: "COLUMN".to_string(),
+            nullable: true,
+            default: None,
+            primary_key: false,
+            unique_key: false,
+            comment: None,
+        };
+        let names: Vec<String> = ["ID", "Score", "FIRST NAME"]
+            .into_iter()
+            .map(|name| ColumnDescriptor::from(column(name)).name)
+            .collect();
+        assert_eq!(names, ["ID", "\"Score\"", "\"FIRST NAME\""]);
+    }
+
+    #[tokio::test]
+    async fn steps_are_applied_in_order() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let mut statements = vec![];
+        for sql in [
+            "ALTER TABLE db.s.users ADD COLUMN CREATED_AT TIMESTAMP_NTZ(9) \
+                DEFAULT CURRENT_TIMESTAMP() NOT NULL",
+            "ALTER TABLE db.s.users ADD COLUMN \"Score\" FLOAT",
+            "ALTER TABLE db.s.users ALTER COLUMN NAME SET DATA TYPE VARCHAR(200)",
+            "ALTER TABLE db.s.users ALTER COLUMN NAME SET NOT NULL",
+            "ALTER TABLE db.s.users ALTER COLUMN EMAIL DROP NOT NULL",
+            "ALTER TABLE db.s.users DROP COLUMN LEGACY",
+        ] {
+            statements.push(
+                server
+                    .mock("POST", mock::QUERY_PATH)
+                    .match_query(Matcher::Any)
+                    .match_body(Matcher::PartialJson(json!({ "sqlText": sql })))
+                    .with_body(mock::query_body(&json!({ "statementTypeId": 24832 })))
+                    .create_async()
+                    .await,
+            );
+        }
+
+        let api = mock::api(&server);
+        let migrations = SchemaEvolution::diff(&v1(), &v2());
+        SchemaEvolution::apply(&api, "db.s.users", &migrations)
+            .await
+            .unwrap();
+        for statement in statements {
+            statement.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_step_stops_before_anything_is_changed() {
+        let mut server = mockito::Server::new_async().await;
+        let query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .expect(0)
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let migrations = [
+            SchemaMigration::AddColumn(ColumnDescriptor::new("OK", "INT")),
+            SchemaMigration::DropColumn("bad; DROP TABLE users".to_string()),
+        ];
+        assert!(SchemaEvolution::apply(&api, "users", &migrations)
+            .await
+            .is_err());
+        query.assert_async().await;
+    }
+}
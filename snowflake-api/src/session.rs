@@ -1,20 +1,42 @@
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 
 use futures::lock::Mutex;
 #[cfg(feature = "cert-auth")]
-use snowflake_jwt::generate_jwt_token;
+use snowflake_jwt::{
+    generate_jwt_ec, generate_jwt_token_from_key, load_ec_private_key, load_private_key, JwtCache,
+    JwtGenerator,
+};
 use thiserror::Error;
 
 use crate::connection;
 use crate::connection::{Connection, QueryType};
+use crate::mfa::{DuoFactor, MfaHandler, DUO_ALL, DUO_PUSH_N_PASSCODE};
+use crate::parameters::ParameterMap;
+use crate::query_context::QueryContextCache;
+use crate::quote::quote_ident;
 #[cfg(feature = "cert-auth")]
 use crate::requests::{CertLoginRequest, CertRequestData};
 use crate::requests::{
-    ClientEnvironment, LoginRequest, LoginRequestCommon, PasswordLoginRequest, PasswordRequestData,
-    RenewSessionRequest, SessionParameters,
+    ClientEnvironment, ExecRequest, LoginRequest, LoginRequestCommon, OAuthLoginRequest,
+    OAuthRequestData, PasswordLoginRequest, PasswordRequestData, RenewSessionRequest,
+    SamlLoginRequest, SamlRequestData, SessionParameters,
 };
-use crate::responses::AuthResponse;
+use crate::responses::{AuthResponse, ExecResponse, HeartbeatResponse};
+use crate::sso::ProgrammaticSsoAuth;
+
+/// Session token has expired, it has to be renewed with the master token
+pub(crate) const SESSION_EXPIRED: &str = "390112";
+/// Master token has expired, new session has to be created
+const MASTER_TOKEN_EXPIRED: &str = "390114";
+
+/// Key-pair JWT is only used for the login request, matches the lifetime of RSA tokens
+#[cfg(feature = "cert-auth")]
+const JWT_LIFETIME: Duration = Duration::from_hours(24);
+
+/// Snowflake drops sessions after 4 hours of inactivity, validate the session a bit earlier
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_mins(4 * 60 - 10);
 
 #[derive(Error, Debug)]
 pub enum AuthError {
@@ -31,6 +53,9 @@ pub enum AuthError {
     #[error("Password auth was requested, but password wasn't provided")]
     MissingPassword,
 
+    #[error("Session has expired while the transaction was open, the transaction is lost")]
+    SessionLostInTransaction,
+
     #[error("Certificate auth was requested, but certificate wasn't provided")]
     MissingCertificate,
 
@@ -48,8 +73,32 @@ pub enum AuthError {
     #[error("Failed to exchange or request a new token")]
     TokenFetchFailed,
 
+    #[error("OAuth auth was requested, but token wasn't provided")]
+    MissingOAuthToken,
+
     #[error("Enable the cert-auth feature to use certificate authentication")]
     CertAuthNotEnabled,
+
+    #[error("Duo MFA is required, but MFA handler wasn't provided. Message: {message}")]
+    MfaRequired {
+        next_action: String,
+        message: String,
+    },
+
+    #[error("Failed to get the second factor: {0}")]
+    MfaFailed(String),
+
+    #[error("SSO auth was requested, but IdP credentials weren't provided")]
+    MissingSsoCredentials,
+
+    #[error("Failed to get SAML response from the identity provider: {0}")]
+    SsoFailed(String),
+
+    #[error("Failed to initialize session. Error code: {0}. Message: {1}")]
+    SessionInitFailed(String, String),
+
+    #[error("Secondary role `{0}` can't be quoted as an identifier")]
+    InvalidSecondaryRole(String),
 }
 
 #[derive(Debug)]
@@ -58,6 +107,8 @@ struct AuthTokens {
     master_token: AuthToken,
     /// expected by snowflake api for all requests within session to follow sequence id
     sequence_id: u64,
+    /// time of the last request issued within the session, server resets its idle timer on it
+    last_used: Instant,
 }
 
 #[derive(Debug, Clone)]
@@ -105,6 +156,43 @@ impl AuthToken {
 enum AuthType {
     Certificate,
     Password,
+    OAuth,
+    Sso,
+}
+
+/// Secondary roles which are activated right after the session is created,
+/// see <https://docs.snowflake.com/en/sql-reference/sql/use-secondary-roles>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecondaryRoles {
+    All,
+    None,
+    List(Vec<String>),
+}
+
+impl SecondaryRoles {
+    fn uppercase(self) -> Self {
+        match self {
+            Self::List(roles) => Self::List(roles.iter().map(|r| r.to_uppercase()).collect()),
+            other => other,
+        }
+    }
+
+    /// Role names are quoted, they are already upper-cased the way the server would resolve them
+    fn statement(&self) -> Result<String, AuthError> {
+        match self {
+            Self::All => Ok("USE SECONDARY ROLES ALL".to_string()),
+            Self::None => Ok("USE SECONDARY ROLES NONE".to_string()),
+            Self::List(roles) => {
+                let roles = roles
+                    .iter()
+                    .map(|role| {
+                        quote_ident(role).map_err(|_| AuthError::InvalidSecondaryRole(role.clone()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(format!("USE SECONDARY ROLES {}", roles.join(", ")))
+            }
+        }
+    }
 }
 
 /// Requests, caches, and renews authentication tokens.
@@ -116,8 +204,15 @@ pub struct Session {
     connection: Arc<Connection>,
 
     auth_tokens: Mutex<Option<AuthTokens>>,
+    /// Copy of the sequence id of the current session, readable without waiting for the tokens
+    sequence_id: AtomicU64,
+    /// Explicit transaction is open, a new session would silently drop it
+    in_transaction: AtomicBool,
     auth_type: AuthType,
+    parameters: ParameterMap,
+    query_context: QueryContextCache,
     account_identifier: String,
+    idle_timeout: Duration,
 
     warehouse: Option<String>,
     database: Option<String>,
@@ -125,10 +220,22 @@ pub struct Session {
 
     username: String,
     role: Option<String>,
+    secondary_roles: Option<SecondaryRoles>,
+    session_parameters: SessionParameters,
+    /// autocommit mode requested by the user, `None` keeps the server default
+    autocommit: RwLock<Option<bool>>,
     // This is not used with the certificate auth crate
     #[allow(dead_code)]
     private_key_pem: Option<String>,
+    #[allow(dead_code)]
+    private_key_passphrase: Option<String>,
     password: Option<String>,
+    oauth_token: Option<String>,
+    sso: Option<ProgrammaticSsoAuth>,
+    mfa_handler: Option<Arc<dyn MfaHandler>>,
+    /// JWT of the key-pair login is reused by the following logins until it expires
+    #[cfg(feature = "cert-auth")]
+    jwt_cache: std::sync::Mutex<JwtCache>,
 }
 
 // todo: make builder
@@ -159,15 +266,29 @@ impl Session {
         Self {
             connection,
             auth_tokens: Mutex::new(None),
+            sequence_id: AtomicU64::new(0),
+            in_transaction: AtomicBool::new(false),
+            parameters: ParameterMap::default(),
+            query_context: QueryContextCache::default(),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
             auth_type: AuthType::Certificate,
             private_key_pem,
+            private_key_passphrase: None,
             account_identifier,
             warehouse: warehouse.map(str::to_uppercase),
             database,
             username,
             role,
+            secondary_roles: None,
+            session_parameters: SessionParameters::default(),
+            autocommit: RwLock::new(None),
             schema,
             password: None,
+            oauth_token: None,
+            sso: None,
+            mfa_handler: None,
+            #[cfg(feature = "cert-auth")]
+            jwt_cache: std::sync::Mutex::default(),
         }
     }
 
@@ -196,59 +317,372 @@ impl Session {
         Self {
             connection,
             auth_tokens: Mutex::new(None),
+            sequence_id: AtomicU64::new(0),
+            in_transaction: AtomicBool::new(false),
+            parameters: ParameterMap::default(),
+            query_context: QueryContextCache::default(),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
             auth_type: AuthType::Password,
             account_identifier,
             warehouse: warehouse.map(str::to_uppercase),
             database,
             username,
             role,
+            secondary_roles: None,
+            session_parameters: SessionParameters::default(),
+            autocommit: RwLock::new(None),
             password,
             schema,
             private_key_pem: None,
+            private_key_passphrase: None,
+            oauth_token: None,
+            sso: None,
+            mfa_handler: None,
+            #[cfg(feature = "cert-auth")]
+            jwt_cache: std::sync::Mutex::default(),
+        }
+    }
+
+    /// Authenticate using OAuth access token issued by the external identity provider
+    // fixme: add builder or introduce structs
+    #[allow(clippy::too_many_arguments)]
+    pub fn oauth_auth(
+        connection: Arc<Connection>,
+        account_identifier: &str,
+        warehouse: Option<&str>,
+        database: Option<&str>,
+        schema: Option<&str>,
+        username: &str,
+        role: Option<&str>,
+        token: &str,
+    ) -> Self {
+        let account_identifier = account_identifier.to_uppercase();
+
+        let database = database.map(str::to_uppercase);
+        let schema = schema.map(str::to_uppercase);
+
+        let username = username.to_uppercase();
+        let oauth_token = Some(token.to_string());
+        let role = role.map(str::to_uppercase);
+
+        Self {
+            connection,
+            auth_tokens: Mutex::new(None),
+            sequence_id: AtomicU64::new(0),
+            in_transaction: AtomicBool::new(false),
+            parameters: ParameterMap::default(),
+            query_context: QueryContextCache::default(),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            auth_type: AuthType::OAuth,
+            account_identifier,
+            warehouse: warehouse.map(str::to_uppercase),
+            database,
+            username,
+            role,
+            secondary_roles: None,
+            session_parameters: SessionParameters::default(),
+            autocommit: RwLock::new(None),
+            password: None,
+            schema,
+            private_key_pem: None,
+            private_key_passphrase: None,
+            oauth_token,
+            sso: None,
+            mfa_handler: None,
+            #[cfg(feature = "cert-auth")]
+            jwt_cache: std::sync::Mutex::default(),
+        }
+    }
+
+    /// Authenticate with the SAML response obtained from the identity provider without a browser,
+    /// user is the one of the identity provider credentials
+    pub fn sso_auth(
+        connection: Arc<Connection>,
+        account_identifier: &str,
+        warehouse: Option<&str>,
+        database: Option<&str>,
+        schema: Option<&str>,
+        role: Option<&str>,
+        sso: ProgrammaticSsoAuth,
+    ) -> Self {
+        let account_identifier = account_identifier.to_uppercase();
+
+        let database = database.map(str::to_uppercase);
+        let schema = schema.map(str::to_uppercase);
+
+        let username = sso.username.to_uppercase();
+        let role = role.map(str::to_uppercase);
+
+        Self {
+            connection,
+            auth_tokens: Mutex::new(None),
+            sequence_id: AtomicU64::new(0),
+            in_transaction: AtomicBool::new(false),
+            parameters: ParameterMap::default(),
+            query_context: QueryContextCache::default(),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            auth_type: AuthType::Sso,
+            account_identifier,
+            warehouse: warehouse.map(str::to_uppercase),
+            database,
+            username,
+            role,
+            secondary_roles: None,
+            session_parameters: SessionParameters::default(),
+            autocommit: RwLock::new(None),
+            password: None,
+            schema,
+            private_key_pem: None,
+            private_key_passphrase: None,
+            oauth_token: None,
+            sso: Some(sso),
+            mfa_handler: None,
+            #[cfg(feature = "cert-auth")]
+            jwt_cache: std::sync::Mutex::default(),
         }
     }
 
+    /// Decrypt the `ENCRYPTED PRIVATE KEY` used for the certificate auth
+    #[must_use]
+    pub fn with_private_key_passphrase(mut self, passphrase: &str) -> Self {
+        self.private_key_passphrase = Some(passphrase.to_string());
+        self
+    }
+
+    /// Activate secondary roles every time a new session is created
+    #[must_use]
+    pub fn with_secondary_roles(mut self, secondary_roles: SecondaryRoles) -> Self {
+        self.secondary_roles = Some(secondary_roles.uppercase());
+        self
+    }
+
+    /// Parameters applied to every new session as part of the login
+    #[must_use]
+    pub fn with_session_parameters(mut self, session_parameters: SessionParameters) -> Self {
+        self.session_parameters = session_parameters;
+        self
+    }
+
+    /// Session is validated with a heartbeat before use if it was idle for longer than `idle_timeout`
+    #[must_use]
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Provide the second factor when password login asks for Duo MFA
+    #[must_use]
+    pub fn with_mfa_handler(mut self, mfa_handler: Arc<dyn MfaHandler>) -> Self {
+        self.mfa_handler = Some(mfa_handler);
+        self
+    }
+
+    /// Session parameters announced by the server
+    pub fn parameters(&self) -> &ParameterMap {
+        &self.parameters
+    }
+
+    /// Sequence id of the last request of the current session, ids start over with every session
+    pub fn sequence_id(&self) -> u64 {
+        self.sequence_id.load(Ordering::Relaxed)
+    }
+
+    /// Query context echoed back to the server with every query
+    pub fn query_context(&self) -> &QueryContextCache {
+        &self.query_context
+    }
+
+    /// Secondary roles which are applied to the session after login
+    pub fn secondary_roles(&self) -> Option<&SecondaryRoles> {
+        self.secondary_roles.as_ref()
+    }
+
+    /// Autocommit mode set with [`Session::record_autocommit`], if any
+    pub fn autocommit(&self) -> Option<bool> {
+        *self.autocommit.read().unwrap()
+    }
+
+    /// Remember autocommit mode already applied to the current session,
+    /// so it's applied again to the new session on re-login
+    pub fn record_autocommit(&self, autocommit: bool) {
+        *self.autocommit.write().unwrap() = Some(autocommit);
+    }
+
     /// Get cached token or request a new one if old one has expired.
     pub async fn get_token(&self) -> Result<AuthParts, AuthError> {
         let mut auth_tokens = self.auth_tokens.lock().await;
-        if auth_tokens.is_none()
-            || auth_tokens
-                .as_ref()
-                .is_some_and(|at| at.master_token.is_expired())
-        {
-            // Create new session if tokens are absent or can not be exchange
-            let tokens = match self.auth_type {
-                AuthType::Certificate => {
-                    log::info!("Starting session with certificate authentication");
-                    if cfg!(feature = "cert-auth") {
-                        self.create(self.cert_request_body()?).await
-                    } else {
-                        Err(AuthError::MissingCertificate)?
-                    }
-                }
-                AuthType::Password => {
-                    log::info!("Starting session with password authentication");
-                    self.create(self.passwd_request_body()?).await
-                }
-            }?;
-            *auth_tokens = Some(tokens);
+        if auth_tokens.is_none() {
+            // first session, there is no open transaction to lose yet
+            *auth_tokens = Some(self.login().await?);
         } else if auth_tokens
             .as_ref()
-            .is_some_and(|at| at.session_token.is_expired())
+            .is_some_and(|at| at.master_token.is_expired())
         {
-            // Renew old session token
-            let old_token = auth_tokens.take().unwrap();
-            let tokens = self.renew(old_token).await?;
-            *auth_tokens = Some(tokens);
+            // Create new session if tokens can not be exchanged
+            *auth_tokens = Some(self.relogin().await?);
+        } else {
+            // Server drops the idle session after 4 hours, long after the session token expiry,
+            // so idleness is checked on its own rather than only when the token is still valid
+            let idle = auth_tokens
+                .as_ref()
+                .is_some_and(|at| at.last_used.elapsed() >= self.idle_timeout);
+            if auth_tokens
+                .as_ref()
+                .is_some_and(|at| at.session_token.is_expired())
+            {
+                // Renew old session token, the old tokens are kept if renewal fails, so the next
+                // call doesn't take the new session for the first one and lose the transaction
+                let old_tokens = auth_tokens.as_ref().unwrap();
+                let tokens = match self.renew(old_tokens).await {
+                    // master token could be invalidated on the server side before its local expiry,
+                    // the only way to continue is to start over with the stored credentials
+                    Err(AuthError::AuthFailed(code, _)) if code == MASTER_TOKEN_EXPIRED => {
+                        tracing::warn!(
+                            "Master token has expired, logging in again. \
+                            Session state (temporary objects, session variables) is lost"
+                        );
+                        self.relogin().await?
+                    }
+                    // tokens of the session dropped by the server can't be renewed either
+                    Err(AuthError::AuthFailed(code, message)) if idle => {
+                        tracing::warn!(
+                            "Session was dropped after being idle ({code}: {message}), \
+                            logging in again. Session state (temporary objects, session variables) \
+                            is lost"
+                        );
+                        self.relogin().await?
+                    }
+                    res => res?,
+                };
+                *auth_tokens = Some(tokens);
+            } else if idle {
+                // Server could have dropped the idle session, while tokens are still valid locally
+                let tokens = auth_tokens.as_ref().unwrap();
+                if !self.heartbeat(&tokens.session_token).await? {
+                    tracing::warn!(
+                        "Session was dropped after being idle, logging in again. \
+                        Session state (temporary objects, session variables) is lost"
+                    );
+                    *auth_tokens = Some(self.relogin().await?);
+                }
+            }
         }
-        auth_tokens.as_mut().unwrap().sequence_id += 1;
+        let tokens = auth_tokens.as_mut().unwrap();
+        tokens.sequence_id += 1;
+        self.sequence_id
+            .store(tokens.sequence_id, Ordering::Relaxed);
+        tokens.last_used = Instant::now();
         Ok(AuthParts {
-            session_token_auth_header: auth_tokens.as_ref().unwrap().session_token.auth_header(),
-            sequence_id: auth_tokens.as_ref().unwrap().sequence_id,
+            session_token_auth_header: tokens.session_token.auth_header(),
+            sequence_id: tokens.sequence_id,
         })
     }
 
-    pub async fn close(&mut self) -> Result<(), AuthError> {
+    /// Login which replaces the current session, refused while a transaction is open
+    async fn relogin(&self) -> Result<AuthTokens, AuthError> {
+        if self.in_transaction() {
+            return Err(AuthError::SessionLostInTransaction);
+        }
+        self.login().await
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.in_transaction.load(Ordering::SeqCst)
+    }
+
+    /// Mark the explicit transaction as open or closed, returns the previous state
+    pub fn set_in_transaction(&self, in_transaction: bool) -> bool {
+        self.in_transaction.swap(in_transaction, Ordering::SeqCst)
+    }
+
+    /// Roll back the open transaction of the current session, if there is one.
+    /// Never logs in, the transaction is gone together with the session anyway.
+    pub async fn rollback(&self) -> Result<(), AuthError> {
+        let mut auth_tokens = self.auth_tokens.lock().await;
+        let res = match auth_tokens.as_mut() {
+            Some(tokens) => self.exec_internal(tokens, "ROLLBACK").await,
+            None => Ok(()),
+        };
+        self.set_in_transaction(false);
+        res
+    }
+
+    /// Mark session token as expired, it will be renewed on the next [`Session::get_token`] call.
+    /// Used when server reports session expiry before the token validity runs out locally.
+    pub async fn expire_session_token(&self) {
+        if let Some(tokens) = self.auth_tokens.lock().await.as_mut() {
+            tokens.session_token.valid_for = Duration::ZERO;
+        }
+    }
+
+    /// Check whether the server still considers the session alive, this also resets its idle timer
+    async fn heartbeat(&self, session_token: &AuthToken) -> Result<bool, AuthError> {
+        log::debug!("Sending session heartbeat");
+        let resp = self
+            .connection
+            .request::<HeartbeatResponse>(
+                QueryType::Heartbeat,
+                &self.account_identifier,
+                &[],
+                Some(&session_token.auth_header()),
+                serde_json::Value::default(),
+            )
+            .await?;
+
+        if !resp.success {
+            log::debug!(
+                "Session heartbeat failed. Code: {}. Message: {}",
+                resp.code.unwrap_or_default(),
+                resp.message.unwrap_or_default()
+            );
+        }
+        Ok(resp.success)
+    }
+
+    /// Full login with the stored credentials, only the MFA handler could be interactive
+    #[tracing::instrument(
+        name = "snowflake.login",
+        skip_all,
+        fields(account = %self.account_identifier, user = %self.username)
+    )]
+    async fn login(&self) -> Result<AuthTokens, AuthError> {
+        let mut tokens = match self.auth_type {
+            AuthType::Certificate => {
+                log::info!("Starting session with certificate authentication");
+                if cfg!(feature = "cert-auth") {
+                    self.create(self.cert_request_body()?).await
+                } else {
+                    Err(AuthError::MissingCertificate)?
+                }
+            }
+            AuthType::Password => {
+                log::info!("Starting session with password authentication");
+                match self.create(self.passwd_request_body(None)?).await {
+                    Err(AuthError::MfaRequired {
+                        next_action,
+                        message,
+                    }) if self.mfa_handler.is_some() => {
+                        self.mfa_login(&next_action, &message).await
+                    }
+                    res => res,
+                }
+            }
+            AuthType::OAuth => {
+                log::info!("Starting session with OAuth authentication");
+                self.create(self.oauth_request_body()?).await
+            }
+            AuthType::Sso => {
+                log::info!("Starting session with programmatic SSO authentication");
+                self.create(self.saml_request_body().await?).await
+            }
+        }?;
+        self.initialize(&mut tokens).await?;
+        Ok(tokens)
+    }
+
+    pub async fn close(&self) -> Result<(), AuthError> {
+        // open transaction is rolled back by the server together with the session
+        self.set_in_transaction(false);
         if let Some(tokens) = self.auth_tokens.lock().await.take() {
             log::debug!("Closing sessions");
 
@@ -278,12 +712,19 @@ impl Session {
 
     #[cfg(feature = "cert-auth")]
     fn cert_request_body(&self) -> Result<CertLoginRequest, AuthError> {
-        let full_identifier = format!("{}.{}", &self.account_identifier, &self.username);
         let private_key_pem = self
             .private_key_pem
-            .as_ref()
+            .as_deref()
             .ok_or(AuthError::MissingCertificate)?;
-        let jwt_token = generate_jwt_token(private_key_pem, &full_identifier)?;
+        let jwt_token = self
+            .jwt_cache
+            .lock()
+            .unwrap()
+            .get_or_refresh(&KeyPairJwt {
+                session: self,
+                private_key_pem,
+            })?
+            .to_string();
 
         Ok(CertLoginRequest {
             data: CertRequestData {
@@ -294,13 +735,82 @@ impl Session {
         })
     }
 
-    fn passwd_request_body(&self) -> Result<PasswordLoginRequest, AuthError> {
+    /// Retry the password login with the second factor provided by the MFA handler
+    async fn mfa_login(&self, next_action: &str, message: &str) -> Result<AuthTokens, AuthError> {
+        let handler = self
+            .mfa_handler
+            .as_ref()
+            .ok_or_else(|| AuthError::MfaRequired {
+                next_action: next_action.to_string(),
+                message: message.to_string(),
+            })?;
+
+        let prompt = if message.is_empty() {
+            "Duo MFA is required to log in"
+        } else {
+            message
+        };
+        let factor = match handler.handle_mfa(prompt).await? {
+            // passcode isn't accepted unless the server has offered it
+            Some(_) if next_action != DUO_PUSH_N_PASSCODE => {
+                log::warn!("Server doesn't accept MFA passcode, using push notification instead");
+                DuoFactor::Push
+            }
+            Some(passcode) => DuoFactor::Passcode(passcode),
+            None => DuoFactor::Push,
+        };
+        log::info!("Logging in with Duo {}", factor.method());
+
+        self.create(self.passwd_request_body(Some(factor))?).await
+    }
+
+    /// Second factor is only sent when the server has asked for it
+    fn passwd_request_body(
+        &self,
+        factor: Option<DuoFactor>,
+    ) -> Result<PasswordLoginRequest, AuthError> {
         let password = self.password.as_ref().ok_or(AuthError::MissingPassword)?;
+        let ext_authn_duo_method = factor.as_ref().map(|f| f.method().to_string());
+        let passcode = match factor {
+            Some(DuoFactor::Passcode(passcode)) => Some(passcode),
+            _ => None,
+        };
 
         Ok(PasswordLoginRequest {
             data: PasswordRequestData {
                 login_request_common: self.login_request_common(),
-                password: password.to_string(),
+                password: password.clone(),
+                ext_authn_duo_method,
+                passcode,
+            },
+        })
+    }
+
+    fn oauth_request_body(&self) -> Result<OAuthLoginRequest, AuthError> {
+        let token = self
+            .oauth_token
+            .as_ref()
+            .ok_or(AuthError::MissingOAuthToken)?;
+
+        Ok(OAuthLoginRequest {
+            data: OAuthRequestData {
+                login_request_common: self.login_request_common(),
+                authenticator: "OAUTH".to_string(),
+                token: token.clone(),
+            },
+        })
+    }
+
+    /// SAML response is single use, so the identity provider is asked for a new one on every login
+    async fn saml_request_body(&self) -> Result<SamlLoginRequest, AuthError> {
+        let sso = self.sso.as_ref().ok_or(AuthError::MissingSsoCredentials)?;
+        let raw_saml_response = sso.fetch_token(&self.connection).await?;
+
+        Ok(SamlLoginRequest {
+            data: SamlRequestData {
+                login_request_common: self.login_request_common(),
+                authenticator: sso.idp_url.to_string(),
+                raw_saml_response,
             },
         })
     }
@@ -338,10 +848,16 @@ impl Session {
                 body,
             )
             .await?;
-        log::debug!("Auth response: {:?}", resp);
+        log::debug!("Auth response: {resp:?}");
 
         match resp {
             AuthResponse::Login(lr) => {
+                // parameters of the previous session (if any) are no longer relevant
+                self.parameters.clear();
+                self.query_context.clear();
+                self.sequence_id.store(0, Ordering::Relaxed);
+                self.parameters.apply(&lr.data.parameters);
+
                 let session_token = AuthToken::new(&lr.data.token, lr.data.validity_in_seconds);
                 let master_token =
                     AuthToken::new(&lr.data.master_token, lr.data.master_validity_in_seconds);
@@ -350,6 +866,18 @@ impl Session {
                     session_token,
                     master_token,
                     sequence_id: 0,
+                    last_used: Instant::now(),
+                })
+            }
+            AuthResponse::Error(e)
+                if e.data
+                    .next_action
+                    .as_deref()
+                    .is_some_and(|a| a == DUO_ALL || a == DUO_PUSH_N_PASSCODE) =>
+            {
+                Err(AuthError::MfaRequired {
+                    next_action: e.data.next_action.unwrap_or_default(),
+                    message: e.message.unwrap_or_default(),
                 })
             }
             AuthResponse::Error(e) => Err(AuthError::AuthFailed(
@@ -360,6 +888,57 @@ impl Session {
         }
     }
 
+    /// Apply the session state which can't be passed along with the login request
+    async fn initialize(&self, tokens: &mut AuthTokens) -> Result<(), AuthError> {
+        if let Some(secondary_roles) = &self.secondary_roles {
+            self.exec_internal(tokens, &secondary_roles.statement()?)
+                .await?;
+        }
+
+        let autocommit = self.autocommit();
+        if let Some(autocommit) = autocommit {
+            self.exec_internal(tokens, &autocommit_statement(autocommit))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Run a statement on behalf of the library, result is discarded
+    async fn exec_internal(&self, tokens: &mut AuthTokens, sql: &str) -> Result<(), AuthError> {
+        log::debug!("Executing session statement: {sql}");
+        tokens.sequence_id += 1;
+        self.sequence_id
+            .store(tokens.sequence_id, Ordering::Relaxed);
+        let body = ExecRequest {
+            sequence_id: tokens.sequence_id,
+            ..ExecRequest::internal(sql)
+        };
+
+        let resp = self
+            .connection
+            .request::<ExecResponse>(
+                QueryType::JsonQuery,
+                &self.account_identifier,
+                &[],
+                Some(&tokens.session_token.auth_header()),
+                body,
+            )
+            .await?;
+
+        match resp {
+            ExecResponse::Query(qr) => {
+                self.parameters.apply(&qr.data.parameters);
+                Ok(())
+            }
+            ExecResponse::Error(e) => Err(AuthError::SessionInitFailed(
+                e.data.error_code,
+                e.message.unwrap_or_default(),
+            )),
+            ExecResponse::PutGet(_) => Err(AuthError::UnexpectedResponse),
+        }
+    }
+
     fn login_request_common(&self) -> LoginRequestCommon {
         LoginRequestCommon {
             client_app_id: "Go".to_string(),
@@ -367,20 +946,13 @@ impl Session {
             svn_revision: String::new(),
             account_name: self.account_identifier.clone(),
             login_name: self.username.clone(),
-            session_parameters: SessionParameters {
-                client_validate_default_parameters: true,
-            },
-            client_environment: ClientEnvironment {
-                application: "Rust".to_string(),
-                // todo: detect os
-                os: "darwin".to_string(),
-                os_version: "gc-arm64".to_string(),
-                ocsp_mode: "FAIL_OPEN".to_string(),
-            },
+            session_parameters: self.session_parameters.clone(),
+            client_environment: ClientEnvironment::detect(),
         }
     }
 
-    async fn renew(&self, token: AuthTokens) -> Result<AuthTokens, AuthError> {
+    #[tracing::instrument(name = "snowflake.renew", skip_all, fields(account = %self.account_identifier))]
+    async fn renew(&self, token: &AuthTokens) -> Result<AuthTokens, AuthError> {
         log::debug!("Renewing the token");
         let auth = token.master_token.auth_header();
         let body = RenewSessionRequest {
@@ -410,6 +982,7 @@ impl Session {
                     session_token,
                     master_token,
                     sequence_id: token.sequence_id,
+                    last_used: token.last_used,
                 })
             }
             AuthResponse::Error(e) => Err(AuthError::AuthFailed(
@@ -420,3 +993,275 @@ impl Session {
         }
     }
 }
+
+pub(crate) fn autocommit_statement(autocommit: bool) -> String {
+    format!(
+        "ALTER SESSION SET AUTOCOMMIT = {}",
+        if autocommit { "TRUE" } else { "FALSE" }
+    )
+}
+
+/// Signs the login JWT with the private key of the session
+#[cfg(feature = "cert-auth")]
+struct KeyPairJwt<'a> {
+    session: &'a Session,
+    private_key_pem: &'a str,
+}
+
+#[cfg(feature = "cert-auth")]
+impl JwtGenerator for KeyPairJwt<'_> {
+    fn generate(&self) -> Result<(String, Duration), snowflake_jwt::JwtError> {
+        let session = self.session;
+        let full_identifier = format!("{}.{}", &session.account_identifier, &session.username);
+        let private_key_pem = self.private_key_pem;
+        let jwt_token = match load_private_key(
+            private_key_pem.as_bytes(),
+            session.private_key_passphrase.as_deref().map(str::as_bytes),
+        ) {
+            Ok(private_key) => generate_jwt_token_from_key(&private_key, &full_identifier)?,
+            // not an RSA key, try P-256 and P-384 before reporting the original error
+            Err(e) => match load_ec_private_key(private_key_pem.as_bytes()) {
+                Ok(ec_key) => generate_jwt_ec(
+                    &session.account_identifier,
+                    &session.username,
+                    &ec_key,
+                    JWT_LIFETIME,
+                )?,
+                Err(_) => return Err(e),
+            },
+        };
+        Ok((jwt_token, JWT_LIFETIME))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Matcher, ServerGuard};
+    use serde_json::json;
+    use tracing::Level;
+
+    use super::*;
+    use crate::mock;
+
+    fn session(server: &ServerGuard, idle_timeout: Duration) -> Session {
+        Session::password_auth(
+            Arc::new(mock::connection(server)),
+            "xy12345",
+            None,
+            None,
+            None,
+            "user",
+            None,
+            "password",
+        )
+        .with_idle_timeout(idle_timeout)
+    }
+
+    async fn failed_renewal(server: &mut ServerGuard) -> mockito::Mock {
+        server
+            .mock("POST", "/session/token-request")
+            .match_query(Matcher::Any)
+            .with_body(
+                json!({
+                    "code": "390111",
+                    "message": "Session no longer exists.",
+                    "success": false,
+                    "data": {}
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await
+    }
+
+    #[tokio::test]
+    async fn secondary_roles_are_quoted() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let roles = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({
+                "sqlText": "USE SECONDARY ROLES \"ANALYST\", \"DATA; DROP TABLE T\""
+            })))
+            .with_body(mock::query_body(&json!({})))
+            .create_async()
+            .await;
+
+        let session =
+            session(&server, DEFAULT_IDLE_TIMEOUT).with_secondary_roles(SecondaryRoles::List(
+                vec!["analyst".to_string(), "data; drop table t".to_string()],
+            ));
+        session.get_token().await.unwrap();
+        roles.assert_async().await;
+    }
+
+    #[test]
+    fn unquotable_secondary_roles_are_rejected() {
+        let roles = SecondaryRoles::List(vec!["ANALYST".to_string(), String::new()]);
+        assert!(matches!(
+            roles.statement(),
+            Err(AuthError::InvalidSecondaryRole(role)) if role.is_empty()
+        ));
+        assert_eq!(
+            SecondaryRoles::All.statement().unwrap(),
+            "USE SECONDARY ROLES ALL"
+        );
+    }
+
+    #[tokio::test]
+    async fn idle_session_with_expired_token_logs_in_again() {
+        let mut server = mockito::Server::new_async().await;
+        // session token expires right away, so the next call has to renew it
+        let login = mock::login_with_validity(&mut server, 0).await.expect(2);
+        let renew = failed_renewal(&mut server).await;
+
+        let session = session(&server, Duration::from_millis(10));
+        session.get_token().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        session.get_token().await.unwrap();
+
+        renew.assert_async().await;
+        login.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn failed_renewal_of_active_session_is_reported() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login_with_validity(&mut server, 0).await.expect(1);
+        let _renew = failed_renewal(&mut server).await;
+
+        let session = session(&server, DEFAULT_IDLE_TIMEOUT);
+        session.get_token().await.unwrap();
+        let err = session.get_token().await.unwrap_err();
+
+        assert!(
+            matches!(err, AuthError::AuthFailed(ref code, _) if code == "390111"),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn idle_session_is_validated_with_heartbeat() {
+        let mut server = mockito::Server::new_async().await;
+        let login = mock::login(&mut server).await.expect(2);
+        let heartbeat = server
+            .mock("POST", "/session/heartbeat")
+            .match_query(Matcher::Any)
+            .with_body(
+                json!({
+                    "code": "390111",
+                    "message": "Session no longer exists.",
+                    "success": false,
+                    "data": null
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let session = session(&server, Duration::from_millis(10));
+        session.get_token().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        session.get_token().await.unwrap();
+
+        heartbeat.assert_async().await;
+        login.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn failed_renewal_keeps_the_open_transaction() {
+        let mut server = mockito::Server::new_async().await;
+        let login = mock::login_with_validity(&mut server, 0).await.expect(1);
+        let renew = server
+            .mock("POST", "/session/token-request")
+            .match_query(Matcher::Any)
+            .with_body(
+                json!({
+                    "code": MASTER_TOKEN_EXPIRED,
+                    "message": "Master token has expired.",
+                    "success": false,
+                    "data": {}
+                })
+                .to_string(),
+            )
+            .expect(2)
+            .create_async()
+            .await;
+
+        let session = session(&server, DEFAULT_IDLE_TIMEOUT);
+        session.get_token().await.unwrap();
+        session.set_in_transaction(true);
+        for _ in 0..2 {
+            let err = session.get_token().await.unwrap_err();
+            assert!(matches!(err, AuthError::SessionLostInTransaction), "{err}");
+        }
+
+        renew.assert_async().await;
+        login.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn expired_master_token_logs_in_again() {
+        let spans = mock::Spans::default();
+        let _subscriber = tracing::subscriber::set_default(spans.clone());
+
+        let mut server = mockito::Server::new_async().await;
+        let login = mock::login_with_validity(&mut server, 0).await.expect(2);
+        let renew = server
+            .mock("POST", "/session/token-request")
+            .match_query(Matcher::Any)
+            .with_body(
+                json!({
+                    "code": MASTER_TOKEN_EXPIRED,
+                    "message": "Master token has expired.",
+                    "success": false,
+                    "data": {}
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        let session = session(&server, DEFAULT_IDLE_TIMEOUT);
+        session.get_token().await.unwrap();
+        session.get_token().await.unwrap();
+
+        renew.assert_async().await;
+        login.assert_async().await;
+        let warnings = spans.events(Level::WARN);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].fields["message"].starts_with("Master token has expired"));
+    }
+
+    #[tokio::test]
+    async fn oauth_login_sends_the_token() {
+        let mut server = mockito::Server::new_async().await;
+        let login = mock::login_matching(
+            &mut server,
+            Matcher::PartialJson(json!({
+                "data": {
+                    "AUTHENTICATOR": "OAUTH",
+                    "TOKEN": "access-token",
+                    "LOGIN_NAME": "USER",
+                    "ACCOUNT_NAME": "XY12345"
+                }
+            })),
+        )
+        .await;
+
+        let session = Session::oauth_auth(
+            Arc::new(mock::connection(&server)),
+            "xy12345",
+            None,
+            None,
+            None,
+            "user",
+            None,
+            "access-token",
+        );
+        session.get_token().await.unwrap();
+        login.assert_async().await;
+    }
+}
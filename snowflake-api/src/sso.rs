@@ -0,0 +1,175 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+use url::Url;
+
+use crate::connection::Connection;
+use crate::session::AuthError;
+
+/// IdP-initiated SAML login without a browser: credentials are posted to the identity provider
+/// and the SAML response is taken from the auto-submit form it returns.
+/// Meant for CI/CD and other non-interactive environments.
+#[derive(Clone)]
+pub struct ProgrammaticSsoAuth {
+    pub idp_url: Url,
+    pub username: String,
+    pub password: String,
+}
+
+impl ProgrammaticSsoAuth {
+    pub fn new(idp_url: Url, username: &str, password: &str) -> Self {
+        Self {
+            idp_url,
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    /// Log in to the identity provider and return the SAML response, it's single use and has to be fetched
+    /// again for every new session
+    pub async fn fetch_token(&self, client: &Connection) -> Result<String, AuthError> {
+        log::debug!("Requesting SAML response from {}", self.idp_url);
+        let html = client
+            .post_form(
+                self.idp_url.clone(),
+                &[
+                    ("username", self.username.as_str()),
+                    ("password", self.password.as_str()),
+                ],
+            )
+            .await?;
+
+        saml_response(&html).ok_or_else(|| {
+            AuthError::SsoFailed(format!(
+                "{} didn't return the SAML response, check the credentials",
+                self.idp_url
+            ))
+        })
+    }
+}
+
+/// Value of the `SAMLResponse` input of the form posted back to Snowflake
+fn saml_response(html: &str) -> Option<String> {
+    static INPUT_RE: OnceLock<Regex> = OnceLock::new();
+    static VALUE_RE: OnceLock<Regex> = OnceLock::new();
+    let input_re = INPUT_RE.get_or_init(|| {
+        Regex::new(r#"(?is)<input\b[^>]*\bname\s*=\s*["']SAMLResponse["'][^>]*>"#).unwrap()
+    });
+    let value_re =
+        VALUE_RE.get_or_init(|| Regex::new(r#"(?is)\bvalue\s*=\s*["']([^"']*)["']"#).unwrap());
+
+    let input = input_re.find(html)?.as_str();
+    let value = value_re.captures(input)?.get(1)?.as_str();
+    Some(unescape_html(value)).filter(|v| !v.is_empty())
+}
+
+/// Identity providers escape the base64 padding and `+`, eg `&#x3d;` for `=`
+fn unescape_html(value: &str) -> String {
+    static ENTITY_RE: OnceLock<Regex> = OnceLock::new();
+    let entity_re = ENTITY_RE
+        .get_or_init(|| Regex::new(r"&(#x[0-9a-fA-F]+|#[0-9]+|amp|quot|apos|lt|gt);").unwrap());
+    entity_re
+        .replace_all(value, |caps: &regex::Captures| {
+            let entity = &caps[1];
+            let c = match entity {
+                "amp" => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                _ => {
+                    let code = match entity.strip_prefix("#x") {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => entity[1..].parse().ok(),
+                    };
+                    code.and_then(char::from_u32)
+                }
+            };
+            c.map_or_else(|| caps[0].to_string(), String::from)
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+
+    use super::*;
+    use crate::mock;
+
+    const FORM: &str = r#"<html><body onload="document.forms[0].submit()">
+<form method="post" action="https://xy12345.snowflakecomputing.com/fed/login">
+  <input type="hidden" name="RelayState" value="ver%3A1" />
+  <INPUT TYPE="hidden"
+         NAME="SAMLResponse" VALUE="PHNhbWxwOlJlc3BvbnNl&#x2b;Pg&#x3d;&#61;"/>
+  <noscript><input type="submit" value="Continue"/></noscript>
+</form></body></html>"#;
+
+    #[test]
+    fn saml_response_is_taken_from_the_form() {
+        assert_eq!(
+            saml_response(FORM).as_deref(),
+            Some("PHNhbWxwOlJlc3BvbnNl+Pg==")
+        );
+        // attribute order doesn't matter
+        assert_eq!(
+            saml_response(r"<input value='abc&#x3D;' name='SAMLResponse'>").as_deref(),
+            Some("abc=")
+        );
+    }
+
+    #[test]
+    fn missing_saml_response_is_none() {
+        let login_page =
+            r#"<form><input name="username"/><input type="password" name="password"/></form>"#;
+        assert_eq!(saml_response(login_page), None);
+        assert_eq!(
+            saml_response(r#"<input name="SAMLResponse" value="">"#),
+            None
+        );
+        assert_eq!(saml_response(r#"<input name="SAMLResponse">"#), None);
+    }
+
+    #[test]
+    fn entities_are_unescaped() {
+        assert_eq!(
+            unescape_html("a&amp;b&lt;c&gt;&quot;&apos;&#43;&#x2F;"),
+            "a&b<c>\"'+/"
+        );
+        // unknown entities and invalid code points are kept as is
+        assert_eq!(unescape_html("&nbsp;&#xD800;"), "&nbsp;&#xD800;");
+    }
+
+    #[tokio::test]
+    async fn token_is_fetched_from_the_identity_provider() {
+        let mut server = mockito::Server::new_async().await;
+        let idp = server
+            .mock("POST", "/sso/saml")
+            .match_header("content-type", "application/x-www-form-urlencoded")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("username".to_string(), "ci-bot".to_string()),
+                Matcher::UrlEncoded("password".to_string(), "p&ss=word".to_string()),
+            ]))
+            .with_body(FORM)
+            .create_async()
+            .await;
+        let _rejected = server
+            .mock("POST", "/sso/denied")
+            .with_body("<html><body>Invalid credentials</body></html>")
+            .create_async()
+            .await;
+
+        let connection = mock::connection(&server);
+        let url = |path: &str| Url::parse(&format!("{}{path}", server.url())).unwrap();
+        let auth = ProgrammaticSsoAuth::new(url("/sso/saml"), "ci-bot", "p&ss=word");
+        assert_eq!(
+            auth.fetch_token(&connection).await.unwrap(),
+            "PHNhbWxwOlJlc3BvbnNl+Pg=="
+        );
+        idp.assert_async().await;
+
+        let auth = ProgrammaticSsoAuth::new(url("/sso/denied"), "ci-bot", "wrong");
+        let err = auth.fetch_token(&connection).await.unwrap_err();
+        assert!(matches!(err, AuthError::SsoFailed(_)), "{err}");
+    }
+}
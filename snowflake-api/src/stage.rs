@@ -0,0 +1,354 @@
+use std::fmt::{Debug, Formatter, Write};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer};
+
+use crate::metadata::qualified_identifier;
+use crate::quote::{quote_literal, quote_stage_path};
+use crate::{ConfigError, CopyFileFormat, SnowflakeApi, SnowflakeApiError};
+
+/// Access keys of the S3 bucket, storage integrations are preferred over them
+#[derive(Clone)]
+pub struct AwsCredentials {
+    pub key_id: String,
+    pub secret_key: String,
+    /// Session token of the temporary credentials
+    pub token: Option<String>,
+}
+
+impl Debug for AwsCredentials {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum S3Auth {
+    StorageIntegration(String),
+    Credentials(AwsCredentials),
+}
+
+#[derive(Clone)]
+pub enum AzureAuth {
+    StorageIntegration(String),
+    SasToken(String),
+}
+
+impl Debug for AzureAuth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::StorageIntegration(name) => {
+                f.debug_tuple("StorageIntegration").field(name).finish()
+            }
+            Self::SasToken(_) => f.debug_tuple("SasToken").finish_non_exhaustive(),
+        }
+    }
+}
+
+/// Where the files of the stage are stored, external stages without auth only work
+/// with public buckets
+#[derive(Debug, Clone)]
+pub enum StageLocation {
+    /// Snowflake-managed storage
+    Internal,
+    /// `s3://bucket/path/`
+    S3 { url: String, auth: Option<S3Auth> },
+    /// `gcs://bucket/path/`, only storage integrations are supported by GCS
+    Gcs {
+        url: String,
+        storage_integration: Option<String>,
+    },
+    /// `azure://account.blob.core.windows.net/container/path/`
+    Azure {
+        url: String,
+        auth: Option<AzureAuth>,
+    },
+}
+
+impl StageLocation {
+    fn to_sql(&self) -> Result<String, SnowflakeApiError> {
+        let (url, scheme) = match self {
+            Self::Internal => return Ok(String::new()),
+            Self::S3 { url, .. } => (url, "s3://"),
+            Self::Gcs { url, .. } => (url, "gcs://"),
+            Self::Azure { url, .. } => (url, "azure://"),
+        };
+        if !url.starts_with(scheme) {
+            return Err(ConfigError::InvalidField {
+                field: "url",
+                reason: format!("`{url}` has to start with `{scheme}`"),
+            }
+            .into());
+        }
+
+        let mut sql = format!(" URL = {}", quote_literal(url));
+        let storage_integration = match self {
+            Self::S3 {
+                auth: Some(S3Auth::StorageIntegration(name)),
+                ..
+            }
+            | Self::Azure {
+                auth: Some(AzureAuth::StorageIntegration(name)),
+                ..
+            }
+            | Self::Gcs {
+                storage_integration: Some(name),
+                ..
+            } => Some(name),
+            _ => None,
+        };
+        if let Some(name) = storage_integration {
+            let _ = write!(
+                sql,
+                " STORAGE_INTEGRATION = {}",
+                qualified_identifier(name)?
+            );
+        }
+        match self {
+            Self::S3 {
+                auth: Some(S3Auth::Credentials(credentials)),
+                ..
+            } => {
+                let _ = write!(
+                    sql,
+                    " CREDENTIALS = (AWS_KEY_ID = {} AWS_SECRET_KEY = {}",
+                    quote_literal(&credentials.key_id),
+                    quote_literal(&credentials.secret_key)
+                );
+                if let Some(token) = &credentials.token {
+                    let _ = write!(sql, " AWS_TOKEN = {}", quote_literal(token));
+                }
+                sql.push(')');
+            }
+            Self::Azure {
+                auth: Some(AzureAuth::SasToken(token)),
+                ..
+            } => {
+                let _ = write!(
+                    sql,
+                    " CREDENTIALS = (AZURE_SAS_TOKEN = {})",
+                    quote_literal(token)
+                );
+            }
+            _ => {}
+        }
+        Ok(sql)
+    }
+}
+
+/// Properties of the new stage, unset ones keep the server defaults
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct StageSpec {
+    name: String,
+    location: StageLocation,
+    file_format: Option<CopyFileFormat>,
+    comment: Option<String>,
+    temporary: bool,
+    if_not_exists: bool,
+}
+
+impl StageSpec {
+    pub fn new(name: &str, location: StageLocation) -> Self {
+        Self {
+            name: name.to_string(),
+            location,
+            file_format: None,
+            comment: None,
+            temporary: false,
+            if_not_exists: false,
+        }
+    }
+
+    /// Default format of the files loaded from the stage
+    pub fn with_file_format(mut self, file_format: CopyFileFormat) -> Self {
+        self.file_format = Some(file_format);
+        self
+    }
+
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        self.comment = Some(comment.to_string());
+        self
+    }
+
+    /// Drop the stage, along with its files for internal stages, when the session ends
+    pub fn with_temporary(mut self, temporary: bool) -> Self {
+        self.temporary = temporary;
+        self
+    }
+
+    /// Succeed without changes if the stage already exists
+    pub fn with_if_not_exists(mut self, if_not_exists: bool) -> Self {
+        self.if_not_exists = if_not_exists;
+        self
+    }
+
+    fn to_sql(&self) -> Result<String, SnowflakeApiError> {
+        let temporary = if self.temporary { " TEMPORARY" } else { "" };
+        let if_not_exists = if self.if_not_exists {
+            " IF NOT EXISTS"
+        } else {
+            ""
+        };
+        let mut sql = format!(
+            "CREATE{temporary} STAGE{if_not_exists} {}{}",
+            qualified_identifier(&self.name)?,
+            self.location.to_sql()?
+        );
+        if let Some(file_format) = &self.file_format {
+            let _ = write!(sql, " FILE_FORMAT = ({})", file_format.to_sql()?);
+        }
+        if let Some(comment) = &self.comment {
+            let _ = write!(sql, " COMMENT = {}", quote_literal(comment));
+        }
+        Ok(sql)
+    }
+}
+
+/// Row of `LIST @stage`
+#[derive(Debug, Clone, Deserialize)]
+pub struct StageFileInfo {
+    /// Path of the file, prefixed with the stage name for internal stages
+    /// and with the URL for external ones
+    pub name: String,
+    /// Size in bytes, compressed size for the files compressed by `PUT`
+    pub size: u64,
+    #[serde(default)]
+    pub md5: Option<String>,
+    #[serde(deserialize_with = "rfc2822")]
+    pub last_modified: DateTime<Utc>,
+}
+
+/// `LIST` reports the modification time as text, eg `Tue, 4 Jun 2024 10:00:00 GMT`
+fn rfc2822<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    DateTime::parse_from_rfc2822(&value)
+        .map(|ts| ts.with_timezone(&Utc))
+        .map_err(serde::de::Error::custom)
+}
+
+impl SnowflakeApi {
+    /// Creating the external stage with credentials or a storage integration
+    /// needs the privileges of the owner of the integration
+    pub async fn create_stage(&self, spec: &StageSpec) -> Result<(), SnowflakeApiError> {
+        self.execute(&spec.to_sql()?).await?;
+        Ok(())
+    }
+
+    /// Files of the stage location, eg `@my_stage/2024/`, `pattern` is the regular expression
+    /// matched against the whole path
+    pub async fn list_stage(
+        &self,
+        stage: &str,
+        pattern: Option<&str>,
+    ) -> Result<Vec<StageFileInfo>, SnowflakeApiError> {
+        let mut sql = format!("LIST {}", quote_stage_path(stage)?);
+        if let Some(pattern) = pattern {
+            let _ = write!(sql, " PATTERN = {}", quote_literal(pattern));
+        }
+        self.query_as(&sql).await
+    }
+
+    /// Remove the file, or all files under the path if it ends with `/`,
+    /// returns the number of removed files
+    pub async fn remove_stage_file(
+        &self,
+        stage: &str,
+        path: &str,
+    ) -> Result<usize, SnowflakeApiError> {
+        let location = format!(
+            "{}/{}",
+            stage.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        );
+        let rows = self
+            .query(&format!("REMOVE {}", quote_stage_path(&location)?))
+            .await?;
+        Ok(rows.len())
+    }
+
+    pub async fn drop_stage(&self, name: &str, if_exists: bool) -> Result<(), SnowflakeApiError> {
+        let if_exists = if if_exists { " IF EXISTS" } else { "" };
+        self.execute(&format!(
+            "DROP STAGE{if_exists} {}",
+            qualified_identifier(name)?
+        ))
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+
+    #[tokio::test]
+    async fn credentials_are_sent_but_not_traced() {
+        let spans = mock::Spans::default();
+        let _subscriber = tracing::subscriber::set_default(spans.clone());
+
+        let s3 = StageSpec::new(
+            "raw",
+            StageLocation::S3 {
+                url: "s3://bucket/raw/".to_string(),
+                auth: Some(S3Auth::Credentials(AwsCredentials {
+                    key_id: "AKIA".to_string(),
+                    secret_key: "aws-secret".to_string(),
+                    token: Some("aws-token".to_string()),
+                })),
+            },
+        );
+        let azure = StageSpec::new(
+            "blobs",
+            StageLocation::Azure {
+                url: "azure://account.blob.core.windows.net/c/".to_string(),
+                auth: Some(AzureAuth::SasToken("sas-token".to_string())),
+            },
+        );
+
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::AnyOf(vec![
+                Matcher::PartialJson(json!({
+                    "sqlText": "CREATE STAGE raw URL = 's3://bucket/raw/' CREDENTIALS = \
+                        (AWS_KEY_ID = 'AKIA' AWS_SECRET_KEY = 'aws-secret' AWS_TOKEN = 'aws-token')"
+                })),
+                Matcher::PartialJson(json!({
+                    "sqlText": "CREATE STAGE blobs URL = 'azure://account.blob.core.windows.net/c/' \
+                        CREDENTIALS = (AZURE_SAS_TOKEN = 'sas-token')"
+                })),
+            ]))
+            .with_body(mock::query_body(&json!({ "statementTypeId": 24832 })))
+            .expect(2)
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        api.create_stage(&s3).await.unwrap();
+        api.create_stage(&azure).await.unwrap();
+        query.assert_async().await;
+
+        let traced: Vec<String> = spans
+            .named("snowflake.query")
+            .into_iter()
+            .filter_map(|span| span.fields.get("sql").cloned())
+            .collect();
+        assert_eq!(
+            traced,
+            vec![
+                "CREATE STAGE raw URL = 's3://bucket/raw/' CREDENTIALS = (***)",
+                "CREATE STAGE blobs URL = 'azure://account.blob.core.windows.net/c/' \
+                    CREDENTIALS = (***)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,361 @@
+//! Classification of the statements which go through the file transfer flow instead of the query one,
+//! and masking of the secrets in the statement text before it's logged
+
+use std::borrow::Cow;
+
+/// Kind of the statement, as told by its leading keyword
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StatementKind {
+    Put,
+    Get,
+    Other,
+}
+
+impl StatementKind {
+    pub(crate) fn of(sql: &str) -> Self {
+        match leading_keyword(sql) {
+            Some(keyword) if keyword.eq_ignore_ascii_case("put") => Self::Put,
+            Some(keyword) if keyword.eq_ignore_ascii_case("get") => Self::Get,
+            _ => Self::Other,
+        }
+    }
+
+    pub(crate) fn is_file_transfer(self) -> bool {
+        matches!(self, Self::Put | Self::Get)
+    }
+}
+
+/// Locations of `PUT <file> <stage>` or `GET <stage> <file>`, quoted ones are unescaped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FileTransfer {
+    pub(crate) kind: StatementKind,
+    /// `file://` URI of the local file or directory
+    pub(crate) local: String,
+    pub(crate) stage: String,
+}
+
+impl FileTransfer {
+    pub(crate) fn parse(sql: &str) -> Option<Self> {
+        let kind = StatementKind::of(sql);
+        let (_, rest) = next_token(sql)?;
+        let (first, rest) = next_token(rest)?;
+        let (second, _) = next_token(rest)?;
+        let (local, stage) = match kind {
+            StatementKind::Put => (first, second),
+            StatementKind::Get => (second, first),
+            StatementKind::Other => return None,
+        };
+        Some(Self { kind, local, stage })
+    }
+}
+
+/// First word of the statement, leading whitespace and comments are skipped
+pub(crate) fn leading_keyword(sql: &str) -> Option<&str> {
+    let sql = skip_trivia(sql);
+    let end = sql
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(sql.len());
+    (end > 0).then(|| &sql[..end])
+}
+
+/// Skip whitespace along with `--`, `//` and `/* */` comments
+fn skip_trivia(mut sql: &str) -> &str {
+    loop {
+        sql = sql.trim_start();
+        if sql.starts_with("--") || sql.starts_with("//") {
+            sql = sql.find('\n').map_or("", |end| &sql[end + 1..]);
+        } else if let Some(comment) = sql.strip_prefix("/*") {
+            // unterminated comment swallows the rest of the statement
+            sql = comment.find("*/").map_or("", |end| &comment[end + 2..]);
+        } else {
+            return sql;
+        }
+    }
+}
+
+/// Next whitespace separated word or single-quoted string, along with the rest of the statement
+fn next_token(sql: &str) -> Option<(String, &str)> {
+    let sql = skip_trivia(sql);
+    if let Some(quoted) = sql.strip_prefix('\'') {
+        let mut token = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => token.push(chars.next()?.1),
+                '\'' if quoted[i + 1..].starts_with('\'') => {
+                    token.push('\'');
+                    chars.next();
+                }
+                '\'' => return Some((token, &quoted[i + 1..])),
+                c => token.push(c),
+            }
+        }
+        // unterminated literal
+        return None;
+    }
+
+    let end = sql
+        .find(|c: char| c.is_whitespace() || c == ';')
+        .unwrap_or(sql.len());
+    (end > 0).then(|| (sql[..end].to_string(), &sql[end..]))
+}
+
+/// Statement text safe to log: contents of `CREDENTIALS = (...)` and `ENCRYPTION = (...)`
+/// clauses, eg the `AWS_SECRET_KEY` or `AZURE_SAS_TOKEN` of the stage, are masked
+pub(crate) fn redact_secrets(sql: &str) -> Cow<'_, str> {
+    let mut redacted = String::new();
+    // end of the text already copied into `redacted`
+    let mut copied = 0;
+    while let Some(contents) = next_secret_clause(sql, copied) {
+        redacted.push_str(&sql[copied..contents]);
+        redacted.push_str("***");
+        // unterminated clause hides the rest of the statement
+        copied = closing_paren(sql, contents).unwrap_or(sql.len());
+    }
+    if copied == 0 {
+        return Cow::Borrowed(sql);
+    }
+    redacted.push_str(&sql[copied..]);
+    Cow::Owned(redacted)
+}
+
+const SECRET_CLAUSES: [&str; 2] = ["CREDENTIALS", "ENCRYPTION"];
+
+/// Start of the contents of the next secret clause at or after `from`, right after its `(`.
+/// Quoted literals and identifiers are skipped.
+fn next_secret_clause(sql: &str, mut from: usize) -> Option<usize> {
+    while let Some(c) = sql[from..].chars().next() {
+        if c == '\'' || c == '"' {
+            from = after_quoted(sql, from)?;
+        } else if is_identifier_char(c) {
+            let end = sql[from..]
+                .find(|c| !is_identifier_char(c))
+                .map_or(sql.len(), |end| from + end);
+            let word = &sql[from..end];
+            from = end;
+            if SECRET_CLAUSES.iter().any(|k| word.eq_ignore_ascii_case(k)) {
+                let value = sql[end..].trim_start().strip_prefix('=');
+                if let Some(contents) = value.and_then(|v| v.trim_start().strip_prefix('(')) {
+                    return Some(sql.len() - contents.len());
+                }
+            }
+        } else {
+            from += c.len_utf8();
+        }
+    }
+    None
+}
+
+/// Position of the `)` closing the clause which starts at `from`, literals may contain parens
+fn closing_paren(sql: &str, mut from: usize) -> Option<usize> {
+    while let Some(c) = sql[from..].chars().next() {
+        match c {
+            '\'' | '"' => from = after_quoted(sql, from)?,
+            ')' => return Some(from),
+            c => from += c.len_utf8(),
+        }
+    }
+    None
+}
+
+/// Position right after the literal or quoted identifier starting at `start`, `None` if it's
+/// unterminated. Doubled quotes and backslash escapes in literals are part of it.
+fn after_quoted(sql: &str, start: usize) -> Option<usize> {
+    let quote = sql[start..].chars().next()?;
+    let mut chars = sql[start + 1..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' && quote == '\'' {
+            chars.next();
+        } else if c == quote {
+            if sql[start + 1 + i + 1..].starts_with(quote) {
+                chars.next();
+            } else {
+                return Some(start + 1 + i + 1);
+            }
+        }
+    }
+    None
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '$'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statement_kind() {
+        use StatementKind::{Get, Other, Put};
+
+        let cases = [
+            ("PUT file:///tmp/a.csv @s", Put),
+            ("put file:///tmp/a.csv @s", Put),
+            ("Put file:///tmp/a.csv @s", Put),
+            ("  \n\tPUT file:///tmp/a.csv @s", Put),
+            ("PUT\nfile:///tmp/a.csv @s", Put),
+            ("put;", Put),
+            ("-- upload\nPUT file:///tmp/a.csv @s", Put),
+            ("// upload\nput file:///tmp/a.csv @s", Put),
+            ("/* multi\nline */ PUT file:///tmp/a.csv @s", Put),
+            ("/* a */ -- b\n// c\nGET @s file:///tmp/", Get),
+            ("GET @s/dir/ file:///tmp/out/", Get),
+            ("get @~ file:///tmp", Get),
+            ("/**/GET @s file:///tmp", Get),
+            ("WITH put AS (SELECT 1) SELECT * FROM put", Other),
+            ("SELECT put, get FROM t", Other),
+            ("PUTS", Other),
+            ("PUT_ROWS()", Other),
+            ("SELECT GET_DDL('table', 't')", Other),
+            ("GET_DDL('table', 't')", Other),
+            ("-- PUT file:///tmp/a.csv @s\nSELECT 1", Other),
+            ("// GET @s file:///tmp\nSELECT 1", Other),
+            ("/* PUT */ SELECT 1", Other),
+            ("/* unterminated PUT file:///tmp/a.csv @s", Other),
+            ("-- only a comment PUT", Other),
+            ("INSERT INTO t SELECT 'PUT file:///tmp/a.csv @s'", Other),
+            ("(SELECT 1)", Other),
+            ("'PUT'", Other),
+            ("", Other),
+            ("   \n ", Other),
+        ];
+        for (sql, kind) in cases {
+            assert_eq!(StatementKind::of(sql), kind, "{sql:?}");
+        }
+    }
+
+    #[test]
+    fn file_transfer_locations() {
+        use StatementKind::{Get, Put};
+
+        let cases = [
+            (
+                "PUT file:///tmp/data.csv @my_stage",
+                Put,
+                "file:///tmp/data.csv",
+                "@my_stage",
+            ),
+            (
+                "put 'file:///tmp/my data.csv' '@my_stage/path with spaces/'",
+                Put,
+                "file:///tmp/my data.csv",
+                "@my_stage/path with spaces/",
+            ),
+            (
+                "GET @my_stage/out/ file:///tmp/out/",
+                Get,
+                "file:///tmp/out/",
+                "@my_stage/out/",
+            ),
+            (
+                "get '@%orders/o''brien/' 'file:///tmp/o''brien/'",
+                Get,
+                "file:///tmp/o'brien/",
+                "@%orders/o'brien/",
+            ),
+            (
+                r"PUT 'file:///C:\\data\\a b.csv' @s",
+                Put,
+                r"file:///C:\data\a b.csv",
+                "@s",
+            ),
+            (
+                r"PUT 'file:///tmp/it\'s.csv' @s",
+                Put,
+                "file:///tmp/it's.csv",
+                "@s",
+            ),
+            (
+                "-- upload\nPUT file:///a.csv @s AUTO_COMPRESS = FALSE OVERWRITE = TRUE",
+                Put,
+                "file:///a.csv",
+                "@s",
+            ),
+            ("PUT file:///a.csv @s;", Put, "file:///a.csv", "@s"),
+            (
+                "PUT /* c */ file:///a.csv /* d */ @s",
+                Put,
+                "file:///a.csv",
+                "@s",
+            ),
+            (
+                "PUT\n\tfile:///tmp/*.csv\n\t@~/staged\n",
+                Put,
+                "file:///tmp/*.csv",
+                "@~/staged",
+            ),
+            (
+                "GET @s file:///tmp PATTERN = '.*[.]csv'",
+                Get,
+                "file:///tmp",
+                "@s",
+            ),
+        ];
+        for (sql, kind, local, stage) in cases {
+            assert_eq!(
+                FileTransfer::parse(sql),
+                Some(FileTransfer {
+                    kind,
+                    local: local.to_string(),
+                    stage: stage.to_string(),
+                }),
+                "{sql:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn incomplete_file_transfers() {
+        for sql in [
+            "PUT file:///a.csv",
+            "PUT",
+            "GET @s",
+            "PUT 'file:///unterminated @s",
+            "PUT file:///a.csv 'unterminated",
+            "PUT file:///a.csv -- @s",
+            "SELECT 1",
+            "",
+        ] {
+            assert_eq!(FileTransfer::parse(sql), None, "{sql:?}");
+        }
+    }
+
+    #[test]
+    fn secrets_are_redacted() {
+        let cases = [
+            (
+                "CREATE STAGE s URL = 's3://b/' CREDENTIALS = (AWS_KEY_ID = 'id' AWS_SECRET_KEY = 'secret')",
+                "CREATE STAGE s URL = 's3://b/' CREDENTIALS = (***)",
+            ),
+            (
+                "CREATE STAGE s URL = 's3://b/' credentials=(AWS_KEY_ID='id' AWS_SECRET_KEY='se)c''r\\'et' AWS_TOKEN='t') FILE_FORMAT = (TYPE = CSV)",
+                "CREATE STAGE s URL = 's3://b/' credentials=(***) FILE_FORMAT = (TYPE = CSV)",
+            ),
+            (
+                "CREATE STAGE s URL = 'azure://a/c' CREDENTIALS = (AZURE_SAS_TOKEN = 'sv=1') ENCRYPTION = (TYPE = 'AZURE_CSE' MASTER_KEY = 'k')",
+                "CREATE STAGE s URL = 'azure://a/c' CREDENTIALS = (***) ENCRYPTION = (***)",
+            ),
+            (
+                "COPY INTO t FROM 's3://b/' CREDENTIALS = (AWS_SECRET_KEY = 'unterminated",
+                "COPY INTO t FROM 's3://b/' CREDENTIALS = (***",
+            ),
+        ];
+        for (sql, redacted) in cases {
+            assert_eq!(redact_secrets(sql), redacted, "{sql:?}");
+        }
+
+        for sql in [
+            "SELECT 1",
+            "SELECT 'CREDENTIALS = (a)' FROM t",
+            r#"SELECT "CREDENTIALS" FROM t"#,
+            "SELECT my_credentials FROM t WHERE credentials_id = (1)",
+            "SELECT 'unterminated CREDENTIALS = (a)",
+        ] {
+            assert!(
+                matches!(redact_secrets(sql), Cow::Borrowed(s) if s == sql),
+                "{sql:?}"
+            );
+        }
+    }
+}
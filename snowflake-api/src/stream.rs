@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::record_batch::RecordBatch;
+use base64::Engine;
+use bytes::Bytes;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use tracing::Instrument;
+
+use crate::compression;
+use crate::connection::QueryType;
+use crate::responses::{ExecResponse, ExecResponseChunk};
+use crate::{query_response, RawQueryResult, SnowflakeApi, SnowflakeApiError};
+
+/// Number of chunks downloaded ahead of the consumer of [`SnowflakeApi::exec_streamed`]
+pub(crate) const PREFETCH_CHUNKS: usize = 2;
+
+impl SnowflakeApi {
+    /// Execute a single SELECT query and yield the record batches as the result chunks arrive.
+    /// Only a couple of chunks are downloaded ahead of the consumer and every chunk is dropped
+    /// once its batches are yielded, so memory use doesn't grow with the size of the result.
+    /// Dropping the stream stops further downloads.
+    pub async fn exec_streamed(
+        &self,
+        sql: &str,
+    ) -> Result<
+        impl Stream<Item = Result<RecordBatch, SnowflakeApiError>> + Send + 'static,
+        SnowflakeApiError,
+    > {
+        let resp = self
+            .run_sql::<ExecResponse>(sql, QueryType::ArrowQuery)
+            .await?;
+        let resp = query_response(resp)?;
+        // non-SELECT statements are answered with JSON even if Arrow was requested
+        if resp.data.returned > 0 && resp.data.is_json() {
+            return Err(SnowflakeApiError::UnexpectedResponse);
+        }
+
+        let inline = match resp.data.rowset_base64.as_deref() {
+            Some(base64) if !base64.is_empty() && resp.data.returned > 0 => {
+                let bytes = Bytes::from(base64::engine::general_purpose::STANDARD.decode(base64)?);
+                RawQueryResult::bytes_to_batches(bytes)?
+            }
+            _ => vec![],
+        };
+        log::debug!("Streaming result of {} chunks", resp.data.chunks.len());
+
+        let chunks = self
+            .chunk_stream(resp.data.chunks, resp.data.chunk_headers, resp.data.span)
+            .and_then(|bytes| async move {
+                Ok::<_, SnowflakeApiError>(RawQueryResult::flat_bytes_to_batches(bytes)?)
+            })
+            .map_ok(|batches| stream::iter(batches.into_iter().map(Ok)))
+            .try_flatten();
+
+        Ok(stream::iter(inline.into_iter().map(Ok)).chain(chunks))
+    }
+
+    /// Decompressed Arrow IPC payloads of the chunks in order, only a couple of chunks
+    /// are downloaded ahead of the consumer
+    pub(crate) fn chunk_stream(
+        &self,
+        chunks: Vec<ExecResponseChunk>,
+        headers: HashMap<String, String>,
+        span: tracing::Span,
+    ) -> impl Stream<Item = Result<Vec<Bytes>, SnowflakeApiError>> + Send + 'static {
+        let connection = Arc::clone(&self.connection);
+        stream::iter(chunks)
+            .map(move |chunk| {
+                let connection = Arc::clone(&connection);
+                let headers = headers.clone();
+                async move {
+                    let bytes = connection.get_chunk(&chunk.url, &headers).await?;
+                    Ok::<_, SnowflakeApiError>(compression::decompress_chunks(vec![bytes]).await?)
+                }
+                .instrument(span.clone())
+            })
+            .buffered(PREFETCH_CHUNKS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::Int64Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::ipc::writer::StreamWriter;
+    use arrow::record_batch::RecordBatch;
+    use futures::TryStreamExt;
+    use mockito::Matcher;
+    use serde_json::json;
+
+    use crate::mock;
+
+    fn ipc_chunk(values: Vec<i64>) -> Vec<u8> {
+        let schema = Arc::new(Schema::new(vec![Field::new("N", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![Arc::new(Int64Array::from(values))],
+        )
+        .unwrap();
+        let mut writer = StreamWriter::try_new(Vec::new(), &schema).unwrap();
+        writer.write(&batch).unwrap();
+        writer.into_inner().unwrap()
+    }
+
+    #[tokio::test]
+    async fn streamed_chunks_are_downloaded_within_query_span() {
+        let spans = mock::Spans::default();
+        let _subscriber = tracing::subscriber::set_default(spans.clone());
+
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _query = server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(&json!({
+                "queryResultFormat": "arrow",
+                "rowset": null,
+                "rowsetBase64": "",
+                "total": 3,
+                "returned": 3,
+                "chunks": [
+                    {"url": format!("{}/chunks/0", server.url()), "rowCount": 2, "uncompressedSize": 16},
+                    {"url": format!("{}/chunks/1", server.url()), "rowCount": 1, "uncompressedSize": 16}
+                ]
+            })))
+            .create_async()
+            .await;
+        let _first = server
+            .mock("GET", "/chunks/0")
+            .with_body(ipc_chunk(vec![1, 2]))
+            .create_async()
+            .await;
+        let _second = server
+            .mock("GET", "/chunks/1")
+            .with_body(ipc_chunk(vec![3]))
+            .create_async()
+            .await;
+
+        let api = mock::api(&server);
+        let batches: Vec<RecordBatch> = api
+            .exec_streamed("SELECT n FROM t")
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), 3);
+
+        let query = spans.named("snowflake.query")[0].id;
+        let chunks = spans.named("snowflake.chunk");
+        assert_eq!(chunks.len(), 2);
+        assert!(
+            chunks.iter().all(|chunk| chunk.parent == Some(query)),
+            "{chunks:?}"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Deserializer};
+
+use crate::metadata::{identifier, qualified_identifier};
+use crate::quote::quote_literal;
+use crate::{ConfigError, SnowflakeApi, SnowflakeApiError};
+
+/// When the task runs on its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskSchedule {
+    /// Cron expression followed by the time zone, eg `0 9 * * MON-FRI America/New_York`
+    Cron(String),
+    /// Interval between the runs
+    Minutes(u32),
+}
+
+impl TaskSchedule {
+    fn to_sql(&self) -> String {
+        match self {
+            Self::Cron(expr) => quote_literal(&format!("USING CRON {expr}")),
+            Self::Minutes(minutes) => format!("'{minutes} MINUTE'"),
+        }
+    }
+}
+
+/// Whether the task is scheduled, tasks are created suspended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    Started,
+    Suspended,
+}
+
+/// Task to create, runs either on the schedule or after the predecessor, not both
+#[derive(Debug, Clone)]
+pub struct TaskSpec {
+    pub name: String,
+    /// Serverless task if unset, compute is managed by Snowflake
+    pub warehouse: Option<String>,
+    pub schedule: Option<TaskSchedule>,
+    /// Task of the same DAG which has to finish first, may be qualified with the database and schema
+    pub predecessor: Option<String>,
+    /// Single statement, or a call of the stored procedure
+    pub sql: String,
+}
+
+/// Row of `SHOW TASKS`, columns which aren't listed here are ignored
+#[derive(Debug, Clone, Deserialize)]
+pub struct TaskInfo {
+    pub name: String,
+    #[serde(rename = "database_name")]
+    pub database: String,
+    #[serde(rename = "schema_name")]
+    pub schema: String,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub warehouse: Option<String>,
+    #[serde(default)]
+    pub schedule: Option<String>,
+    /// Fully qualified names of the predecessors
+    #[serde(default, deserialize_with = "json_list")]
+    pub predecessors: Vec<String>,
+    pub state: TaskState,
+    /// SQL run by the task
+    #[serde(default)]
+    pub definition: Option<String>,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+impl SnowflakeApi {
+    pub async fn create_task(&self, spec: &TaskSpec) -> Result<(), SnowflakeApiError> {
+        let mut sql = format!("CREATE TASK {}", identifier(&spec.name)?);
+        if let Some(warehouse) = &spec.warehouse {
+            sql = format!("{sql} WAREHOUSE = {}", identifier(warehouse)?);
+        }
+        match (&spec.schedule, &spec.predecessor) {
+            (Some(_), Some(_)) => {
+                return Err(ConfigError::InvalidField {
+                    field: "schedule",
+                    reason: "task with a predecessor runs after it and can't have a schedule"
+                        .to_string(),
+                }
+                .into())
+            }
+            (Some(schedule), None) => sql = format!("{sql} SCHEDULE = {}", schedule.to_sql()),
+            (None, Some(predecessor)) => {
+                sql = format!("{sql} AFTER {}", qualified_identifier(predecessor)?);
+            }
+            (None, None) => {}
+        }
+        self.execute(&format!("{sql} AS {}", spec.sql)).await?;
+        Ok(())
+    }
+
+    /// Resume the task with [`TaskState::Started`] or suspend it with [`TaskState::Suspended`].
+    /// Predecessors have to be suspended while the DAG is changed, and the root task
+    /// has to be resumed last.
+    pub async fn alter_task_state(
+        &self,
+        name: &str,
+        state: TaskState,
+    ) -> Result<(), SnowflakeApiError> {
+        let action = match state {
+            TaskState::Started => "RESUME",
+            TaskState::Suspended => "SUSPEND",
+        };
+        self.execute(&format!("ALTER TASK {} {action}", identifier(name)?))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn drop_task(&self, name: &str, if_exists: bool) -> Result<(), SnowflakeApiError> {
+        let if_exists = if if_exists { " IF EXISTS" } else { "" };
+        self.execute(&format!("DROP TASK{if_exists} {}", identifier(name)?))
+            .await?;
+        Ok(())
+    }
+
+    /// Tasks of the current schema visible to the current role
+    pub async fn show_tasks(&self) -> Result<Vec<TaskInfo>, SnowflakeApiError> {
+        self.query_as("SHOW TASKS").await
+    }
+}
+
+/// `SHOW TASKS` lists the predecessors as JSON array text
+fn json_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let value = Option::<String>::deserialize(deserializer)?;
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(vec![]),
+        Some(text) => serde_json::from_str(text).map_err(serde::de::Error::custom),
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::{SnowflakeApi, SnowflakeApiError};
+
+/// Expected result of a query: column names and rows with the values as the server
+/// sends them in JSON results, ie strings or `null`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryResultSnapshot {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl QueryResultSnapshot {
+    pub fn new(columns: Vec<String>, rows: Vec<Vec<Value>>) -> Self {
+        Self { columns, rows }
+    }
+
+    /// Load the snapshot saved by [`SnowflakeApi::record_query_result`]
+    pub fn from_file(path: &Path) -> Result<Self, SnowflakeApiError> {
+        let content = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Save the snapshot as pretty printed JSON, so changes are easy to review
+    pub fn to_file(&self, path: &Path) -> Result<(), SnowflakeApiError> {
+        let mut content = serde_json::to_vec_pretty(self)?;
+        content.push(b'\n');
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AssertionError {
+    #[error(transparent)]
+    Query(#[from] SnowflakeApiError),
+
+    #[error("Columns differ, expected `{expected:?}`, got `{actual:?}`")]
+    ColumnMismatch {
+        expected: Vec<String>,
+        actual: Vec<String>,
+    },
+
+    #[error("Row count differs, expected {expected}, got {actual}")]
+    RowCountMismatch { expected: usize, actual: usize },
+
+    #[error("Row {row} differs, expected `{expected}`, got `{actual}`")]
+    RowMismatch {
+        /// Zero-based index of the first differing row
+        row: usize,
+        /// Values of the expected row formatted as `COLUMN=value` pairs
+        expected: String,
+        actual: String,
+    },
+}
+
+impl SnowflakeApi {
+    /// Run the query and compare its result with the snapshot, rows are compared in order,
+    /// so the query should have an `ORDER BY` to be deterministic
+    pub async fn assert_query_result(
+        &self,
+        sql: &str,
+        expected: &QueryResultSnapshot,
+    ) -> Result<(), AssertionError> {
+        let actual = self.query_result_snapshot(sql).await?;
+        if actual.columns != expected.columns {
+            return Err(AssertionError::ColumnMismatch {
+                expected: expected.columns.clone(),
+                actual: actual.columns,
+            });
+        }
+        if actual.rows.len() != expected.rows.len() {
+            return Err(AssertionError::RowCountMismatch {
+                expected: expected.rows.len(),
+                actual: actual.rows.len(),
+            });
+        }
+        let mismatch = expected
+            .rows
+            .iter()
+            .zip(&actual.rows)
+            .position(|(expected, actual)| expected != actual);
+        match mismatch {
+            Some(row) => Err(AssertionError::RowMismatch {
+                row,
+                expected: format_row(&expected.columns, &expected.rows[row]),
+                actual: format_row(&actual.columns, &actual.rows[row]),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Run the query and save its result to be used with [`SnowflakeApi::assert_query_result`]
+    pub async fn record_query_result(
+        &self,
+        sql: &str,
+        path: &Path,
+    ) -> Result<QueryResultSnapshot, SnowflakeApiError> {
+        let snapshot = self.query_result_snapshot(sql).await?;
+        snapshot.to_file(path)?;
+        Ok(snapshot)
+    }
+
+    async fn query_result_snapshot(
+        &self,
+        sql: &str,
+    ) -> Result<QueryResultSnapshot, SnowflakeApiError> {
+        let rows = self.query(sql).await?;
+        let columns = rows.schema().iter().map(|f| f.name.clone()).collect();
+        let rows = rows.map(|row| row.values().to_vec()).collect();
+        Ok(QueryResultSnapshot::new(columns, rows))
+    }
+}
+
+fn format_row(columns: &[String], values: &[Value]) -> String {
+    let mut row = String::new();
+    for (i, (column, value)) in columns.iter().zip(values).enumerate() {
+        if i > 0 {
+            row.push_str(", ");
+        }
+        let _ = write!(row, "{column}={value}");
+    }
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Matcher, ServerGuard};
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock;
+
+    const SQL: &str = "SELECT id, name FROM users ORDER BY id";
+
+    async fn users(server: &mut ServerGuard, rows: &Value) -> mockito::Mock {
+        let column = |name: &str, type_: &str| {
+            json!({
+                "name": name, "type": type_, "nullable": true,
+                "byteLength": null, "length": null, "precision": null, "scale": null
+            })
+        };
+        server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .with_body(mock::query_body(&json!({
+                "rowtype": [column("ID", "fixed"), column("NAME", "text")],
+                "rowset": rows,
+                "total": rows.as_array().unwrap().len(),
+                "returned": rows.as_array().unwrap().len()
+            })))
+            .create_async()
+            .await
+    }
+
+    fn expected() -> QueryResultSnapshot {
+        QueryResultSnapshot::new(
+            vec!["ID".to_string(), "NAME".to_string()],
+            vec![
+                vec![json!("1"), json!("alice")],
+                vec![json!("2"), Value::Null],
+            ],
+        )
+    }
+
+    #[tokio::test]
+    async fn matching_result() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _users = users(&mut server, &json!([["1", "alice"], ["2", null]])).await;
+
+        let api = mock::api(&server);
+        api.assert_query_result(SQL, &expected()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn row_count_mismatch() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _users = users(&mut server, &json!([["1", "alice"]])).await;
+
+        let api = mock::api(&server);
+        let err = api.assert_query_result(SQL, &expected()).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                AssertionError::RowCountMismatch {
+                    expected: 2,
+                    actual: 1
+                }
+            ),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn first_differing_row_is_reported() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _users = users(&mut server, &json!([["1", "alice"], ["2", "bob"]])).await;
+
+        let api = mock::api(&server);
+        let err = api.assert_query_result(SQL, &expected()).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Row 1 differs, expected `ID=\"2\", NAME=null`, got `ID=\"2\", NAME=\"bob\"`"
+        );
+    }
+
+    #[tokio::test]
+    async fn recorded_snapshot_is_loaded_back() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _users = users(&mut server, &json!([["1", "alice"], ["2", null]])).await;
+        let path =
+            std::env::temp_dir().join(format!("snowflake-snapshot-{}.json", uuid::Uuid::new_v4()));
+
+        let api = mock::api(&server);
+        let recorded = api.record_query_result(SQL, &path).await.unwrap();
+        let loaded = QueryResultSnapshot::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(recorded, expected());
+        assert_eq!(loaded.unwrap(), expected());
+    }
+}
@@ -0,0 +1,249 @@
+use std::sync::Arc;
+
+use crate::session::Session;
+use crate::{QueryResult, SnowflakeApi, SnowflakeApiError};
+
+/// Explicit transaction of the session, finish it with [`Transaction::commit`] or
+/// [`Transaction::rollback`]. Dropping the guard without either of them, eg when the future
+/// is cancelled, schedules a best-effort rollback on the current Tokio runtime.
+/// [`SnowflakeApi::begin_transaction`] waits for that rollback to finish, statements executed
+/// directly on [`SnowflakeApi`] in the meantime could still run within the dropped transaction.
+/// Session can't be replaced by an automatic re-login while the transaction is open,
+/// such requests fail with [`crate::AuthError::SessionLostInTransaction`].
+pub struct Transaction<'a> {
+    api: &'a SnowflakeApi,
+    id: Option<String>,
+    finished: bool,
+}
+
+impl SnowflakeApi {
+    /// Start the transaction, fails with [`SnowflakeApiError::TransactionInProgress`]
+    /// if there is one open already. A warning is emitted if autocommit was switched on with
+    /// [`SnowflakeApi::set_autocommit`]: the transaction only lasts until its commit or rollback,
+    /// the statements after it are committed one by one again.
+    pub async fn begin_transaction(&self) -> Result<Transaction<'_>, SnowflakeApiError> {
+        let abandoned = self.abandoned_rollback.lock().unwrap().take();
+        if let Some(rollback) = abandoned {
+            if let Err(e) = rollback.await {
+                log::error!("Rollback of the abandoned transaction has panicked: {e}");
+                self.session.set_in_transaction(false);
+            }
+        }
+
+        if self.session.set_in_transaction(true) {
+            return Err(SnowflakeApiError::TransactionInProgress);
+        }
+        if self.autocommit() == Some(true) {
+            tracing::warn!(
+                "Transaction is started while autocommit is on, \
+                statements after its commit or rollback are committed one by one again"
+            );
+        }
+        let mut transaction = Transaction {
+            api: self,
+            id: None,
+            finished: false,
+        };
+
+        // guard rolls the transaction back if any of these fail
+        self.exec("BEGIN").await?;
+        let row = self
+            .query_internal("SELECT CURRENT_TRANSACTION()")
+            .await?
+            .next()
+            .ok_or(SnowflakeApiError::EmptyResponse)?;
+        transaction.id = row.get_by_index(0)?;
+        log::debug!("Transaction {:?} has started", transaction.id);
+
+        Ok(transaction)
+    }
+}
+
+impl Transaction<'_> {
+    /// Id of the transaction, as reported by `CURRENT_TRANSACTION()`
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// Execute the statement within the transaction
+    pub async fn exec(&self, sql: &str) -> Result<QueryResult, SnowflakeApiError> {
+        self.api.exec(sql).await
+    }
+
+    pub async fn commit(mut self) -> Result<(), SnowflakeApiError> {
+        self.finished = true;
+        let res = self.api.exec("COMMIT").await;
+        self.api.session.set_in_transaction(false);
+        log::debug!("Transaction {:?} is committed", self.id);
+        res.map(|_| ())
+    }
+
+    pub async fn rollback(mut self) -> Result<(), SnowflakeApiError> {
+        self.finished = true;
+        self.api.session.rollback().await?;
+        log::debug!("Transaction {:?} is rolled back", self.id);
+        Ok(())
+    }
+
+    /// Release the guard without finishing the transaction, it stays open on the server
+    /// until the caller runs `COMMIT` or `ROLLBACK`, but isn't tracked by the session anymore
+    pub(crate) fn leave_open(mut self) {
+        self.finished = true;
+        self.api.session.set_in_transaction(false);
+        log::debug!("Transaction {:?} is left open", self.id);
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+
+        log::warn!(
+            "Transaction {:?} was neither committed nor rolled back, rolling it back",
+            self.id
+        );
+        let session: Arc<Session> = Arc::clone(&self.api.session);
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let rollback = handle.spawn(async move {
+                if let Err(e) = session.rollback().await {
+                    log::error!("Failed to roll back the abandoned transaction: {e}");
+                }
+            });
+            *self.api.abandoned_rollback.lock().unwrap() = Some(rollback);
+        } else {
+            log::error!("No Tokio runtime to roll back the abandoned transaction");
+            session.set_in_transaction(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Matcher, Mock, ServerGuard};
+    use serde_json::json;
+    use tracing::Level;
+
+    use crate::mock;
+    use crate::ExecManyOptions;
+
+    async fn statement(server: &mut ServerGuard, sql: &str, hits: usize) -> Mock {
+        let body = if sql == "SELECT CURRENT_TRANSACTION()" {
+            mock::query_body(&json!({
+                "rowtype": [{
+                    "name": "CURRENT_TRANSACTION()", "type": "text", "nullable": true,
+                    "byteLength": null, "length": null, "precision": null, "scale": null
+                }],
+                "rowset": [["1700000000000000000"]],
+                "total": 1,
+                "returned": 1
+            }))
+        } else {
+            mock::query_body(&json!({}))
+        };
+        server
+            .mock("POST", mock::QUERY_PATH)
+            .match_query(Matcher::Any)
+            .match_body(Matcher::PartialJson(json!({ "sqlText": sql })))
+            .with_body(body)
+            .expect(hits)
+            .create_async()
+            .await
+    }
+
+    #[tokio::test]
+    async fn exec_many_tracks_its_transaction() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let begin = statement(&mut server, "BEGIN", 2).await;
+        let _id = statement(&mut server, "SELECT CURRENT_TRANSACTION()", 2).await;
+        let insert = statement(&mut server, "INSERT INTO t VALUES (1)", 1).await;
+        let commit = statement(&mut server, "COMMIT", 2).await;
+
+        let api = mock::api(&server);
+        let opts = ExecManyOptions::default().with_transaction(true);
+        api.exec_many(&["INSERT INTO t VALUES (1)"], &opts)
+            .await
+            .unwrap();
+        assert!(!api.session.in_transaction());
+
+        // flag is cleared after the commit, so the next transaction could begin
+        api.begin_transaction()
+            .await
+            .unwrap()
+            .commit()
+            .await
+            .unwrap();
+        begin.assert_async().await;
+        insert.assert_async().await;
+        commit.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn exec_many_refuses_to_nest_transactions() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _begin = statement(&mut server, "BEGIN", 1).await;
+        let _id = statement(&mut server, "SELECT CURRENT_TRANSACTION()", 1).await;
+
+        let api = mock::api(&server);
+        let _transaction = api.begin_transaction().await.unwrap();
+        let opts = ExecManyOptions::default().with_transaction(true);
+        let err = api.exec_many(&["SELECT 1"], &opts).await.unwrap_err();
+        assert!(
+            matches!(err.source, crate::SnowflakeApiError::TransactionInProgress),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn transaction_begins_after_dropped_one_is_rolled_back() {
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let begin = statement(&mut server, "BEGIN", 2).await;
+        let _id = statement(&mut server, "SELECT CURRENT_TRANSACTION()", 2).await;
+        let rollback = statement(&mut server, "ROLLBACK", 1).await;
+
+        let api = mock::api(&server);
+        drop(api.begin_transaction().await.unwrap());
+        let transaction = api.begin_transaction().await.unwrap();
+        rollback.assert_async().await;
+        begin.assert_async().await;
+        assert!(api.session.in_transaction());
+        drop(transaction);
+    }
+
+    #[tokio::test]
+    async fn transaction_with_autocommit_on_is_warned_about() {
+        let spans = mock::Spans::default();
+        let _subscriber = tracing::subscriber::set_default(spans.clone());
+
+        let mut server = mockito::Server::new_async().await;
+        let _login = mock::login(&mut server).await;
+        let _autocommit = statement(&mut server, "ALTER SESSION SET AUTOCOMMIT = TRUE", 1).await;
+        let _begin = statement(&mut server, "BEGIN", 2).await;
+        let _id = statement(&mut server, "SELECT CURRENT_TRANSACTION()", 2).await;
+        let _commit = statement(&mut server, "COMMIT", 2).await;
+
+        let api = mock::api(&server);
+        api.begin_transaction()
+            .await
+            .unwrap()
+            .commit()
+            .await
+            .unwrap();
+        assert!(spans.events(Level::WARN).is_empty());
+
+        api.set_autocommit(true).await.unwrap();
+        api.begin_transaction()
+            .await
+            .unwrap()
+            .commit()
+            .await
+            .unwrap();
+        let warnings = spans.events(Level::WARN);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].fields["message"].contains("autocommit is on"));
+    }
+}
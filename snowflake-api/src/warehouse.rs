@@ -0,0 +1,180 @@
+use std::fmt::Write;
+
+use crate::metadata::identifier;
+use crate::quote::quote_literal;
+use crate::{SnowflakeApi, SnowflakeApiError};
+
+/// Size of the warehouse, each size has twice the compute of the previous one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarehouseSize {
+    XSmall,
+    Small,
+    Medium,
+    Large,
+    XLarge,
+    TwoXLarge,
+    ThreeXLarge,
+    FourXLarge,
+}
+
+impl WarehouseSize {
+    /// Value of the `WAREHOUSE_SIZE` property
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::XSmall => "XSMALL",
+            Self::Small => "SMALL",
+            Self::Medium => "MEDIUM",
+            Self::Large => "LARGE",
+            Self::XLarge => "XLARGE",
+            Self::TwoXLarge => "XXLARGE",
+            Self::ThreeXLarge => "XXXLARGE",
+            Self::FourXLarge => "X4LARGE",
+        }
+    }
+}
+
+/// Properties of the new warehouse, unset ones keep the server defaults
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct WarehouseSpec {
+    name: String,
+    size: Option<WarehouseSize>,
+    auto_suspend_secs: Option<u32>,
+    auto_resume: Option<bool>,
+    initially_suspended: Option<bool>,
+    comment: Option<String>,
+    if_not_exists: bool,
+}
+
+impl WarehouseSpec {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            size: None,
+            auto_suspend_secs: None,
+            auto_resume: None,
+            initially_suspended: None,
+            comment: None,
+            if_not_exists: false,
+        }
+    }
+
+    pub fn with_size(mut self, size: WarehouseSize) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Suspend the warehouse after this many seconds of inactivity, `0` never suspends it
+    pub fn with_auto_suspend_secs(mut self, auto_suspend_secs: u32) -> Self {
+        self.auto_suspend_secs = Some(auto_suspend_secs);
+        self
+    }
+
+    pub fn with_auto_resume(mut self, auto_resume: bool) -> Self {
+        self.auto_resume = Some(auto_resume);
+        self
+    }
+
+    /// Don't start the warehouse right after it's created
+    pub fn with_initially_suspended(mut self, initially_suspended: bool) -> Self {
+        self.initially_suspended = Some(initially_suspended);
+        self
+    }
+
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        self.comment = Some(comment.to_string());
+        self
+    }
+
+    /// Succeed without changes if the warehouse already exists
+    pub fn with_if_not_exists(mut self, if_not_exists: bool) -> Self {
+        self.if_not_exists = if_not_exists;
+        self
+    }
+
+    fn to_sql(&self) -> Result<String, SnowflakeApiError> {
+        let if_not_exists = if self.if_not_exists {
+            " IF NOT EXISTS"
+        } else {
+            ""
+        };
+        let mut sql = format!(
+            "CREATE WAREHOUSE{if_not_exists} {}",
+            identifier(&self.name)?
+        );
+        if let Some(size) = self.size {
+            let _ = write!(sql, " WAREHOUSE_SIZE = '{}'", size.as_str());
+        }
+        if let Some(auto_suspend_secs) = self.auto_suspend_secs {
+            let _ = write!(sql, " AUTO_SUSPEND = {auto_suspend_secs}");
+        }
+        if let Some(auto_resume) = self.auto_resume {
+            let _ = write!(sql, " AUTO_RESUME = {}", sql_bool(auto_resume));
+        }
+        if let Some(initially_suspended) = self.initially_suspended {
+            let _ = write!(
+                sql,
+                " INITIALLY_SUSPENDED = {}",
+                sql_bool(initially_suspended)
+            );
+        }
+        if let Some(comment) = &self.comment {
+            let _ = write!(sql, " COMMENT = {}", quote_literal(comment));
+        }
+        Ok(sql)
+    }
+}
+
+impl SnowflakeApi {
+    /// Stop the warehouse, running queries are finished first
+    pub async fn suspend_warehouse(&self, name: &str) -> Result<(), SnowflakeApiError> {
+        self.execute(&format!("ALTER WAREHOUSE {} SUSPEND", identifier(name)?))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn resume_warehouse(&self, name: &str) -> Result<(), SnowflakeApiError> {
+        self.execute(&format!("ALTER WAREHOUSE {} RESUME", identifier(name)?))
+            .await?;
+        Ok(())
+    }
+
+    /// Change the size, running queries keep the resources they have started with
+    pub async fn resize_warehouse(
+        &self,
+        name: &str,
+        size: WarehouseSize,
+    ) -> Result<(), SnowflakeApiError> {
+        self.execute(&format!(
+            "ALTER WAREHOUSE {} SET WAREHOUSE_SIZE = '{}'",
+            identifier(name)?,
+            size.as_str()
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub async fn create_warehouse(&self, spec: &WarehouseSpec) -> Result<(), SnowflakeApiError> {
+        self.execute(&spec.to_sql()?).await?;
+        Ok(())
+    }
+
+    pub async fn drop_warehouse(
+        &self,
+        name: &str,
+        if_exists: bool,
+    ) -> Result<(), SnowflakeApiError> {
+        let if_exists = if if_exists { " IF EXISTS" } else { "" };
+        self.execute(&format!("DROP WAREHOUSE{if_exists} {}", identifier(name)?))
+            .await?;
+        Ok(())
+    }
+}
+
+pub(crate) fn sql_bool(value: bool) -> &'static str {
+    if value {
+        "TRUE"
+    } else {
+        "FALSE"
+    }
+}